@@ -16,6 +16,9 @@ use tokio::sync::Mutex;
 use tower::{Layer, Service};
 use tracing::{info, warn};
 
+/// Where the agent writes its audit log.
+pub const DEFAULT_AUDIT_LOG_PATH: &str = "/var/lib/keel/audit/audit.log";
+
 /// A single structured audit log entry, serialized as one JSON line.
 #[derive(Debug, Clone, Serialize)]
 pub struct AuditEntry {
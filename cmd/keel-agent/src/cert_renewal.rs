@@ -1,10 +1,48 @@
 use crate::cert_metrics::cert_metrics;
 use crate::k8s_csr::K8sCsrManager;
-use keel_crypto::{check_cert_needs_renewal, parse_cert_expiry};
+use keel_crypto::{
+    check_cert_needs_renewal, parse_cert_expiry, set_key_permissions, write_private_file,
+};
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
 use tracing::{debug, error, info, warn};
 
+/// Persist a renewed certificate and key, backing up any previous pair
+///
+/// The key and its backup are written with mode `0600`.
+pub fn store_certificate_pair(
+    cert_path: &str,
+    key_path: &str,
+    cert_pem: &str,
+    key_pem: &str,
+) -> Result<(), String> {
+    if std::path::Path::new(cert_path).exists() {
+        let backup_cert = format!("{}.backup", cert_path);
+        let backup_key = format!("{}.backup", key_path);
+
+        if let Err(e) = std::fs::copy(cert_path, &backup_cert) {
+            warn!("Failed to backup old certificate: {}", e);
+        } else {
+            info!("Backed up old certificate to {}", backup_cert);
+        }
+
+        match std::fs::copy(key_path, &backup_key) {
+            Ok(_) => {
+                if let Err(e) = set_key_permissions(&backup_key) {
+                    warn!("Failed to restrict permissions on key backup: {}", e);
+                }
+                info!("Backed up old key to {}", backup_key);
+            }
+            Err(e) => warn!("Failed to backup old key: {}", e),
+        }
+    }
+
+    keel_config::atomic_write(cert_path, cert_pem)
+        .map_err(|e| format!("Failed to write new certificate: {}", e))?;
+
+    write_private_file(key_path, key_pem).map_err(|e| format!("Failed to write new key: {}", e))
+}
+
 /// Configuration for certificate renewal
 #[derive(Debug, Clone)]
 pub struct CertRenewalConfig {
@@ -138,30 +176,7 @@ impl CertRenewalManager {
             .await
             .map_err(|e| format!("Failed to request certificate: {}", e))?;
 
-        // Create backup of old certificates
-        if std::path::Path::new(&self.cert_path).exists() {
-            let backup_cert = format!("{}.backup", self.cert_path);
-            let backup_key = format!("{}.backup", self.key_path);
-
-            if let Err(e) = std::fs::copy(&self.cert_path, &backup_cert) {
-                warn!("Failed to backup old certificate: {}", e);
-            } else {
-                info!("Backed up old certificate to {}", backup_cert);
-            }
-
-            if let Err(e) = std::fs::copy(&self.key_path, &backup_key) {
-                warn!("Failed to backup old key: {}", e);
-            } else {
-                info!("Backed up old key to {}", backup_key);
-            }
-        }
-
-        // Write new certificates
-        std::fs::write(&self.cert_path, &cert_pem)
-            .map_err(|e| format!("Failed to write new certificate: {}", e))?;
-
-        std::fs::write(&self.key_path, &key_pem)
-            .map_err(|e| format!("Failed to write new key: {}", e))?;
+        store_certificate_pair(&self.cert_path, &self.key_path, &cert_pem, &key_pem)?;
 
         // Parse and log new expiry
         if let Ok(new_expiry) = parse_cert_expiry(&cert_pem) {
@@ -176,3 +191,59 @@ impl CertRenewalManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn file_mode(path: &str) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_certificate_pair_key_is_0600() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let cert_path = dir.path().join("operational.pem");
+        let key_path = dir.path().join("operational.key");
+        let cert_path = cert_path.to_str().unwrap();
+        let key_path = key_path.to_str().unwrap();
+
+        // A previous pair with a too-permissive key
+        std::fs::write(cert_path, "old cert").unwrap();
+        std::fs::write(key_path, "old key").unwrap();
+        std::fs::set_permissions(key_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        store_certificate_pair(cert_path, key_path, "new cert", "new key").unwrap();
+
+        assert_eq!(std::fs::read_to_string(key_path).unwrap(), "new key");
+        assert_eq!(file_mode(key_path), 0o600);
+
+        let backup_key = format!("{}.backup", key_path);
+        assert_eq!(std::fs::read_to_string(&backup_key).unwrap(), "old key");
+        assert_eq!(file_mode(&backup_key), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_certificate_pair_fresh_key_is_0600() {
+        let dir = TempDir::new().unwrap();
+        let cert_path = dir.path().join("operational.pem");
+        let key_path = dir.path().join("operational.key");
+
+        store_certificate_pair(
+            cert_path.to_str().unwrap(),
+            key_path.to_str().unwrap(),
+            "cert",
+            "key",
+        )
+        .unwrap();
+
+        assert_eq!(file_mode(key_path.to_str().unwrap()), 0o600);
+    }
+}
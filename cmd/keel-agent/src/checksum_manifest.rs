@@ -0,0 +1,227 @@
+//! `SHA256SUMS` checksum manifests for update images
+//!
+//! Instead of passing `expected_sha256`, an update may name a manifest in
+//! the format written by `sha256sum`:
+//!
+//! ```text
+//! # KeelOS 1.4.0
+//! 3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855e  keel-1.4.0.squashfs
+//! 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 *keel-1.4.0.delta
+//! ```
+//!
+//! The agent fetches the manifest and verifies the image against the entry
+//! for the image URL's file name. A delta update is verified as the full
+//! image it rebuilds, so its entry is the one for the full image URL.
+
+use std::collections::HashMap;
+use std::io;
+use tracing::info;
+
+use crate::download;
+
+/// Parse manifest content into file name → lowercase SHA256
+///
+/// Blank lines and `#` comments are skipped. The `*` binary-mode marker
+/// and a leading `./` are stripped from file names. Any other line that is
+/// not `<64 hex digits> <name>` is an error, so a truncated or mistyped
+/// manifest is not silently treated as having no entry.
+pub fn parse_manifest(content: &str) -> Result<HashMap<String, String>, String> {
+    let mut entries = HashMap::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hash, name) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("line {}: expected '<sha256>  <file>'", number + 1))?;
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "line {}: '{}' is not a SHA256 hash",
+                number + 1,
+                hash
+            ));
+        }
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        let name = name.strip_prefix("./").unwrap_or(name);
+        if name.is_empty() {
+            return Err(format!("line {}: missing file name", number + 1));
+        }
+        entries.insert(name.to_string(), hash.to_ascii_lowercase());
+    }
+    Ok(entries)
+}
+
+/// File name of `url`'s path, without query or fragment
+pub fn image_file_name(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// URL whose manifest entry an update is verified against
+///
+/// A delta is checked after patching, against the hash of the full image,
+/// so the manifest is searched for the full image's file name.
+pub fn manifest_image_url<'a>(
+    source_url: &'a str,
+    is_delta: bool,
+    full_image_url: &'a str,
+) -> Result<&'a str, String> {
+    if !is_delta {
+        return Ok(source_url);
+    }
+    if full_image_url.is_empty() {
+        return Err(
+            "a delta update verified by a checksum manifest needs full_image_url, whose file \
+             name the manifest lists"
+                .to_string(),
+        );
+    }
+    Ok(full_image_url)
+}
+
+/// Hash the manifest lists for the image at `image_url`
+///
+/// Entries match on the full name or, for manifests that list paths, on
+/// the last path component.
+pub fn lookup(manifest: &HashMap<String, String>, image_url: &str) -> Result<String, String> {
+    let file = image_file_name(image_url)
+        .ok_or_else(|| format!("cannot tell the file name of '{}'", image_url))?;
+    if let Some(hash) = manifest.get(file) {
+        return Ok(hash.clone());
+    }
+    manifest
+        .iter()
+        .find(|(name, _)| name.rsplit('/').next() == Some(file))
+        .map(|(_, hash)| hash.clone())
+        .ok_or_else(|| format!("checksum manifest has no entry for '{}'", file))
+}
+
+/// Expected SHA256 of `image_url` from the manifest at `manifest_url`
+pub async fn fetch_expected_sha256(
+    manifest_url: &str,
+    image_url: &str,
+    auth_header: Option<&str>,
+) -> io::Result<String> {
+    let response = download::get(manifest_url, auth_header)
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to fetch checksum manifest: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Checksum manifest server returned error: {}",
+            response.status()
+        )));
+    }
+    let body = download::body(response)
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to read checksum manifest: {}", e)))?;
+    let body = String::from_utf8_lossy(&body);
+
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let manifest =
+        parse_manifest(&body).map_err(|e| invalid(format!("Invalid checksum manifest: {}", e)))?;
+    let hash = lookup(&manifest, image_url).map_err(invalid)?;
+    info!(manifest = %manifest_url, sha256 = %hash, "Expected checksum taken from manifest");
+    Ok(hash)
+}
+
+/// Expected SHA256 for an update, combining an explicit hash with the
+/// manifest entry; the two must agree when both are given
+pub async fn resolve_expected_sha256(
+    explicit: Option<String>,
+    manifest_url: &str,
+    image_url: &str,
+    auth_header: Option<&str>,
+) -> io::Result<Option<String>> {
+    if manifest_url.is_empty() {
+        return Ok(explicit);
+    }
+    let from_manifest = fetch_expected_sha256(manifest_url, image_url, auth_header).await?;
+    match explicit {
+        Some(explicit) if !explicit.eq_ignore_ascii_case(&from_manifest) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "expected_sha256 {} does not match the checksum manifest ({})",
+                explicit, from_manifest
+            ),
+        )),
+        _ => Ok(Some(from_manifest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_A: &str = "3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855e";
+    const HASH_B: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn test_parse_manifest() {
+        let content = format!(
+            "# KeelOS 1.4.0 release\n\n{}  keel-1.4.0.squashfs\n{} *./keel-1.4.0.delta\n",
+            HASH_A,
+            HASH_B.to_uppercase()
+        );
+        let manifest = parse_manifest(&content).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest["keel-1.4.0.squashfs"], HASH_A);
+        assert_eq!(manifest["keel-1.4.0.delta"], HASH_B);
+
+        assert!(parse_manifest("").unwrap().is_empty());
+        assert!(parse_manifest("not-a-hash  keel.squashfs\n").is_err());
+        assert!(parse_manifest(&format!("{}\n", HASH_A)).is_err());
+    }
+
+    #[test]
+    fn test_lookup_by_file_name() {
+        let content = format!(
+            "{}  keel-1.4.0.squashfs\n{}  arm64/keel-1.4.0.delta\n",
+            HASH_A, HASH_B
+        );
+        let manifest = parse_manifest(&content).unwrap();
+
+        assert_eq!(
+            lookup(
+                &manifest,
+                "https://example.com/releases/keel-1.4.0.squashfs?token=x"
+            )
+            .unwrap(),
+            HASH_A
+        );
+        assert_eq!(
+            lookup(&manifest, "https://example.com/arm64/keel-1.4.0.delta").unwrap(),
+            HASH_B
+        );
+        let err = lookup(&manifest, "https://example.com/keel-1.5.0.squashfs").unwrap_err();
+        assert!(err.contains("keel-1.5.0.squashfs"), "{}", err);
+        assert!(lookup(&manifest, "https://example.com/releases/").is_err());
+    }
+
+    #[test]
+    fn test_delta_is_looked_up_by_full_image_name() {
+        assert_eq!(
+            manifest_image_url("https://example.com/keel-1.4.0.squashfs", false, "").unwrap(),
+            "https://example.com/keel-1.4.0.squashfs"
+        );
+        assert_eq!(
+            manifest_image_url(
+                "https://example.com/keel-1.3.0-1.4.0.delta",
+                true,
+                "https://example.com/keel-1.4.0.squashfs"
+            )
+            .unwrap(),
+            "https://example.com/keel-1.4.0.squashfs"
+        );
+        assert!(manifest_image_url("https://example.com/keel.delta", true, "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_without_manifest_keeps_explicit_hash() {
+        let resolved = resolve_expected_sha256(Some(HASH_A.to_string()), "", "http://x/img", None)
+            .await
+            .unwrap();
+        assert_eq!(resolved.as_deref(), Some(HASH_A));
+    }
+}
@@ -0,0 +1,229 @@
+//! Live reload of the declarative node configuration
+//!
+//! The agent loads `/etc/keel/node.yaml` at startup and shares it with the
+//! gRPC service through an `Arc<RwLock<NodeConfig>>`. On SIGHUP the file is
+//! re-read and re-validated; a config that fails to parse or validate is
+//! rejected and the previous one stays in effect.
+
+use keel_config::{ConfigError, NodeConfig};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{Notify, RwLock};
+use tracing::{info, warn};
+
+/// Default location of the declarative node configuration
+pub const NODE_CONFIG_PATH: &str = "/etc/keel/node.yaml";
+
+/// Load and validate the node configuration, falling back to defaults if
+/// the file does not exist.
+pub fn load_node_config<P: AsRef<Path>>(path: P) -> Result<NodeConfig, ConfigError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        warn!(path = %path.display(), "Configuration not found, using defaults");
+        return Ok(NodeConfig::default_config());
+    }
+
+    info!(path = %path.display(), "Loading configuration");
+    let config = NodeConfig::load(path)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Re-read the configuration at `path` and swap it into `current`.
+///
+/// Returns the list of human-readable changes that were applied. On a parse
+/// or validation error the current configuration is left untouched.
+pub async fn reload_node_config<P: AsRef<Path>>(
+    path: P,
+    current: &RwLock<NodeConfig>,
+) -> Result<Vec<String>, ConfigError> {
+    let new_config = load_node_config(path)?;
+
+    let mut guard = current.write().await;
+    let changes = diff_node_config(&guard, &new_config);
+    *guard = new_config;
+    Ok(changes)
+}
+
+/// Describe the differences between two configurations
+pub fn diff_node_config(old: &NodeConfig, new: &NodeConfig) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if old.version != new.version {
+        changes.push(format!("version: {} -> {}", old.version, new.version));
+    }
+    if old.hostname != new.hostname {
+        changes.push(format!("hostname: {} -> {}", old.hostname, new.hostname));
+    }
+    if old.kubernetes.version != new.kubernetes.version {
+        changes.push(format!(
+            "kubernetes.version: {:?} -> {:?}",
+            old.kubernetes.version, new.kubernetes.version
+        ));
+    }
+
+    if old.scheduler.poll_interval_secs != new.scheduler.poll_interval_secs {
+        changes.push(format!(
+            "scheduler.poll_interval_secs: {:?} -> {:?}",
+            old.scheduler.poll_interval_secs, new.scheduler.poll_interval_secs
+        ));
+    }
+    if old.scheduler.max_in_flight != new.scheduler.max_in_flight {
+        changes.push(format!(
+            "scheduler.max_in_flight: {:?} -> {:?}",
+            old.scheduler.max_in_flight, new.scheduler.max_in_flight
+        ));
+    }
+    if old.scheduler.keep_finished != new.scheduler.keep_finished {
+        changes.push(format!(
+            "scheduler.keep_finished: {:?} -> {:?}",
+            old.scheduler.keep_finished, new.scheduler.keep_finished
+        ));
+    }
+
+    for container in &new.containers {
+        match old.containers.iter().find(|c| c.name == container.name) {
+            None => changes.push(format!("container added: {}", container.name)),
+            Some(prev) if prev.image != container.image => changes.push(format!(
+                "container {} image: {} -> {}",
+                container.name, prev.image, container.image
+            )),
+            Some(_) => {}
+        }
+    }
+    for container in &old.containers {
+        if !new.containers.iter().any(|c| c.name == container.name) {
+            changes.push(format!("container removed: {}", container.name));
+        }
+    }
+
+    changes
+}
+
+/// Reload the configuration every time the process receives SIGHUP
+///
+/// `changed` is notified after a reload that changed anything, so that
+/// consumers such as the container reconciler can act on it right away.
+/// `on_reload` runs first with the new configuration, for settings that are
+/// copied out of it (e.g. the scheduler limits).
+pub async fn watch_sighup(
+    path: String,
+    config: Arc<RwLock<NodeConfig>>,
+    changed: Arc<Notify>,
+    on_reload: impl Fn(&NodeConfig),
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!(error = %e, "Failed to install SIGHUP handler, config reload disabled");
+            return;
+        }
+    };
+
+    info!(path = %path, "Config reload on SIGHUP enabled");
+
+    while hangup.recv().await.is_some() {
+        info!(path = %path, "SIGHUP received, reloading configuration");
+        match reload_node_config(&path, &config).await {
+            Ok(changes) if changes.is_empty() => {
+                info!("Configuration reloaded, no changes");
+            }
+            Ok(changes) => {
+                for change in &changes {
+                    info!(change = %change, "Configuration changed");
+                }
+                info!(count = changes.len(), "Configuration reloaded");
+                on_reload(&*config.read().await);
+                changed.notify_one();
+            }
+            Err(e) => {
+                warn!(error = %e, "Rejected new configuration, keeping previous config");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keel_config::ContainerConfig;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_reload_keeps_prior_config_on_parse_error() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "not: valid: yaml: [").unwrap();
+
+        let mut prior = NodeConfig::default_config();
+        prior.hostname = "keel-prior".to_string();
+        let current = RwLock::new(prior);
+
+        let result = reload_node_config(file.path(), &current).await;
+        assert!(result.is_err());
+        assert_eq!(current.read().await.hostname, "keel-prior");
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_prior_config_on_validation_error() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "version: v1\nhostname: \"\"\ncontainers: []\n").unwrap();
+
+        let current = RwLock::new(NodeConfig::default_config());
+
+        let result = reload_node_config(file.path(), &current).await;
+        assert!(matches!(result, Err(ConfigError::Validation(_))));
+        assert_eq!(current.read().await.hostname, "keel-node");
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_valid_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "version: v1\nhostname: keel-02\ncontainers:\n  - name: app\n    image: alpine:3\n"
+        )
+        .unwrap();
+
+        let current = RwLock::new(NodeConfig::default_config());
+
+        let changes = reload_node_config(file.path(), &current).await.unwrap();
+        assert!(changes.contains(&"hostname: keel-node -> keel-02".to_string()));
+        assert!(changes.contains(&"container added: app".to_string()));
+        assert_eq!(current.read().await.hostname, "keel-02");
+    }
+
+    #[test]
+    fn test_diff_node_config_image_change() {
+        let mut old = NodeConfig::default_config();
+        old.containers.push(ContainerConfig {
+            name: "app".to_string(),
+            image: "alpine:3".to_string(),
+        });
+        let mut new = old.clone();
+        new.containers[0].image = "alpine:4".to_string();
+
+        assert_eq!(
+            diff_node_config(&old, &new),
+            vec!["container app image: alpine:3 -> alpine:4".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_diff_node_config_scheduler_limits() {
+        let old = NodeConfig::default_config();
+        let mut new = old.clone();
+        new.scheduler.max_in_flight = Some(10);
+        new.scheduler.keep_finished = Some(50);
+
+        assert_eq!(
+            diff_node_config(&old, &new),
+            vec![
+                "scheduler.max_in_flight: None -> Some(10)".to_string(),
+                "scheduler.keep_finished: None -> Some(50)".to_string(),
+            ]
+        );
+    }
+}
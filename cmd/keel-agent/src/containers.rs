@@ -0,0 +1,372 @@
+//! Reconciliation of declared containers against containerd
+//!
+//! `NodeConfig.containers` lists the system containers a node should run.
+//! The reconciler compares that list with what containerd reports in the
+//! dedicated `keel` namespace and creates, replaces or stops containers to
+//! match. It drives containerd through `ctr`, the same way `keel-init`
+//! imports preloaded images; an image without `ctr` cannot run declared
+//! containers and reports so.
+
+use keel_config::{ContainerConfig, NodeConfig};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::sync::{Notify, RwLock};
+use tracing::{debug, info, warn};
+
+/// containerd CLI
+const CTR_PATH: &str = "/usr/bin/ctr";
+
+/// Namespace for declared containers, kept apart from Kubernetes' `k8s.io`
+pub const CONTAINERD_NAMESPACE: &str = "keel";
+
+/// How often declared containers are re-checked without a config change
+pub const RECONCILE_INTERVAL_SECS: u64 = 60;
+
+/// A container as reported by containerd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActualContainer {
+    pub name: String,
+    pub image: String,
+    /// Whether the container's task is running
+    pub running: bool,
+}
+
+/// A single step towards the desired state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerAction {
+    /// Pull the image and run a new container
+    Create { name: String, image: String },
+    /// Stop the task and delete the container
+    Stop { name: String },
+}
+
+/// Expand a short image reference to the fully qualified form `ctr` expects
+///
+/// `alpine:3` becomes `docker.io/library/alpine:3`; a reference without a
+/// tag or digest gets `:latest`.
+pub fn normalize_image_ref(image: &str) -> String {
+    let mut reference = match image.split_once('/') {
+        None => format!("docker.io/library/{}", image),
+        Some((registry, _))
+            if registry.contains('.') || registry.contains(':') || registry == "localhost" =>
+        {
+            image.to_string()
+        }
+        Some(_) => format!("docker.io/{}", image),
+    };
+
+    let name = reference.rsplit('/').next().unwrap_or_default();
+    if !name.contains(':') && !name.contains('@') {
+        reference.push_str(":latest");
+    }
+    reference
+}
+
+/// Decide which actions bring `actual` in line with `desired`
+///
+/// Containers whose image changed or whose task is not running are stopped
+/// and created again; containers that are no longer declared are stopped.
+pub fn plan_reconcile(
+    desired: &[ContainerConfig],
+    actual: &[ActualContainer],
+) -> Vec<ContainerAction> {
+    let mut actions = Vec::new();
+
+    for container in actual {
+        if !desired.iter().any(|d| d.name == container.name) {
+            actions.push(ContainerAction::Stop {
+                name: container.name.clone(),
+            });
+        }
+    }
+
+    for container in desired {
+        let image = normalize_image_ref(&container.image);
+        match actual.iter().find(|a| a.name == container.name) {
+            Some(existing) if existing.image == image && existing.running => {}
+            Some(_) => {
+                actions.push(ContainerAction::Stop {
+                    name: container.name.clone(),
+                });
+                actions.push(ContainerAction::Create {
+                    name: container.name.clone(),
+                    image,
+                });
+            }
+            None => actions.push(ContainerAction::Create {
+                name: container.name.clone(),
+                image,
+            }),
+        }
+    }
+
+    actions
+}
+
+/// Parse `ctr containers list` and `ctr tasks list` output
+///
+/// Both print a header line followed by whitespace-separated columns
+/// (`CONTAINER IMAGE RUNTIME` and `TASK PID STATUS`).
+pub fn parse_ctr_state(containers_out: &str, tasks_out: &str) -> Vec<ActualContainer> {
+    let running: Vec<&str> = tasks_out
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            (cols.len() >= 3 && cols[2] == "RUNNING").then_some(cols[0])
+        })
+        .collect();
+
+    containers_out
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let name = cols.next()?;
+            let image = cols.next()?;
+            Some(ActualContainer {
+                name: name.to_string(),
+                image: image.to_string(),
+                running: running.contains(&name),
+            })
+        })
+        .collect()
+}
+
+/// Run `ctr -n keel <args>` and return stdout
+fn ctr(ctr_path: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(ctr_path)
+        .args(["-n", CONTAINERD_NAMESPACE])
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run ctr {}: {}", args.join(" "), e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ctr {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Query containerd for the containers in the `keel` namespace
+fn list_actual(ctr_path: &Path) -> Result<Vec<ActualContainer>, String> {
+    let containers = ctr(ctr_path, &["containers", "list"])?;
+    let tasks = ctr(ctr_path, &["tasks", "list"])?;
+    Ok(parse_ctr_state(&containers, &tasks))
+}
+
+/// Carry out a single action against containerd
+fn apply_action(ctr_path: &Path, action: &ContainerAction) -> Result<(), String> {
+    match action {
+        ContainerAction::Create { name, image } => {
+            info!(container = %name, image = %image, "Starting declared container");
+            ctr(ctr_path, &["images", "pull", image])?;
+            ctr(ctr_path, &["run", "-d", image, name])?;
+        }
+        ContainerAction::Stop { name } => {
+            info!(container = %name, "Stopping container");
+            // The task may already be gone; only the container delete must succeed
+            if let Err(e) = ctr(ctr_path, &["tasks", "kill", "-s", "SIGTERM", name]) {
+                debug!(container = %name, error = %e, "No task to kill");
+            }
+            if let Err(e) = ctr(ctr_path, &["tasks", "delete", "-f", name]) {
+                debug!(container = %name, error = %e, "No task to delete");
+            }
+            ctr(ctr_path, &["containers", "delete", name])?;
+        }
+    }
+    Ok(())
+}
+
+/// Reconcile containerd with the declared containers once
+///
+/// Returns the errors of the actions that failed; the remaining actions
+/// are still attempted.
+pub fn reconcile(desired: &[ContainerConfig]) -> Result<Vec<String>, String> {
+    reconcile_with(Path::new(CTR_PATH), desired)
+}
+
+/// [`reconcile`] using the `ctr` binary at `ctr_path`
+///
+/// Without the binary there is nothing to reconcile against: that is fine
+/// while no containers are declared and an error once some are.
+fn reconcile_with(ctr_path: &Path, desired: &[ContainerConfig]) -> Result<Vec<String>, String> {
+    if !ctr_path.exists() {
+        if desired.is_empty() {
+            debug!(path = %ctr_path.display(), "containerd CLI not installed, no containers declared");
+            return Ok(Vec::new());
+        }
+        return Err(format!(
+            "containerd CLI not found at {}; this image cannot run declared containers",
+            ctr_path.display()
+        ));
+    }
+
+    let actual = list_actual(ctr_path)?;
+    let actions = plan_reconcile(desired, &actual);
+    if actions.is_empty() {
+        debug!("Declared containers are up to date");
+        return Ok(Vec::new());
+    }
+
+    Ok(actions
+        .iter()
+        .filter_map(|action| apply_action(ctr_path, action).err())
+        .collect())
+}
+
+/// Reconcile on startup, whenever `changed` is notified and periodically
+pub async fn reconcile_loop(config: Arc<RwLock<NodeConfig>>, changed: Arc<Notify>) {
+    let interval = tokio::time::Duration::from_secs(RECONCILE_INTERVAL_SECS);
+
+    loop {
+        let desired = config.read().await.containers.clone();
+        match tokio::task::spawn_blocking(move || reconcile(&desired)).await {
+            Ok(Ok(errors)) => {
+                for e in errors {
+                    warn!(error = %e, "Container reconcile action failed");
+                }
+            }
+            Ok(Err(e)) => warn!(error = %e, "Failed to reconcile declared containers"),
+            Err(e) => warn!(error = %e, "Container reconcile task panicked"),
+        }
+
+        tokio::select! {
+            _ = changed.notified() => debug!("Configuration changed, reconciling containers"),
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desired(name: &str, image: &str) -> ContainerConfig {
+        ContainerConfig {
+            name: name.to_string(),
+            image: image.to_string(),
+        }
+    }
+
+    fn actual(name: &str, image: &str, running: bool) -> ActualContainer {
+        ActualContainer {
+            name: name.to_string(),
+            image: image.to_string(),
+            running,
+        }
+    }
+
+    #[test]
+    fn test_normalize_image_ref() {
+        assert_eq!(
+            normalize_image_ref("alpine:3"),
+            "docker.io/library/alpine:3"
+        );
+        assert_eq!(
+            normalize_image_ref("alpine"),
+            "docker.io/library/alpine:latest"
+        );
+        assert_eq!(
+            normalize_image_ref("grafana/agent:v1"),
+            "docker.io/grafana/agent:v1"
+        );
+        assert_eq!(
+            normalize_image_ref("ghcr.io/org/app:1.2"),
+            "ghcr.io/org/app:1.2"
+        );
+        assert_eq!(
+            normalize_image_ref("localhost:5000/app"),
+            "localhost:5000/app:latest"
+        );
+        assert_eq!(
+            normalize_image_ref("quay.io/app@sha256:abcd"),
+            "quay.io/app@sha256:abcd"
+        );
+    }
+
+    #[test]
+    fn test_plan_creates_missing_and_stops_undeclared() {
+        let plan = plan_reconcile(
+            &[desired("app", "alpine:3")],
+            &[actual("old", "docker.io/library/busybox:latest", true)],
+        );
+        assert_eq!(
+            plan,
+            vec![
+                ContainerAction::Stop {
+                    name: "old".to_string()
+                },
+                ContainerAction::Create {
+                    name: "app".to_string(),
+                    image: "docker.io/library/alpine:3".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_no_changes_when_converged() {
+        let plan = plan_reconcile(
+            &[desired("app", "alpine:3")],
+            &[actual("app", "docker.io/library/alpine:3", true)],
+        );
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn test_plan_replaces_changed_or_stopped() {
+        let stop_app = ContainerAction::Stop {
+            name: "app".to_string(),
+        };
+        let create_app = ContainerAction::Create {
+            name: "app".to_string(),
+            image: "docker.io/library/alpine:4".to_string(),
+        };
+
+        let image_changed = plan_reconcile(
+            &[desired("app", "alpine:4")],
+            &[actual("app", "docker.io/library/alpine:3", true)],
+        );
+        assert_eq!(image_changed, vec![stop_app.clone(), create_app.clone()]);
+
+        let not_running = plan_reconcile(
+            &[desired("app", "alpine:4")],
+            &[actual("app", "docker.io/library/alpine:4", false)],
+        );
+        assert_eq!(not_running, vec![stop_app, create_app]);
+    }
+
+    #[test]
+    fn test_parse_ctr_state() {
+        let containers = "CONTAINER    IMAGE                              RUNTIME\n\
+                          app          docker.io/library/alpine:3         io.containerd.runc.v2\n\
+                          sidecar      docker.io/library/busybox:latest   io.containerd.runc.v2\n";
+        let tasks = "TASK    PID     STATUS\n\
+                     app     1234    RUNNING\n\
+                     sidecar 1240    STOPPED\n";
+
+        assert_eq!(
+            parse_ctr_state(containers, tasks),
+            vec![
+                actual("app", "docker.io/library/alpine:3", true),
+                actual("sidecar", "docker.io/library/busybox:latest", false),
+            ]
+        );
+        assert!(parse_ctr_state("CONTAINER    IMAGE    RUNTIME\n", "").is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_without_ctr() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("ctr");
+
+        assert_eq!(reconcile_with(&missing, &[]), Ok(Vec::new()));
+        let err = reconcile_with(&missing, &[desired("app", "alpine:3")]).unwrap_err();
+        assert!(err.contains("containerd CLI not found"), "{err}");
+    }
+}
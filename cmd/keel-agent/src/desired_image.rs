@@ -0,0 +1,250 @@
+//! Declarative OS updates from `NodeConfig.desired_image`
+//!
+//! The reconciler compares the desired image hash with the hash recorded in
+//! the booted slot's marker and, when they differ, schedules one update to
+//! the desired image in the next maintenance window. The executor then runs
+//! it like any other scheduled update.
+//!
+//! A desired image is scheduled at most once: while that schedule is
+//! pending, running, or completed and waiting for a reboot, nothing new is
+//! created, and a failed or rolled back update is not retried until
+//! `desired_image` changes.
+
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use keel_config::{DesiredImageConfig, NodeConfig};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::disk;
+use crate::download;
+use crate::update_scheduler::{UpdateSchedule, UpdateScheduler};
+
+/// How often the running image is compared with the desired one
+pub const RECONCILE_INTERVAL_SECS: u64 = 60;
+
+/// Maintenance window length when only the start is configured
+pub const DEFAULT_WINDOW_SECS: u32 = 3600;
+
+/// What the reconciler should do about the desired image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesiredImageAction {
+    /// The booted slot already runs the desired image
+    UpToDate,
+    /// An update to the desired image was already scheduled (`id`)
+    AlreadyScheduled(String),
+    /// Schedule an update to the desired image
+    Schedule,
+}
+
+/// Compare the desired image with the running one and existing schedules
+///
+/// `running_sha256` is the hash recorded when the booted slot was flashed;
+/// a slot without one (e.g. from the installer) counts as different.
+pub fn desired_image_action(
+    desired: &DesiredImageConfig,
+    running_sha256: Option<&str>,
+    schedules: &[UpdateSchedule],
+) -> DesiredImageAction {
+    if running_sha256.is_some_and(|running| running.eq_ignore_ascii_case(&desired.sha256)) {
+        return DesiredImageAction::UpToDate;
+    }
+    let existing = schedules
+        .iter()
+        .filter(|s| {
+            s.source_url == desired.url
+                && s.expected_sha256
+                    .as_deref()
+                    .is_some_and(|sha| sha.eq_ignore_ascii_case(&desired.sha256))
+        })
+        .min_by_key(|s| s.created_at);
+    match existing {
+        Some(schedule) => DesiredImageAction::AlreadyScheduled(schedule.id.clone()),
+        None => DesiredImageAction::Schedule,
+    }
+}
+
+/// Start of the maintenance window the update should run in
+///
+/// `None` without a configured window (update right away). A window that
+/// opened today and is still open is used as is; otherwise the next one.
+pub fn next_window_start(
+    start: Option<&str>,
+    window_secs: u32,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let (hours, minutes) = keel_config::parse_time_of_day(start?)?;
+    let today = now
+        .date_naive()
+        .and_time(NaiveTime::from_hms_opt(hours, minutes, 0)?)
+        .and_utc();
+    let candidates = [today - Duration::days(1), today, today + Duration::days(1)];
+    candidates
+        .into_iter()
+        .find(|start| now <= *start + Duration::seconds(i64::from(window_secs)))
+}
+
+/// Hash recorded for the booted slot, if any
+fn running_image_sha256() -> Option<String> {
+    let active = disk::get_active_partition().ok()?;
+    disk::read_slot_marker(disk::SLOT_MARKER_DIR, active.index)
+        .ok()?
+        .sha256
+}
+
+/// Schedule an update to the desired image if the node does not run it
+///
+/// Returns the new schedule, if one was created.
+pub async fn reconcile(
+    desired: &DesiredImageConfig,
+    running_sha256: Option<&str>,
+    scheduler: &UpdateScheduler,
+) -> Result<Option<UpdateSchedule>, String> {
+    let schedules = scheduler.get_schedules().await;
+    match desired_image_action(desired, running_sha256, &schedules) {
+        DesiredImageAction::UpToDate => {
+            debug!(sha256 = %desired.sha256, "Running the desired image");
+            Ok(None)
+        }
+        DesiredImageAction::AlreadyScheduled(id) => {
+            debug!(schedule_id = %id, "Update to the desired image already scheduled");
+            Ok(None)
+        }
+        DesiredImageAction::Schedule => {
+            let window_secs = desired
+                .maintenance_window_secs
+                .unwrap_or(DEFAULT_WINDOW_SECS);
+            let scheduled_at = next_window_start(
+                desired.maintenance_window_start.as_deref(),
+                window_secs,
+                Utc::now(),
+            );
+            let schedule = scheduler
+                .schedule_update(
+                    desired.url.clone(),
+                    Some(desired.sha256.to_lowercase()),
+                    scheduled_at,
+                    scheduled_at.map(|_| window_secs),
+                    true,
+                    None,
+                    None,
+                    None,
+                    false,
+                    false,
+                    None,
+                    scheduled_at.is_some_and(|at| at > Utc::now()),
+                    true, // force
+                )
+                .await
+                .map_err(|e| e.to_string())?
+                .into_schedule();
+            info!(
+                schedule_id = %schedule.id,
+                url = %download::redact_url(&desired.url),
+                scheduled_at = ?schedule.scheduled_at,
+                "Scheduled update to the desired image"
+            );
+            Ok(Some(schedule))
+        }
+    }
+}
+
+/// Reconcile the desired image periodically
+pub async fn reconcile_loop(config: Arc<RwLock<NodeConfig>>, scheduler: Arc<UpdateScheduler>) {
+    let interval = tokio::time::Duration::from_secs(RECONCILE_INTERVAL_SECS);
+    loop {
+        let desired = config.read().await.desired_image.clone();
+        if let Some(desired) = desired {
+            let running = tokio::task::spawn_blocking(running_image_sha256)
+                .await
+                .unwrap_or_default();
+            if let Err(e) = reconcile(&desired, running.as_deref(), &scheduler).await {
+                warn!(error = %e, "Failed to schedule update to the desired image");
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn desired(sha: &str) -> DesiredImageConfig {
+        DesiredImageConfig {
+            url: "https://images.example.com/keel-1.4.0.squashfs".to_string(),
+            sha256: sha.to_string(),
+            maintenance_window_start: None,
+            maintenance_window_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_desired_image_action() {
+        let want = desired(&"ab".repeat(32));
+        assert_eq!(
+            desired_image_action(&want, Some(&"AB".repeat(32)), &[]),
+            DesiredImageAction::UpToDate
+        );
+        assert_eq!(
+            desired_image_action(&want, Some(&"cd".repeat(32)), &[]),
+            DesiredImageAction::Schedule
+        );
+        // Installer-provisioned slots have no recorded hash
+        assert_eq!(
+            desired_image_action(&want, None, &[]),
+            DesiredImageAction::Schedule
+        );
+    }
+
+    #[test]
+    fn test_next_window_start() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 1, 0, 0).unwrap();
+        assert_eq!(next_window_start(None, 3600, now), None);
+        assert_eq!(
+            next_window_start(Some("02:30"), 3600, now),
+            Some(Utc.with_ymd_and_hms(2026, 3, 10, 2, 30, 0).unwrap())
+        );
+        // Today's window is over: wait for tomorrow's
+        assert_eq!(
+            next_window_start(Some("00:00"), 1800, now),
+            Some(Utc.with_ymd_and_hms(2026, 3, 11, 0, 0, 0).unwrap())
+        );
+        // Still inside a window that opened before midnight
+        assert_eq!(
+            next_window_start(Some("23:00"), 4 * 3600, now),
+            Some(Utc.with_ymd_and_hms(2026, 3, 9, 23, 0, 0).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_creates_exactly_one_schedule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let scheduler = UpdateScheduler::new(dir.path().join("schedules.json").to_string_lossy());
+        let want = desired(&"ab".repeat(32));
+        let running = "cd".repeat(32);
+
+        let created = reconcile(&want, Some(&running), &scheduler)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.source_url, want.url);
+        assert!(created.enable_auto_rollback);
+        for _ in 0..3 {
+            assert!(reconcile(&want, Some(&running), &scheduler)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(scheduler.get_schedules().await.len(), 1);
+
+        // Already running the desired image: nothing to do
+        let fresh = UpdateScheduler::new(dir.path().join("other.json").to_string_lossy());
+        assert!(reconcile(&want, Some(&want.sha256), &fresh)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(fresh.get_schedules().await.is_empty());
+    }
+}
@@ -3,16 +3,20 @@
 //! This module handles:
 //! - Detecting active/inactive partitions
 //! - Flashing OS images to partitions with optional SHA256 verification
-//! - Switching boot partitions using GPT attributes
+//! - Switching boot partitions using GPT attributes (with sgdisk, or the
+//!   built-in [`crate::gpt`] writer when sgdisk is not installed)
 
 use futures::StreamExt;
+use keel_config::{BootFlagScheme, Slots};
 use sha2::{Digest, Sha256};
 use std::fs;
 use std::io;
 use std::process::Command;
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::{download, gpt, staging};
 
 /// Information about a partition
 pub struct PartitionInfo {
@@ -25,9 +29,11 @@ pub struct PartitionInfo {
 /// Default disk device for KeelOS
 const DEFAULT_DISK: &str = "/dev/sda";
 
-/// Partition indices for A/B slots
+/// Kernel command line used to detect the booted slot
+pub const PROC_CMDLINE: &str = "/proc/cmdline";
+
+/// Partition assumed to be booted when detection fails (slot A)
 const SLOT_A_INDEX: u32 = 2;
-const SLOT_B_INDEX: u32 = 3;
 
 /// Detect the currently active (booted) partition by parsing /proc/cmdline
 ///
@@ -35,7 +41,14 @@ const SLOT_B_INDEX: u32 = 3;
 /// - Direct device path (e.g., root=/dev/sda2)
 /// - PARTUUID (looks up via /dev/disk/by-partuuid/)
 pub fn get_active_partition() -> io::Result<PartitionInfo> {
-    let cmdline = fs::read_to_string("/proc/cmdline")?;
+    get_active_partition_from(PROC_CMDLINE)
+}
+
+/// Detect the active partition from the kernel command line at `cmdline_path`
+pub fn get_active_partition_from<P: AsRef<std::path::Path>>(
+    cmdline_path: P,
+) -> io::Result<PartitionInfo> {
+    let cmdline = fs::read_to_string(cmdline_path)?;
 
     // Try to find root= parameter
     for param in cmdline.split_whitespace() {
@@ -124,26 +137,187 @@ fn parse_device_path(device: &str) -> io::Result<PartitionInfo> {
 }
 
 /// Get the inactive partition (the one we can safely write to)
-pub fn get_inactive_partition() -> io::Result<PartitionInfo> {
-    let active = get_active_partition()?;
+///
+/// With more than two slots this is the next slot in rotation order.
+pub fn get_inactive_partition(slots: &Slots) -> io::Result<PartitionInfo> {
+    get_inactive_partition_from(PROC_CMDLINE, slots)
+}
+
+/// Get the inactive partition using the kernel command line at `cmdline_path`
+pub fn get_inactive_partition_from<P: AsRef<std::path::Path>>(
+    cmdline_path: P,
+    slots: &Slots,
+) -> io::Result<PartitionInfo> {
+    let active = get_active_partition_from(cmdline_path)?;
+    let inactive_index = slots
+        .next_inactive(active.index)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no update slots configured"))?;
 
+    Ok(PartitionInfo {
+        device: sibling_device(&active, inactive_index),
+        index: inactive_index,
+    })
+}
+
+/// Device of partition `index` on the same disk as `partition`
+fn sibling_device(partition: &PartitionInfo, index: u32) -> String {
     // Determine the base disk device (e.g., "/dev/sda" from "/dev/sda2")
-    let base_device: String = active
+    let base_device: String = partition
         .device
         .chars()
         .take_while(|c| !c.is_ascii_digit())
         .collect();
+    format!("{}{}", base_device, index)
+}
 
-    let inactive_index = if active.index == SLOT_A_INDEX {
-        SLOT_B_INDEX
-    } else {
-        SLOT_A_INDEX
-    };
+/// Environment variable that lets flashing write to regular files
+///
+/// Only meant for development setups that flash into image files.
+pub const ALLOW_NON_BLOCK_TARGET_ENV: &str = "KEEL_ALLOW_NON_BLOCK_TARGET";
 
-    Ok(PartitionInfo {
-        device: format!("{}{}", base_device, inactive_index),
-        index: inactive_index,
-    })
+/// Confirm that `target_device` is a block device before writing to it
+///
+/// Opening a path for writing succeeds for regular files too, so a typo in
+/// the target would otherwise silently write the update into a file. With
+/// `allow_non_block` a regular file is accepted (tests and development);
+/// a missing path is always an error.
+pub fn check_flash_target(target_device: &str, allow_non_block: bool) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = fs::metadata(target_device).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Flash target {} is not accessible: {}", target_device, e),
+        )
+    })?;
+
+    if metadata.file_type().is_block_device() || (allow_non_block && metadata.is_file()) {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Flash target {} is not a block device", target_device),
+    ))
+}
+
+/// Mount points backed by the persistent data partition
+///
+/// keel-init mounts it at `/data`; `/var/lib/keel` holds agent state that
+/// must survive updates and may be a separate mount of the same disk.
+pub const DATA_MOUNT_POINTS: &[&str] = &["/data", "/var/lib/keel"];
+
+/// Devices mounted at one of [`DATA_MOUNT_POINTS`] in a `/proc/mounts` table
+fn data_partition_devices(mounts: &str) -> Vec<&str> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?;
+            (device.starts_with("/dev/") && DATA_MOUNT_POINTS.contains(&mount_point))
+                .then_some(device)
+        })
+        .collect()
+}
+
+/// Refuse to flash the device that holds the persistent data partition
+///
+/// A slot misconfiguration (e.g. `update.slots: [2, 4]`) would otherwise
+/// overwrite container images and agent state with an OS image. Both sides
+/// are canonicalized so `/dev/disk/by-*` links in the mount table match.
+pub fn check_not_data_partition(target_device: &str, mounts: &str) -> io::Result<()> {
+    let target = canonical_device(target_device);
+
+    for device in data_partition_devices(mounts) {
+        if device == target_device || canonical_device(device) == target {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Flash target {} is the data partition; check update.slots",
+                    target_device
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `path` with symlinks resolved, or unchanged if it cannot be resolved
+fn canonical_device(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Refuse to flash the partition the node is running from
+///
+/// The target is normally derived from the active partition and so can
+/// never equal it, but wrong slot detection on an unusual layout would
+/// otherwise overwrite the running system.
+pub fn check_not_active_partition(target_device: &str, active: &PartitionInfo) -> io::Result<()> {
+    if target_device != active.device
+        && canonical_device(target_device) != canonical_device(&active.device)
+    {
+        return Ok(());
+    }
+
+    error!(
+        device = %target_device,
+        active = %active.device,
+        "Refusing to flash the active partition"
+    );
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "Flash target {} is the active partition (partition {}); refusing to overwrite the running system",
+            target_device, active.index
+        ),
+    ))
+}
+
+/// [`check_not_active_partition`] against the detected active partition
+fn check_not_booted_partition(target_device: &str) -> io::Result<()> {
+    match get_active_partition() {
+        Ok(active) => check_not_active_partition(target_device, &active),
+        Err(e) => {
+            warn!(error = %e, "Could not detect the active partition to check the flash target");
+            Ok(())
+        }
+    }
+}
+
+/// [`check_not_data_partition`] against the live mount table
+fn check_not_mounted_data_partition(target_device: &str) -> io::Result<()> {
+    match fs::read_to_string("/proc/mounts") {
+        Ok(mounts) => check_not_data_partition(target_device, &mounts),
+        Err(e) => {
+            warn!(error = %e, "Could not read /proc/mounts to check the flash target");
+            Ok(())
+        }
+    }
+}
+
+/// Whether [`ALLOW_NON_BLOCK_TARGET_ENV`] opts flashing into regular files
+///
+/// Only `1` or `true` enable it, so `KEEL_ALLOW_NON_BLOCK_TARGET=0` keeps
+/// the guard on.
+pub fn allow_non_block_targets() -> bool {
+    std::env::var(ALLOW_NON_BLOCK_TARGET_ENV).is_ok_and(|value| is_truthy(&value))
+}
+
+/// `1` or `true` (any case)
+fn is_truthy(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true")
+}
+
+/// What flashing an image wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flashed {
+    /// Size of the image now at the start of the partition
+    pub image_bytes: u64,
+    /// Download saved by a delta update (zero for full images)
+    pub bytes_saved: u64,
 }
 
 /// Flash an OS image from a URL to a target device with optional SHA256 verification
@@ -154,27 +328,73 @@ pub fn get_inactive_partition() -> io::Result<PartitionInfo> {
 /// * `expected_sha256` - Optional SHA256 hash to verify the downloaded image
 /// * `is_delta` - If true, treat source as a delta file to apply
 /// * `fallback_url` - Optional URL for full image if delta fails
+/// * `auth_header` - Optional `Authorization` header for `source_url`; the
+///   fallback only gets it if it is on the same host (see
+///   [`download::auth_header_for`])
+/// * `max_bytes_per_sec` - Download rate limit; zero means unlimited
+/// * `write_block_bytes` - Bytes buffered per write to the device; zero
+///   means [`DEFAULT_WRITE_BLOCK_BYTES`]
+/// * `staging_dir` - Scratch directory for delta files
+/// * `allow_non_block` - Accept a regular file as `target_device` (see
+///   [`check_flash_target`])
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "flash",
+    skip_all,
+    fields(
+        source_url = %download::redact_url(source_url),
+        device = %target_device,
+        is_delta = is_delta,
+        bytes = tracing::field::Empty
+    )
+)]
 pub async fn flash_image(
     source_url: &str,
     target_device: &str,
     expected_sha256: Option<&str>,
     is_delta: bool,
     fallback_url: Option<&str>,
-) -> io::Result<u64> {
-    if is_delta {
-        info!(url = %source_url, device = %target_device, "Attempting delta update");
+    auth_header: Option<&str>,
+    max_bytes_per_sec: u64,
+    write_block_bytes: u64,
+    staging_dir: &std::path::Path,
+    allow_non_block: bool,
+) -> io::Result<Flashed> {
+    check_flash_target(target_device, allow_non_block)?;
+    check_not_booted_partition(target_device)?;
+    check_not_mounted_data_partition(target_device)?;
 
-        match apply_delta_update(source_url, target_device, expected_sha256).await {
-            Ok(bytes_saved) => {
-                info!(bytes_saved = bytes_saved, "Delta update successful");
-                Ok(bytes_saved)
+    if is_delta {
+        info!(url = %download::redact_url(source_url), device = %target_device, "Attempting delta update");
+
+        match apply_delta_update(
+            source_url,
+            target_device,
+            expected_sha256,
+            auth_header,
+            max_bytes_per_sec,
+            staging_dir,
+        )
+        .await
+        {
+            Ok(flashed) => {
+                info!(bytes_saved = flashed.bytes_saved, "Delta update successful");
+                Ok(flashed)
             }
             Err(e) => {
                 warn!(error = %e, "Delta update failed");
 
                 if let Some(full_url) = fallback_url {
-                    info!(fallback_url = %full_url, "Falling back to full image download");
-                    flash_full_image(full_url, target_device, expected_sha256).await
+                    info!(fallback_url = %download::redact_url(full_url), "Falling back to full image download");
+                    flash_full_image(
+                        full_url,
+                        target_device,
+                        expected_sha256,
+                        download::auth_header_for(auth_header, source_url, full_url),
+                        max_bytes_per_sec,
+                        write_block_bytes,
+                    )
+                    .await
                 } else {
                     Err(io::Error::other(format!(
                         "Delta update failed and no fallback URL provided: {}",
@@ -184,7 +404,15 @@ pub async fn flash_image(
             }
         }
     } else {
-        flash_full_image(source_url, target_device, expected_sha256).await
+        flash_full_image(
+            source_url,
+            target_device,
+            expected_sha256,
+            auth_header,
+            max_bytes_per_sec,
+            write_block_bytes,
+        )
+        .await
     }
 }
 
@@ -193,35 +421,54 @@ async fn apply_delta_update(
     delta_url: &str,
     target_device: &str,
     expected_sha256: Option<&str>,
-) -> io::Result<u64> {
+    auth_header: Option<&str>,
+    max_bytes_per_sec: u64,
+    staging_dir: &std::path::Path,
+) -> io::Result<Flashed> {
     use std::io::Write;
 
-    info!(delta_url = %delta_url, "Downloading delta file");
+    info!(delta_url = %download::redact_url(delta_url), "Downloading delta file");
+    staging::prepare_staging_dir(staging_dir)?;
 
-    // Download delta file to temporary location
-    let delta_file = tempfile::NamedTempFile::new()?;
-    let delta_path = delta_file.path();
+    let delta_bytes = async {
+        let response = download::get(delta_url, auth_header)
+            .await
+            .map_err(|e| io::Error::other(format!("Delta download failed: {}", e)))?;
 
-    let response = reqwest::get(delta_url)
-        .await
-        .map_err(|e| io::Error::other(format!("Delta download failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(io::Error::other(format!(
+                "Server returned error for delta: {}",
+                response.status()
+            )));
+        }
 
-    if !response.status().is_success() {
-        return Err(io::Error::other(format!(
-            "Server returned error for delta: {}",
-            response.status()
-        )));
+        staging::ensure_free_space(staging_dir, response.content_length().unwrap_or(0))?;
+        info!("Downloading delta");
+
+        // Write delta to temp file
+        let mut throttle = download::Throttle::new(max_bytes_per_sec);
+        let mut delta_bytes = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(item) = stream.next().await {
+            let chunk =
+                item.map_err(|e| io::Error::other(format!("Failed to download delta: {}", e)))?;
+            delta_bytes.extend_from_slice(&chunk);
+            throttle.consume(chunk.len()).await;
+        }
+        tracing::Span::current().record("bytes", delta_bytes.len() as u64);
+        info!(delta_size_bytes = delta_bytes.len(), "Delta downloaded");
+        Ok(delta_bytes)
     }
-
-    let delta_size = response.content_length().unwrap_or(0);
-    info!(delta_size_bytes = delta_size, "Downloading delta");
-
-    // Write delta to temp file
-    let delta_bytes = response
-        .bytes()
-        .await
-        .map_err(|e| io::Error::other(format!("Failed to download delta: {}", e)))?;
-
+    .instrument(tracing::info_span!(
+        "download",
+        url = %download::redact_url(delta_url),
+        bytes = tracing::field::Empty
+    ))
+    .await?;
+
+    // Keep the delta in the staging directory while it is applied
+    let delta_file = tempfile::NamedTempFile::new_in(staging_dir)?;
+    let delta_path = delta_file.path();
     std::fs::write(delta_path, &delta_bytes)?;
     info!(delta_path = ?delta_path, "Delta file downloaded");
 
@@ -249,23 +496,7 @@ async fn apply_delta_update(
         "Patch applied successfully"
     );
 
-    // Verify SHA256 if provided
-    if let Some(expected) = expected_sha256 {
-        if !expected.is_empty() {
-            let mut hasher = Sha256::new();
-            hasher.update(&new_image);
-            let actual = format!("{:x}", hasher.finalize());
-
-            if actual != expected.to_lowercase() {
-                error!(expected = %expected, actual = %actual, "SHA256 verification failed");
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("SHA256 mismatch: expected {}, got {}", expected, actual),
-                ));
-            }
-            info!(hash = %actual, "SHA256 verification passed");
-        }
-    }
+    verify_sha256(Sha256::new_with_prefix(&new_image), expected_sha256)?;
 
     // Write patched image to target device
     info!(device = %target_device, size_bytes = new_image.len(), "Writing patched image");
@@ -276,12 +507,104 @@ async fn apply_delta_update(
     target_file.write_all(&new_image)?;
     target_file.flush()?;
     target_file.sync_all()?;
+    tracing::Span::current().record("bytes", new_image.len() as u64);
 
     info!(device = %target_device, "Delta update completed");
 
-    // Calculate bandwidth savings (delta size vs full image size)
-    let bytes_saved = new_image.len() as u64 - delta_size;
-    Ok(bytes_saved)
+    // Calculate bandwidth savings (delta size vs full image size); a delta
+    // can be larger than the image it produces, which saves nothing
+    let bytes_saved = (new_image.len() as u64).saturating_sub(delta_bytes.len() as u64);
+    Ok(Flashed {
+        image_bytes: new_image.len() as u64,
+        bytes_saved,
+    })
+}
+
+/// Default bytes buffered per write to the target partition
+pub const DEFAULT_WRITE_BLOCK_BYTES: u64 = 1024 * 1024;
+
+/// Optimal I/O size the kernel reports for `device`, if it reports one
+///
+/// Partitions have no request queue of their own, so their disk's is used.
+fn optimal_io_size(device: &str) -> Option<u64> {
+    let canonical = canonical_device(device);
+    let name = std::path::Path::new(&canonical).file_name()?;
+    let sys = std::path::Path::new("/sys/class/block").join(name);
+    [
+        sys.join("queue/optimal_io_size"),
+        sys.join("../queue/optimal_io_size"),
+    ]
+    .iter()
+    .find_map(|path| fs::read_to_string(path).ok()?.trim().parse::<u64>().ok())
+    .filter(|&size| size > 0)
+}
+
+/// Bytes to buffer per write: `configured` (zero for
+/// [`DEFAULT_WRITE_BLOCK_BYTES`]) rounded up to a multiple of `optimal_io`
+///
+/// The result never exceeds [`keel_config::MAX_WRITE_BLOCK_BYTES`], however
+/// large the device reports its optimal I/O size; it stays aligned if the
+/// unit fits in that limit.
+fn write_block_size(configured: u64, optimal_io: Option<u64>) -> usize {
+    let max = keel_config::MAX_WRITE_BLOCK_BYTES;
+    let block = match configured {
+        0 => DEFAULT_WRITE_BLOCK_BYTES,
+        bytes => bytes,
+    };
+    let block = match optimal_io {
+        Some(io) => block.div_ceil(io).saturating_mul(io),
+        None => block,
+    };
+    let block = match optimal_io {
+        _ if block <= max => block,
+        Some(io) if io <= max => max / io * io,
+        _ => max,
+    };
+    block as usize
+}
+
+/// Writes an image in blocks of a fixed size, hashing everything written
+///
+/// Download chunks are often only a few KiB; collecting them into larger
+/// blocks avoids many small writes, which are slow on some media.
+struct BlockWriter<W> {
+    inner: W,
+    buffer: Vec<u8>,
+    block_size: usize,
+    hasher: Sha256,
+}
+
+impl<W: tokio::io::AsyncWrite + Unpin> BlockWriter<W> {
+    fn new(inner: W, block_size: usize) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(block_size),
+            block_size,
+            hasher: Sha256::new(),
+        }
+    }
+
+    async fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        self.hasher.update(data);
+        while !data.is_empty() {
+            let take = (self.block_size - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() == self.block_size {
+                self.inner.write_all(&self.buffer).await?;
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the partial last block and return the writer and the hash of
+    /// everything written
+    async fn finish(mut self) -> io::Result<(W, Sha256)> {
+        self.inner.write_all(&self.buffer).await?;
+        self.inner.flush().await?;
+        Ok((self.inner, self.hasher))
+    }
 }
 
 /// Download and flash a full OS image (original implementation)
@@ -289,154 +612,799 @@ async fn flash_full_image(
     source_url: &str,
     target_device: &str,
     expected_sha256: Option<&str>,
-) -> io::Result<u64> {
-    info!(url = %source_url, device = %target_device, "Starting image download");
+    auth_header: Option<&str>,
+    max_bytes_per_sec: u64,
+    write_block_bytes: u64,
+) -> io::Result<Flashed> {
+    let flash_span = tracing::Span::current();
+    let download_span = tracing::info_span!("download", url = %download::redact_url(source_url), bytes = tracing::field::Empty);
+    let (hasher, bytes_written) = async {
+        info!(url = %download::redact_url(source_url), device = %target_device, "Starting image download");
+
+        let response = download::get(source_url, auth_header)
+            .await
+            .map_err(|e| io::Error::other(format!("Download failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(io::Error::other(format!(
+                "Server returned error: {}",
+                response.status()
+            )));
+        }
 
-    let response = reqwest::get(source_url)
-        .await
-        .map_err(|e| io::Error::other(format!("Download failed: {}", e)))?;
+        let content_length = response.content_length().unwrap_or(0);
+        info!(
+            size_bytes = content_length,
+            device = %target_device,
+            max_bytes_per_sec = max_bytes_per_sec,
+            "Flashing image"
+        );
+
+        let file = OpenOptions::new().write(true).open(target_device).await?;
+        let block_size = write_block_size(write_block_bytes, optimal_io_size(target_device));
+        debug!(block_size = block_size, "Buffering image writes");
+
+        let mut writer = BlockWriter::new(file, block_size);
+        let mut bytes_written: u64 = 0;
+        let mut throttle = download::Throttle::new(max_bytes_per_sec);
+
+        let mut stream = response.bytes_stream();
+        while let Some(item) = stream.next().await {
+            let chunk = item.map_err(|e| io::Error::other(format!("Stream error: {}", e)))?;
+            throttle.consume(chunk.len()).await;
+
+            // Hash and write to device
+            writer.write(&chunk).await?;
+            bytes_written += chunk.len() as u64;
+
+            // Progress indication (every ~10MB)
+            if bytes_written % (10 * 1024 * 1024) < chunk.len() as u64 && content_length > 0 {
+                let percent = (bytes_written * 100) / content_length;
+                debug!(
+                    percent = percent,
+                    bytes = bytes_written,
+                    total = content_length,
+                    "Flash progress"
+                );
+            }
+        }
 
-    if !response.status().is_success() {
-        return Err(io::Error::other(format!(
-            "Server returned error: {}",
-            response.status()
-        )));
+        let (file, hasher) = writer.finish().await?;
+        file.sync_all().await?;
+        tracing::Span::current().record("bytes", bytes_written);
+        flash_span.record("bytes", bytes_written);
+        info!(bytes = bytes_written, device = %target_device, "Image written successfully");
+        Ok((hasher, bytes_written))
     }
+    .instrument(download_span)
+    .await?;
 
-    let content_length = response.content_length().unwrap_or(0);
-    info!(size_bytes = content_length, device = %target_device, "Flashing image");
+    verify_sha256(hasher, expected_sha256)?;
 
+    // Nothing saved on a full download
+    Ok(Flashed {
+        image_bytes: bytes_written,
+        bytes_saved: 0,
+    })
+}
+
+/// Check the SHA256 accumulated in `hasher` against `expected_sha256`
+///
+/// A missing or empty expected hash skips verification.
+#[tracing::instrument(name = "verify", skip_all)]
+fn verify_sha256(hasher: Sha256, expected_sha256: Option<&str>) -> io::Result<()> {
+    let Some(expected) = expected_sha256.filter(|e| !e.is_empty()) else {
+        return Ok(());
+    };
+
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != expected.to_lowercase() {
+        error!(expected = %expected, actual = %actual, "SHA256 verification failed");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("SHA256 mismatch: expected {}, got {}", expected, actual),
+        ));
+    }
+    info!(hash = %actual, "SHA256 verification passed");
+    Ok(())
+}
+
+/// Flash an OS image from a local file (e.g. a pre-staged download)
+///
+/// With an `expected_sha256` the file is hashed before anything is written,
+/// so a corrupted or tampered file never reaches the partition. The copy
+/// is hashed again and checked once the write completes, in case the file
+/// changed in between. `allow_non_block` accepts a regular file as
+/// `target_device` (see [`check_flash_target`]).
+#[tracing::instrument(
+    name = "flash",
+    skip_all,
+    fields(
+        path = %image_path.display(),
+        device = %target_device,
+        bytes = tracing::field::Empty
+    )
+)]
+pub async fn flash_local_image(
+    image_path: &std::path::Path,
+    target_device: &str,
+    expected_sha256: Option<&str>,
+    allow_non_block: bool,
+) -> io::Result<Flashed> {
+    use tokio::io::AsyncReadExt;
+
+    info!(path = %image_path.display(), device = %target_device, "Flashing local image");
+    check_flash_target(target_device, allow_non_block)?;
+    check_not_booted_partition(target_device)?;
+    check_not_mounted_data_partition(target_device)?;
+
+    let mut buf = vec![0u8; 1024 * 1024];
+    if expected_sha256.is_some_and(|e| !e.is_empty()) {
+        let mut source = tokio::fs::File::open(image_path).await?;
+        let mut hasher = Sha256::new();
+        loop {
+            let n = source.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        verify_sha256(hasher, expected_sha256)?;
+    }
+
+    let mut source = tokio::fs::File::open(image_path).await?;
     let mut file = OpenOptions::new().write(true).open(target_device).await?;
 
     let mut hasher = Sha256::new();
     let mut bytes_written: u64 = 0;
 
-    let mut stream = response.bytes_stream();
-    while let Some(item) = stream.next().await {
-        let chunk = item.map_err(|e| io::Error::other(format!("Stream error: {}", e)))?;
-
-        // Update hash
-        hasher.update(&chunk);
-
-        // Write to device
-        file.write_all(&chunk).await?;
-        bytes_written += chunk.len() as u64;
-
-        // Progress indication (every ~10MB)
-        if bytes_written % (10 * 1024 * 1024) < chunk.len() as u64 && content_length > 0 {
-            let percent = (bytes_written * 100) / content_length;
-            debug!(
-                percent = percent,
-                bytes = bytes_written,
-                total = content_length,
-                "Flash progress"
-            );
+    loop {
+        let n = source.read(&mut buf).await?;
+        if n == 0 {
+            break;
         }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n]).await?;
+        bytes_written += n as u64;
     }
 
     file.flush().await?;
     file.sync_all().await?;
+    tracing::Span::current().record("bytes", bytes_written);
     info!(bytes = bytes_written, device = %target_device, "Image written successfully");
 
-    // Verify SHA256 if provided
-    if let Some(expected) = expected_sha256 {
-        if !expected.is_empty() {
-            let actual = format!("{:x}", hasher.finalize());
-            if actual != expected.to_lowercase() {
-                error!(expected = %expected, actual = %actual, "SHA256 verification failed");
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("SHA256 mismatch: expected {}, got {}", expected, actual),
-                ));
-            }
-            info!(hash = %actual, "SHA256 verification passed");
-        }
-    }
+    verify_sha256(hasher, expected_sha256)?;
 
-    // Return 0 for bytes saved (full download)
-    Ok(0)
+    Ok(Flashed {
+        image_bytes: bytes_written,
+        bytes_saved: 0,
+    })
 }
 
 /// Switch the boot partition by updating GPT partition attributes
 ///
-/// This uses sgdisk to:
-/// 1. Clear the "legacy BIOS bootable" attribute from all partitions
+/// With [`BootFlagScheme::LegacyBios`] this uses sgdisk to:
+/// 1. Clear the "legacy BIOS bootable" attribute from every other slot
 /// 2. Set the "legacy BIOS bootable" attribute on the target partition
 /// 3. Set GPT attribute bit 2 (legacy_boot) on the target partition
 ///
+/// With [`BootFlagScheme::PriorityBits`] the target gets the highest
+/// priority in bits 48-51 and the other slots drop by one instead.
+///
 /// For systems using GRUB or other bootloaders that respect these flags,
 /// this will cause the target partition to be booted on next restart.
-pub fn switch_boot_partition(target_index: u32) -> io::Result<()> {
+#[tracing::instrument(name = "switch_boot", skip(slots))]
+pub fn switch_boot_partition(
+    target_index: u32,
+    slots: &Slots,
+    scheme: BootFlagScheme,
+) -> io::Result<()> {
     info!(target_index = target_index, "Switching boot partition");
 
-    // Check if sgdisk is available
-    if !std::path::Path::new("/usr/sbin/sgdisk").exists()
-        && !std::path::Path::new("/sbin/sgdisk").exists()
+    let tool = partition_tool();
+
+    // Priorities are lowered relative to the attributes before the switch,
+    // so plan the changes once and write the same set on every attempt
+    let (clear, set) = plan_boot_flags(tool, target_index, slots, scheme)?;
+
+    // sgdisk can exit successfully without the change reaching the disk
+    // (e.g. a concurrent writer), so read the flag back and retry once
+    let mut attempt = 1;
+    loop {
+        apply_boot_flags(tool, target_index, &clear, set)?;
+        let attributes = read_partition_attributes(tool, target_index)?;
+        if boot_flag_set(scheme, attributes) {
+            break;
+        }
+        if attempt == BOOT_FLAG_ATTEMPTS {
+            return Err(io::Error::other(format!(
+                "Boot flag did not apply to partition {} (attributes {:016x})",
+                target_index, attributes
+            )));
+        }
+        warn!(
+            partition = target_index,
+            attributes = format!("{:016x}", attributes),
+            "Boot flag not set after sgdisk, retrying"
+        );
+        attempt += 1;
+    }
+
+    info!(
+        device = format!("{}{}", DEFAULT_DISK, target_index),
+        "Boot partition switched"
+    );
+
+    // Also record the switch as a software-level indicator (if writable)
+    if let Err(e) = fs::write(BOOT_NEXT_MARKER, format!("{}", target_index)) {
+        warn!(error = %e, "Could not write boot marker");
+    }
+
+    Ok(())
+}
+
+/// Partition index the boot flags were last switched to during this boot
+pub const BOOT_NEXT_MARKER: &str = "/tmp/boot.next";
+
+/// Partition the node boots next: the last boot flag switch recorded in
+/// `marker` during this boot, otherwise the active partition
+pub fn next_boot_partition<P: AsRef<std::path::Path>, Q: AsRef<std::path::Path>>(
+    marker: P,
+    cmdline_path: Q,
+) -> io::Result<PartitionInfo> {
+    match fs::read_to_string(marker)
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
     {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "sgdisk not found. Cannot modify partition table.",
-        ));
+        Some(index) => Ok(PartitionInfo {
+            device: format!("{}{}", DEFAULT_DISK, index),
+            index,
+        }),
+        None => get_active_partition_from(cmdline_path),
     }
+}
 
-    let sgdisk = if std::path::Path::new("/usr/sbin/sgdisk").exists() {
-        "/usr/sbin/sgdisk"
-    } else {
-        "/sbin/sgdisk"
-    };
+/// GPT attribute bit the bootloader reads (legacy BIOS bootable)
+const BOOT_FLAG_BIT: u32 = 2;
+
+/// First of the four priority attribute bits ([`BootFlagScheme::PriorityBits`])
+const PRIORITY_SHIFT: u32 = 48;
 
-    // Determine which partition to clear (the other one)
-    let other_index = if target_index == SLOT_A_INDEX {
-        SLOT_B_INDEX
+/// Priority attribute bits
+const PRIORITY_MASK: u64 = 0xf << PRIORITY_SHIFT;
+
+/// Priority given to the slot to boot
+const PRIORITY_MAX: u64 = 15;
+
+/// Times the boot flags are written before giving up
+const BOOT_FLAG_ATTEMPTS: u32 = 2;
+
+/// Times sgdisk is run while it reports the disk busy
+const SGDISK_BUSY_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry of a busy sgdisk; doubled on every retry
+const SGDISK_BUSY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Kind of sgdisk failure, judged from its stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgdiskFailure {
+    /// Another process holds the disk (`EBUSY`, a lock); worth retrying
+    Busy,
+    /// Anything else, such as a damaged partition table; retrying won't help
+    Permanent,
+}
+
+/// Classify a failed sgdisk run by its stderr
+///
+/// sgdisk reports open failures as `Problem opening <dev> for writing!
+/// Error is <errno>.`, so `EBUSY` (16) and `EAGAIN` (11) are matched by
+/// number as well as by message.
+pub fn classify_sgdisk_error(stderr: &str) -> SgdiskFailure {
+    const BUSY: &[&str] = &[
+        "device or resource busy",
+        "resource temporarily unavailable",
+        "error is 16.",
+        "error is 11.",
+        "is locked",
+        "lock held",
+    ];
+    let stderr = stderr.to_ascii_lowercase();
+    if BUSY.iter().any(|pattern| stderr.contains(pattern)) {
+        SgdiskFailure::Busy
     } else {
-        SLOT_A_INDEX
+        SgdiskFailure::Permanent
+    }
+}
+
+/// Run sgdisk, retrying with backoff while it reports the disk busy
+///
+/// The last output is returned whether or not it succeeded; only failing
+/// to start sgdisk is an error.
+fn run_sgdisk(sgdisk: &str, args: &[&str]) -> io::Result<std::process::Output> {
+    let mut backoff = SGDISK_BUSY_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let output = Command::new(sgdisk).args(args).output()?;
+        if output.status.success() || attempt == SGDISK_BUSY_ATTEMPTS {
+            return Ok(output);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if classify_sgdisk_error(&stderr) == SgdiskFailure::Permanent {
+            return Ok(output);
+        }
+        warn!(
+            args = ?args,
+            attempt,
+            retry_in_ms = backoff.as_millis() as u64,
+            error = %stderr.trim(),
+            "Disk busy, retrying sgdisk"
+        );
+        std::thread::sleep(backoff);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Run a partition table operation on the blocking thread pool
+///
+/// sgdisk runs and its busy backoff sleep would otherwise stall a runtime
+/// worker. The caller's span is carried over to the blocking thread.
+pub async fn blocking<T: Send + 'static>(
+    operation: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(operation))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+}
+
+/// Error for a failed sgdisk run; `ResourceBusy` if the disk stayed busy
+fn sgdisk_error(context: String, output: &std::process::Output) -> io::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let kind = match classify_sgdisk_error(&stderr) {
+        SgdiskFailure::Busy => io::ErrorKind::ResourceBusy,
+        SgdiskFailure::Permanent => io::ErrorKind::Other,
     };
+    io::Error::new(kind, format!("{}: {}", context, stderr))
+}
 
-    // Clear legacy_boot attribute from the other partition
-    // Attribute bit 2 is "Legacy BIOS Bootable"
-    let output = Command::new(sgdisk)
-        .args([
-            &format!("--attributes={}:clear:2", other_index),
-            DEFAULT_DISK,
-        ])
-        .output()?;
+/// What partition attributes are changed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PartitionTool {
+    /// The sgdisk binary at this path
+    Sgdisk(&'static str),
+    /// [`gpt`], writing the partition table directly
+    Builtin,
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        warn!(partition = other_index, error = %stderr, "Failed to clear boot flag");
-        // Continue anyway - setting the target is more important
+/// sgdisk if it is installed, otherwise the built-in GPT writer
+fn partition_tool() -> PartitionTool {
+    match ["/usr/sbin/sgdisk", "/sbin/sgdisk"]
+        .into_iter()
+        .find(|path| std::path::Path::new(path).exists())
+    {
+        Some(sgdisk) => PartitionTool::Sgdisk(sgdisk),
+        None => {
+            info!("sgdisk not found, using the built-in GPT writer");
+            PartitionTool::Builtin
+        }
+    }
+}
+
+/// One change to a partition's attribute bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttributeChange {
+    Set(u32),
+    Clear(u32),
+    /// Replace all 64 bits
+    Assign(u64),
+}
+
+impl AttributeChange {
+    fn apply(self, attributes: u64) -> u64 {
+        match self {
+            AttributeChange::Set(bit) => attributes | (1 << bit),
+            AttributeChange::Clear(bit) => attributes & !(1 << bit),
+            AttributeChange::Assign(value) => value,
+        }
+    }
+
+    /// The same change as an sgdisk `--attributes` argument
+    fn sgdisk_arg(self, index: u32) -> String {
+        match self {
+            AttributeChange::Set(bit) => format!("--attributes={}:set:{}", index, bit),
+            AttributeChange::Clear(bit) => format!("--attributes={}:clear:{}", index, bit),
+            AttributeChange::Assign(value) => format!("--attributes={}:=:{:016x}", index, value),
+        }
+    }
+}
+
+/// Apply `change` to partition `index` of [`DEFAULT_DISK`] with `tool`
+fn change_partition_attributes(
+    tool: PartitionTool,
+    index: u32,
+    change: AttributeChange,
+) -> io::Result<()> {
+    match tool {
+        PartitionTool::Sgdisk(sgdisk) => {
+            let output = run_sgdisk(sgdisk, &[&change.sgdisk_arg(index), DEFAULT_DISK])?;
+            if !output.status.success() {
+                return Err(sgdisk_error(
+                    format!("Failed to change attributes of partition {}", index),
+                    &output,
+                ));
+            }
+            Ok(())
+        }
+        PartitionTool::Builtin => {
+            let mut disk = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(DEFAULT_DISK)?;
+            gpt::update_attributes(&mut disk, index, |attributes| change.apply(attributes))?;
+            disk.sync_all()
+        }
+    }
+}
+
+/// Attribute changes that move the boot flag to `target_index`, computed
+/// from the slots' current attributes
+fn plan_boot_flags(
+    tool: PartitionTool,
+    target_index: u32,
+    slots: &Slots,
+    scheme: BootFlagScheme,
+) -> io::Result<(Vec<(u32, AttributeChange)>, AttributeChange)> {
+    // Priorities are rewritten relative to the current attributes
+    let mut current = std::collections::HashMap::new();
+    if scheme == BootFlagScheme::PriorityBits {
+        for &index in slots.indices() {
+            current.insert(index, read_partition_attributes(tool, index)?);
+        }
+    }
+    Ok(boot_flag_changes(scheme, target_index, slots, |index| {
+        current.get(&index).copied().unwrap_or(0)
+    }))
+}
+
+/// Write the planned boot flag changes: `clear` on the other slots, then
+/// `set` on `target_index`
+fn apply_boot_flags(
+    tool: PartitionTool,
+    target_index: u32,
+    clear: &[(u32, AttributeChange)],
+    set: AttributeChange,
+) -> io::Result<()> {
+    // Clear legacy_boot attribute from every other slot
+    for &(index, change) in clear {
+        if let Err(e) = change_partition_attributes(tool, index, change) {
+            warn!(partition = index, error = %e, "Failed to clear boot flag");
+            // Continue anyway - setting the target is more important
+        }
     }
 
     // Set legacy_boot attribute on the target partition
-    let output = Command::new(sgdisk)
-        .args([
-            &format!("--attributes={}:set:2", target_index),
-            DEFAULT_DISK,
-        ])
-        .output()?;
+    change_partition_attributes(tool, target_index, set).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "Failed to set boot flag on partition {}: {}",
+                target_index, e
+            ),
+        )
+    })
+}
 
+/// GPT attribute bits of partition `index`, read back with `sgdisk --info`
+/// or from the partition table itself
+fn read_partition_attributes(tool: PartitionTool, index: u32) -> io::Result<u64> {
+    let sgdisk = match tool {
+        PartitionTool::Sgdisk(sgdisk) => sgdisk,
+        PartitionTool::Builtin => return read_gpt_attributes(index),
+    };
+    let output = run_sgdisk(sgdisk, &[&format!("--info={}", index), DEFAULT_DISK])?;
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(io::Error::other(format!(
-            "Failed to set boot flag on partition {}: {}",
-            target_index, stderr
-        )));
+        return Err(sgdisk_error(
+            format!("Failed to read partition {}", index),
+            &output,
+        ));
     }
+    parse_attribute_flags(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "No attribute flags in sgdisk output for partition {}",
+                index
+            ),
+        )
+    })
+}
 
-    info!(
-        device = format!("{}{}", DEFAULT_DISK, target_index),
-        "Boot partition switched"
-    );
+/// Attribute bits from `sgdisk --info` output (`Attribute flags: 0000000000000004`)
+fn parse_attribute_flags(info: &str) -> Option<u64> {
+    info.lines().find_map(|line| {
+        let flags = line.trim().strip_prefix("Attribute flags:")?;
+        u64::from_str_radix(flags.trim(), 16).ok()
+    })
+}
 
-    // Also update /etc/keel/boot.next as a software-level indicator (if writable)
-    let boot_marker = "/tmp/boot.next";
-    if let Err(e) = fs::write(boot_marker, format!("{}", target_index)) {
-        warn!(error = %e, "Could not write boot marker");
-    }
+/// Directory holding a marker per slot that was flashed successfully
+pub const SLOT_MARKER_DIR: &str = "/var/lib/keel/slots";
+
+/// Partition index of a slot name (`a` is the first configured slot, `b`
+/// the second, and so on)
+pub fn slot_index(slots: &Slots, name: &str) -> io::Result<u32> {
+    let position = match name.to_ascii_lowercase().as_bytes() {
+        [c @ b'a'..=b'z'] => usize::from(c - b'a'),
+        _ => usize::MAX,
+    };
+
+    slots.indices().get(position).copied().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unknown slot '{}' (this node has {} slots)",
+                name,
+                slots.indices().len()
+            ),
+        )
+    })
+}
+
+/// What is known about the image in a slot
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SlotMarker {
+    /// SHA256 the image was verified against, if one was given
+    pub sha256: Option<String>,
+    /// RFC3339 time the slot was flashed
+    pub flashed_at: String,
+    /// Size of the image, which is usually smaller than the partition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+fn slot_marker_path(marker_dir: &std::path::Path, index: u32) -> std::path::PathBuf {
+    marker_dir.join(format!("{}.json", index))
+}
+
+/// Marker written before markers carried the image hash
+fn legacy_slot_marker_path(marker_dir: &std::path::Path, index: u32) -> std::path::PathBuf {
+    marker_dir.join(format!("{}.flashed", index))
+}
+
+/// Record that partition `index` now holds a complete image
+///
+/// Only call this once the flash has finished and the image was verified;
+/// [`clear_slot_marker`] removes the old marker before flashing starts.
+pub fn mark_slot_flashed<P: AsRef<std::path::Path>>(
+    marker_dir: P,
+    index: u32,
+    sha256: Option<&str>,
+    size: Option<u64>,
+) -> io::Result<()> {
+    let marker = SlotMarker {
+        sha256: sha256.map(str::to_lowercase),
+        flashed_at: chrono::Utc::now().to_rfc3339(),
+        size,
+    };
+    let json = serde_json::to_string_pretty(&marker)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    keel_config::atomic_write(slot_marker_path(marker_dir.as_ref(), index), json)
+}
 
+/// Forget that partition `index` holds a complete image
+///
+/// Called before the first byte of a new image is written, so a flash that
+/// is interrupted or fails leaves the slot unbootable instead of approved
+/// by the marker of the image it overwrote.
+pub fn clear_slot_marker<P: AsRef<std::path::Path>>(marker_dir: P, index: u32) -> io::Result<()> {
+    let marker_dir = marker_dir.as_ref();
+    for path in [
+        slot_marker_path(marker_dir, index),
+        legacy_slot_marker_path(marker_dir, index),
+    ] {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    // The removal must be durable before the slot is overwritten
+    if let Ok(dir) = fs::File::open(marker_dir) {
+        let _ = dir.sync_all();
+    }
     Ok(())
 }
 
+/// Read the marker of partition `index`
+///
+/// Fails with `NotFound` if the slot was never flashed and `InvalidData` if
+/// the marker is unreadable. A marker from before hashes were recorded is
+/// accepted with no hash.
+pub fn read_slot_marker<P: AsRef<std::path::Path>>(
+    marker_dir: P,
+    index: u32,
+) -> io::Result<SlotMarker> {
+    let marker_dir = marker_dir.as_ref();
+    match fs::read_to_string(slot_marker_path(marker_dir, index)) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Slot marker for partition {} is corrupt: {}", index, e),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            match fs::read_to_string(legacy_slot_marker_path(marker_dir, index)) {
+                Ok(flashed_at) => Ok(SlotMarker {
+                    sha256: None,
+                    flashed_at: flashed_at.trim().to_string(),
+                    size: None,
+                }),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Partition {} has never been flashed", index),
+                )),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Refuse to boot a slot that was never flashed
+///
+/// The booted slot is always accepted; any other slot needs a valid marker
+/// written by [`mark_slot_flashed`].
+pub fn check_slot_bootable<P: AsRef<std::path::Path>>(
+    marker_dir: P,
+    index: u32,
+    active_index: u32,
+) -> io::Result<()> {
+    if index == active_index {
+        return Ok(());
+    }
+    read_slot_marker(marker_dir, index).map(|_| ())
+}
+
+/// Boot state of one slot, as shown by `osctl disk slots`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    /// Slot name (`a`, `b`, ...)
+    pub name: String,
+    pub device: String,
+    pub index: u32,
+    /// The node is booted from this slot
+    pub active: bool,
+    /// Flashed marker, or why the slot has no valid one
+    pub marker: Result<SlotMarker, String>,
+    /// GPT attribute bits, or why they could not be read
+    pub attributes: Result<u64, String>,
+}
+
+impl SlotInfo {
+    /// Legacy BIOS bootable attribute (bit 2)
+    pub fn legacy_bootable(&self) -> bool {
+        self.attributes
+            .as_ref()
+            .is_ok_and(|&attributes| attributes & (1 << BOOT_FLAG_BIT) != 0)
+    }
+
+    /// Boot priority (bits 48-51)
+    pub fn priority(&self) -> u32 {
+        self.attributes.as_ref().map_or(0, |&attributes| {
+            ((attributes & PRIORITY_MASK) >> PRIORITY_SHIFT) as u32
+        })
+    }
+
+    /// Whether the slot is marked to boot under `scheme`
+    pub fn boot_flag(&self, scheme: BootFlagScheme) -> bool {
+        self.attributes
+            .as_ref()
+            .is_ok_and(|&attributes| boot_flag_set(scheme, attributes))
+    }
+}
+
+/// Describe every configured slot
+///
+/// Devices are named after the disk of the `active` partition. Markers are
+/// read from `marker_dir` and attribute bits from `attributes`; a slot whose
+/// marker or attributes cannot be read is still listed, with the error.
+pub fn slot_info<P: AsRef<std::path::Path>>(
+    slots: &Slots,
+    active: &PartitionInfo,
+    marker_dir: P,
+    attributes: impl Fn(u32) -> io::Result<u64>,
+) -> Vec<SlotInfo> {
+    slots
+        .indices()
+        .iter()
+        .zip(b'a'..=b'z')
+        .map(|(&index, name)| SlotInfo {
+            name: char::from(name).to_string(),
+            device: sibling_device(active, index),
+            index,
+            active: index == active.index,
+            marker: read_slot_marker(marker_dir.as_ref(), index).map_err(|e| e.to_string()),
+            attributes: attributes(index).map_err(|e| e.to_string()),
+        })
+        .collect()
+}
+
+/// GPT attribute bits of partition `index` of the boot disk, read with the
+/// built-in [`gpt`] reader
+pub fn read_gpt_attributes(index: u32) -> io::Result<u64> {
+    gpt::read_attributes(&mut fs::File::open(DEFAULT_DISK)?, index)
+}
+
+/// sgdisk `--attributes` arguments to boot `target_index`
+///
+/// Returns the clear arguments for every other slot and the set argument
+/// for the target. For [`BootFlagScheme::LegacyBios`] these clear and set
+/// attribute bit 2, "Legacy BIOS Bootable". For
+/// [`BootFlagScheme::PriorityBits`] they assign each slot's whole attribute
+/// value, computed from `current` attributes: the target's priority becomes
+/// [`PRIORITY_MAX`] and every other priority drops by one, keeping their
+/// order and every other bit.
+#[cfg(test)]
+fn boot_flag_attributes(
+    scheme: BootFlagScheme,
+    target_index: u32,
+    slots: &Slots,
+    current: impl Fn(u32) -> u64,
+) -> (Vec<String>, String) {
+    let (others, target) = boot_flag_changes(scheme, target_index, slots, current);
+    (
+        others
+            .into_iter()
+            .map(|(index, change)| change.sgdisk_arg(index))
+            .collect(),
+        target.sgdisk_arg(target_index),
+    )
+}
+
+/// Attribute changes to boot `target_index`: one per other slot, then the
+/// one for the target (see [`boot_flag_attributes`])
+fn boot_flag_changes(
+    scheme: BootFlagScheme,
+    target_index: u32,
+    slots: &Slots,
+    current: impl Fn(u32) -> u64,
+) -> (Vec<(u32, AttributeChange)>, AttributeChange) {
+    match scheme {
+        BootFlagScheme::LegacyBios => {
+            let clear = slots
+                .others(target_index)
+                .map(|index| (index, AttributeChange::Clear(BOOT_FLAG_BIT)))
+                .collect();
+            (clear, AttributeChange::Set(BOOT_FLAG_BIT))
+        }
+        BootFlagScheme::PriorityBits => {
+            let with_priority = |index: u32, priority: u64| {
+                AttributeChange::Assign(
+                    (current(index) & !PRIORITY_MASK) | (priority << PRIORITY_SHIFT),
+                )
+            };
+            let lower = slots
+                .others(target_index)
+                .map(|index| {
+                    let priority = (current(index) & PRIORITY_MASK) >> PRIORITY_SHIFT;
+                    (index, with_priority(index, priority.saturating_sub(1)))
+                })
+                .collect();
+            (lower, with_priority(target_index, PRIORITY_MAX))
+        }
+    }
+}
+
+/// Whether `attributes` mark the partition as the one to boot
+fn boot_flag_set(scheme: BootFlagScheme, attributes: u64) -> bool {
+    match scheme {
+        BootFlagScheme::LegacyBios => attributes & (1 << BOOT_FLAG_BIT) != 0,
+        BootFlagScheme::PriorityBits => {
+            (attributes & PRIORITY_MASK) >> PRIORITY_SHIFT == PRIORITY_MAX
+        }
+    }
+}
+
 /// State file for tracking rollback information
 const ROLLBACK_STATE_FILE: &str = "/var/lib/keel/rollback_state.json";
 
@@ -449,7 +1417,11 @@ struct RollbackState {
 
 /// Load rollback state from disk
 fn load_rollback_state() -> RollbackState {
-    match fs::read_to_string(ROLLBACK_STATE_FILE) {
+    load_rollback_state_from(ROLLBACK_STATE_FILE)
+}
+
+fn load_rollback_state_from<P: AsRef<std::path::Path>>(state_file: P) -> RollbackState {
+    match fs::read_to_string(state_file) {
         Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
         Err(_) => RollbackState::default(),
     }
@@ -457,15 +1429,17 @@ fn load_rollback_state() -> RollbackState {
 
 /// Save rollback state to disk
 fn save_rollback_state(state: &RollbackState) -> io::Result<()> {
-    // Ensure parent directory exists
-    if let Some(parent) = std::path::Path::new(ROLLBACK_STATE_FILE).parent() {
-        fs::create_dir_all(parent)?;
-    }
+    save_rollback_state_to(ROLLBACK_STATE_FILE, state)
+}
 
+fn save_rollback_state_to<P: AsRef<std::path::Path>>(
+    state_file: P,
+    state: &RollbackState,
+) -> io::Result<()> {
     let json = serde_json::to_string_pretty(state)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-    fs::write(ROLLBACK_STATE_FILE, json)?;
+    keel_config::atomic_write(state_file, json)?;
     debug!("Saved rollback state");
     Ok(())
 }
@@ -474,6 +1448,13 @@ fn save_rollback_state(state: &RollbackState) -> io::Result<()> {
 #[allow(dead_code)]
 pub fn record_active_partition_for_rollback() -> io::Result<()> {
     let active = get_active_partition()?;
+
+    // The running slot is known to boot, so it is a valid rollback target
+    // even if it was installed rather than flashed by an update
+    if read_slot_marker(SLOT_MARKER_DIR, active.index).is_err() {
+        mark_slot_flashed(SLOT_MARKER_DIR, active.index, None, None)?;
+    }
+
     let mut state = load_rollback_state();
     state.previous_partition = Some(active.index);
     state.last_update_time = Some(chrono::Utc::now().to_rfc3339());
@@ -486,7 +1467,7 @@ pub fn record_active_partition_for_rollback() -> io::Result<()> {
 }
 
 /// Rollback to the previous partition
-pub fn rollback_to_previous_partition() -> io::Result<()> {
+pub fn rollback_to_previous_partition(slots: &Slots, scheme: BootFlagScheme) -> io::Result<()> {
     let state = load_rollback_state();
 
     let previous_index = state.previous_partition.ok_or_else(|| {
@@ -496,13 +1477,17 @@ pub fn rollback_to_previous_partition() -> io::Result<()> {
         )
     })?;
 
+    // Never point the boot flags at a blank or half-written partition
+    let active_index = get_active_partition().map_or(0, |p| p.index);
+    check_slot_bootable(SLOT_MARKER_DIR, previous_index, active_index)?;
+
     info!(
         target_partition = previous_index,
         "Rolling back to previous partition"
     );
 
     // Switch back to the previous partition
-    switch_boot_partition(previous_index)?;
+    switch_boot_partition(previous_index, slots, scheme)?;
 
     // Clear the rollback state
     let mut state = load_rollback_state();
@@ -513,6 +1498,25 @@ pub fn rollback_to_previous_partition() -> io::Result<()> {
     Ok(())
 }
 
+/// Run `rollback`, then `reboot` unless the reboot is deferred
+///
+/// Returns whether a reboot is still pending, i.e. the boot flags were
+/// switched but the node keeps running the current partition.
+pub fn rollback_with_reboot(
+    rollback: impl FnOnce() -> io::Result<()>,
+    reboot_now: bool,
+    reboot: impl FnOnce(),
+) -> io::Result<bool> {
+    rollback()?;
+    if reboot_now {
+        reboot();
+        Ok(false)
+    } else {
+        info!("Rollback staged, waiting for a manual reboot");
+        Ok(true)
+    }
+}
+
 /// Get the current boot counter
 #[allow(dead_code)]
 pub fn get_boot_counter() -> u32 {
@@ -533,11 +1537,18 @@ pub fn increment_boot_counter() -> io::Result<()> {
 }
 
 /// Clear the boot counter after successful boot + health checks
-#[allow(dead_code)]
 pub fn clear_boot_counter() -> io::Result<()> {
-    let mut state = load_rollback_state();
+    clear_boot_counter_at(ROLLBACK_STATE_FILE)
+}
+
+/// Clear the boot counter in the rollback state file at `state_file`
+///
+/// The recorded previous partition is kept, so a manual rollback stays
+/// possible.
+pub fn clear_boot_counter_at<P: AsRef<std::path::Path>>(state_file: P) -> io::Result<()> {
+    let mut state = load_rollback_state_from(&state_file);
     state.boot_counter = 0;
-    save_rollback_state(&state)?;
+    save_rollback_state_to(&state_file, &state)?;
     info!("Cleared boot counter");
     Ok(())
 }
@@ -562,6 +1573,140 @@ pub fn is_boot_loop() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::download::test_server::{serve_body, serve_payload};
+
+    #[test]
+    fn test_check_flash_target_rejects_regular_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let err = check_flash_target(path, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("not a block device"));
+    }
+
+    #[test]
+    fn test_check_flash_target_opt_out_allows_regular_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(check_flash_target(file.path().to_str().unwrap(), true).is_ok());
+    }
+
+    #[test]
+    fn test_non_block_opt_out_requires_truthy_value() {
+        for enabled in ["1", "true", "TRUE", " true\n"] {
+            assert!(is_truthy(enabled), "{enabled:?}");
+        }
+        for disabled in ["", "0", "false", "no", "yes"] {
+            assert!(!is_truthy(disabled), "{disabled:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flash_image_guards_regular_file_without_opt_out() {
+        let url = serve_payload(b"image".to_vec()).await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"previous").unwrap();
+        let target = target.to_str().unwrap();
+
+        let err = flash_image(
+            &url,
+            target,
+            None,
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = flash_local_image(&dir.path().join("target.img"), target, None, false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(std::fs::read(target).unwrap(), b"previous");
+    }
+
+    #[test]
+    fn test_check_not_data_partition() {
+        let mounts = "\
+rootfs / rootfs rw 0 0
+/dev/sda2 /usr ext4 ro,relatime 0 0
+/dev/sda4 /data ext4 rw,relatime 0 0
+/dev/sda4 /var/lib/keel ext4 rw,relatime 0 0
+tmpfs /run tmpfs rw 0 0
+";
+        let err = check_not_data_partition("/dev/sda4", mounts).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("data partition"));
+
+        assert!(check_not_data_partition("/dev/sda3", mounts).is_ok());
+        // Not a data mount point, and not even a device
+        assert!(check_not_data_partition("/dev/sda2", mounts).is_ok());
+        assert!(check_not_data_partition("/dev/sda4", "tmpfs /data tmpfs rw 0 0\n").is_ok());
+    }
+
+    #[test]
+    fn test_check_not_active_partition() {
+        let active = PartitionInfo {
+            device: "/dev/sda2".to_string(),
+            index: 2,
+        };
+        let inactive = PartitionInfo {
+            device: "/dev/sda3".to_string(),
+            index: 3,
+        };
+        assert!(check_not_active_partition(&inactive.device, &active).is_ok());
+
+        let err = check_not_active_partition(&active.device, &active).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("active partition"), "{}", err);
+
+        // A symlink to the active device is the same partition
+        let dir = tempfile::TempDir::new().unwrap();
+        let device = dir.path().join("sda2");
+        fs::write(&device, b"").unwrap();
+        let link = dir.path().join("by-partuuid");
+        std::os::unix::fs::symlink(&device, &link).unwrap();
+        let active = PartitionInfo {
+            device: device.to_string_lossy().into_owned(),
+            index: 2,
+        };
+        assert!(check_not_active_partition(link.to_str().unwrap(), &active).is_err());
+    }
+
+    #[test]
+    fn test_check_flash_target_rejects_missing_and_non_files() {
+        let err = check_flash_target("/dev/keel-does-not-exist3", true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // Character devices and directories are never valid targets
+        assert!(check_flash_target("/dev/null", true).is_err());
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(check_flash_target(dir.path().to_str().unwrap(), true).is_err());
+    }
+
+    #[test]
+    fn test_clear_boot_counter_keeps_previous_partition() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let state_file = dir.path().join("rollback_state.json");
+        let state = RollbackState {
+            previous_partition: Some(2),
+            boot_counter: 2,
+            last_update_time: None,
+        };
+        save_rollback_state_to(&state_file, &state).unwrap();
+
+        clear_boot_counter_at(&state_file).unwrap();
+
+        let state = load_rollback_state_from(&state_file);
+        assert_eq!(state.boot_counter, 0);
+        assert_eq!(state.previous_partition, Some(2));
+    }
 
     #[test]
     fn test_parse_device_path() {
@@ -576,19 +1721,613 @@ mod tests {
 
     #[test]
     fn test_inactive_partition_calculation() {
-        // When slot A is active, slot B should be inactive
-        let active_a = PartitionInfo {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cmdline = dir.path().join("cmdline");
+
+        std::fs::write(&cmdline, "root=/dev/sda2 ro").unwrap();
+        let inactive = get_inactive_partition_from(&cmdline, &Slots::default()).unwrap();
+        assert_eq!(inactive.device, "/dev/sda3");
+        assert_eq!(inactive.index, 3);
+
+        std::fs::write(&cmdline, "root=/dev/sda3 ro").unwrap();
+        let inactive = get_inactive_partition_from(&cmdline, &Slots::default()).unwrap();
+        assert_eq!(inactive.index, 2);
+    }
+
+    #[test]
+    fn test_inactive_partition_three_slots() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cmdline = dir.path().join("cmdline");
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+
+        for (active, expected) in [(2, 3), (3, 4), (4, 2)] {
+            std::fs::write(&cmdline, format!("root=/dev/sda{} ro", active)).unwrap();
+            let inactive = get_inactive_partition_from(&cmdline, &slots).unwrap();
+            assert_eq!(inactive.index, expected);
+            assert_eq!(inactive.device, format!("/dev/sda{}", expected));
+        }
+    }
+
+    #[test]
+    fn test_boot_flag_attributes_clear_all_other_slots() {
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+        let legacy = BootFlagScheme::LegacyBios;
+        let (clear, set) = boot_flag_attributes(legacy, 3, &slots, |_| 0);
+        assert_eq!(
+            clear,
+            vec!["--attributes=2:clear:2", "--attributes=4:clear:2"]
+        );
+        assert_eq!(set, "--attributes=3:set:2");
+
+        let (clear, set) = boot_flag_attributes(legacy, 2, &Slots::default(), |_| 0);
+        assert_eq!(clear, vec!["--attributes=3:clear:2"]);
+        assert_eq!(set, "--attributes=2:set:2");
+        assert!(boot_flag_set(legacy, 0x4));
+        assert!(!boot_flag_set(legacy, 0x000f_0000_0000_0000));
+    }
+
+    #[test]
+    fn test_boot_flag_attributes_priority_bits() {
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+        let scheme = BootFlagScheme::PriorityBits;
+        // Slot 2 is booted (15), slot 4 is an older fallback (14) that also
+        // carries an unrelated bit
+        let current = |index| match index {
+            2 => 0x000f_0000_0000_0000,
+            4 => 0x000e_0000_0000_0001,
+            _ => 0,
+        };
+        let (lower, set) = boot_flag_attributes(scheme, 3, &slots, current);
+        assert_eq!(
+            lower,
+            vec![
+                "--attributes=2:=:000e000000000000",
+                "--attributes=4:=:000d000000000001",
+            ]
+        );
+        assert_eq!(set, "--attributes=3:=:000f000000000000");
+
+        // Priorities never wrap below zero
+        let (lower, _) = boot_flag_attributes(scheme, 2, &Slots::default(), |_| 0);
+        assert_eq!(lower, vec!["--attributes=3:=:0000000000000000"]);
+
+        assert!(boot_flag_set(scheme, 0x000f_0000_0000_0004));
+        assert!(!boot_flag_set(scheme, 0x000e_0000_0000_0004));
+        assert!(!boot_flag_set(scheme, 0x4));
+    }
+
+    #[test]
+    fn test_boot_flag_changes_retry_writes_same_attributes() {
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+        let before = |index| match index {
+            2 => 0x000f_0000_0000_0000,
+            4 => 0x000e_0000_0000_0001,
+            _ => 0,
+        };
+        for scheme in [BootFlagScheme::LegacyBios, BootFlagScheme::PriorityBits] {
+            let (clear, set) = boot_flag_changes(scheme, 3, &slots, before);
+            // A retry writes the planned changes again on top of the first
+            // attempt; the other slots must not drop a second priority
+            for &(index, change) in &clear {
+                let once = change.apply(before(index));
+                assert_eq!(change.apply(once), once, "{scheme:?} slot {index}");
+            }
+            let once = set.apply(before(3));
+            assert_eq!(set.apply(once), once);
+            assert!(boot_flag_set(scheme, once));
+        }
+
+        let (lower, _) = boot_flag_changes(BootFlagScheme::PriorityBits, 3, &slots, before);
+        assert_eq!(
+            lower[0],
+            (2, AttributeChange::Assign(0x000e_0000_0000_0000))
+        );
+    }
+
+    #[test]
+    fn test_next_boot_partition_prefers_switch_marker() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cmdline = dir.path().join("cmdline");
+        fs::write(&cmdline, "console=ttyS0 root=/dev/sda2 ro\n").unwrap();
+        let marker = dir.path().join("boot.next");
+
+        assert_eq!(next_boot_partition(&marker, &cmdline).unwrap().index, 2);
+
+        fs::write(&marker, "3").unwrap();
+        let next = next_boot_partition(&marker, &cmdline).unwrap();
+        assert_eq!(next.index, 3);
+        assert_eq!(next.device, "/dev/sda3");
+    }
+
+    #[test]
+    fn test_classify_sgdisk_error() {
+        for busy in [
+            "Problem opening /dev/sda for writing! Error is 16.\n",
+            "Problem opening /dev/sda for reading! Error is 11.\n",
+            "Warning: unable to open /dev/sda: Device or resource busy\n",
+            "flock: Resource temporarily unavailable\n",
+        ] {
+            assert_eq!(classify_sgdisk_error(busy), SgdiskFailure::Busy, "{busy}");
+        }
+
+        for permanent in [
+            "Caution: invalid main GPT header, but valid backup; regenerating main header\n",
+            "Partition #7 does not exist.\n",
+            "Problem opening /dev/sdz for reading! Error is 2.\n",
+            "Problem opening /dev/sda for writing! Error is 13.\n",
+            "Could not change partition 2's attributes to 'set:2'\n",
+            "",
+        ] {
+            assert_eq!(
+                classify_sgdisk_error(permanent),
+                SgdiskFailure::Permanent,
+                "{permanent}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_attribute_flags() {
+        let info = "Partition GUID code: 0FC63DAF-8483-4772-8E79-3D69D8477DE4 (Linux filesystem)\n\
+                    Partition unique GUID: 5C4A3A5E-1D2B-4B8E-9F3A-7A1C2D3E4F50\n\
+                    First sector: 1050624 (at 513.0 MiB)\n\
+                    Last sector: 5244927 (at 2.5 GiB)\n\
+                    Partition size: 4194304 sectors (2.0 GiB)\n\
+                    Attribute flags: 0000000000000004\n\
+                    Partition name: 'ROOT-A'\n";
+        let flags = parse_attribute_flags(info).unwrap();
+        assert_eq!(flags, 0x4);
+        assert_ne!(flags & (1 << BOOT_FLAG_BIT), 0);
+
+        // Other bits do not count as the boot flag
+        let flags = parse_attribute_flags("Attribute flags: 1000000000000001\n").unwrap();
+        assert_eq!(flags, 0x1000_0000_0000_0001);
+        assert_eq!(flags & (1 << BOOT_FLAG_BIT), 0);
+
+        assert_eq!(
+            parse_attribute_flags("Partition #7 does not exist.\n"),
+            None
+        );
+        assert_eq!(parse_attribute_flags("Attribute flags: zz\n"), None);
+    }
+
+    #[test]
+    fn test_slot_index_mapping() {
+        let slots = Slots::default();
+        assert_eq!(slot_index(&slots, "a").unwrap(), 2);
+        assert_eq!(slot_index(&slots, "B").unwrap(), 3);
+
+        let err = slot_index(&slots, "c").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(slot_index(&slots, "ab").is_err());
+        assert!(slot_index(&slots, "").is_err());
+
+        let three = Slots::new(vec![2, 3, 4]).unwrap();
+        assert_eq!(slot_index(&three, "c").unwrap(), 4);
+    }
+
+    #[test]
+    fn test_check_slot_bootable_requires_marker() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        // The booted slot needs no marker
+        assert!(check_slot_bootable(dir.path(), 2, 2).is_ok());
+
+        let err = check_slot_bootable(dir.path(), 3, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("never been flashed"));
+
+        mark_slot_flashed(dir.path(), 3, None, None).unwrap();
+        assert!(check_slot_bootable(dir.path(), 3, 2).is_ok());
+
+        // A corrupt marker does not count
+        std::fs::write(dir.path().join("3.json"), "{").unwrap();
+        let err = check_slot_bootable(dir.path(), 3, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_clear_slot_marker_before_reflash() {
+        let dir = tempfile::TempDir::new().unwrap();
+        mark_slot_flashed(dir.path(), 3, Some("abc123"), Some(4096)).unwrap();
+        std::fs::write(dir.path().join("3.flashed"), "2026-01-01T00:00:00+00:00").unwrap();
+        mark_slot_flashed(dir.path(), 4, None, None).unwrap();
+
+        // Neither the current nor the legacy marker survives
+        clear_slot_marker(dir.path(), 3).unwrap();
+        assert_eq!(
+            check_slot_bootable(dir.path(), 3, 2).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(check_slot_bootable(dir.path(), 4, 2).is_ok());
+
+        // Clearing a slot that has no marker is fine
+        clear_slot_marker(dir.path(), 3).unwrap();
+        clear_slot_marker(dir.path().join("missing"), 3).unwrap();
+    }
+
+    #[test]
+    fn test_slot_marker_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        mark_slot_flashed(dir.path(), 3, Some("ABC123"), Some(4096)).unwrap();
+        let marker = read_slot_marker(dir.path(), 3).unwrap();
+        assert_eq!(marker.sha256.as_deref(), Some("abc123"));
+        assert_eq!(marker.size, Some(4096));
+        assert!(chrono::DateTime::parse_from_rfc3339(&marker.flashed_at).is_ok());
+
+        // Markers written by older agents are still honoured
+        std::fs::write(dir.path().join("2.flashed"), "2026-01-01T00:00:00+00:00").unwrap();
+        assert_eq!(
+            read_slot_marker(dir.path(), 2).unwrap(),
+            SlotMarker {
+                sha256: None,
+                flashed_at: "2026-01-01T00:00:00+00:00".to_string(),
+                size: None,
+            }
+        );
+        assert_eq!(
+            read_slot_marker(dir.path(), 4).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_slot_info_combines_markers_and_attributes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        mark_slot_flashed(dir.path(), 3, Some("abc123"), None).unwrap();
+        std::fs::write(dir.path().join("4.json"), "{").unwrap();
+
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+        let active = PartitionInfo {
             device: "/dev/sda2".to_string(),
-            index: SLOT_A_INDEX,
+            index: 2,
         };
+        let info = slot_info(&slots, &active, dir.path(), |index| match index {
+            2 => Ok(14 << PRIORITY_SHIFT),
+            3 => Ok((15 << PRIORITY_SHIFT) | (1 << BOOT_FLAG_BIT)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad GPT header")),
+        });
+
+        let names: Vec<_> = info.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        let devices: Vec<_> = info.iter().map(|s| s.device.as_str()).collect();
+        assert_eq!(devices, ["/dev/sda2", "/dev/sda3", "/dev/sda4"]);
+
+        // Booted from the installer's slot, which has no marker
+        assert!(info[0].active);
+        assert!(info[0]
+            .marker
+            .as_ref()
+            .unwrap_err()
+            .contains("never been flashed"));
+        assert!(!info[0].legacy_bootable());
+        assert_eq!(info[0].priority(), 14);
+
+        // The slot switched to after an update
+        assert!(!info[1].active);
+        assert_eq!(
+            info[1].marker.as_ref().unwrap().sha256.as_deref(),
+            Some("abc123")
+        );
+        assert!(info[1].legacy_bootable());
+        assert!(info[1].boot_flag(BootFlagScheme::LegacyBios));
+        assert!(info[1].boot_flag(BootFlagScheme::PriorityBits));
+        assert!(!info[0].boot_flag(BootFlagScheme::PriorityBits));
+
+        // Errors are reported per slot
+        assert!(info[2].marker.as_ref().unwrap_err().contains("corrupt"));
+        assert_eq!(info[2].attributes.as_ref().unwrap_err(), "bad GPT header");
+        assert!(!info[2].boot_flag(BootFlagScheme::LegacyBios));
+        assert_eq!(info[2].priority(), 0);
+    }
 
-        // Can't call get_inactive_partition directly in unit tests
-        // (requires /proc/cmdline), but we can test the logic
-        let inactive_index = if active_a.index == SLOT_A_INDEX {
-            SLOT_B_INDEX
-        } else {
-            SLOT_A_INDEX
+    #[test]
+    fn test_rollback_without_reboot_only_switches() {
+        let mut switched = false;
+        let mut rebooted = false;
+        let pending = rollback_with_reboot(
+            || {
+                switched = true;
+                Ok(())
+            },
+            false,
+            || rebooted = true,
+        )
+        .unwrap();
+        assert!(switched);
+        assert!(!rebooted);
+        assert!(pending);
+
+        let mut rebooted = false;
+        let pending = rollback_with_reboot(|| Ok(()), true, || rebooted = true).unwrap();
+        assert!(rebooted);
+        assert!(!pending);
+
+        // A failed switch never reboots
+        let mut rebooted = false;
+        assert!(rollback_with_reboot(
+            || Err(io::Error::other("sgdisk failed")),
+            true,
+            || rebooted = true
+        )
+        .is_err());
+        assert!(!rebooted);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_leaves_runtime_free() {
+        let flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let seen = flag.clone();
+        let (result, ()) = tokio::join!(
+            blocking(move || {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                Ok(seen.load(std::sync::atomic::Ordering::SeqCst))
+            }),
+            async { flag.store(true, std::sync::atomic::Ordering::SeqCst) },
+        );
+        // The other task ran while the operation slept
+        assert!(result.unwrap());
+
+        let err = blocking(|| -> io::Result<()> { Err(io::Error::other("busy")) })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "busy");
+    }
+
+    #[tokio::test]
+    async fn test_block_writer_matches_unbuffered_output() {
+        let payload: Vec<u8> = (0..300_000u32).map(|i| (i * 7 % 253) as u8).collect();
+        let digest = Sha256::digest(&payload);
+
+        // Unbuffered: each chunk written as it arrives
+        let chunk_sizes = [1, 17, 4096, 65_536, 1000];
+        let chunks = || {
+            let mut rest = payload.as_slice();
+            let mut chunks = Vec::new();
+            for size in chunk_sizes.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+                chunks.push(chunk);
+                rest = tail;
+            }
+            chunks
         };
-        assert_eq!(inactive_index, SLOT_B_INDEX);
+        let mut unbuffered = Vec::new();
+        for chunk in chunks() {
+            unbuffered.write_all(chunk).await.unwrap();
+        }
+
+        // Block sizes that do and do not divide the payload or the chunks
+        for block_size in [1, 3, 4096, 1024 * 1024] {
+            let mut writer = BlockWriter::new(Vec::new(), block_size);
+            for chunk in chunks() {
+                writer.write(chunk).await.unwrap();
+            }
+            let (written, hasher) = writer.finish().await.unwrap();
+            assert_eq!(written, unbuffered, "block size {}", block_size);
+            assert_eq!(hasher.finalize(), digest, "block size {}", block_size);
+        }
+    }
+
+    #[test]
+    fn test_write_block_size_aligns_to_optimal_io() {
+        assert_eq!(write_block_size(0, None), 1024 * 1024);
+        assert_eq!(write_block_size(4 * 1024 * 1024, None), 4 * 1024 * 1024);
+        assert_eq!(write_block_size(0, Some(4096)), 1024 * 1024);
+        // Rounded up to a whole number of optimal I/O units
+        assert_eq!(
+            write_block_size(1024 * 1024, Some(1536 * 1024)),
+            1536 * 1024
+        );
+        assert_eq!(write_block_size(5000, Some(4096)), 8192);
+        // Never past the configured maximum, aligned down when possible
+        let max = keel_config::MAX_WRITE_BLOCK_BYTES;
+        assert_eq!(write_block_size(0, Some(max * 2)), max as usize);
+        assert_eq!(
+            write_block_size(max, Some(48 * 1024 * 1024)),
+            48 * 1024 * 1024
+        );
+        assert_eq!(write_block_size(u64::MAX, Some(4096)), max as usize);
+        // Regular files report nothing
+        assert_eq!(optimal_io_size("/nonexistent/keel-target.img"), None);
+    }
+
+    #[tokio::test]
+    async fn test_flash_image_rate_limit() {
+        let payload = vec![0x5a; 64 * 1024];
+        let url = serve_payload(payload.clone()).await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"").unwrap();
+        let target = target.to_str().unwrap();
+
+        let started = std::time::Instant::now();
+        flash_image(
+            &url,
+            target,
+            None,
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap();
+        let unlimited = started.elapsed();
+
+        // 64 KiB at 128 KiB/s takes at least half a second
+        let started = std::time::Instant::now();
+        flash_image(
+            &url,
+            target,
+            None,
+            false,
+            None,
+            None,
+            128 * 1024,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap();
+        let limited = started.elapsed();
+
+        assert!(
+            limited >= std::time::Duration::from_millis(450),
+            "{limited:?}"
+        );
+        assert!(limited > unlimited, "{limited:?} <= {unlimited:?}");
+        assert_eq!(std::fs::read(target).unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn test_update_phases_emit_spans() {
+        let (recorder, _guard) = crate::telemetry::span_recorder::record_spans();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"").unwrap();
+        let target = target.to_str().unwrap();
+
+        // download + flash + verify
+        let image = b"span test image".to_vec();
+        let digest = format!("{:x}", Sha256::digest(&image));
+        // A pre-signed URL's query must not end up in exported spans
+        let url = format!(
+            "{}?X-Amz-Signature=span-secret",
+            serve_payload(image.clone()).await
+        );
+        flash_image(
+            &url,
+            target,
+            Some(&digest),
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        // flash from a local (pre-staged) file
+        let staged = dir.path().join("staged.img");
+        std::fs::write(&staged, &image).unwrap();
+        flash_local_image(&staged, target, Some(&digest), true)
+            .await
+            .unwrap();
+
+        let fields = recorder.fields();
+        assert!(
+            fields.iter().any(|f| f.contains("?[REDACTED]")),
+            "{fields:?}"
+        );
+        assert!(
+            !fields.iter().any(|f| f.contains("span-secret")),
+            "{fields:?}"
+        );
+
+        let names = recorder.names();
+        for phase in ["download", "flash", "verify"] {
+            assert!(
+                names.iter().any(|n| n == phase),
+                "no {phase} span in {names:?}"
+            );
+        }
+        assert_eq!(
+            names.iter().filter(|n| *n == "flash").count(),
+            2,
+            "{names:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flash_image_decodes_deflate_body() {
+        use std::io::Write;
+
+        let payload: Vec<u8> = b"keel image ".repeat(10_000);
+        let digest = format!("{:x}", Sha256::digest(&payload));
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&payload).unwrap();
+        let url = serve_body(encoder.finish().unwrap(), "Content-Encoding: deflate\r\n").await;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"").unwrap();
+        let target = target.to_str().unwrap();
+
+        flash_image(
+            &url,
+            target,
+            Some(&digest),
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(target).unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn test_flash_image_decodes_gzip_body() {
+        use std::io::Write;
+
+        let payload: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let digest = format!("{:x}", Sha256::digest(&payload));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&payload).unwrap();
+        let url = serve_body(encoder.finish().unwrap(), "Content-Encoding: gzip\r\n").await;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"").unwrap();
+        let target = target.to_str().unwrap();
+
+        flash_image(
+            &url,
+            target,
+            Some(&digest),
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(target).unwrap(), payload);
+
+        // The hash covers the decoded image, not the compressed transfer
+        let err = flash_image(
+            &url,
+            target,
+            Some(&"0".repeat(64)),
+            false,
+            None,
+            None,
+            0,
+            0,
+            dir.path(),
+            true,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains(&digest), "{}", err);
     }
 }
@@ -0,0 +1,207 @@
+//! Graceful shutdown of the agent's servers
+//!
+//! On SIGTERM or SIGINT the servers stop accepting connections and get
+//! [`DRAIN_TIMEOUT`] to finish in-flight RPCs, so an `InstallUpdate` stream
+//! ends with a clean HTTP/2 close instead of a reset. Anything still running
+//! after that is dropped.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// How long in-flight RPCs get to finish once shutdown starts
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves once shutdown has started; clone it for every server
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    /// Wait for shutdown to start
+    pub async fn wait(mut self) {
+        // A dropped trigger also means shutdown
+        let _ = self.0.wait_for(|started| *started).await;
+    }
+
+    /// Whether shutdown has started
+    pub fn started(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// Starts shutdown for every [`ShutdownSignal`] made with it
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// A trigger and the signal it fires
+pub fn channel() -> (ShutdownTrigger, ShutdownSignal) {
+    let (tx, rx) = watch::channel(false);
+    (ShutdownTrigger(tx), ShutdownSignal(rx))
+}
+
+/// Fire `trigger` on the first SIGTERM or SIGINT
+///
+/// The SIGTERM handler is installed before this returns, so a signal that
+/// arrives before the future is first polled is not missed.
+pub fn trigger_on_terminate(trigger: ShutdownTrigger) -> impl Future<Output = ()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let terminate = signal(SignalKind::terminate());
+    async move {
+        let mut terminate = match terminate {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error = %e, "Failed to install SIGTERM handler, graceful shutdown disabled");
+                // Keep the trigger alive so the servers are not shut down
+                std::future::pending::<()>().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => info!("SIGTERM received, draining servers"),
+            _ = tokio::signal::ctrl_c() => info!("SIGINT received, draining servers"),
+        }
+        trigger.trigger();
+    }
+}
+
+/// Run `server` until it exits, giving it `timeout` to finish after
+/// `signal` fires
+///
+/// `server` must watch the same signal (tonic's `serve_with_shutdown`,
+/// axum's `with_graceful_shutdown`) to stop accepting work. Returns `None`
+/// if it was still draining when the timeout ran out.
+pub async fn serve_with_drain<F, T>(
+    server: F,
+    signal: ShutdownSignal,
+    timeout: Duration,
+) -> Option<T>
+where
+    F: Future<Output = T>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return Some(result),
+        _ = signal.wait() => {}
+    }
+    match tokio::time::timeout(timeout, server).await {
+        Ok(result) => {
+            info!("Server drained");
+            Some(result)
+        }
+        Err(_) => {
+            warn!(
+                timeout_secs = timeout.as_secs(),
+                "In-flight requests did not finish in time, dropping them"
+            );
+            None
+        }
+    }
+}
+
+/// Run the gRPC and health servers until the gRPC server has stopped
+///
+/// The gRPC server gets `timeout` to drain once `signal` fires. The health
+/// server stops almost at once on the same signal, so it only ends the wait
+/// early if it fails before shutdown has started.
+pub async fn serve_all<G, H, GE, HE>(grpc: G, health: H, signal: ShutdownSignal, timeout: Duration)
+where
+    G: Future<Output = Result<(), GE>>,
+    H: Future<Output = Result<(), HE>>,
+    GE: Display,
+    HE: Display,
+{
+    let grpc = serve_with_drain(grpc, signal.clone(), timeout);
+    tokio::pin!(grpc, health);
+    let mut health_done = false;
+    loop {
+        tokio::select! {
+            result = &mut grpc => {
+                if let Some(Err(e)) = result {
+                    warn!(error = %e, "gRPC server error");
+                }
+                return;
+            }
+            result = &mut health, if !health_done => {
+                health_done = true;
+                if let Err(e) = result {
+                    warn!(error = %e, "Health server error");
+                    if !signal.started() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_times_out_stuck_server() {
+        let (trigger, signal) = channel();
+        trigger.trigger();
+        let result = serve_with_drain(
+            std::future::pending::<()>(),
+            signal,
+            Duration::from_millis(50),
+        )
+        .await;
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn test_server_exiting_on_its_own() {
+        let (_trigger, signal) = channel();
+        let result = serve_with_drain(async { 7 }, signal, DRAIN_TIMEOUT).await;
+        assert_eq!(result, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_serve_all_waits_for_grpc_after_health_stops() {
+        let (trigger, signal) = channel();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        let grpc_signal = signal.clone();
+        let grpc = async move {
+            grpc_signal.wait().await;
+            // Still finishing an in-flight RPC
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let _ = done_tx.send(());
+            Ok::<(), String>(())
+        };
+        let health_signal = signal.clone();
+        let health = async move {
+            health_signal.wait().await;
+            Ok::<(), String>(())
+        };
+
+        trigger.trigger();
+        serve_all(grpc, health, signal, DRAIN_TIMEOUT).await;
+        assert!(
+            done_rx.await.is_ok(),
+            "gRPC server was dropped while draining"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_all_stops_when_health_fails_early() {
+        let (_trigger, signal) = channel();
+        let grpc = std::future::pending::<Result<(), String>>();
+        let health = async { Err::<(), String>("address in use".to_string()) };
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            serve_all(grpc, health, signal, DRAIN_TIMEOUT),
+        )
+        .await
+        .expect("a failed health server should stop the agent");
+    }
+}
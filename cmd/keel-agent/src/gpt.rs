@@ -0,0 +1,331 @@
+//! Minimal GPT attribute reader and writer
+//!
+//! Boot slots are switched by changing partition attribute bits, which
+//! normally goes through `sgdisk`. Images without it fall back to this
+//! module, which only knows enough GPT to do that: it finds the primary
+//! header (512 or 4096 byte sectors), checks both header and entry array
+//! CRC32s, changes one entry's attributes and writes the entry array and
+//! header back to both the primary and the backup copy.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// GPT header signature
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Logical sector sizes the primary header is looked for with
+const SECTOR_SIZES: [u64; 2] = [512, 4096];
+
+/// Smallest valid header size (UEFI 2.x)
+const MIN_HEADER_SIZE: usize = 92;
+
+/// Largest entry array read (the usual 128 entries of 128 bytes is 16 KiB)
+const MAX_ENTRIES_BYTES: usize = 1024 * 1024;
+
+/// Offset of the attributes in a partition entry
+const ENTRY_ATTRIBUTES: usize = 48;
+
+/// Header field offsets
+const HEADER_SIZE: usize = 12;
+const HEADER_CRC: usize = 16;
+const ALTERNATE_LBA: usize = 32;
+const ENTRIES_LBA: usize = 72;
+const NUM_ENTRIES: usize = 80;
+const ENTRY_SIZE: usize = 84;
+const ENTRIES_CRC: usize = 88;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A GPT header as stored on disk
+struct Header {
+    raw: Vec<u8>,
+    lba: u64,
+    sector_size: u64,
+}
+
+impl Header {
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.raw[offset..offset + 4].try_into().unwrap_or_default())
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.raw[offset..offset + 8].try_into().unwrap_or_default())
+    }
+
+    fn entries_len(&self) -> usize {
+        self.u32_at(NUM_ENTRIES) as usize * self.u32_at(ENTRY_SIZE) as usize
+    }
+
+    /// Record the CRC of a new entry array and re-seal the header
+    fn set_entries_crc(&mut self, crc: u32) {
+        self.raw[ENTRIES_CRC..ENTRIES_CRC + 4].copy_from_slice(&crc.to_le_bytes());
+        let crc = header_crc(&self.raw);
+        self.raw[HEADER_CRC..HEADER_CRC + 4].copy_from_slice(&crc.to_le_bytes());
+    }
+}
+
+/// CRC32 of a header, computed with its own CRC field zeroed
+fn header_crc(raw: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&raw[..HEADER_CRC]);
+    hasher.update(&[0; 4]);
+    hasher.update(&raw[HEADER_CRC + 4..]);
+    hasher.finalize()
+}
+
+/// Read and check the header at `lba`
+fn read_header<D: Read + Seek>(disk: &mut D, lba: u64, sector_size: u64) -> io::Result<Header> {
+    let mut sector = vec![0; sector_size as usize];
+    disk.seek(SeekFrom::Start(lba * sector_size))?;
+    disk.read_exact(&mut sector)?;
+    if &sector[..8] != SIGNATURE {
+        return Err(invalid(format!("No GPT header at LBA {}", lba)));
+    }
+
+    let size = u32::from_le_bytes(
+        sector[HEADER_SIZE..HEADER_SIZE + 4]
+            .try_into()
+            .unwrap_or_default(),
+    ) as usize;
+    if !(MIN_HEADER_SIZE..=sector.len()).contains(&size) {
+        return Err(invalid(format!(
+            "Bad GPT header size {} at LBA {}",
+            size, lba
+        )));
+    }
+    sector.truncate(size);
+    let header = Header {
+        raw: sector,
+        lba,
+        sector_size,
+    };
+    if header_crc(&header.raw) != header.u32_at(HEADER_CRC) {
+        return Err(invalid(format!("GPT header CRC mismatch at LBA {}", lba)));
+    }
+    if header.entries_len() > MAX_ENTRIES_BYTES {
+        return Err(invalid(format!(
+            "GPT entry array of {} bytes is too large",
+            header.entries_len()
+        )));
+    }
+    Ok(header)
+}
+
+/// The primary header, trying each supported sector size
+fn read_primary<D: Read + Seek>(disk: &mut D) -> io::Result<Header> {
+    let mut last_error = invalid("No GPT found".to_string());
+    for sector_size in SECTOR_SIZES {
+        match read_header(disk, 1, sector_size) {
+            Ok(header) => return Ok(header),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Read the entry array `header` points at and check its CRC
+fn read_entries<D: Read + Seek>(disk: &mut D, header: &Header) -> io::Result<Vec<u8>> {
+    let mut entries = vec![0; header.entries_len()];
+    disk.seek(SeekFrom::Start(
+        header.u64_at(ENTRIES_LBA) * header.sector_size,
+    ))?;
+    disk.read_exact(&mut entries)?;
+    if crc32fast::hash(&entries) != header.u32_at(ENTRIES_CRC) {
+        return Err(invalid(format!(
+            "GPT partition entry CRC mismatch (header at LBA {})",
+            header.lba
+        )));
+    }
+    Ok(entries)
+}
+
+/// Byte offset of partition `index` (1-based) in the entry array
+fn entry_offset(header: &Header, entries: &[u8], index: u32) -> io::Result<usize> {
+    let entry_size = header.u32_at(ENTRY_SIZE) as usize;
+    let missing = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Partition #{} does not exist", index),
+        )
+    };
+    if index == 0 || index > header.u32_at(NUM_ENTRIES) || entry_size < ENTRY_ATTRIBUTES + 8 {
+        return Err(missing());
+    }
+    let offset = (index as usize - 1) * entry_size;
+    // An all-zero type GUID marks an unused entry
+    if entries[offset..offset + 16].iter().all(|&b| b == 0) {
+        return Err(missing());
+    }
+    Ok(offset)
+}
+
+/// Write `header` and `entries` back where `header` says they belong
+fn write_copy<D: Write + Seek>(disk: &mut D, header: &Header, entries: &[u8]) -> io::Result<()> {
+    disk.seek(SeekFrom::Start(
+        header.u64_at(ENTRIES_LBA) * header.sector_size,
+    ))?;
+    disk.write_all(entries)?;
+    disk.seek(SeekFrom::Start(header.lba * header.sector_size))?;
+    disk.write_all(&header.raw)
+}
+
+/// Attribute bits of partition `index`, from the primary GPT
+pub fn read_attributes<D: Read + Seek>(disk: &mut D, index: u32) -> io::Result<u64> {
+    let header = read_primary(disk)?;
+    let entries = read_entries(disk, &header)?;
+    let offset = entry_offset(&header, &entries, index)? + ENTRY_ATTRIBUTES;
+    Ok(u64::from_le_bytes(
+        entries[offset..offset + 8].try_into().unwrap_or_default(),
+    ))
+}
+
+/// Replace the attributes of partition `index` with `change(current)`,
+/// returning the new value
+///
+/// Both headers are checked before anything is written. The primary entry
+/// array is copied over the backup one, so both copies end up identical.
+pub fn update_attributes<D: Read + Write + Seek>(
+    disk: &mut D,
+    index: u32,
+    change: impl FnOnce(u64) -> u64,
+) -> io::Result<u64> {
+    let mut primary = read_primary(disk)?;
+    let mut entries = read_entries(disk, &primary)?;
+    let mut backup = read_header(disk, primary.u64_at(ALTERNATE_LBA), primary.sector_size)?;
+    if backup.entries_len() != entries.len() {
+        return Err(invalid(
+            "GPT backup header describes a different entry array".to_string(),
+        ));
+    }
+
+    let offset = entry_offset(&primary, &entries, index)? + ENTRY_ATTRIBUTES;
+    let current = u64::from_le_bytes(entries[offset..offset + 8].try_into().unwrap_or_default());
+    let attributes = change(current);
+    entries[offset..offset + 8].copy_from_slice(&attributes.to_le_bytes());
+
+    let crc = crc32fast::hash(&entries);
+    primary.set_entries_crc(crc);
+    backup.set_entries_crc(crc);
+    write_copy(disk, &primary, &entries)?;
+    write_copy(disk, &backup, &entries)?;
+    disk.flush()?;
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SECTOR: usize = 512;
+    const SECTORS: usize = 64;
+    const ENTRIES: usize = 4;
+
+    fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+        buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// 32 KiB disk with four entry slots: partitions 1-3 in use, 4 empty
+    fn synthetic_disk() -> Vec<u8> {
+        let mut disk = vec![0; SECTOR * SECTORS];
+        let mut entries = vec![0; ENTRIES * 128];
+        for (i, attributes) in [(0usize, 0u64), (1, 0x4), (2, 1 << 60)] {
+            let entry = &mut entries[i * 128..(i + 1) * 128];
+            put(entry, 0, &[0xaf; 16]);
+            put(entry, 16, &[i as u8 + 1; 16]);
+            put(entry, 32, &(34 + i as u64 * 8).to_le_bytes());
+            put(entry, 40, &(41 + i as u64 * 8).to_le_bytes());
+            put(entry, 48, &attributes.to_le_bytes());
+        }
+        let entries_crc = crc32fast::hash(&entries);
+
+        for (lba, alternate, entries_lba) in [(1u64, 63u64, 2u64), (63, 1, 62)] {
+            let mut header = vec![0; MIN_HEADER_SIZE];
+            put(&mut header, 0, SIGNATURE);
+            put(&mut header, 8, &0x0001_0000u32.to_le_bytes());
+            put(
+                &mut header,
+                HEADER_SIZE,
+                &(MIN_HEADER_SIZE as u32).to_le_bytes(),
+            );
+            put(&mut header, 24, &lba.to_le_bytes());
+            put(&mut header, ALTERNATE_LBA, &alternate.to_le_bytes());
+            put(&mut header, ENTRIES_LBA, &entries_lba.to_le_bytes());
+            put(&mut header, NUM_ENTRIES, &(ENTRIES as u32).to_le_bytes());
+            put(&mut header, ENTRY_SIZE, &128u32.to_le_bytes());
+            put(&mut header, ENTRIES_CRC, &entries_crc.to_le_bytes());
+            let crc = header_crc(&header);
+            put(&mut header, HEADER_CRC, &crc.to_le_bytes());
+
+            put(&mut disk, lba as usize * SECTOR, &header);
+            put(&mut disk, entries_lba as usize * SECTOR, &entries);
+        }
+        disk
+    }
+
+    #[test]
+    fn test_crc32_matches_gpt_polynomial() {
+        // Check value of the IEEE 802.3 CRC32 that GPT uses
+        assert_eq!(crc32fast::hash(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_round_trip_attribute_change() {
+        let mut disk = Cursor::new(synthetic_disk());
+        assert_eq!(read_attributes(&mut disk, 1).unwrap(), 0);
+        assert_eq!(read_attributes(&mut disk, 2).unwrap(), 0x4);
+
+        let new = update_attributes(&mut disk, 1, |a| a | 0x4).unwrap();
+        assert_eq!(new, 0x4);
+        let new = update_attributes(&mut disk, 2, |a| a & !0x4).unwrap();
+        assert_eq!(new, 0);
+
+        // Headers and entry arrays re-parse, so every CRC was recomputed
+        let primary = read_primary(&mut disk).unwrap();
+        let backup = read_header(&mut disk, 63, 512).unwrap();
+        let primary_entries = read_entries(&mut disk, &primary).unwrap();
+        let backup_entries = read_entries(&mut disk, &backup).unwrap();
+        assert_eq!(primary_entries, backup_entries);
+
+        assert_eq!(read_attributes(&mut disk, 1).unwrap(), 0x4);
+        assert_eq!(read_attributes(&mut disk, 2).unwrap(), 0);
+        assert_eq!(read_attributes(&mut disk, 3).unwrap(), 1 << 60);
+
+        // Only the attribute bytes of the two entries changed
+        let before = synthetic_disk();
+        let after = disk.into_inner();
+        let changed: Vec<usize> = (0..before.len())
+            .filter(|&i| before[i] != after[i])
+            .collect();
+        assert!(changed.iter().all(|&i| {
+            let in_sector = |lba: usize| (lba * SECTOR..(lba + 1) * SECTOR).contains(&i);
+            in_sector(1) || in_sector(2) || in_sector(62) || in_sector(63)
+        }));
+    }
+
+    #[test]
+    fn test_missing_partitions_and_corruption() {
+        let mut disk = Cursor::new(synthetic_disk());
+        for index in [0, 4, 5] {
+            let err = read_attributes(&mut disk, index).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound, "{}", index);
+        }
+
+        // A damaged entry array is refused rather than rewritten
+        let mut damaged = synthetic_disk();
+        damaged[2 * SECTOR + ENTRY_ATTRIBUTES] ^= 0xff;
+        let mut disk = Cursor::new(damaged.clone());
+        let err = update_attributes(&mut disk, 1, |a| a | 0x4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(disk.into_inner(), damaged);
+
+        // So is a disk whose backup header is gone
+        let mut damaged = synthetic_disk();
+        damaged[63 * SECTOR] = 0;
+        let mut disk = Cursor::new(damaged);
+        assert!(update_attributes(&mut disk, 1, |a| a | 0x4).is_err());
+
+        assert!(read_attributes(&mut Cursor::new(vec![0; SECTOR * 16]), 1).is_err());
+    }
+}
@@ -4,11 +4,24 @@
 //! - /healthz - Liveness check
 //! - /readyz - Readiness check
 //! - /metrics - Prometheus metrics
+//! - /status - Read-only node status (no mTLS required)
+//!
+//! Requests are cut off after a timeout and only a limited number are
+//! served at once (see [`HealthLimits`]), so a scrape storm or slow client
+//! cannot tie up a node that is already under pressure.
 
-use axum::{extract::State, response::IntoResponse, routing::get, Json, Router};
+use axum::{
+    error_handling::HandleErrorLayer, extract::State, http::StatusCode, response::IntoResponse,
+    routing::get, BoxError, Json, Router,
+};
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::ServiceBuilder;
+use tracing::warn;
 
 use crate::telemetry::SystemMetrics;
 
@@ -31,9 +44,141 @@ pub struct ReadinessChecks {
     pub filesystem: String,
 }
 
+/// Read-only node status served over plain HTTP
+///
+/// Mirrors the non-sensitive fields of `GetStatusResponse`. Anything that
+/// needs mTLS to read (certificates, bootstrap state, configuration) stays
+/// off this endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub hostname: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub uptime_seconds: f64,
+    pub active_partition: Option<ActivePartition>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActivePartition {
+    pub device: String,
+    pub index: u32,
+}
+
+/// A single value served by `/metrics`
+pub struct MetricCollector {
+    pub name: &'static str,
+    pub collect: fn(&SystemMetrics) -> Result<serde_json::Value, String>,
+}
+
+/// The system metrics served by default
+pub fn default_collectors() -> Vec<MetricCollector> {
+    vec![
+        MetricCollector {
+            name: "cpu_usage",
+            collect: |m| Ok(m.cpu_usage().into()),
+        },
+        MetricCollector {
+            name: "total_memory_bytes",
+            collect: |m| Ok(m.total_memory().into()),
+        },
+        MetricCollector {
+            name: "used_memory_bytes",
+            collect: |m| Ok(m.used_memory().into()),
+        },
+        MetricCollector {
+            name: "total_swap_bytes",
+            collect: |m| Ok(m.total_swap().into()),
+        },
+        MetricCollector {
+            name: "used_swap_bytes",
+            collect: |m| Ok(m.used_swap().into()),
+        },
+    ]
+}
+
+/// Seconds a health request may take, including time spent waiting for a
+/// free slot
+pub const HEALTH_REQUEST_TIMEOUT_ENV: &str = "KEEL_HEALTH_REQUEST_TIMEOUT_SECS";
+
+/// Requests the health server handles at once; the rest wait
+pub const HEALTH_MAX_CONCURRENT_ENV: &str = "KEEL_HEALTH_MAX_CONCURRENT_REQUESTS";
+
+/// Default for [`HEALTH_REQUEST_TIMEOUT_ENV`]
+pub const DEFAULT_HEALTH_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default for [`HEALTH_MAX_CONCURRENT_ENV`]
+pub const DEFAULT_HEALTH_MAX_CONCURRENT: usize = 32;
+
+/// Resource limits of the health HTTP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthLimits {
+    pub request_timeout: Duration,
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for HealthLimits {
+    fn default() -> Self {
+        Self {
+            request_timeout: DEFAULT_HEALTH_REQUEST_TIMEOUT,
+            max_concurrent_requests: DEFAULT_HEALTH_MAX_CONCURRENT,
+        }
+    }
+}
+
+impl HealthLimits {
+    /// Limits from the process environment
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Limits from `lookup`, which returns a variable's value if set
+    ///
+    /// Unset, zero, or unparsable values keep the default.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let positive = |name: &str| {
+            let value = lookup(name)?;
+            match value.trim().parse::<u64>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    warn!(variable = name, value = %value, "Ignoring invalid health server limit");
+                    None
+                }
+            }
+        };
+        let defaults = Self::default();
+        Self {
+            request_timeout: positive(HEALTH_REQUEST_TIMEOUT_ENV)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.request_timeout),
+            max_concurrent_requests: positive(HEALTH_MAX_CONCURRENT_ENV)
+                .map(|n| n as usize)
+                .unwrap_or(defaults.max_concurrent_requests),
+        }
+    }
+}
+
 /// Shared state for health endpoints
 pub struct HealthState {
     pub metrics: Arc<RwLock<SystemMetrics>>,
+    pub collectors: Vec<MetricCollector>,
+    /// Failed collections since startup
+    pub collection_errors: AtomicU64,
+    /// Kernel command line used to detect the active partition
+    pub cmdline_path: String,
+    pub limits: HealthLimits,
+}
+
+impl HealthState {
+    /// State serving the [`default_collectors`]
+    pub fn new(metrics: Arc<RwLock<SystemMetrics>>) -> Self {
+        Self {
+            metrics,
+            collectors: default_collectors(),
+            collection_errors: AtomicU64::new(0),
+            cmdline_path: crate::disk::PROC_CMDLINE.to_string(),
+            limits: HealthLimits::default(),
+        }
+    }
 }
 
 /// Liveness check handler
@@ -65,45 +210,121 @@ async fn readyz() -> impl IntoResponse {
     })
 }
 
+/// Status endpoint handler
+///
+/// Returns the read-only node status in JSON format
+async fn status(State(state): State<Arc<HealthState>>) -> impl IntoResponse {
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "unknown".to_string());
+    let kernel_version = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|v| v.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    let uptime_seconds = std::fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|u| u.split_whitespace().next()?.parse().ok())
+        .unwrap_or(0.0);
+    let active_partition = crate::disk::get_active_partition_from(&state.cmdline_path)
+        .ok()
+        .map(|p| ActivePartition {
+            device: p.device,
+            index: p.index,
+        });
+
+    Json(StatusResponse {
+        hostname,
+        os_version: crate::image_metadata::running_os_version().unwrap_or_default(),
+        kernel_version,
+        uptime_seconds,
+        active_partition,
+    })
+}
+
 /// Metrics endpoint handler
 ///
-/// Returns system metrics in JSON format
+/// Returns system metrics in JSON format. A collector that returns an error
+/// is left out and counted in `keel_metrics_collection_errors_total`, so
+/// one unreadable source does not break the whole scrape.
 async fn metrics(State(state): State<Arc<HealthState>>) -> impl IntoResponse {
     let mut metrics = state.metrics.write().await;
+    let mut errors = 0;
+
     metrics.update();
 
-    // Return basic system info as JSON
-    let response = serde_json::json!({
-        "cpu_usage": metrics.cpu_usage(),
-        "total_memory_bytes": metrics.total_memory(),
-        "used_memory_bytes": metrics.used_memory(),
-        "total_swap_bytes": metrics.total_swap(),
-        "used_swap_bytes": metrics.used_swap(),
-    });
+    let mut response = serde_json::Map::new();
+    for collector in &state.collectors {
+        match (collector.collect)(&metrics) {
+            Ok(value) => {
+                response.insert(collector.name.to_string(), value);
+            }
+            Err(e) => {
+                warn!(metric = collector.name, error = %e, "Metric collection failed");
+                errors += 1;
+            }
+        }
+    }
+
+    let total = state.collection_errors.fetch_add(errors, Ordering::Relaxed) + errors;
+    response.insert(
+        "keel_metrics_collection_errors_total".to_string(),
+        total.into(),
+    );
 
-    Json(response).into_response()
+    Json(serde_json::Value::Object(response)).into_response()
+}
+
+/// Response for a request the middleware gave up on
+async fn limit_error(error: BoxError) -> (StatusCode, String) {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        (StatusCode::REQUEST_TIMEOUT, "request timed out".to_string())
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("request failed: {}", error),
+        )
+    }
+}
+
+/// Wrap `router` in the request timeout and concurrency limit
+///
+/// The timeout starts once a request holds a slot, so a queued request
+/// waits for a slot and then gets the full timeout. The stack wraps the whole router rather than
+/// each route, so every endpoint draws from the same concurrency budget.
+fn with_limits(router: Router, limits: HealthLimits) -> Router {
+    Router::new().fallback_service(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(limit_error))
+            .timeout(limits.request_timeout)
+            .layer(GlobalConcurrencyLimitLayer::new(
+                limits.max_concurrent_requests,
+            ))
+            .service(router),
+    )
 }
 
 /// Create health check router
 pub fn create_health_router(state: Arc<HealthState>) -> Router {
-    Router::new()
+    let limits = state.limits;
+    let router = Router::new()
         .route("/healthz", get(healthz))
         .route("/readyz", get(readyz))
         .route("/metrics", get(metrics))
-        .with_state(state)
+        .route("/status", get(status))
+        .with_state(state);
+    with_limits(router, limits)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use axum::body::Body;
-    use axum::http::{Request, StatusCode};
+    use axum::http::Request;
     use tower::ServiceExt;
 
     #[tokio::test]
     async fn test_healthz() {
         let metrics = Arc::new(RwLock::new(SystemMetrics::default()));
-        let state = Arc::new(HealthState { metrics });
+        let state = Arc::new(HealthState::new(metrics));
         let app = create_health_router(state);
 
         let response = app
@@ -122,7 +343,7 @@ mod tests {
     #[tokio::test]
     async fn test_readyz() {
         let metrics = Arc::new(RwLock::new(SystemMetrics::default()));
-        let state = Arc::new(HealthState { metrics });
+        let state = Arc::new(HealthState::new(metrics));
         let app = create_health_router(state);
 
         let response = app
@@ -137,4 +358,181 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_metrics_survive_failing_collectors() {
+        let metrics = Arc::new(RwLock::new(SystemMetrics::default()));
+        let mut state = HealthState::new(metrics);
+        state.collectors.push(MetricCollector {
+            name: "broken",
+            collect: |_| Err("/proc unreadable".to_string()),
+        });
+        state.collectors.push(MetricCollector {
+            name: "unsupported",
+            collect: |_| Err("not available on this kernel".to_string()),
+        });
+        let app = create_health_router(Arc::new(state));
+
+        for expected_errors in [2, 4] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/metrics")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(json.get("total_memory_bytes").is_some());
+            assert!(json.get("broken").is_none());
+            assert!(json.get("unsupported").is_none());
+            assert_eq!(
+                json["keel_metrics_collection_errors_total"],
+                expected_errors
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_exposes_only_public_fields() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cmdline = dir.path().join("cmdline");
+        std::fs::write(&cmdline, "root=/dev/sda3 ro").unwrap();
+
+        let metrics = Arc::new(RwLock::new(SystemMetrics::default()));
+        let mut state = HealthState::new(metrics);
+        state.cmdline_path = cmdline.to_string_lossy().into_owned();
+        let app = create_health_router(Arc::new(state));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/status")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "active_partition",
+                "hostname",
+                "kernel_version",
+                "os_version",
+                "uptime_seconds"
+            ]
+        );
+        assert_eq!(
+            json["active_partition"],
+            serde_json::json!({ "device": "/dev/sda3", "index": 3 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_spans_routes() {
+        let limits = HealthLimits {
+            request_timeout: Duration::from_millis(200),
+            max_concurrent_requests: 1,
+        };
+        let entered = Arc::new(tokio::sync::Notify::new());
+        let slow_entered = entered.clone();
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    slow_entered.notify_one();
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    "done"
+                }),
+            )
+            .route("/fast", get(|| async { "fast" }));
+        let app = with_limits(router, limits);
+
+        let slow = tokio::spawn(
+            app.clone()
+                .oneshot(Request::builder().uri("/slow").body(Body::empty()).unwrap()),
+        );
+        entered.notified().await;
+
+        // The only slot is held by /slow, so /fast queues behind it
+        let mut fast =
+            tokio::spawn(app.oneshot(Request::builder().uri("/fast").body(Body::empty()).unwrap()));
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut fast)
+            .await
+            .is_err());
+
+        // Once /slow is cut off the slot is free again
+        assert_eq!(
+            slow.await.unwrap().unwrap().status(),
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_eq!(fast.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_is_cut_off_at_timeout() {
+        let limits = HealthLimits {
+            request_timeout: Duration::from_millis(100),
+            max_concurrent_requests: 1,
+        };
+        let router = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                "done"
+            }),
+        );
+        let app = with_limits(router, limits);
+
+        let started = std::time::Instant::now();
+        let response = app
+            .oneshot(Request::builder().uri("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_limits_from_env() {
+        assert_eq!(HealthLimits::from_lookup(|_| None), HealthLimits::default());
+
+        let limits = HealthLimits::from_lookup(|name| match name {
+            HEALTH_REQUEST_TIMEOUT_ENV => Some("3".to_string()),
+            HEALTH_MAX_CONCURRENT_ENV => Some("4".to_string()),
+            _ => None,
+        });
+        assert_eq!(limits.request_timeout, Duration::from_secs(3));
+        assert_eq!(limits.max_concurrent_requests, 4);
+
+        // Zero would block every request, so it keeps the default
+        let limits = HealthLimits::from_lookup(|name| match name {
+            HEALTH_REQUEST_TIMEOUT_ENV => Some("soon".to_string()),
+            HEALTH_MAX_CONCURRENT_ENV => Some("0".to_string()),
+            _ => None,
+        });
+        assert_eq!(limits, HealthLimits::default());
+    }
 }
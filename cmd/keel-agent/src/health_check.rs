@@ -5,6 +5,7 @@
 //! - Configurable timeout and retry logic
 //! - Multiple check types (boot, service, network, API)
 //! - Detailed result tracking
+//! - Declarative check selection from the node configuration
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -64,6 +65,46 @@ pub struct CheckExecution {
     pub name: String,
     pub result: HealthCheckResult,
     pub duration_ms: u64,
+    /// Whether a failure of this check makes the node unhealthy
+    #[serde(default)]
+    pub critical: bool,
+}
+
+/// One-line explanation of the overall status
+///
+/// Lists failing critical checks first, then failing non-critical checks
+/// (which only degrade the node), each with its failure message.
+pub fn health_summary(executions: &[CheckExecution]) -> String {
+    let mut failing: Vec<&CheckExecution> = executions
+        .iter()
+        .filter(|e| matches!(e.result, HealthCheckResult::Fail(_)))
+        .collect();
+    failing.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let describe = |critical: bool| {
+        failing
+            .iter()
+            .filter(|e| e.critical == critical)
+            .map(|e| format!("{} ({})", e.name, e.result.message()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut parts = Vec::new();
+    let critical = describe(true);
+    if !critical.is_empty() {
+        parts.push(format!("critical checks failing: {}", critical));
+    }
+    let degraded = describe(false);
+    if !degraded.is_empty() {
+        parts.push(format!("degraded: {}", degraded));
+    }
+
+    if parts.is_empty() {
+        "all checks passing".to_string()
+    } else {
+        parts.join("; ")
+    }
 }
 
 /// Trait for implementing health checks
@@ -82,8 +123,28 @@ pub trait HealthCheck: Send + Sync {
     }
 }
 
+/// Default uptime below which the boot check fails
+pub const DEFAULT_MIN_UPTIME_SECS: u64 = 10;
+
+/// Default port of the API check
+pub const DEFAULT_API_PORT: u16 = 50051;
+
 /// Boot verification check
-pub struct BootCheck;
+pub struct BootCheck {
+    min_uptime_secs: u64,
+}
+
+impl BootCheck {
+    pub fn new(min_uptime_secs: u64) -> Self {
+        Self { min_uptime_secs }
+    }
+}
+
+impl Default for BootCheck {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_UPTIME_SECS)
+    }
+}
 
 #[async_trait]
 impl HealthCheck for BootCheck {
@@ -94,7 +155,7 @@ impl HealthCheck for BootCheck {
                 let parts: Vec<&str> = uptime.split_whitespace().collect();
                 if let Some(uptime_str) = parts.first() {
                     if let Ok(uptime_secs) = uptime_str.parse::<f64>() {
-                        if uptime_secs > 10.0 {
+                        if uptime_secs > self.min_uptime_secs as f64 {
                             info!(uptime_secs, "Boot check passed");
                             return HealthCheckResult::Pass;
                         }
@@ -112,13 +173,11 @@ impl HealthCheck for BootCheck {
 }
 
 /// Service status check
-#[allow(dead_code)]
 pub struct ServiceCheck {
     service_name: String,
 }
 
 impl ServiceCheck {
-    #[allow(dead_code)]
     pub fn new(service_name: impl Into<String>) -> Self {
         Self {
             service_name: service_name.into(),
@@ -158,7 +217,17 @@ impl HealthCheck for ServiceCheck {
 }
 
 /// Network connectivity check
-pub struct NetworkCheck;
+#[derive(Default)]
+pub struct NetworkCheck {
+    critical: bool,
+}
+
+impl NetworkCheck {
+    /// `critical` makes a failure trigger rollback instead of degrading
+    pub fn new(critical: bool) -> Self {
+        Self { critical }
+    }
+}
 
 #[async_trait]
 impl HealthCheck for NetworkCheck {
@@ -194,7 +263,7 @@ impl HealthCheck for NetworkCheck {
     }
 
     fn is_critical(&self) -> bool {
-        false // Network failure is degraded, not critical
+        self.critical // Network failure is degraded unless configured otherwise
     }
 }
 
@@ -231,6 +300,155 @@ impl HealthCheck for ApiCheck {
     }
 }
 
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_OFFSET_SECS: f64 = 2_208_988_800.0;
+
+/// How long to wait for an SNTP reply
+const SNTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default largest tolerated clock offset
+pub const DEFAULT_MAX_CLOCK_SKEW_SECS: u64 = 30;
+
+/// Clock skew check against an NTP/SNTP server
+///
+/// Certificate validation and Kubernetes authentication fail under large
+/// skew. An unreachable server yields `Unknown` rather than a failure.
+pub struct ClockSkewCheck {
+    server: String,
+    max_skew_secs: u64,
+}
+
+impl ClockSkewCheck {
+    pub fn new(server: impl Into<String>, max_skew_secs: u64) -> Self {
+        Self {
+            server: server.into(),
+            max_skew_secs,
+        }
+    }
+
+    /// Query the server once and return the local clock's offset in seconds
+    async fn query_offset(&self) -> std::io::Result<f64> {
+        let addr = if self.server.matches(':').count() == 1 || self.server.starts_with('[') {
+            self.server.clone()
+        } else {
+            format!("{}:123", self.server)
+        };
+
+        let server = tokio::net::lookup_host(&addr)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} did not resolve to an address", addr),
+                )
+            })?;
+        // Bind in the server's address family, so IPv6-only servers work
+        let local = if server.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = tokio::net::UdpSocket::bind(local).await?;
+        socket.connect(server).await?;
+
+        // LI = 0, version 3, mode 3 (client)
+        let mut packet = [0u8; 48];
+        packet[0] = 0x1B;
+        let t0 = unix_now();
+        socket.send(&packet).await?;
+
+        let len = tokio::time::timeout(SNTP_TIMEOUT, socket.recv(&mut packet))
+            .await
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "no reply from NTP server")
+            })??;
+        let t3 = unix_now();
+        if len < packet.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("short NTP reply ({} bytes)", len),
+            ));
+        }
+
+        match (
+            ntp_timestamp(&packet[32..40]),
+            ntp_timestamp(&packet[40..48]),
+        ) {
+            (Some(t1), Some(t2)) => Ok(clock_offset(t0, t1, t2, t3)),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "NTP reply without server timestamps",
+            )),
+        }
+    }
+}
+
+/// Current time as fractional seconds since the Unix epoch
+fn unix_now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Convert an 8-byte NTP timestamp to seconds since the Unix epoch;
+/// `None` if `bytes` is shorter than that
+pub fn ntp_timestamp(bytes: &[u8]) -> Option<f64> {
+    let secs = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?);
+    let frac = u32::from_be_bytes(bytes.get(4..8)?.try_into().ok()?);
+    Some(f64::from(secs) + f64::from(frac) / 4_294_967_296.0 - NTP_UNIX_OFFSET_SECS)
+}
+
+/// NTP clock offset: how far the server is ahead of the local clock
+///
+/// `t0`/`t3` are the local send/receive times, `t1`/`t2` the server's
+/// receive/transmit times; averaging cancels out symmetric network delay.
+pub fn clock_offset(t0: f64, t1: f64, t2: f64, t3: f64) -> f64 {
+    ((t1 - t0) + (t2 - t3)) / 2.0
+}
+
+/// Judge a measured offset against the tolerated skew
+pub fn evaluate_clock_skew(offset_secs: f64, max_skew_secs: u64) -> HealthCheckResult {
+    if offset_secs.abs() > max_skew_secs as f64 {
+        HealthCheckResult::Fail(format!(
+            "Clock skew {:.1}s exceeds {}s",
+            offset_secs, max_skew_secs
+        ))
+    } else {
+        HealthCheckResult::Pass
+    }
+}
+
+#[async_trait]
+impl HealthCheck for ClockSkewCheck {
+    async fn check(&self) -> HealthCheckResult {
+        match self.query_offset().await {
+            Ok(offset) => {
+                let result = evaluate_clock_skew(offset, self.max_skew_secs);
+                if result.is_passing() {
+                    debug!(server = %self.server, offset_secs = offset, "Clock skew check passed");
+                } else {
+                    warn!(server = %self.server, offset_secs = offset, "Clock skew too large");
+                }
+                result
+            }
+            Err(e) => {
+                warn!(server = %self.server, error = %e, "NTP server unreachable");
+                HealthCheckResult::Unknown(format!("NTP server {} unreachable: {}", self.server, e))
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        "clock_skew".to_string()
+    }
+
+    fn is_critical(&self) -> bool {
+        false // Skew degrades the node, it does not warrant a rollback
+    }
+}
+
 /// Health checker configuration
 #[derive(Debug, Clone)]
 pub struct HealthCheckerConfig {
@@ -252,6 +470,72 @@ impl Default for HealthCheckerConfig {
     }
 }
 
+impl HealthCheckerConfig {
+    /// Defaults overridden by the thresholds set in `health_checks`
+    pub fn from_node_config(checks: &keel_config::HealthChecksConfig) -> Self {
+        let defaults = Self::default();
+        Self {
+            timeout_secs: checks.timeout_secs.unwrap_or(defaults.timeout_secs),
+            retry_interval_secs: checks
+                .retry_interval_secs
+                .unwrap_or(defaults.retry_interval_secs),
+            max_retries: checks.max_retries.unwrap_or(defaults.max_retries),
+        }
+    }
+}
+
+/// Checks declared in a `health_checks` section
+pub fn checks_from_node_config(
+    checks: &keel_config::HealthChecksConfig,
+) -> Vec<Box<dyn HealthCheck>> {
+    let mut registered: Vec<Box<dyn HealthCheck>> = Vec::new();
+    if let Some(boot) = &checks.boot {
+        registered.push(Box::new(BootCheck::new(
+            boot.min_uptime_secs.unwrap_or(DEFAULT_MIN_UPTIME_SECS),
+        )));
+    }
+    if let Some(network) = &checks.network {
+        registered.push(Box::new(NetworkCheck::new(network.critical)));
+    }
+    if let Some(api) = &checks.api {
+        registered.push(Box::new(ApiCheck::new(
+            api.port.unwrap_or(DEFAULT_API_PORT),
+        )));
+    }
+    for service in &checks.services {
+        registered.push(Box::new(ServiceCheck::new(service.clone())));
+    }
+    registered
+}
+
+/// Run one check and time it
+async fn execute_check(name: &str, check: &dyn HealthCheck) -> CheckExecution {
+    let start = Instant::now();
+    let result = check.check().await;
+    CheckExecution {
+        name: name.to_string(),
+        result,
+        duration_ms: start.elapsed().as_millis() as u64,
+        critical: check.is_critical(),
+    }
+}
+
+/// Overall status: unhealthy on any critical failure, degraded on any other
+pub fn overall_status(executions: &[CheckExecution]) -> HealthStatus {
+    let failed = |critical: bool| {
+        executions
+            .iter()
+            .any(|e| e.critical == critical && matches!(e.result, HealthCheckResult::Fail(_)))
+    };
+    if failed(true) {
+        HealthStatus::Unhealthy
+    } else if failed(false) {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Healthy
+    }
+}
+
 /// Health checker orchestrator
 pub struct HealthChecker {
     checks: Arc<RwLock<HashMap<String, Box<dyn HealthCheck>>>>,
@@ -263,12 +547,22 @@ pub struct HealthChecker {
 impl HealthChecker {
     /// Create a new health checker with default checks
     pub fn new(config: HealthCheckerConfig) -> Self {
-        let mut checks: HashMap<String, Box<dyn HealthCheck>> = HashMap::new();
+        Self::with_checks(
+            config,
+            vec![
+                Box::new(BootCheck::default()),
+                Box::new(NetworkCheck::default()),
+                Box::new(ApiCheck::new(DEFAULT_API_PORT)),
+            ],
+        )
+    }
 
-        // Register default checks
-        checks.insert("boot".to_string(), Box::new(BootCheck));
-        checks.insert("network".to_string(), Box::new(NetworkCheck));
-        checks.insert("api".to_string(), Box::new(ApiCheck::new(50051)));
+    /// Create a health checker running exactly `checks`
+    pub fn with_checks(config: HealthCheckerConfig, checks: Vec<Box<dyn HealthCheck>>) -> Self {
+        let checks = checks
+            .into_iter()
+            .map(|check| (check.name(), check))
+            .collect();
 
         Self {
             checks: Arc::new(RwLock::new(checks)),
@@ -277,6 +571,26 @@ impl HealthChecker {
         }
     }
 
+    /// Create the health checker described by the node configuration,
+    /// falling back to the default checks when it has no `health_checks`
+    pub fn from_node_config(checks: Option<&keel_config::HealthChecksConfig>) -> Self {
+        match checks {
+            Some(checks) => Self::with_checks(
+                HealthCheckerConfig::from_node_config(checks),
+                checks_from_node_config(checks),
+            ),
+            None => Self::new(HealthCheckerConfig::default()),
+        }
+    }
+
+    /// Names of the registered checks, sorted
+    pub async fn check_names(&self) -> Vec<String> {
+        let checks = self.checks.read().await;
+        let mut names: Vec<String> = checks.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
     /// Register a custom health check
     #[allow(dead_code)]
     pub async fn register_check(&self, check: Box<dyn HealthCheck>) {
@@ -296,39 +610,24 @@ impl HealthChecker {
         info!(count = checks.len(), "Running health checks");
 
         for (name, check) in checks.iter() {
-            let start = Instant::now();
-            let result = check.check().await;
-            let duration_ms = start.elapsed().as_millis() as u64;
-
-            let execution = CheckExecution {
-                name: name.clone(),
-                result: result.clone(),
-                duration_ms,
-            };
+            let execution = execute_check(name, check.as_ref()).await;
 
-            executions.push(execution);
-
-            if let HealthCheckResult::Fail(_) = result {
-                if check.is_critical() {
+            if let HealthCheckResult::Fail(_) = execution.result {
+                if execution.critical {
                     critical_failures += 1;
                 } else {
                     non_critical_failures += 1;
                 }
             }
+
+            executions.push(execution);
         }
 
         // Update last results
         let mut last_results = self.last_results.write().await;
         *last_results = executions.clone();
 
-        // Determine overall status
-        let status = if critical_failures > 0 {
-            HealthStatus::Unhealthy
-        } else if non_critical_failures > 0 {
-            HealthStatus::Degraded
-        } else {
-            HealthStatus::Healthy
-        };
+        let status = overall_status(&executions);
 
         info!(
             status = %status.to_string(),
@@ -340,6 +639,16 @@ impl HealthChecker {
         (status, executions)
     }
 
+    /// Run a single registered check by name
+    ///
+    /// Returns `None` if no check with that name is registered. The result
+    /// is not recorded in the last results, which always hold a full run.
+    pub async fn run_check(&self, name: &str) -> Option<CheckExecution> {
+        let checks = self.checks.read().await;
+        let check = checks.get(name)?;
+        Some(execute_check(name, check.as_ref()).await)
+    }
+
     /// Run health checks with retry logic until timeout or success
     #[allow(dead_code)]
     pub async fn run_with_retry(&self) -> HealthStatus {
@@ -396,11 +705,68 @@ mod tests {
 
     #[tokio::test]
     async fn test_boot_check() {
-        let check = BootCheck;
+        let check = BootCheck::default();
         let result = check.check().await;
         assert!(result.is_passing());
     }
 
+    fn execution(name: &str, result: HealthCheckResult, critical: bool) -> CheckExecution {
+        CheckExecution {
+            name: name.to_string(),
+            result,
+            duration_ms: 1,
+            critical,
+        }
+    }
+
+    #[test]
+    fn test_health_summary() {
+        let executions = vec![
+            execution(
+                "network",
+                HealthCheckResult::Fail("no default route".into()),
+                false,
+            ),
+            execution("boot", HealthCheckResult::Pass, true),
+            execution(
+                "service:kubelet",
+                HealthCheckResult::Fail("not running".into()),
+                true,
+            ),
+            execution(
+                "clock_skew",
+                HealthCheckResult::Unknown("server unreachable".into()),
+                false,
+            ),
+            execution(
+                "api",
+                HealthCheckResult::Fail("connection refused".into()),
+                true,
+            ),
+        ];
+        assert_eq!(
+            health_summary(&executions),
+            "critical checks failing: api (connection refused), service:kubelet (not running); \
+             degraded: network (no default route)"
+        );
+
+        // Only non-critical failures
+        assert_eq!(
+            health_summary(&executions[..2]),
+            "degraded: network (no default route)"
+        );
+
+        // Unknown results do not count as failures
+        assert_eq!(
+            health_summary(&[
+                execution("boot", HealthCheckResult::Pass, true),
+                execution("clock_skew", HealthCheckResult::Unknown("?".into()), false),
+            ]),
+            "all checks passing"
+        );
+        assert_eq!(health_summary(&[]), "all checks passing");
+    }
+
     #[tokio::test]
     async fn test_health_checker() {
         let config = HealthCheckerConfig {
@@ -419,4 +785,107 @@ mod tests {
             HealthStatus::Healthy | HealthStatus::Degraded | HealthStatus::Unhealthy
         ));
     }
+
+    #[tokio::test]
+    async fn test_from_node_config() {
+        // Without a section the defaults are registered
+        let checker = HealthChecker::from_node_config(None);
+        assert_eq!(checker.check_names().await, ["api", "boot", "network"]);
+
+        let checks = keel_config::HealthChecksConfig {
+            boot: Some(keel_config::BootCheckConfig::default()),
+            timeout_secs: Some(60),
+            ..Default::default()
+        };
+        let checker = HealthChecker::from_node_config(Some(&checks));
+        assert_eq!(checker.check_names().await, ["boot"]);
+        assert_eq!(checker.config.timeout_secs, 60);
+        assert_eq!(checker.config.max_retries, 30);
+
+        let checks = keel_config::HealthChecksConfig {
+            network: Some(keel_config::NetworkCheckConfig { critical: true }),
+            api: Some(keel_config::ApiCheckConfig { port: Some(6443) }),
+            services: vec!["kubelet".to_string()],
+            ..Default::default()
+        };
+        let checker = HealthChecker::from_node_config(Some(&checks));
+        assert_eq!(
+            checker.check_names().await,
+            ["api", "network", "service:kubelet"]
+        );
+        assert!(checker.run_check("network").await.unwrap().critical);
+    }
+
+    #[tokio::test]
+    async fn test_run_check_by_name() {
+        let checker = HealthChecker::new(HealthCheckerConfig::default());
+
+        let execution = checker.run_check("boot").await.unwrap();
+        assert_eq!(execution.name, "boot");
+        assert!(execution.critical);
+        // A single run does not replace the last full results
+        assert!(checker.get_last_results().await.is_empty());
+
+        assert!(checker.run_check("no-such-check").await.is_none());
+    }
+
+    #[test]
+    fn test_clock_skew_from_synthetic_times() {
+        // Server is 45s ahead, 0.2s round trip, 0.1s server processing
+        let offset = clock_offset(1000.0, 1045.05, 1045.15, 1000.2);
+        assert!((offset - 45.0).abs() < 1e-9, "offset {}", offset);
+        assert!(matches!(
+            evaluate_clock_skew(offset, DEFAULT_MAX_CLOCK_SKEW_SECS),
+            HealthCheckResult::Fail(_)
+        ));
+
+        // Local clock 10s ahead of the server is within the threshold
+        let offset = clock_offset(1010.0, 1000.0, 1000.0, 1010.0);
+        assert_eq!(offset, -10.0);
+        assert_eq!(
+            evaluate_clock_skew(offset, DEFAULT_MAX_CLOCK_SKEW_SECS),
+            HealthCheckResult::Pass
+        );
+        assert!(matches!(
+            evaluate_clock_skew(offset, 5),
+            HealthCheckResult::Fail(_)
+        ));
+    }
+
+    #[test]
+    fn test_ntp_timestamp() {
+        // 2208988800 + 1.5s in NTP format
+        let mut bytes = (2_208_988_801u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&0x8000_0000u32.to_be_bytes());
+        assert_eq!(ntp_timestamp(&bytes), Some(1.5));
+        assert_eq!(ntp_timestamp(&bytes[..7]), None);
+        assert_eq!(ntp_timestamp(&[]), None);
+    }
+
+    /// Answer one SNTP request on `bind` with the current time
+    async fn serve_sntp(bind: &str) -> std::net::SocketAddr {
+        let socket = tokio::net::UdpSocket::bind(bind).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut request = [0u8; 48];
+            let (_, peer) = socket.recv_from(&mut request).await.unwrap();
+            let now = unix_now() + NTP_UNIX_OFFSET_SECS;
+            let mut reply = [0u8; 48];
+            reply[0] = 0x1C;
+            for offset in [32, 40] {
+                reply[offset..offset + 4].copy_from_slice(&(now as u32).to_be_bytes());
+            }
+            socket.send_to(&reply, peer).await.unwrap();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_check_queries_ipv4_and_ipv6_servers() {
+        for bind in ["127.0.0.1:0", "[::1]:0"] {
+            let server = serve_sntp(bind).await;
+            let check = ClockSkewCheck::new(server.to_string(), DEFAULT_MAX_CLOCK_SKEW_SECS);
+            assert_eq!(check.check().await, HealthCheckResult::Pass, "{server}");
+        }
+    }
 }
@@ -2,50 +2,205 @@
 //!
 //! Provides safe execution of pre/post update hooks.
 
-use std::process::Command;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
 use tracing::{error, info, warn};
 
+/// How long a hook may run before it is killed
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Environment variable telling a hook which phase it runs in
+/// (`pre-update` or `post-update`)
+pub const HOOK_PHASE_ENV: &str = "KEEL_HOOK_PHASE";
+
+/// Environment variable set to `1` when a hook runs through `TestHook`
+/// rather than a real update
+pub const HOOK_TEST_ENV: &str = "KEEL_HOOK_TEST";
+
+/// Captured output is cut to this many bytes per stream
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// Result of running a hook to completion (or until it was killed)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookOutcome {
+    /// Exit code; `None` if the hook was killed by a signal or timed out
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+    pub duration: Duration,
+}
+
+impl HookOutcome {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// One-line description of a failed run, as reported for real updates
+    pub fn failure_message(&self, timeout: Duration) -> String {
+        if self.timed_out {
+            format!("Hook timed out after {:?}", timeout)
+        } else {
+            format!("Hook failed with exit code: {:?}", self.exit_code)
+        }
+    }
+}
+
+/// Lossy UTF-8 of `output`, cut to [`MAX_CAPTURED_OUTPUT`] bytes
+fn captured(output: &[u8]) -> String {
+    String::from_utf8_lossy(&output[..output.len().min(MAX_CAPTURED_OUTPUT)]).into_owned()
+}
+
+/// Run `command` for `phase`, capturing its output
+///
+/// The command is split on whitespace (no shell). [`HOOK_PHASE_ENV`] and
+/// `extra_env` are added to the agent's environment. A hook still running
+/// after `timeout` is killed. `Err` means the hook could not be started.
+pub async fn run_hook(
+    command: &str,
+    phase: &str,
+    extra_env: &[(&str, &str)],
+    timeout: Duration,
+) -> Result<HookOutcome, String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let Some((program, args)) = parts.split_first() else {
+        return Err("Hook command is empty".to_string());
+    };
+
+    let started = Instant::now();
+    let child = Command::new(program)
+        .args(args)
+        .env(HOOK_PHASE_ENV, phase)
+        .envs(extra_env.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to execute hook: {}", e))?;
+
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => Ok(HookOutcome {
+            exit_code: output.status.code(),
+            stdout: captured(&output.stdout),
+            stderr: captured(&output.stderr),
+            timed_out: false,
+            duration: started.elapsed(),
+        }),
+        Ok(Err(e)) => Err(format!("Failed to execute hook: {}", e)),
+        // Dropping the future kills the child
+        Err(_) => Ok(HookOutcome {
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            timed_out: true,
+            duration: started.elapsed(),
+        }),
+    }
+}
+
 /// Execute a hook command
 ///
-/// Limits execution to a timeout and checks exit status.
+/// Limits execution to [`HOOK_TIMEOUT`] and checks exit status.
 /// Note: Sandbox limitations apply - should ideally use a constrained user.
+#[tracing::instrument(name = "hooks", skip(command), fields(command = %command))]
 pub async fn execute_hook(command: &str, phase: &str) -> Result<(), String> {
-    if command.is_empty() {
+    if command.trim().is_empty() {
         return Ok(());
     }
 
     info!(phase = phase, command = command, "Executing update hook");
 
-    // Split command into program and args
-    let parts: Vec<&str> = command.split_whitespace().collect();
-    if parts.is_empty() {
-        return Ok(());
-    }
-
-    let program = parts[0];
-    let args = &parts[1..];
-
-    let result = Command::new(program).args(args).status();
-
-    match result {
-        Ok(status) => {
-            if status.success() {
-                info!(
-                    phase = phase,
-                    command = command,
-                    "Hook executed successfully"
-                );
-                Ok(())
-            } else {
-                let msg = format!("Hook failed with exit code: {:?}", status.code());
-                warn!(phase = phase, error = %msg, "Hook failure");
-                Err(msg)
-            }
+    match run_hook(command, phase, &[], HOOK_TIMEOUT).await {
+        Ok(outcome) if outcome.success() => {
+            info!(
+                phase = phase,
+                command = command,
+                "Hook executed successfully"
+            );
+            Ok(())
         }
-        Err(e) => {
-            let msg = format!("Failed to execute hook: {}", e);
+        Ok(outcome) => {
+            let msg = outcome.failure_message(HOOK_TIMEOUT);
+            warn!(phase = phase, error = %msg, stderr = %outcome.stderr.trim(), "Hook failure");
+            Err(msg)
+        }
+        Err(msg) => {
             error!(phase = phase, error = %msg, "Hook execution error");
             Err(msg)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_hook_emits_span() {
+        let (recorder, _guard) = crate::telemetry::span_recorder::record_spans();
+        execute_hook("true", "pre-update").await.unwrap();
+        assert!(recorder.names().iter().any(|n| n == "hooks"));
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_success_captures_output_and_env() {
+        let outcome = run_hook("printenv KEEL_HOOK_PHASE", "pre-update", &[], HOOK_TIMEOUT)
+            .await
+            .unwrap();
+        assert!(outcome.success());
+        assert_eq!(outcome.stdout, "pre-update\n");
+        assert!(!outcome.timed_out);
+
+        let outcome = run_hook(
+            "printenv KEEL_HOOK_TEST",
+            "post-update",
+            &[(HOOK_TEST_ENV, "1")],
+            HOOK_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.stdout, "1\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_failure_reports_exit_code() {
+        let outcome = run_hook(
+            "ls /nonexistent-keel-hook-path",
+            "pre-update",
+            &[],
+            HOOK_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert!(!outcome.success());
+        assert!(outcome.exit_code.is_some_and(|code| code != 0));
+        assert!(!outcome.stderr.is_empty());
+        assert!(execute_hook("ls /nonexistent-keel-hook-path", "pre-update")
+            .await
+            .unwrap_err()
+            .contains("exit code"));
+
+        assert!(
+            run_hook("/nonexistent/hook", "pre-update", &[], HOOK_TIMEOUT)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_timeout() {
+        let timeout = Duration::from_millis(200);
+        let outcome = run_hook("sleep 10", "pre-update", &[], timeout)
+            .await
+            .unwrap();
+        assert!(outcome.timed_out);
+        assert_eq!(outcome.exit_code, None);
+        assert!(outcome.duration < Duration::from_secs(5));
+        assert_eq!(
+            outcome.failure_message(timeout),
+            "Hook timed out after 200ms"
+        );
+    }
+}
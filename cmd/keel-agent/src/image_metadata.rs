@@ -0,0 +1,354 @@
+//! Compatibility checks for update images
+//!
+//! An image may be published with a sidecar `<image>.json` describing it:
+//!
+//! ```json
+//! { "version": "1.4.0", "arch": "x86_64", "min_from_version": "1.2.0" }
+//! ```
+//!
+//! Before flashing, the agent fetches the sidecar and refuses images built
+//! for another architecture, older than the running OS, or requiring a newer
+//! OS to upgrade from. Images without a sidecar, or whose sidecar cannot be
+//! fetched, are flashed unchecked.
+//!
+//! The running version is `VERSION_ID` from [`OS_RELEASE_PATH`], which the
+//! image build writes.
+
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::io;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+use crate::download;
+
+/// os-release file naming the running OS version in `VERSION_ID`
+pub const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+/// `VERSION_ID` from os-release contents, with any quotes removed
+pub fn parse_os_version(os_release: &str) -> Option<String> {
+    os_release
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("VERSION_ID="))
+        .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+        .find(|version| !version.is_empty())
+}
+
+/// Version of the running OS from the os-release file at `path`
+pub fn running_os_version_from<P: AsRef<Path>>(path: P) -> Option<String> {
+    let contents = std::fs::read_to_string(path.as_ref())
+        .map_err(|e| debug!(path = %path.as_ref().display(), error = %e, "os-release unreadable"))
+        .ok()?;
+    parse_os_version(&contents)
+}
+
+/// Version of the running OS; `None` if [`OS_RELEASE_PATH`] does not name one
+pub fn running_os_version() -> Option<String> {
+    running_os_version_from(OS_RELEASE_PATH)
+}
+
+/// Contents of an image's `.json` sidecar
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ImageMetadata {
+    /// OS version contained in the image
+    pub version: String,
+    /// Machine architecture (`uname -m`) the image was built for
+    #[serde(default)]
+    pub arch: Option<String>,
+    /// Oldest running version that may upgrade to this image
+    #[serde(default)]
+    pub min_from_version: Option<String>,
+}
+
+/// URL of the sidecar for `image_url`
+pub fn metadata_url(image_url: &str) -> String {
+    match image_url.split_once('?') {
+        Some((path, query)) => format!("{}.json?{}", path, query),
+        None => format!("{}.json", image_url),
+    }
+}
+
+/// Canonical architecture name, folding Debian/Go style aliases
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" | "x86-64" => "x86_64",
+        "arm64" => "aarch64",
+        other => other,
+    }
+}
+
+/// Numeric components of a version (`v1.2.3-rc1` → `[1, 2, 3]`)
+fn parse_version(version: &str) -> Result<Vec<u64>, String> {
+    let core = version.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next().unwrap_or_default();
+    core.split('.')
+        .map(|part| {
+            part.parse::<u64>()
+                .map_err(|_| format!("invalid version '{}'", version))
+        })
+        .collect()
+}
+
+/// Compare two versions numerically; missing components count as 0
+fn compare_versions(a: &str, b: &str) -> Result<Ordering, String> {
+    let (mut a, mut b) = (parse_version(a)?, parse_version(b)?);
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    Ok(a.cmp(&b))
+}
+
+/// Check that an image was built for the node's architecture
+pub fn check_arch(metadata: &ImageMetadata, node_arch: &str) -> Result<(), String> {
+    match &metadata.arch {
+        Some(arch) if normalize_arch(arch) != normalize_arch(node_arch) => Err(format!(
+            "image is built for {}, but this node is {}",
+            arch, node_arch
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check an image against the running version and the node's architecture
+///
+/// Reinstalling the running version is allowed; anything older is a
+/// downgrade and refused (use a rollback to go back instead).
+pub fn check_compatibility(
+    metadata: &ImageMetadata,
+    running_version: &str,
+    node_arch: &str,
+) -> Result<(), String> {
+    check_arch(metadata, node_arch)?;
+
+    if compare_versions(&metadata.version, running_version)? == Ordering::Less {
+        return Err(format!(
+            "image version {} is older than the running version {}",
+            metadata.version, running_version
+        ));
+    }
+
+    if let Some(min_from) = &metadata.min_from_version {
+        if compare_versions(running_version, min_from)? == Ordering::Less {
+            return Err(format!(
+                "image {} requires upgrading from {} or later, running {}",
+                metadata.version, min_from, running_version
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Machine architecture as reported by `uname -m`
+pub fn node_arch() -> String {
+    std::process::Command::new("uname")
+        .arg("-m")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|arch| !arch.is_empty())
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+/// Fetch the sidecar for `image_url`
+///
+/// `None` if the server has none, or if it cannot be fetched at all: many
+/// image servers answer a missing file with 403 or an error page rather
+/// than 404. A sidecar that is served but does not parse is an error.
+pub async fn fetch(
+    image_url: &str,
+    auth_header: Option<&str>,
+) -> io::Result<Option<ImageMetadata>> {
+    let url = metadata_url(image_url);
+    let response = match download::get(&url, auth_header).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!(error = %e, "Failed to fetch image metadata, treating image as unversioned");
+            return Ok(None);
+        }
+    };
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        warn!(
+            status = %response.status(),
+            "Image metadata server returned error, treating image as unversioned"
+        );
+        return Ok(None);
+    }
+
+    let body = match download::body(response).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(error = %e, "Failed to read image metadata, treating image as unversioned");
+            return Ok(None);
+        }
+    };
+    serde_json::from_slice(&body).map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid image metadata: {}", e),
+        )
+    })
+}
+
+/// Refuse `image_url` if its sidecar says it does not fit this node
+///
+/// Returns the sidecar, if the image has one.
+pub async fn verify_image(
+    image_url: &str,
+    auth_header: Option<&str>,
+) -> io::Result<Option<ImageMetadata>> {
+    let Some(metadata) = fetch(image_url, auth_header).await? else {
+        debug!(url = %download::redact_url(image_url), "No image metadata published, skipping compatibility check");
+        return Ok(None);
+    };
+
+    let arch = node_arch();
+    let checked = match running_os_version() {
+        Some(running) => check_compatibility(&metadata, &running, &arch),
+        None => {
+            warn!(
+                path = OS_RELEASE_PATH,
+                "Running OS version unknown, checking the image architecture only"
+            );
+            check_arch(&metadata, &arch)
+        }
+    };
+    checked.map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Incompatible image: {}", e),
+        )
+    })?;
+
+    info!(version = %metadata.version, arch = %arch, "Image compatible with this node");
+    Ok(Some(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(version: &str, arch: Option<&str>, min_from: Option<&str>) -> ImageMetadata {
+        ImageMetadata {
+            version: version.to_string(),
+            arch: arch.map(str::to_string),
+            min_from_version: min_from.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_valid_upgrade() {
+        let meta = metadata("1.4.0", Some("x86_64"), Some("1.2.0"));
+        assert!(check_compatibility(&meta, "1.2.0", "x86_64").is_ok());
+        assert!(check_compatibility(&meta, "1.3.9", "x86_64").is_ok());
+        // Reinstalling the running version is fine
+        assert!(check_compatibility(&meta, "1.4.0", "x86_64").is_ok());
+        // Aliases and missing fields
+        assert!(
+            check_compatibility(&metadata("v2.0", Some("amd64"), None), "1.9.9", "x86_64").is_ok()
+        );
+        assert!(check_compatibility(&metadata("2.0.0", None, None), "1.0.0", "aarch64").is_ok());
+    }
+
+    #[test]
+    fn test_arch_mismatch() {
+        let err = check_compatibility(&metadata("1.4.0", Some("aarch64"), None), "1.2.0", "x86_64")
+            .unwrap_err();
+        assert!(err.contains("aarch64") && err.contains("x86_64"), "{err}");
+        assert!(
+            check_compatibility(&metadata("1.4.0", Some("arm64"), None), "1.2.0", "aarch64")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_downgrade_blocked() {
+        let err =
+            check_compatibility(&metadata("1.1.0", None, None), "1.2.0", "x86_64").unwrap_err();
+        assert!(err.contains("older"), "{err}");
+        // Numeric, not lexical, comparison
+        assert!(check_compatibility(&metadata("1.10.0", None, None), "1.9.0", "x86_64").is_ok());
+        assert!(check_compatibility(&metadata("1.2.0-rc1", None, None), "1.2", "x86_64").is_ok());
+    }
+
+    #[test]
+    fn test_min_from_version() {
+        let err = check_compatibility(&metadata("2.0.0", None, Some("1.5.0")), "1.4.2", "x86_64")
+            .unwrap_err();
+        assert!(err.contains("1.5.0"), "{err}");
+    }
+
+    #[test]
+    fn test_invalid_version_rejected() {
+        assert!(check_compatibility(&metadata("latest", None, None), "1.0.0", "x86_64").is_err());
+    }
+
+    #[test]
+    fn test_metadata_url() {
+        assert_eq!(
+            metadata_url("https://images.example.com/keel-1.4.0.img"),
+            "https://images.example.com/keel-1.4.0.img.json"
+        );
+        assert_eq!(
+            metadata_url("https://images.example.com/keel.img?sig=abc"),
+            "https://images.example.com/keel.img.json?sig=abc"
+        );
+    }
+
+    #[test]
+    fn test_parse_os_version() {
+        let os_release =
+            "NAME=\"KeelOS\"\nID=keelos\nVERSION_ID=\"1.4.0\"\nPRETTY_NAME=\"KeelOS 1.4.0\"\n";
+        assert_eq!(parse_os_version(os_release).as_deref(), Some("1.4.0"));
+        assert_eq!(parse_os_version("VERSION_ID=1.2\n").as_deref(), Some("1.2"));
+        assert_eq!(parse_os_version("VERSION_ID=''\n"), None);
+        assert_eq!(parse_os_version("NAME=KeelOS\nVERSION=1.4.0\n"), None);
+    }
+
+    #[test]
+    fn test_running_os_version_from_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("os-release");
+        assert_eq!(running_os_version_from(&path), None);
+        std::fs::write(&path, "ID=keelos\nVERSION_ID=2.0.1\n").unwrap();
+        assert_eq!(running_os_version_from(&path).as_deref(), Some("2.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_treats_server_errors_as_no_metadata() {
+        use crate::download::test_server::serve_response;
+
+        for status in [
+            "404 Not Found",
+            "403 Forbidden",
+            "500 Internal Server Error",
+        ] {
+            let url = serve_response(status, b"nope".to_vec(), "").await;
+            assert_eq!(fetch(&url, None).await.unwrap(), None, "{status}");
+        }
+
+        let url = serve_response("200 OK", br#"{"version": "1.4.0"}"#.to_vec(), "").await;
+        assert_eq!(
+            fetch(&url, None).await.unwrap(),
+            Some(metadata("1.4.0", None, None))
+        );
+
+        // A sidecar that is served but malformed is still refused
+        let url = serve_response("200 OK", b"not json".to_vec(), "").await;
+        assert_eq!(
+            fetch(&url, None).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_metadata_parses_optional_fields() {
+        let meta: ImageMetadata = serde_json::from_str(r#"{"version": "1.4.0"}"#).unwrap();
+        assert_eq!(meta, metadata("1.4.0", None, None));
+    }
+}
@@ -0,0 +1,289 @@
+//! Periodic re-verification of the booted slot
+//!
+//! Bit rot on the active partition shows up as confusing failures long
+//! after the image was flashed. When `update.verify_interval_secs` is set,
+//! the agent re-reads the image from the booted slot that often and compares
+//! its SHA256 with the hash in the slot's flashed marker (see
+//! [`disk::read_slot_marker`]). A mismatch is logged, counted in
+//! `keel.partition.integrity.failures`, and fails the non-critical
+//! `partition_integrity` health check, which is always registered and
+//! reports `Unknown` while verification is off.
+//!
+//! Only the image is hashed, not the whole partition, so slots whose marker
+//! predates recorded image sizes (or has no hash) cannot be verified.
+
+use async_trait::async_trait;
+use keel_config::NodeConfig;
+use opentelemetry::global;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::disk;
+use crate::health_check::{HealthCheck, HealthCheckResult};
+
+/// How often a disabled verifier re-reads the configuration
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reported by the health check while verification is switched off
+const DISABLED_REASON: &str = "Periodic verification is off (update.verify_interval_secs)";
+
+/// Outcome of comparing a slot with its marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// The image still hashes to the recorded SHA256
+    Verified,
+    /// The image on disk no longer matches its marker
+    Mismatch { expected: String, actual: String },
+    /// There is nothing to compare against
+    Unverifiable(String),
+}
+
+/// Hash the image at the start of `device` and compare it with the marker
+/// of partition `index` in `marker_dir`
+///
+/// Fails if the device cannot be read or is shorter than the image.
+pub fn verify_against_marker<P: AsRef<Path>, Q: AsRef<Path>>(
+    device: P,
+    marker_dir: Q,
+    index: u32,
+) -> io::Result<Verification> {
+    let marker = match disk::read_slot_marker(marker_dir, index) {
+        Ok(marker) => marker,
+        Err(e) => return Ok(Verification::Unverifiable(e.to_string())),
+    };
+    let (Some(expected), Some(size)) = (marker.sha256, marker.size) else {
+        return Ok(Verification::Unverifiable(format!(
+            "Marker of partition {} records no image hash and size",
+            index
+        )));
+    };
+
+    let mut image = fs::File::open(device)?.take(size);
+    let mut hasher = Sha256::new();
+    let copied = io::copy(&mut image, &mut hasher)?;
+    if copied < size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Partition {} holds {} bytes, the image has {}",
+                index, copied, size
+            ),
+        ));
+    }
+
+    let actual = format!("{:x}", hasher.finalize());
+    Ok(if actual == expected.to_lowercase() {
+        Verification::Verified
+    } else {
+        Verification::Mismatch { expected, actual }
+    })
+}
+
+/// Result of the most recent verification, shared with the health check
+pub type LastVerification = Arc<Mutex<Option<Verification>>>;
+
+/// Verify the booted slot once, logging and recording the outcome
+async fn verify_active_slot(last: &LastVerification) {
+    let result = tokio::task::spawn_blocking(|| {
+        let active = disk::get_active_partition()?;
+        verify_against_marker(&active.device, disk::SLOT_MARKER_DIR, active.index)
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e)));
+
+    let verification = match result {
+        Ok(verification) => verification,
+        Err(e) => {
+            warn!(error = %e, "Failed to verify the active partition");
+            return;
+        }
+    };
+    match &verification {
+        Verification::Verified => info!("Active partition matches its flashed image"),
+        Verification::Mismatch { expected, actual } => {
+            error!(expected = %expected, actual = %actual, "Active partition is corrupt");
+            global::meter("keel_agent")
+                .u64_counter("keel.partition.integrity.failures")
+                .with_description("Active partition hash mismatches")
+                .build()
+                .add(1, &[]);
+        }
+        Verification::Unverifiable(reason) => {
+            debug!(reason = %reason, "Active partition cannot be verified")
+        }
+    }
+    if let Ok(mut last) = last.lock() {
+        *last = Some(verification);
+    }
+}
+
+/// Re-verify the booted slot every `update.verify_interval_secs`
+///
+/// The setting is re-read each round, so enabling or disabling it with
+/// SIGHUP takes effect without a restart. While it is unset the health
+/// check reports verification as off.
+pub async fn verify_loop(config: Arc<RwLock<NodeConfig>>, last: LastVerification) {
+    let disabled = Verification::Unverifiable(DISABLED_REASON.to_string());
+    loop {
+        let interval = config.read().await.update.verify_interval_secs;
+        if let Ok(mut last) = last.lock() {
+            match interval {
+                None => *last = Some(disabled.clone()),
+                // Re-enabled: nothing verified under the new setting yet
+                Some(_) if last.as_ref() == Some(&disabled) => *last = None,
+                Some(_) => {}
+            }
+        }
+        tokio::time::sleep(interval.map_or(DISABLED_POLL_INTERVAL, Duration::from_secs)).await;
+        if config.read().await.update.verify_interval_secs.is_some() {
+            verify_active_slot(&last).await;
+        }
+    }
+}
+
+/// Fails when the last verification found the active partition corrupt
+pub struct PartitionIntegrityCheck {
+    last: LastVerification,
+}
+
+impl PartitionIntegrityCheck {
+    pub fn new(last: LastVerification) -> Self {
+        Self { last }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for PartitionIntegrityCheck {
+    async fn check(&self) -> HealthCheckResult {
+        let last = self.last.lock().map(|last| last.clone()).ok().flatten();
+        match last {
+            None => HealthCheckResult::Unknown("Not verified yet".to_string()),
+            Some(Verification::Verified) => HealthCheckResult::Pass,
+            Some(Verification::Mismatch { expected, actual }) => HealthCheckResult::Fail(format!(
+                "Active partition hash {} does not match flashed image {}",
+                actual, expected
+            )),
+            Some(Verification::Unverifiable(reason)) => HealthCheckResult::Unknown(reason),
+        }
+    }
+
+    fn name(&self) -> String {
+        "partition_integrity".to_string()
+    }
+
+    fn is_critical(&self) -> bool {
+        false // The running slot cannot be fixed by rolling back to it
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_reports_disabled_verification() {
+        let config = Arc::new(RwLock::new(NodeConfig::default_config()));
+        assert!(config.read().await.update.verify_interval_secs.is_none());
+        let last = LastVerification::default();
+        let check = PartitionIntegrityCheck::new(last.clone());
+
+        let verifier = tokio::spawn(verify_loop(config, last.clone()));
+        while last.lock().unwrap().is_none() {
+            tokio::task::yield_now().await;
+        }
+        verifier.abort();
+
+        assert_eq!(
+            check.check().await,
+            HealthCheckResult::Unknown(DISABLED_REASON.to_string())
+        );
+    }
+
+    #[test]
+    fn test_verify_against_marker() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let markers = dir.path().join("slots");
+        let partition = dir.path().join("sda3");
+
+        // The partition is larger than the image it holds
+        let image: Vec<u8> = (0..10_000u32).map(|i| (i % 199) as u8).collect();
+        let mut content = image.clone();
+        content.extend(std::iter::repeat_n(0xee, 4096));
+        fs::write(&partition, &content).unwrap();
+
+        let digest = format!("{:x}", Sha256::digest(&image));
+        disk::mark_slot_flashed(&markers, 3, Some(&digest), Some(image.len() as u64)).unwrap();
+        assert_eq!(
+            verify_against_marker(&partition, &markers, 3).unwrap(),
+            Verification::Verified
+        );
+
+        // A flipped bit inside the image is caught
+        content[5000] ^= 0x01;
+        fs::write(&partition, &content).unwrap();
+        match verify_against_marker(&partition, &markers, 3).unwrap() {
+            Verification::Mismatch { expected, actual } => {
+                assert_eq!(expected, digest);
+                assert_ne!(actual, digest);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+
+        // Changes past the end of the image are not
+        content[5000] ^= 0x01;
+        content[image.len() + 10] = 0;
+        fs::write(&partition, &content).unwrap();
+        assert_eq!(
+            verify_against_marker(&partition, &markers, 3).unwrap(),
+            Verification::Verified
+        );
+
+        // A partition shorter than the image cannot be verified
+        fs::write(&partition, &image[..100]).unwrap();
+        let err = verify_against_marker(&partition, &markers, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_markers_without_hash_are_unverifiable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let partition = dir.path().join("sda2");
+        fs::write(&partition, b"installer image").unwrap();
+
+        // Never flashed by the agent
+        assert!(matches!(
+            verify_against_marker(&partition, dir.path(), 2).unwrap(),
+            Verification::Unverifiable(_)
+        ));
+
+        // Recorded before image sizes were
+        disk::mark_slot_flashed(dir.path(), 2, Some(&"ab".repeat(32)), None).unwrap();
+        assert!(matches!(
+            verify_against_marker(&partition, dir.path(), 2).unwrap(),
+            Verification::Unverifiable(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_integrity_check_reports_last_verification() {
+        let last = LastVerification::default();
+        let check = PartitionIntegrityCheck::new(last.clone());
+        assert!(matches!(check.check().await, HealthCheckResult::Unknown(_)));
+
+        *last.lock().unwrap() = Some(Verification::Verified);
+        assert_eq!(check.check().await, HealthCheckResult::Pass);
+
+        *last.lock().unwrap() = Some(Verification::Mismatch {
+            expected: "aa".to_string(),
+            actual: "bb".to_string(),
+        });
+        assert!(matches!(check.check().await, HealthCheckResult::Fail(_)));
+        assert!(!check.is_critical());
+    }
+}
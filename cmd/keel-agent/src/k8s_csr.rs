@@ -9,7 +9,7 @@
 
 use base64::{engine::general_purpose, Engine as _};
 use k8s_openapi::api::certificates::v1::{
-    CertificateSigningRequest, CertificateSigningRequestSpec,
+    CertificateSigningRequest, CertificateSigningRequestSpec, CertificateSigningRequestStatus,
 };
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::{
@@ -17,15 +17,170 @@ use kube::{
     Client,
 };
 use std::collections::BTreeMap;
+use std::time::Duration;
 use tracing::{info, warn};
 
-const CSR_SIGNER: &str = "kubernetes.io/kube-apiserver-client";
-const CSR_USAGES: &[&str] = &["client auth"];
+/// Signer for node serving certificates
+///
+/// It only signs requests from `system:node:<name>` / `system:nodes` with
+/// DNS and IP SANs, so the agent's serving certificate covers the node's
+/// names and addresses.
+const CSR_SIGNER: &str = "kubernetes.io/kubelet-serving";
+
+/// Usages accepted by the kubelet-serving signer for an ECDSA key
+const CSR_USAGES: &[&str] = &["digital signature", "server auth"];
+
+/// Organization of the node's certificate request
+const CSR_ORGANIZATION: &str = "system:nodes";
+
+/// Common name identifying `node_name` to the Kubernetes node authorizer
+///
+/// Signers and auto-approvers for node certificates only accept
+/// `system:node:<name>` together with the `system:nodes` organization.
+pub fn node_common_name(node_name: &str) -> String {
+    format!("system:node:{}", node_name)
+}
+
+/// CSR and key for `node_name`, with the subject the kubelet-serving signer
+/// requires (`CN=system:node:<name>, O=system:nodes`)
+pub fn node_csr(
+    node_name: &str,
+    sans: &[String],
+) -> Result<(String, String), keel_crypto::CryptoError> {
+    keel_crypto::generate_csr(&node_common_name(node_name), CSR_ORGANIZATION, sans)
+}
+
+/// Delay before the first CSR status check; doubles on every check after
+const CSR_POLL_INITIAL: Duration = Duration::from_secs(2);
+
+/// Longest delay between two CSR status checks
+const CSR_POLL_MAX: Duration = Duration::from_secs(30);
+
+/// Default for how long to wait for a CSR to be signed
+pub const DEFAULT_CSR_MAX_WAIT: Duration = Duration::from_secs(300);
+
+/// CSR signing timeout from `NodeConfig.kubernetes`, or [`DEFAULT_CSR_MAX_WAIT`]
+pub fn csr_max_wait(config: &keel_config::NodeConfig) -> Duration {
+    config
+        .kubernetes
+        .csr_max_wait_secs
+        .map_or(DEFAULT_CSR_MAX_WAIT, Duration::from_secs)
+}
+
+/// SANs for the node's certificate: node name, hostname and node IPs
+///
+/// Loopback and link-local addresses are skipped, as are duplicates.
+pub fn node_subject_alt_names(
+    node_name: &str,
+    hostname: Option<&str>,
+    ips: &[std::net::IpAddr],
+) -> Vec<String> {
+    let mut sans: Vec<String> = Vec::new();
+    let names = std::iter::once(node_name)
+        .chain(hostname)
+        .map(str::to_string);
+    let addresses = ips
+        .iter()
+        .filter(|ip| {
+            !ip.is_loopback()
+                && match ip {
+                    std::net::IpAddr::V4(v4) => !v4.is_link_local(),
+                    std::net::IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) != 0xfe80,
+                }
+        })
+        .map(|ip| ip.to_string());
+    for san in names.chain(addresses) {
+        if !san.is_empty() && !sans.contains(&san) {
+            sans.push(san);
+        }
+    }
+    sans
+}
+
+/// Addresses configured on the node's interfaces
+fn local_ip_addresses() -> Vec<std::net::IpAddr> {
+    sysinfo::Networks::new_with_refreshed_list()
+        .values()
+        .flat_map(|data| data.ip_networks().iter().map(|net| net.addr))
+        .collect()
+}
+
+/// Where a submitted CSR stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsrState {
+    /// Neither signed nor rejected yet
+    Pending,
+    /// Signed; holds the certificate PEM
+    Issued(String),
+    /// Denied by an approver
+    Denied(String),
+    /// Approved, but the signer failed to issue the certificate
+    Failed(String),
+}
+
+/// Classify a CSR from its status
+///
+/// A `Denied` or `Failed` condition wins over a certificate, so a CSR that
+/// was rejected is never treated as pending.
+pub fn csr_state(status: Option<&CertificateSigningRequestStatus>) -> CsrState {
+    let Some(status) = status else {
+        return CsrState::Pending;
+    };
+
+    for condition in status.conditions.iter().flatten() {
+        if condition.status != "True" {
+            continue;
+        }
+        let detail = || {
+            condition
+                .message
+                .clone()
+                .or_else(|| condition.reason.clone())
+                .unwrap_or_else(|| "no reason given".to_string())
+        };
+        match condition.type_.as_str() {
+            "Denied" => return CsrState::Denied(detail()),
+            "Failed" => return CsrState::Failed(detail()),
+            _ => {}
+        }
+    }
+
+    match &status.certificate {
+        Some(cert) if !cert.0.is_empty() => {
+            CsrState::Issued(String::from_utf8_lossy(&cert.0).into_owned())
+        }
+        _ => CsrState::Pending,
+    }
+}
+
+/// Delay before CSR status check number `attempt` (0-based)
+///
+/// [`CSR_POLL_INITIAL`] doubled per attempt, capped at [`CSR_POLL_MAX`],
+/// with ±20% jitter derived from `seed` so that nodes bootstrapped together
+/// do not poll the API server in lockstep.
+pub fn csr_poll_delay(attempt: u32, seed: u64) -> Duration {
+    let base = CSR_POLL_INITIAL
+        .saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+        .min(CSR_POLL_MAX);
+
+    // splitmix64 over seed and attempt
+    let mut z = seed
+        .wrapping_add(u64::from(attempt))
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    // Map to a factor in [0.8, 1.2]
+    let factor = 0.8 + 0.4 * (z as f64 / u64::MAX as f64);
+    base.mul_f64(factor)
+}
 
 #[allow(dead_code)] // Will be used in agent startup logic
 pub struct K8sCsrManager {
     client: Client,
     node_name: String,
+    max_wait: Duration,
 }
 
 #[allow(dead_code)] // Will be used in agent startup logic
@@ -33,7 +188,17 @@ impl K8sCsrManager {
     /// Create a new K8s CSR manager
     pub async fn new(node_name: String) -> Result<Self, Box<dyn std::error::Error>> {
         let client = Client::try_default().await?;
-        Ok(Self { client, node_name })
+        Ok(Self {
+            client,
+            node_name,
+            max_wait: DEFAULT_CSR_MAX_WAIT,
+        })
+    }
+
+    /// Set how long to wait for the CSR to be signed
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
     }
 
     /// Generate a CSR for the node and submit to K8s
@@ -44,11 +209,11 @@ impl K8sCsrManager {
         info!("Generating CSR for node: {}", self.node_name);
 
         // 1. Generate key pair
-        let (cert_pem, key_pem) = self.generate_csr_and_key()?;
+        let (csr_pem, key_pem) = self.generate_csr_and_key()?;
 
         // 2. Submit CSR to K8s
         let csr_name = format!("keel-agent-{}", self.node_name);
-        self.submit_csr(&csr_name, &cert_pem).await?;
+        self.submit_csr(&csr_name, &csr_pem).await?;
 
         // 3. Auto-approve if we have permissions (optional)
         if let Err(e) = self.approve_csr(&csr_name).await {
@@ -69,15 +234,14 @@ impl K8sCsrManager {
         Ok((signed_cert, key_pem))
     }
 
-    /// Generate CSR and private key by leveraging existing keel_crypto functionality
+    /// Generate the CSR and private key, with the node's names and IPs as SANs
     fn generate_csr_and_key(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
-        // For now, generate a self-signed cert and extract the CSR
-        // TODO: Update keel_crypto to have a dedicated CSR generation function
-        let (cert_pem, key_pem) = keel_crypto::generate_bootstrap_certificate(365 * 24)?;
+        let hostname = hostname::get().ok().and_then(|h| h.into_string().ok());
+        let sans =
+            node_subject_alt_names(&self.node_name, hostname.as_deref(), &local_ip_addresses());
+        info!("Requesting certificate with SANs: {}", sans.join(", "));
 
-        // Return the cert as the "CSR" for now
-        // In production, we'd want to generate an actual CSR, but for Phase 2 MVP this works
-        Ok((cert_pem, key_pem))
+        Ok(node_csr(&self.node_name, &sans)?)
     }
 
     /// Submit CSR to Kubernetes
@@ -134,14 +298,14 @@ impl K8sCsrManager {
                 ..Default::default()
             };
 
-        // Patch the status to approve
+        // Approval goes through the approval subresource, not status
         let patch = serde_json::json!({
             "status": {
                 "conditions": [approval_condition]
             }
         });
 
-        csrs.patch_status(
+        csrs.patch_approval(
             csr_name,
             &PatchParams::default(),
             &kube::api::Patch::Merge(patch),
@@ -157,29 +321,43 @@ impl K8sCsrManager {
         csr_name: &str,
     ) -> Result<String, Box<dyn std::error::Error>> {
         let csrs: Api<CertificateSigningRequest> = Api::all(self.client.clone());
-
-        // Poll for certificate (with timeout)
-        for attempt in 1..=30 {
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        let seed = uuid::Uuid::new_v4().as_u64_pair().0;
+        let deadline = tokio::time::Instant::now() + self.max_wait;
+
+        // Poll with exponential backoff until signed, rejected or out of time
+        for attempt in 0.. {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            tokio::time::sleep(csr_poll_delay(attempt, seed).min(remaining)).await;
 
             let csr = csrs.get(csr_name).await?;
 
-            if let Some(status) = &csr.status {
-                if let Some(cert_bytes) = &status.certificate {
-                    let cert_pem = String::from_utf8(cert_bytes.0.clone())?;
-                    return Ok(cert_pem);
+            match csr_state(csr.status.as_ref()) {
+                CsrState::Issued(cert_pem) => return Ok(cert_pem),
+                CsrState::Denied(reason) => {
+                    return Err(format!("CSR {} was denied: {}", csr_name, reason).into())
                 }
+                CsrState::Failed(reason) => {
+                    return Err(format!("CSR {} failed: {}", csr_name, reason).into())
+                }
+                CsrState::Pending => {}
             }
 
-            if attempt % 5 == 0 {
+            if attempt % 5 == 4 {
                 info!(
-                    "Still waiting for CSR to be signed... (attempt {}/30)",
-                    attempt
+                    "Still waiting for CSR to be signed... (attempt {})",
+                    attempt + 1
                 );
             }
         }
 
-        Err("Timeout waiting for certificate to be signed".into())
+        Err(format!(
+            "Timeout waiting for certificate to be signed after {}s",
+            self.max_wait.as_secs()
+        )
+        .into())
     }
 
     /// Clean up old CSRs for this node
@@ -210,7 +388,147 @@ mod tests {
 
     #[test]
     fn test_csr_constants() {
-        assert_eq!(CSR_SIGNER, "kubernetes.io/kube-apiserver-client");
-        assert_eq!(CSR_USAGES, &["client auth"]);
+        assert_eq!(CSR_SIGNER, "kubernetes.io/kubelet-serving");
+        assert_eq!(CSR_USAGES, &["digital signature", "server auth"]);
+    }
+
+    #[test]
+    fn test_node_common_name() {
+        assert_eq!(node_common_name("worker-3"), "system:node:worker-3");
+        assert_eq!(
+            node_common_name("edge-01.example.com"),
+            "system:node:edge-01.example.com"
+        );
+        assert_eq!(CSR_ORGANIZATION, "system:nodes");
+    }
+
+    #[test]
+    fn test_node_csr_subject() {
+        use x509_parser::prelude::FromDer;
+
+        let (csr_pem, _) = node_csr("worker-3", &["worker-3".to_string()]).unwrap();
+        let (_, pem) = x509_parser::pem::parse_x509_pem(csr_pem.as_bytes()).unwrap();
+        let (_, csr) =
+            x509_parser::certification_request::X509CertificationRequest::from_der(&pem.contents)
+                .unwrap();
+
+        let subject = &csr.certification_request_info.subject;
+        let common_names: Vec<&str> = subject
+            .iter_common_name()
+            .map(|cn| cn.as_str().unwrap())
+            .collect();
+        let organizations: Vec<&str> = subject
+            .iter_organization()
+            .map(|o| o.as_str().unwrap())
+            .collect();
+        assert_eq!(common_names, ["system:node:worker-3"]);
+        assert_eq!(organizations, ["system:nodes"]);
+    }
+
+    #[test]
+    fn test_node_subject_alt_names() {
+        let ips: Vec<std::net::IpAddr> = [
+            "127.0.0.1",
+            "10.0.0.5",
+            "169.254.1.1",
+            "fe80::1",
+            "2001:db8::5",
+            "10.0.0.5",
+        ]
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+
+        assert_eq!(
+            node_subject_alt_names("node-1", Some("node-1.example.com"), &ips),
+            vec!["node-1", "node-1.example.com", "10.0.0.5", "2001:db8::5"]
+        );
+        // A hostname equal to the node name is not repeated
+        assert_eq!(
+            node_subject_alt_names("node-1", Some("node-1"), &[]),
+            vec!["node-1"]
+        );
+    }
+
+    #[test]
+    fn test_csr_poll_delay_backs_off_with_jitter() {
+        // 2s, 4s, 8s, 16s, then capped at 30s, each within ±20%
+        let expected = [2, 4, 8, 16, 30, 30, 30];
+        for (attempt, secs) in expected.iter().enumerate() {
+            let base = Duration::from_secs(*secs);
+            for seed in [0, 1, 42, u64::MAX] {
+                let delay = csr_poll_delay(attempt as u32, seed);
+                assert!(
+                    delay >= base.mul_f64(0.8) && delay <= base.mul_f64(1.2),
+                    "attempt {attempt}: {delay:?} outside ±20% of {base:?}"
+                );
+            }
+        }
+
+        // Huge attempt counts must not overflow
+        assert!(csr_poll_delay(u32::MAX, 7) <= CSR_POLL_MAX.mul_f64(1.2));
+
+        // Same seed, same schedule; different seeds spread out
+        assert_eq!(csr_poll_delay(3, 99), csr_poll_delay(3, 99));
+        assert_ne!(csr_poll_delay(3, 1), csr_poll_delay(3, 2));
+    }
+
+    fn status(
+        conditions: &[(&str, &str, Option<&str>)],
+        cert: Option<&str>,
+    ) -> CertificateSigningRequestStatus {
+        CertificateSigningRequestStatus {
+            certificate: cert.map(|c| k8s_openapi::ByteString(c.as_bytes().to_vec())),
+            conditions: Some(
+                conditions
+                    .iter()
+                    .map(|(type_, status, message)| {
+                        k8s_openapi::api::certificates::v1::CertificateSigningRequestCondition {
+                            type_: type_.to_string(),
+                            status: status.to_string(),
+                            message: message.map(str::to_string),
+                            ..Default::default()
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_csr_state() {
+        assert_eq!(csr_state(None), CsrState::Pending);
+        assert_eq!(csr_state(Some(&status(&[], None))), CsrState::Pending);
+
+        // Approved but not yet signed
+        assert_eq!(
+            csr_state(Some(&status(&[("Approved", "True", None)], None))),
+            CsrState::Pending
+        );
+        assert_eq!(
+            csr_state(Some(&status(&[("Approved", "True", None)], Some("PEM")))),
+            CsrState::Issued("PEM".to_string())
+        );
+
+        assert_eq!(
+            csr_state(Some(&status(
+                &[("Denied", "True", Some("node not allowed"))],
+                None
+            ))),
+            CsrState::Denied("node not allowed".to_string())
+        );
+        assert_eq!(
+            csr_state(Some(&status(
+                &[("Approved", "True", None), ("Failed", "True", None)],
+                None
+            ))),
+            CsrState::Failed("no reason given".to_string())
+        );
+
+        // A condition that is not True does not count
+        assert_eq!(
+            csr_state(Some(&status(&[("Denied", "False", None)], None))),
+            CsrState::Pending
+        );
     }
 }
@@ -0,0 +1,105 @@
+//! Kernel console log level
+//!
+//! `/proc/sys/kernel/printk` holds four levels: console, default message,
+//! minimum console and boot-time default. Only the console level, which
+//! decides which messages reach the console, is changed here; writing a
+//! single number to the file sets just that field.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Kernel printk levels
+pub const PRINTK_PATH: &str = "/proc/sys/kernel/printk";
+
+/// Most verbose level (`KERN_DEBUG`)
+pub const MAX_LOG_LEVEL: u32 = 7;
+
+/// Fields of the printk file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Printk {
+    /// Messages more severe than this reach the console
+    pub console: u32,
+    /// Level of messages logged without one
+    pub default_message: u32,
+}
+
+/// Check that `level` is a kernel log level (0-7)
+pub fn validate_level(level: u32) -> Result<u32, String> {
+    if level > MAX_LOG_LEVEL {
+        return Err(format!(
+            "Kernel log level must be 0-{}, got {}",
+            MAX_LOG_LEVEL, level
+        ));
+    }
+    Ok(level)
+}
+
+/// Read the levels from the printk file at `path`
+pub fn read_loglevel<P: AsRef<Path>>(path: P) -> io::Result<Printk> {
+    let content = fs::read_to_string(path)?;
+    let mut fields = content.split_whitespace().map(str::parse::<u32>);
+    let mut next = || {
+        fields.next().and_then(Result::ok).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected printk contents '{}'", content.trim()),
+            )
+        })
+    };
+    let console = next()?;
+    // A file holding only the console level (as written below) is fine
+    let default_message = next().unwrap_or(console);
+    Ok(Printk {
+        console,
+        default_message,
+    })
+}
+
+/// Set the console log level in the printk file at `path`
+pub fn write_loglevel<P: AsRef<Path>>(path: P, level: u32) -> io::Result<()> {
+    validate_level(level).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    fs::write(path, format!("{}\n", level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_level() {
+        assert_eq!(validate_level(0), Ok(0));
+        assert_eq!(validate_level(7), Ok(7));
+        assert_eq!(
+            validate_level(8).unwrap_err(),
+            "Kernel log level must be 0-7, got 8"
+        );
+    }
+
+    #[test]
+    fn test_read_and_write_loglevel() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("printk");
+        fs::write(&path, "4\t4\t1\t7\n").unwrap();
+        assert_eq!(
+            read_loglevel(&path).unwrap(),
+            Printk {
+                console: 4,
+                default_message: 4
+            }
+        );
+
+        write_loglevel(&path, 7).unwrap();
+        assert_eq!(read_loglevel(&path).unwrap().console, 7);
+
+        let err = write_loglevel(&path, 9).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(read_loglevel(&path).unwrap().console, 7);
+
+        fs::write(&path, "garbage\n").unwrap();
+        assert_eq!(
+            read_loglevel(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Rebooting into the next slot with kexec
+//!
+//! With `update.reboot_method: kexec`, the agent mounts the slot that boots
+//! next, loads its kernel and initramfs with `kexec -l` and jumps into them,
+//! skipping firmware and bootloader. Anything that goes wrong along the way
+//! (no `kexec` binary, no kernel in the slot, a failed load) leaves the
+//! caller to fall back to a normal reboot.
+
+use keel_config::RebootMethod;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info, warn};
+
+use crate::disk;
+
+/// Where `kexec` (kexec-tools) is looked for
+pub const KEXEC_PATHS: &[&str] = &["/usr/sbin/kexec", "/sbin/kexec"];
+
+/// Mount point for the next slot while its kernel is loaded
+pub const SLOT_MOUNT_DIR: &str = "/run/keel/kexec-slot";
+
+/// Kernel locations inside a slot, in order of preference
+const KERNEL_FILES: &[&str] = &["boot/vmlinuz", "boot/bzImage"];
+
+/// Initramfs locations inside a slot, in order of preference
+const INITRD_FILES: &[&str] = &["boot/initramfs.cpio.gz", "boot/initrd.img"];
+
+/// Kernel and initramfs to kexec into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KexecImages {
+    pub kernel: PathBuf,
+    pub initrd: Option<PathBuf>,
+}
+
+/// First of `candidates` that exists
+pub fn find_kexec_binary(candidates: &[&str]) -> Option<PathBuf> {
+    candidates
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+/// Reboot method to use, falling back to a normal reboot when kexec was
+/// asked for but `kexec` is not installed
+pub fn select_method(configured: RebootMethod, kexec_binary: Option<&Path>) -> RebootMethod {
+    match (configured, kexec_binary) {
+        (RebootMethod::Kexec, None) => {
+            warn!("kexec reboot requested but kexec is not installed, using a normal reboot");
+            RebootMethod::Reboot
+        }
+        (method, _) => method,
+    }
+}
+
+/// Kernel and initramfs in the slot mounted at `root`, if it ships a kernel
+pub fn find_images(root: &Path) -> Option<KexecImages> {
+    let first = |names: &[&str]| names.iter().map(|n| root.join(n)).find(|p| p.is_file());
+    Some(KexecImages {
+        kernel: first(KERNEL_FILES)?,
+        initrd: first(INITRD_FILES),
+    })
+}
+
+/// Kernel command line for the next slot: the running one with `root=`
+/// pointed at `root_device`
+pub fn kexec_cmdline(current: &str, root_device: &str) -> String {
+    let root = format!("root={}", root_device);
+    let mut params: Vec<&str> = current
+        .split_whitespace()
+        .filter(|param| !param.starts_with("root="))
+        .collect();
+    params.push(&root);
+    params.join(" ")
+}
+
+/// Load the kernel of the slot that boots next, ready for [`execute`]
+pub fn load_next_slot(kexec: &Path) -> io::Result<()> {
+    let next = disk::next_boot_partition(disk::BOOT_NEXT_MARKER, disk::PROC_CMDLINE)?;
+    let cmdline = kexec_cmdline(&std::fs::read_to_string(disk::PROC_CMDLINE)?, &next.device);
+
+    std::fs::create_dir_all(SLOT_MOUNT_DIR)?;
+    run(Command::new("mount").args(["-o", "ro", &next.device, SLOT_MOUNT_DIR]))?;
+
+    // kexec copies the images into kernel memory, so the slot can be
+    // unmounted again whether or not the load worked
+    let result = find_images(Path::new(SLOT_MOUNT_DIR))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No kernel found in {}", next.device),
+            )
+        })
+        .and_then(|images| {
+            debug!(kernel = %images.kernel.display(), initrd = ?images.initrd, cmdline = %cmdline, "Loading kexec kernel");
+            let mut load = Command::new(kexec);
+            load.arg("-l").arg(&images.kernel);
+            if let Some(initrd) = &images.initrd {
+                load.arg(format!("--initrd={}", initrd.display()));
+            }
+            run(load.arg(format!("--command-line={}", cmdline)))
+        });
+
+    if let Err(e) = run(Command::new("umount").arg(SLOT_MOUNT_DIR)) {
+        warn!(error = %e, "Failed to unmount slot after kexec load");
+    }
+    result?;
+
+    info!(device = %next.device, "Loaded next slot for kexec");
+    Ok(())
+}
+
+/// Jump into the loaded kernel; only returns if that failed
+pub fn execute(kexec: &Path) -> io::Error {
+    nix::unistd::sync();
+    match run(Command::new(kexec).arg("-e")) {
+        Ok(()) => io::Error::other("kexec -e returned without rebooting"),
+        Err(e) => e,
+    }
+}
+
+/// Run `command`, turning a non-zero exit into an error with its stderr
+fn run(command: &mut Command) -> io::Result<()> {
+    let output = command.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{:?} failed: {}",
+        command.get_program(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_method() {
+        let kexec = Path::new("/usr/sbin/kexec");
+        assert_eq!(
+            select_method(RebootMethod::Kexec, Some(kexec)),
+            RebootMethod::Kexec
+        );
+        assert_eq!(
+            select_method(RebootMethod::Reboot, Some(kexec)),
+            RebootMethod::Reboot
+        );
+        assert_eq!(
+            select_method(RebootMethod::Reboot, None),
+            RebootMethod::Reboot
+        );
+    }
+
+    #[test]
+    fn test_select_method_falls_back_without_kexec() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("kexec");
+        let binary = find_kexec_binary(&[missing.to_str().unwrap()]);
+        assert!(binary.is_none());
+        assert_eq!(
+            select_method(RebootMethod::Kexec, binary.as_deref()),
+            RebootMethod::Reboot
+        );
+
+        std::fs::write(&missing, "").unwrap();
+        let binary = find_kexec_binary(&[missing.to_str().unwrap()]);
+        assert_eq!(binary.as_deref(), Some(missing.as_path()));
+    }
+
+    #[test]
+    fn test_find_images() {
+        let root = tempfile::TempDir::new().unwrap();
+        assert!(find_images(root.path()).is_none());
+
+        std::fs::create_dir(root.path().join("boot")).unwrap();
+        std::fs::write(root.path().join("boot/bzImage"), "kernel").unwrap();
+        assert_eq!(
+            find_images(root.path()).unwrap(),
+            KexecImages {
+                kernel: root.path().join("boot/bzImage"),
+                initrd: None,
+            }
+        );
+
+        std::fs::write(root.path().join("boot/vmlinuz"), "kernel").unwrap();
+        std::fs::write(root.path().join("boot/initramfs.cpio.gz"), "initrd").unwrap();
+        let images = find_images(root.path()).unwrap();
+        assert_eq!(images.kernel, root.path().join("boot/vmlinuz"));
+        assert_eq!(
+            images.initrd,
+            Some(root.path().join("boot/initramfs.cpio.gz"))
+        );
+    }
+
+    #[test]
+    fn test_kexec_cmdline_replaces_root() {
+        assert_eq!(
+            kexec_cmdline("console=ttyS0 root=/dev/sda2 ro quiet\n", "/dev/sda3"),
+            "console=ttyS0 ro quiet root=/dev/sda3"
+        );
+        assert_eq!(
+            kexec_cmdline("root=PARTUUID=abcd console=tty0", "/dev/sda2"),
+            "console=tty0 root=/dev/sda2"
+        );
+    }
+}
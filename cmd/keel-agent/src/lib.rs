@@ -9,16 +9,36 @@
 pub mod audit;
 pub mod cert_metrics;
 pub mod cert_renewal;
+pub mod checksum_manifest;
+pub mod config_reload;
+pub mod containers;
+pub mod desired_image;
 pub mod diagnostics;
 pub mod disk;
+pub mod download;
+pub mod drain;
+pub mod gpt;
 pub mod health;
 pub mod health_check;
 pub mod hooks;
+pub mod image_metadata;
+pub mod integrity;
 pub mod k8s_csr;
+pub mod kernel_log;
+pub mod kexec;
 pub mod mtls;
 pub mod network;
+pub mod node_metadata;
+pub mod preflight;
+pub mod progress;
 pub mod rbac;
+pub mod reboot_pending;
+pub mod shutdown;
+pub mod staging;
 pub mod telemetry;
+pub mod tls_policy;
+pub mod uncordon;
+pub mod update_journal;
 pub mod update_scheduler;
 
 // ---- re-exports for convenience ----
@@ -33,26 +53,33 @@ use keel_api::node::node_service_server::NodeService;
 use keel_api::node::{
     AnalyzeCrashDumpRequest, AnalyzeCrashDumpResponse, BootstrapKubernetesRequest,
     BootstrapKubernetesResponse, CancelScheduledUpdateRequest, CancelScheduledUpdateResponse,
-    CollectCrashDumpRequest, CollectCrashDumpResponse, ConfigureNetworkRequest,
-    ConfigureNetworkResponse, CrashDumpFinding as ProtoCrashDumpFinding,
-    CreateSystemSnapshotRequest, CreateSystemSnapshotResponse, EnableDebugModeRequest,
+    CollectCrashDumpRequest, CollectCrashDumpResponse, CollectDiagnosticsRequest,
+    ConfigureNetworkRequest, ConfigureNetworkResponse, ConfirmUpdateRequest, ConfirmUpdateResponse,
+    CrashDumpFinding as ProtoCrashDumpFinding, CreateSystemSnapshotRequest,
+    CreateSystemSnapshotResponse, DiagnosticsChunk, EnableDebugModeRequest,
     EnableDebugModeResponse, EnableRecoveryModeRequest, EnableRecoveryModeResponse,
-    GetBootstrapStatusRequest, GetBootstrapStatusResponse, GetDebugStatusRequest,
-    GetDebugStatusResponse, GetHealthRequest, GetHealthResponse, GetNetworkConfigRequest,
+    GetBootstrapStatusRequest, GetBootstrapStatusResponse, GetCaCertRequest, GetCaCertResponse,
+    GetDebugStatusRequest, GetDebugStatusResponse, GetHealthRequest, GetHealthResponse,
+    GetKernelLogLevelRequest, GetKernelLogLevelResponse, GetNetworkConfigRequest,
     GetNetworkConfigResponse, GetNetworkStatusRequest, GetNetworkStatusResponse,
-    GetRollbackHistoryRequest, GetRollbackHistoryResponse, GetStatusRequest, GetStatusResponse,
+    GetRollbackHistoryRequest, GetRollbackHistoryResponse, GetSlotInfoRequest, GetSlotInfoResponse,
+    GetStatusRequest, GetStatusResponse, GetUpdateJournalRequest, GetUpdateJournalResponse,
     GetUpdateScheduleRequest, GetUpdateScheduleResponse,
     HealthCheckResult as ProtoHealthCheckResult, InitBootstrapRequest, InitBootstrapResponse,
-    InstallUpdateRequest, LogEntry, RebootRequest, RebootResponse, RollbackEvent,
+    InstallUpdateRequest, LogEntry, PartitionSlot, RebootRequest, RebootResponse, RollbackEvent,
     RotateCertificateRequest, RotateCertificateResponse, ScheduleUpdateRequest,
-    ScheduleUpdateResponse, StreamLogsRequest, TriggerRollbackRequest, TriggerRollbackResponse,
+    ScheduleUpdateResponse, SetBootSlotRequest, SetBootSlotResponse, SetKernelLogLevelRequest,
+    SetKernelLogLevelResponse, SlotInfo as ProtoSlotInfo, StreamLogsRequest, TestHookRequest,
+    TestHookResponse, TriggerRollbackRequest, TriggerRollbackResponse, UpdateJournalEntry,
     UpdateProgress, UpdateSchedule as ProtoUpdateSchedule,
 };
+use keel_config::RebootMethod;
+use progress::{update_progress, Phase};
 use std::pin::Pin;
 use std::sync::Arc;
 use tokio_stream::Stream;
 use tonic::{Request, Response, Status};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, warn, Instrument};
 
 // ---- gRPC service ----
 
@@ -65,6 +92,59 @@ pub struct HelperNodeService {
     pub health_checker: Arc<HealthChecker>,
     /// Shared diagnostics manager state.
     pub diagnostics: Arc<DiagnosticsManager>,
+    /// Declarative node configuration, swapped in place on SIGHUP.
+    pub config: Arc<tokio::sync::RwLock<keel_config::NodeConfig>>,
+    /// Kernel command line used to detect the booted slot (normally `/proc/cmdline`).
+    pub cmdline_path: String,
+    /// CA certificate returned by `GetCaCert` (normally [`mtls::CA_CERT_PATH`]).
+    pub ca_cert_path: String,
+    /// Maintenance mode marker written by keel-init (normally
+    /// [`keel_config::degraded::DEGRADED_STATE_PATH`]).
+    pub degraded_state_path: String,
+    /// Persistent node ID written by keel-init (normally
+    /// [`keel_config::node_id::NODE_ID_PATH`]).
+    pub node_id_path: String,
+    /// os-release file naming the running OS version (normally
+    /// [`image_metadata::OS_RELEASE_PATH`]).
+    pub os_release_path: String,
+    /// Directory bootstrap files are written under (normally
+    /// [`keel_config::bootstrap::BOOTSTRAP_BASE_PATH`]).
+    pub bootstrap_base_path: String,
+    /// Signal file asking keel-init to restart kubelet (normally
+    /// [`keel_config::bootstrap::KUBELET_RESTART_SIGNAL_PATH`]).
+    pub kubelet_restart_signal_path: String,
+    /// Serializes `BootstrapKubernetes` calls, which write shared files.
+    pub bootstrap_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Journal of update attempts (normally
+    /// [`update_journal::UPDATE_JOURNAL_PATH`]).
+    pub update_journal_path: String,
+    /// Reason a reboot is pending (normally
+    /// [`reboot_pending::REBOOT_PENDING_MARKER`]).
+    pub reboot_pending_path: String,
+    /// Boot flag switch recorded this boot (normally
+    /// [`disk::BOOT_NEXT_MARKER`]).
+    pub boot_next_marker_path: String,
+    /// Kernel printk levels (normally [`kernel_log::PRINTK_PATH`]).
+    pub printk_path: String,
+}
+
+impl HelperNodeService {
+    /// Active and inactive partitions, or `None` if detection failed
+    async fn partition_layout(&self) -> (Option<PartitionSlot>, Option<PartitionSlot>) {
+        let to_slot = |p: disk::PartitionInfo| PartitionSlot {
+            device: p.device,
+            index: p.index,
+        };
+        let active = disk::get_active_partition_from(&self.cmdline_path)
+            .map_err(|e| warn!(error = %e, "Failed to detect active partition"))
+            .ok()
+            .map(to_slot);
+        let slots = self.config.read().await.update.slots.clone();
+        let inactive = disk::get_inactive_partition_from(&self.cmdline_path, &slots)
+            .ok()
+            .map(to_slot);
+        (active, inactive)
+    }
 }
 
 #[tonic::async_trait]
@@ -75,11 +155,28 @@ impl NodeService for HelperNodeService {
     ) -> Result<Response<GetStatusResponse>, Status> {
         rbac::authorize(&_request, rbac::Role::Viewer)?;
         debug!("Received get_status request");
+        let (active_partition, inactive_partition) = self.partition_layout().await;
+        let degraded = keel_config::degraded::DegradedState::load(&self.degraded_state_path);
+        let reboot_reason = reboot_pending::pending_reason(
+            &self.reboot_pending_path,
+            &self.boot_next_marker_path,
+            &self.cmdline_path,
+        );
         let reply = GetStatusResponse {
             hostname: "keel-node".to_string(),    // TODO: Get from hostname
             kernel_version: "6.6.14".to_string(), // TODO: Get from uname
-            os_version: "0.1.0".to_string(),
+            os_version: image_metadata::running_os_version_from(&self.os_release_path)
+                .unwrap_or_default(),
             uptime_seconds: 0.0, // TODO: Get from /proc/uptime
+            active_partition,
+            inactive_partition,
+            degraded: degraded.is_some(),
+            degraded_reason: degraded.map(|d| d.describe()).unwrap_or_default(),
+            node_id: keel_config::node_id::read_node_id(&self.node_id_path)
+                .map_err(|e| debug!(error = %e, "Node ID unavailable"))
+                .unwrap_or_default(),
+            reboot_pending: reboot_reason.is_some(),
+            reboot_reason: reboot_reason.unwrap_or_default(),
         };
         Ok(Response::new(reply))
     }
@@ -91,7 +188,7 @@ impl NodeService for HelperNodeService {
         rbac::authorize(&request, rbac::Role::Admin)?;
         let reason = request.into_inner().reason;
         info!(reason = %reason, "Reboot requested");
-        // In real impl, checking authZ then shelling out to reboot or trigger syscall
+        schedule_reboot(self.config.read().await.update.reboot_method);
         Ok(Response::new(RebootResponse { scheduled: true }))
     }
 
@@ -115,40 +212,92 @@ impl NodeService for HelperNodeService {
         } else {
             None
         };
+        let auth_header = download::resolve_auth_header(Some(&req.auth_header), &source_url);
+        let checksum_manifest_url = req.checksum_manifest_url.clone();
+        let manifest_image_url = if checksum_manifest_url.is_empty() {
+            source_url.clone()
+        } else {
+            checksum_manifest::manifest_image_url(&source_url, is_delta, &req.full_image_url)
+                .map_err(Status::invalid_argument)?
+                .to_string()
+        };
+        // A manifest on another host gets its own netrc entry, not ours
+        let manifest_auth_header =
+            download::auth_header_for(auth_header.as_deref(), &source_url, &checksum_manifest_url)
+                .map(str::to_string);
 
         info!(
-            source = %source_url,
+            source = %download::redact_url(&source_url),
             has_sha256 = expected_sha256.is_some(),
+            has_checksum_manifest = !checksum_manifest_url.is_empty(),
             is_delta = is_delta,
             has_fallback = fallback_url.is_some(),
+            has_auth = auth_header.is_some(),
             "Install update requested"
         );
 
-        let output = async_stream::try_stream! {
-            yield UpdateProgress {
-                percentage: 0,
-                message: "Identifying target partition...".to_string(),
-                success: false,
-                download_speed_bps: 0,
-                eta_seconds: 0,
-                phase: "preparing".to_string(),
-                bytes_saved: 0,
+        let (slots, boot_flag_scheme, max_bytes_per_sec, write_block_bytes, staging_dir) = {
+            let config = self.config.read().await;
+            let max_bytes_per_sec = match req.max_bytes_per_sec {
+                0 => config.update.max_bytes_per_sec.unwrap_or(0),
+                limit => limit,
             };
+            (
+                config.update.slots.clone(),
+                config.update.boot_flag_scheme,
+                max_bytes_per_sec,
+                config.update.write_block_bytes.unwrap_or(0),
+                staging::staging_dir(&config.update),
+            )
+        };
+
+        // Parent span for the phase spans emitted by disk and hooks
+        let update_span = tracing::info_span!(
+            "update",
+            source_url = %download::redact_url(&source_url),
+            is_delta = is_delta
+        );
 
-            let inactive = disk::get_inactive_partition()
+        // Filled in as the update progresses and journaled once it ends
+        let attempt = Arc::new(tokio::sync::Mutex::new(
+            update_journal::JournalEntry::start(&source_url, None),
+        ));
+        let journal_entry = attempt.clone();
+
+        let progress = async_stream::try_stream! {
+            yield update_progress(Phase::Preparing, 0, "Identifying target partition...");
+
+            let inactive = disk::get_inactive_partition(&slots)
                 .map_err(|e| Status::internal(format!("Failed to get inactive partition: {}", e)))?;
 
             debug!(device = %inactive.device, index = inactive.index, "Identified inactive partition");
+            attempt.lock().await.target_partition = Some(inactive.index);
 
-            yield UpdateProgress {
-                percentage: 10,
-                message: format!("Target partition identified: {}", inactive.device),
-                success: false,
-                download_speed_bps: 0,
-                eta_seconds: 0,
-                phase: "preparing".to_string(),
-                bytes_saved: 0,
-            };
+            let metadata = image_metadata::verify_image(&source_url, auth_header.as_deref())
+                .await
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::InvalidInput => Status::failed_precondition(e.to_string()),
+                    _ => Status::internal(e.to_string()),
+                })?;
+            attempt.lock().await.version_after = metadata.map(|m| m.version);
+
+            let expected_sha256 = checksum_manifest::resolve_expected_sha256(
+                expected_sha256,
+                &checksum_manifest_url,
+                &manifest_image_url,
+                manifest_auth_header.as_deref(),
+            )
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidInput => Status::failed_precondition(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            })?;
+
+            yield update_progress(
+                Phase::Preparing,
+                10,
+                format!("Target partition identified: {}", inactive.device),
+            );
 
             let phase_msg = if is_delta {
                 format!("Downloading delta and patching to {}...", inactive.device)
@@ -156,41 +305,52 @@ impl NodeService for HelperNodeService {
                 format!("Downloading and flashing to {}...", inactive.device)
             };
 
-            yield UpdateProgress {
-                percentage: 20,
-                message: phase_msg,
-                success: false,
-                download_speed_bps: 0,
-                eta_seconds: 0,
-                phase: "downloading".to_string(),
-                bytes_saved: 0,
-            };
+            yield update_progress(Phase::Downloading, 20, phase_msg);
+
+            // The slot is not bootable again until the new image is verified
+            disk::clear_slot_marker(disk::SLOT_MARKER_DIR, inactive.index).map_err(|e| {
+                Status::internal(format!("Failed to clear flashed-slot marker: {}", e))
+            })?;
 
             // Disk flashing with delta support
-            let bytes_saved = disk::flash_image(
+            let flashed = disk::flash_image(
                 &source_url,
                 &inactive.device,
                 expected_sha256.as_deref(),
                 is_delta,
                 fallback_url.as_deref(),
-            ).await
-                .map_err(|e| Status::internal(format!("Flash error: {}", e)))?;
+                auth_header.as_deref(),
+                max_bytes_per_sec,
+                write_block_bytes,
+                &staging_dir,
+                disk::allow_non_block_targets(),
+            )
+            .instrument(update_span.clone())
+            .await
+            .map_err(|e| Status::internal(format!("Flash error: {}", e)))?;
 
+            let bytes_saved = flashed.bytes_saved;
             if is_delta && bytes_saved > 0 {
                 info!(bytes_saved = bytes_saved, "Delta update saved bandwidth");
             }
+            if let Err(e) = disk::mark_slot_flashed(
+                disk::SLOT_MARKER_DIR,
+                inactive.index,
+                expected_sha256.as_deref(),
+                Some(flashed.image_bytes),
+            ) {
+                warn!(error = %e, "Failed to record flashed slot");
+            }
 
             yield UpdateProgress {
-                percentage: 80,
-                message: "Image flashed. Toggling boot flags...".to_string(),
-                success: false,
-                download_speed_bps: 0,
-                eta_seconds: 0,
-                phase: "verifying".to_string(),
                 bytes_saved,
+                ..update_progress(Phase::Verifying, 80, "Image flashed. Toggling boot flags...")
             };
 
-            disk::switch_boot_partition(inactive.index)
+            let target = inactive.index;
+            disk::blocking(move || disk::switch_boot_partition(target, &slots, boot_flag_scheme))
+                .instrument(update_span.clone())
+                .await
                 .map_err(|e| Status::internal(format!("Failed to switch boot partition: {}", e)))?;
 
             info!(target_partition = inactive.index, "Update installed successfully");
@@ -202,16 +362,30 @@ impl NodeService for HelperNodeService {
             };
 
             yield UpdateProgress {
-                percentage: 100,
-                message: final_msg,
-                success: true,
-                download_speed_bps: 0,
-                eta_seconds: 0,
-                phase: "completed".to_string(),
                 bytes_saved,
+                ..update_progress(Phase::Completed, 100, final_msg)
             };
         };
 
+        // Moved into the stream, so it is dropped with it if the client goes away
+        let journal = update_journal::JournalGuard::new(&self.update_journal_path, journal_entry);
+        let output = async_stream::stream! {
+            let mut journal = journal;
+            let mut progress = Box::pin(progress);
+            while let Some(item) = tokio_stream::StreamExt::next(&mut progress).await {
+                let item: Result<UpdateProgress, Status> = item;
+                let result = match &item {
+                    Ok(p) if p.success => Some(Ok(())),
+                    Ok(_) => None,
+                    Err(status) => Some(Err(status.message().to_string())),
+                };
+                if let Some(result) = result {
+                    journal.finish(result).await;
+                }
+                yield item;
+            }
+        };
+
         Ok(Response::new(Box::pin(output) as Self::InstallUpdateStream))
     }
 
@@ -223,7 +397,7 @@ impl NodeService for HelperNodeService {
         let req = request.into_inner();
 
         info!(
-            source = %req.source_url,
+            source = %download::redact_url(&req.source_url),
             scheduled_at = %req.scheduled_at,
             "Schedule update requested"
         );
@@ -269,7 +443,8 @@ impl NodeService for HelperNodeService {
             None
         };
 
-        let schedule = self
+        // Coalesces with a pending schedule for the same image unless forced
+        let scheduled = self
             .scheduler
             .schedule_update(
                 req.source_url,
@@ -287,9 +462,74 @@ impl NodeService for HelperNodeService {
                 } else {
                     Some(req.full_image_url)
                 },
+                req.prestage,
+                req.force,
             )
             .await
-            .map_err(|e| Status::internal(format!("Failed to schedule update: {}", e)))?;
+            .map_err(|e| match e {
+                update_scheduler::ScheduleError::TooManySchedules { .. } => {
+                    Status::resource_exhausted(e.to_string())
+                }
+                update_scheduler::ScheduleError::Invalid(msg) => Status::invalid_argument(msg),
+                update_scheduler::ScheduleError::Storage(msg) => {
+                    Status::internal(format!("Failed to schedule update: {}", msg))
+                }
+            })?;
+        let schedule = match scheduled {
+            update_scheduler::Scheduled::Created(schedule) => schedule,
+            update_scheduler::Scheduled::Existing(existing) => {
+                info!(
+                    schedule_id = %existing.id,
+                    source = %download::redact_url(&existing.source_url),
+                    "Pending schedule for this source already exists"
+                );
+                return Ok(Response::new(ScheduleUpdateResponse {
+                    schedule_id: existing.id.clone(),
+                    status: existing.status.to_string(),
+                    scheduled_at: existing
+                        .scheduled_at
+                        .map(|dt| dt.to_rfc3339())
+                        .unwrap_or_default(),
+                    duplicate: true,
+                    message: format!(
+                        "A pending schedule for this source already exists ({}); use force to schedule it again",
+                        existing.id
+                    ),
+                }));
+            }
+        };
+
+        // Pre-stage the image in the background if the update is in the future
+        if schedule.prestage
+            && schedule
+                .scheduled_at
+                .is_some_and(|t| t > chrono::Utc::now())
+        {
+            let scheduler = self.scheduler.clone();
+            let id = schedule.id.clone();
+            let source_url = schedule.source_url.clone();
+            let expected_sha256 = schedule.expected_sha256.clone();
+            let staging_dir = staging::staging_dir(&self.config.read().await.update);
+            tokio::spawn(async move {
+                let dest = staging::staged_image_path(&staging_dir, &id);
+                match staging::stage_image(&source_url, expected_sha256.as_deref(), &dest).await {
+                    Ok(_) => {
+                        let path = dest.to_string_lossy().into_owned();
+                        if let Err(e) = scheduler.set_staged_image(&id, path).await {
+                            warn!(schedule_id = %id, error = %e, "Discarding staged image");
+                            staging::remove_staged_image(&dest);
+                        }
+                    }
+                    Err(e) => {
+                        warn!(
+                            schedule_id = %id,
+                            error = %e,
+                            "Pre-staging failed, image will be downloaded at execution"
+                        );
+                    }
+                }
+            });
+        }
 
         Ok(Response::new(ScheduleUpdateResponse {
             schedule_id: schedule.id.clone(),
@@ -298,6 +538,8 @@ impl NodeService for HelperNodeService {
                 .scheduled_at
                 .map(|dt| dt.to_rfc3339())
                 .unwrap_or_default(),
+            duplicate: false,
+            message: String::new(),
         }))
     }
 
@@ -313,6 +555,7 @@ impl NodeService for HelperNodeService {
         let proto_schedules: Vec<ProtoUpdateSchedule> = schedules
             .into_iter()
             .map(|s| ProtoUpdateSchedule {
+                staged: staging::usable_staged_image(&s).is_some(),
                 id: s.id,
                 source_url: s.source_url,
                 expected_sha256: s.expected_sha256.unwrap_or_default(),
@@ -320,6 +563,8 @@ impl NodeService for HelperNodeService {
                 status: s.status.to_string(),
                 enable_auto_rollback: s.enable_auto_rollback,
                 created_at: s.created_at.to_rfc3339(),
+                prestage: s.prestage,
+                confirmed_at: s.confirmed_at.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
             })
             .collect();
 
@@ -338,10 +583,20 @@ impl NodeService for HelperNodeService {
         info!(schedule_id = %req.schedule_id, "Cancel scheduled update requested");
 
         match self.scheduler.cancel_schedule(&req.schedule_id).await {
-            Ok(_) => Ok(Response::new(CancelScheduledUpdateResponse {
-                success: true,
-                message: "Update cancelled successfully".to_string(),
-            })),
+            Ok(_) => {
+                if let Some(path) = self
+                    .scheduler
+                    .get_schedule(&req.schedule_id)
+                    .await
+                    .and_then(|s| s.staged_image_path)
+                {
+                    staging::remove_staged_image(std::path::Path::new(&path));
+                }
+                Ok(Response::new(CancelScheduledUpdateResponse {
+                    success: true,
+                    message: "Update cancelled successfully".to_string(),
+                }))
+            }
             Err(e) => Ok(Response::new(CancelScheduledUpdateResponse {
                 success: false,
                 message: e,
@@ -351,12 +606,26 @@ impl NodeService for HelperNodeService {
 
     async fn get_health(
         &self,
-        _request: Request<GetHealthRequest>,
+        request: Request<GetHealthRequest>,
     ) -> Result<Response<GetHealthResponse>, Status> {
-        rbac::authorize(&_request, rbac::Role::Viewer)?;
-        debug!("Get health requested");
+        rbac::authorize(&request, rbac::Role::Viewer)?;
+        let check_name = request.into_inner().check_name;
+        debug!(check = %check_name, "Get health requested");
 
-        let (status, executions) = self.health_checker.run_all_checks().await;
+        let (status, executions) = if check_name.is_empty() {
+            self.health_checker.run_all_checks().await
+        } else {
+            let execution = self
+                .health_checker
+                .run_check(&check_name)
+                .await
+                .ok_or_else(|| {
+                    Status::not_found(format!("Unknown health check: {}", check_name))
+                })?;
+            let executions = vec![execution];
+            (health_check::overall_status(&executions), executions)
+        };
+        let summary = health_check::health_summary(&executions);
 
         let proto_checks: Vec<ProtoHealthCheckResult> = executions
             .into_iter()
@@ -376,6 +645,45 @@ impl NodeService for HelperNodeService {
             status: status.to_string(),
             checks: proto_checks,
             last_update_time: chrono::Utc::now().to_rfc3339(),
+            summary,
+        }))
+    }
+
+    async fn set_boot_slot(
+        &self,
+        request: Request<SetBootSlotRequest>,
+    ) -> Result<Response<SetBootSlotResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Admin)?;
+        let req = request.into_inner();
+        let (slots, boot_flag_scheme) = {
+            let config = self.config.read().await;
+            (config.update.slots.clone(), config.update.boot_flag_scheme)
+        };
+
+        let index = disk::slot_index(&slots, &req.slot)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let active = disk::get_active_partition_from(&self.cmdline_path)
+            .map_err(|e| Status::internal(format!("Failed to detect active partition: {}", e)))?;
+        disk::check_slot_bootable(disk::SLOT_MARKER_DIR, index, active.index)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        info!(slot = %req.slot, partition = index, reboot = req.reboot, "Boot slot switch requested");
+        disk::blocking(move || disk::switch_boot_partition(index, &slots, boot_flag_scheme))
+            .await
+            .map_err(|e| Status::internal(format!("Failed to switch boot partition: {}", e)))?;
+
+        if req.reboot {
+            schedule_reboot(self.config.read().await.update.reboot_method);
+        }
+
+        Ok(Response::new(SetBootSlotResponse {
+            success: true,
+            message: if req.reboot {
+                format!("Partition {} set as boot slot. Rebooting.", index)
+            } else {
+                format!("Partition {} set as boot slot. Reboot to apply.", index)
+            },
+            partition_index: index,
         }))
     }
 
@@ -384,18 +692,39 @@ impl NodeService for HelperNodeService {
         request: Request<TriggerRollbackRequest>,
     ) -> Result<Response<TriggerRollbackResponse>, Status> {
         rbac::authorize(&request, rbac::Role::Admin)?;
-        let reason = request.into_inner().reason;
+        let req = request.into_inner();
+        let reboot_now = req.reboot.unwrap_or(true);
 
-        info!(reason = %reason, "Manual rollback requested");
+        info!(reason = %req.reason, reboot_now, "Manual rollback requested");
 
         // Perform rollback
-        match disk::rollback_to_previous_partition() {
-            Ok(_) => {
-                info!("Rollback completed successfully");
+        let (slots, boot_flag_scheme, reboot_method) = {
+            let config = self.config.read().await;
+            (
+                config.update.slots.clone(),
+                config.update.boot_flag_scheme,
+                config.update.reboot_method,
+            )
+        };
+        let rollback = disk::blocking(move || {
+            disk::rollback_with_reboot(
+                || disk::rollback_to_previous_partition(&slots, boot_flag_scheme),
+                reboot_now,
+                || schedule_reboot(reboot_method),
+            )
+        });
+        match rollback.await {
+            Ok(reboot_pending) => {
+                info!(reboot_pending, "Rollback completed successfully");
+                let message = if reboot_pending {
+                    "Rollback staged. Reboot to boot the previous partition."
+                } else {
+                    "Rollback completed. System will reboot to previous partition."
+                };
                 Ok(Response::new(TriggerRollbackResponse {
                     success: true,
-                    message: "Rollback completed. System will reboot to previous partition."
-                        .to_string(),
+                    message: message.to_string(),
+                    reboot_pending,
                 }))
             }
             Err(e) => {
@@ -403,6 +732,7 @@ impl NodeService for HelperNodeService {
                 Ok(Response::new(TriggerRollbackResponse {
                     success: false,
                     message: format!("Rollback failed: {}", e),
+                    reboot_pending: false,
                 }))
             }
         }
@@ -437,6 +767,127 @@ impl NodeService for HelperNodeService {
         }))
     }
 
+    async fn get_update_journal(
+        &self,
+        request: Request<GetUpdateJournalRequest>,
+    ) -> Result<Response<GetUpdateJournalResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Viewer)?;
+        let limit = request.into_inner().limit as usize;
+
+        let entries = update_journal::read(&self.update_journal_path)
+            .map_err(|e| Status::internal(format!("Failed to read update journal: {}", e)))?;
+        let limit = if limit == 0 { entries.len() } else { limit };
+        let entries = entries
+            .into_iter()
+            .rev()
+            .take(limit)
+            .map(|e| UpdateJournalEntry {
+                started_at: e.started_at.to_rfc3339(),
+                finished_at: e.finished_at.to_rfc3339(),
+                source_url: e.source_url,
+                schedule_id: e.schedule_id.unwrap_or_default(),
+                target_partition: e.target_partition.unwrap_or_default(),
+                success: e.success,
+                error: e.error.unwrap_or_default(),
+                version_before: e.version_before,
+                version_after: e.version_after.unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(Response::new(GetUpdateJournalResponse { entries }))
+    }
+
+    async fn get_kernel_log_level(
+        &self,
+        request: Request<GetKernelLogLevelRequest>,
+    ) -> Result<Response<GetKernelLogLevelResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Viewer)?;
+        let printk = kernel_log::read_loglevel(&self.printk_path)
+            .map_err(|e| Status::internal(format!("Failed to read kernel log level: {}", e)))?;
+        Ok(Response::new(GetKernelLogLevelResponse {
+            level: printk.console,
+            default_message_level: printk.default_message,
+        }))
+    }
+
+    async fn set_kernel_log_level(
+        &self,
+        request: Request<SetKernelLogLevelRequest>,
+    ) -> Result<Response<SetKernelLogLevelResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Operator)?;
+        let level = kernel_log::validate_level(request.into_inner().level)
+            .map_err(Status::invalid_argument)?;
+
+        let previous = kernel_log::read_loglevel(&self.printk_path)
+            .map_err(|e| Status::internal(format!("Failed to read kernel log level: {}", e)))?;
+        kernel_log::write_loglevel(&self.printk_path, level)
+            .map_err(|e| Status::internal(format!("Failed to set kernel log level: {}", e)))?;
+        info!(
+            previous = previous.console,
+            level, "Kernel log level changed"
+        );
+
+        Ok(Response::new(SetKernelLogLevelResponse {
+            previous_level: previous.console,
+            level,
+        }))
+    }
+
+    async fn get_slot_info(
+        &self,
+        request: Request<GetSlotInfoRequest>,
+    ) -> Result<Response<GetSlotInfoResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Viewer)?;
+        let (slots, boot_flag_scheme) = {
+            let config = self.config.read().await;
+            (config.update.slots.clone(), config.update.boot_flag_scheme)
+        };
+        let active = disk::get_active_partition_from(&self.cmdline_path)
+            .map_err(|e| Status::internal(format!("Failed to detect active partition: {}", e)))?;
+
+        let slots = disk::slot_info(
+            &slots,
+            &active,
+            disk::SLOT_MARKER_DIR,
+            disk::read_gpt_attributes,
+        )
+        .into_iter()
+        .map(|slot| ProtoSlotInfo {
+            legacy_bootable: slot.legacy_bootable(),
+            priority: slot.priority(),
+            boot_flag: slot.boot_flag(boot_flag_scheme),
+            name: slot.name,
+            device: slot.device,
+            index: slot.index,
+            active: slot.active,
+            flashed: slot.marker.is_ok(),
+            flashed_at: slot
+                .marker
+                .as_ref()
+                .map(|m| m.flashed_at.clone())
+                .unwrap_or_default(),
+            image_sha256: slot
+                .marker
+                .as_ref()
+                .ok()
+                .and_then(|m| m.sha256.clone())
+                .unwrap_or_default(),
+            marker_error: slot.marker.err().unwrap_or_default(),
+            attributes: *slot.attributes.as_ref().unwrap_or(&0),
+            attributes_error: slot.attributes.err().unwrap_or_default(),
+        })
+        .collect();
+
+        Ok(Response::new(GetSlotInfoResponse {
+            slots,
+            boot_flag_scheme: match boot_flag_scheme {
+                keel_config::BootFlagScheme::LegacyBios => "legacy_bios",
+                keel_config::BootFlagScheme::PriorityBits => "priority_bits",
+            }
+            .to_string(),
+        }))
+    }
+
     async fn bootstrap_kubernetes(
         &self,
         request: Request<BootstrapKubernetesRequest>,
@@ -470,39 +921,49 @@ impl NodeService for HelperNodeService {
             ));
         }
 
+        // Reject bad labels/taints before touching any bootstrap state
+        let k8s_config = self.config.read().await.kubernetes.clone();
+        let node_metadata =
+            node_metadata::NodeMetadata::parse(&k8s_config.node_labels, &k8s_config.node_taints)
+                .map_err(|e| {
+                    Status::failed_precondition(format!("Invalid node labels/taints: {}", e))
+                })?;
+
         // Determine node name
         let node_name = if !req.node_name.is_empty() {
+            node_metadata::validate_node_name(&req.node_name)
+                .map_err(|e| Status::invalid_argument(format!("Invalid node_name: {}", e)))?;
             req.node_name.clone()
         } else {
             // Use hostname
-            hostname::get()
-                .map_err(|e| Status::internal(format!("Failed to get hostname: {}", e)))?
-                .to_string_lossy()
-                .to_string()
+            let hostname = hostname::get()
+                .map_err(|e| Status::internal(format!("Failed to get hostname: {}", e)))?;
+            node_metadata::node_name_from_hostname(&hostname.to_string_lossy()).map_err(|e| {
+                Status::invalid_argument(format!(
+                    "{}; set a valid hostname or pass node_name explicitly",
+                    e
+                ))
+            })?
         };
 
-        // Prepare Kubernetes directory
-        let base_path = "/var/lib/keel";
-        keel_config::bootstrap::prepare_k8s_directories(base_path)
-            .map_err(|e| Status::internal(format!("Failed to create directories: {}", e)))?;
+        // Concurrent calls would interleave writes to the same files; the
+        // second one waits and then finds the work already done
+        let _bootstrap_guard = self.bootstrap_lock.lock().await;
 
-        let k8s_dir = format!("{}/kubernetes", base_path);
+        let k8s_dir = format!("{}/kubernetes", self.bootstrap_base_path);
         let ca_cert_path = format!("{}/ca.crt", k8s_dir);
         let kubeconfig_path = format!("{}/kubelet.kubeconfig", k8s_dir);
-
-        // Write CA certificate
-        if !req.ca_cert_pem.is_empty() {
-            std::fs::write(&ca_cert_path, &req.ca_cert_pem)
-                .map_err(|e| Status::internal(format!("Failed to write CA certificate: {}", e)))?;
-            info!(path = %ca_cert_path, "CA certificate written");
-        }
+        let bootstrap_state_path = format!("{}/bootstrap.json", k8s_dir);
 
         // Generate or write kubeconfig
         let kubeconfig_content = if !req.kubeconfig.is_empty() {
             // Use provided kubeconfig
-            String::from_utf8(req.kubeconfig).map_err(|e| {
+            let kubeconfig = String::from_utf8(req.kubeconfig).map_err(|e| {
                 Status::invalid_argument(format!("Invalid kubeconfig encoding: {}", e))
-            })?
+            })?;
+            keel_config::bootstrap::validate_kubeconfig(&kubeconfig)
+                .map_err(|e| Status::invalid_argument(format!("Invalid kubeconfig: {}", e)))?;
+            kubeconfig
         } else {
             // Generate kubeconfig from token
             keel_config::bootstrap::generate_kubeconfig(
@@ -514,8 +975,45 @@ impl NodeService for HelperNodeService {
             .map_err(|e| Status::internal(format!("Failed to generate kubeconfig: {}", e)))?
         };
 
+        if let Some(existing) = completed_bootstrap(
+            &bootstrap_state_path,
+            &req.api_server_endpoint,
+            &node_name,
+            &kubeconfig_content,
+            &req.ca_cert_pem,
+        ) {
+            info!(node_name = %node_name, "Node already bootstrapped with these settings");
+            return Ok(Response::new(BootstrapKubernetesResponse {
+                success: true,
+                message: format!(
+                    "Node '{}' is already bootstrapped to {} (since {}); nothing to do.",
+                    existing.node_name, existing.api_server, existing.bootstrapped_at
+                ),
+                kubeconfig_path: existing.kubeconfig_path,
+            }));
+        }
+
+        // Fail now rather than when kubelet first tries to join
+        preflight::probe_api_server(
+            &req.api_server_endpoint,
+            preflight::API_SERVER_PROBE_TIMEOUT,
+        )
+        .await
+        .map_err(|e| Status::unavailable(format!("API server unreachable: {}", e)))?;
+
+        // Prepare Kubernetes directory
+        keel_config::bootstrap::prepare_k8s_directories(&self.bootstrap_base_path)
+            .map_err(|e| Status::internal(format!("Failed to create directories: {}", e)))?;
+
+        // Write CA certificate
+        if !req.ca_cert_pem.is_empty() {
+            keel_config::atomic_write(&ca_cert_path, &req.ca_cert_pem)
+                .map_err(|e| Status::internal(format!("Failed to write CA certificate: {}", e)))?;
+            info!(path = %ca_cert_path, "CA certificate written");
+        }
+
         // Write kubeconfig
-        std::fs::write(&kubeconfig_path, &kubeconfig_content)
+        keel_crypto::write_private_file(&kubeconfig_path, &kubeconfig_content)
             .map_err(|e| Status::internal(format!("Failed to write kubeconfig: {}", e)))?;
         info!(path = %kubeconfig_path, "Kubeconfig written");
 
@@ -527,14 +1025,15 @@ impl NodeService for HelperNodeService {
             ca_cert_path.clone(),
         );
 
-        let bootstrap_state_path = format!("{}/bootstrap.json", k8s_dir);
         bootstrap_config
             .save(&bootstrap_state_path)
             .map_err(|e| Status::internal(format!("Failed to save bootstrap state: {}", e)))?;
 
         // Signal kubelet restart
-        let restart_signal_path = "/run/keel/restart-kubelet";
-        std::fs::create_dir_all("/run/keel").ok();
+        let restart_signal_path = std::path::Path::new(&self.kubelet_restart_signal_path);
+        if let Some(parent) = restart_signal_path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
         std::fs::write(restart_signal_path, "1")
             .map_err(|e| Status::internal(format!("Failed to create restart signal: {}", e)))?;
 
@@ -544,6 +1043,15 @@ impl NodeService for HelperNodeService {
             "Kubernetes bootstrap completed"
         );
 
+        // The Node object only exists once kubelet has registered; taints
+        // reach it through kubelet's --register-with-taints
+        if !node_metadata.labels.is_empty() {
+            tokio::spawn(node_metadata::apply_after_bootstrap(
+                node_name.clone(),
+                node_metadata.labels,
+            ));
+        }
+
         Ok(Response::new(BootstrapKubernetesResponse {
             success: true,
             message: format!(
@@ -561,8 +1069,7 @@ impl NodeService for HelperNodeService {
         rbac::authorize(&_request, rbac::Role::Viewer)?;
         debug!("Get bootstrap status requested");
 
-        let base_path = "/var/lib/keel";
-        let k8s_dir = format!("{}/kubernetes", base_path);
+        let k8s_dir = format!("{}/kubernetes", self.bootstrap_base_path);
         let bootstrap_state_path = format!("{}/bootstrap.json", k8s_dir);
 
         // Check if bootstrapped
@@ -591,10 +1098,68 @@ impl NodeService for HelperNodeService {
         }))
     }
 
+    async fn confirm_update(
+        &self,
+        request: Request<ConfirmUpdateRequest>,
+    ) -> Result<Response<ConfirmUpdateResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Operator)?;
+        let req = request.into_inner();
+
+        info!(schedule_id = %req.schedule_id, "Update confirmation requested");
+
+        if let Err(e) = self.scheduler.confirm_schedule(&req.schedule_id).await {
+            return Ok(Response::new(ConfirmUpdateResponse {
+                success: false,
+                message: e,
+            }));
+        }
+
+        if let Err(e) = disk::clear_boot_counter() {
+            warn!(error = %e, "Failed to clear boot counter after confirmation");
+            return Ok(Response::new(ConfirmUpdateResponse {
+                success: false,
+                message: format!(
+                    "Update confirmed, but clearing the boot counter failed: {}",
+                    e
+                ),
+            }));
+        }
+
+        Ok(Response::new(ConfirmUpdateResponse {
+            success: true,
+            message: "Update confirmed, automatic rollback disarmed".to_string(),
+        }))
+    }
+
+    async fn get_ca_cert(
+        &self,
+        _request: Request<GetCaCertRequest>,
+    ) -> Result<Response<GetCaCertResponse>, Status> {
+        // Public data: clients call this before they can verify the node
+        debug!(path = %self.ca_cert_path, "CA certificate requested");
+
+        let ca_cert_pem =
+            mtls::read_ca_cert_pem(&self.ca_cert_path).map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    Status::not_found(format!("No CA certificate at {}", self.ca_cert_path))
+                }
+                _ => Status::internal(format!("Failed to read CA certificate: {}", e)),
+            })?;
+
+        Ok(Response::new(GetCaCertResponse { ca_cert_pem }))
+    }
+
     async fn init_bootstrap(
         &self,
         request: Request<InitBootstrapRequest>,
     ) -> Result<Response<InitBootstrapResponse>, Status> {
+        if !self.config.read().await.allow_insecure_bootstrap {
+            warn!("Rejected bootstrap certificate initialization: insecure bootstrap is disabled");
+            return Err(Status::permission_denied(
+                "Insecure bootstrap is disabled on this node (allow_insecure_bootstrap: false)",
+            ));
+        }
+
         let req = request.into_inner();
 
         info!("Received bootstrap certificate initialization request");
@@ -669,9 +1234,11 @@ impl NodeService for HelperNodeService {
         info!("Rotating certificate for node: {}", node_name);
 
         // Create K8s CSR manager and request new certificate
+        let csr_max_wait = k8s_csr::csr_max_wait(&*self.config.read().await);
         let csr_manager = K8sCsrManager::new(node_name)
             .await
-            .map_err(|e| Status::internal(format!("Failed to initialize CSR manager: {}", e)))?;
+            .map_err(|e| Status::internal(format!("Failed to initialize CSR manager: {}", e)))?
+            .with_max_wait(csr_max_wait);
 
         match csr_manager.request_certificate().await {
             Ok((cert_pem, key_pem)) => {
@@ -679,30 +1246,8 @@ impl NodeService for HelperNodeService {
                 let cert_path = "/var/lib/keel/crypto/operational.pem";
                 let key_path = "/var/lib/keel/crypto/operational.key";
 
-                // Create backup of old certificates
-                if std::path::Path::new(cert_path).exists() {
-                    let backup_cert = format!("{}.backup", cert_path);
-                    let backup_key = format!("{}.backup", key_path);
-                    if let Err(e) = std::fs::copy(cert_path, &backup_cert) {
-                        warn!("Failed to backup old certificate: {}", e);
-                    }
-                    if let Err(e) = std::fs::copy(key_path, &backup_key) {
-                        warn!("Failed to backup old key: {}", e);
-                    }
-                    info!("Backed up old certificates");
-                }
-
-                // Write new certificates
-                if let Err(e) = std::fs::write(cert_path, &cert_pem) {
-                    return Err(Status::internal(format!(
-                        "Failed to write new certificate: {}",
-                        e
-                    )));
-                }
-
-                if let Err(e) = std::fs::write(key_path, &key_pem) {
-                    return Err(Status::internal(format!("Failed to write new key: {}", e)));
-                }
+                cert_renewal::store_certificate_pair(cert_path, key_path, &cert_pem, &key_pem)
+                    .map_err(Status::internal)?;
 
                 info!("✓ Certificate rotation successful");
 
@@ -999,6 +1544,164 @@ impl NodeService for HelperNodeService {
             })),
         }
     }
+
+    type CollectDiagnosticsStream =
+        Pin<Box<dyn Stream<Item = Result<DiagnosticsChunk, Status>> + Send>>;
+
+    async fn collect_diagnostics(
+        &self,
+        request: Request<CollectDiagnosticsRequest>,
+    ) -> Result<Response<Self::CollectDiagnosticsStream>, Status> {
+        rbac::authorize(&request, rbac::Role::Operator)?;
+        let log_lines = request.into_inner().log_lines;
+        info!(log_lines, "Diagnostic bundle requested");
+
+        let mut entries = self.diagnostic_bundle_entries().await;
+        entries.extend(
+            tokio::task::spawn_blocking(move || {
+                diagnostics::collect_node_bundle_entries(log_lines)
+            })
+            .await
+            .map_err(|e| Status::internal(format!("Failed to collect diagnostics: {e}")))?,
+        );
+
+        let bundle = diagnostics::build_bundle(&entries)
+            .map_err(|e| Status::internal(format!("Failed to build diagnostic bundle: {e}")))?;
+        info!(
+            size = bundle.len(),
+            files = entries.len(),
+            "Diagnostic bundle built"
+        );
+
+        let output = async_stream::try_stream! {
+            for chunk in bundle.chunks(DIAGNOSTICS_CHUNK_SIZE) {
+                yield DiagnosticsChunk { data: chunk.to_vec() };
+            }
+        };
+        Ok(Response::new(
+            Box::pin(output) as Self::CollectDiagnosticsStream
+        ))
+    }
+
+    async fn test_hook(
+        &self,
+        request: Request<TestHookRequest>,
+    ) -> Result<Response<TestHookResponse>, Status> {
+        rbac::authorize(&request, rbac::Role::Admin)?;
+        let req = request.into_inner();
+
+        let phase = match req.phase.as_str() {
+            "pre" | "pre-update" => "pre-update",
+            "post" | "post-update" => "post-update",
+            other => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown hook phase '{}' (use pre or post)",
+                    other
+                )))
+            }
+        };
+        if req.command.trim().is_empty() {
+            return Err(Status::invalid_argument("command is required"));
+        }
+        info!(phase, command = %req.command, "Hook test requested");
+
+        let outcome = hooks::run_hook(
+            &req.command,
+            phase,
+            &[(hooks::HOOK_TEST_ENV, "1")],
+            hooks::HOOK_TIMEOUT,
+        )
+        .await
+        .map_err(Status::failed_precondition)?;
+
+        let message = if outcome.success() {
+            format!("Hook succeeded in {:.1}s", outcome.duration.as_secs_f64())
+        } else {
+            outcome.failure_message(hooks::HOOK_TIMEOUT)
+        };
+        Ok(Response::new(TestHookResponse {
+            success: outcome.success(),
+            exit_code: outcome.exit_code.unwrap_or(-1),
+            stdout: outcome.stdout,
+            stderr: outcome.stderr,
+            timed_out: outcome.timed_out,
+            duration_ms: outcome.duration.as_millis() as u64,
+            message,
+        }))
+    }
+}
+
+/// Size of the pieces a diagnostic bundle is streamed in
+const DIAGNOSTICS_CHUNK_SIZE: usize = 64 * 1024;
+
+impl HelperNodeService {
+    /// Bundle entries built from the agent's own state: status, health
+    /// history, update schedules, and network status
+    async fn diagnostic_bundle_entries(&self) -> Vec<diagnostics::BundleEntry> {
+        use diagnostics::BundleEntry;
+
+        let mut entries = Vec::new();
+        let status = match self.get_status(Request::new(GetStatusRequest {})).await {
+            Ok(response) => format!("{:#?}\n", response.into_inner()),
+            Err(e) => format!("unavailable: {}\n", e.message()),
+        };
+        entries.push(BundleEntry::new("status.txt", status));
+
+        // Only the most recent run is kept, there is no longer history
+        let health = self.health_checker.get_last_results().await;
+        entries.push(BundleEntry::new(
+            "health-last-run.json",
+            serde_json::to_string_pretty(&health).unwrap_or_default(),
+        ));
+
+        let schedules = self.scheduler.get_schedules().await;
+        entries.push(BundleEntry::new(
+            "update-schedules.json",
+            serde_json::to_string_pretty(&schedules).unwrap_or_default(),
+        ));
+
+        let network = match network::get_network_status(Request::new(GetNetworkStatusRequest {
+            with_rates: false,
+            include_loopback: false,
+        }))
+        .await
+        {
+            Ok(response) => format!("{:#?}\n", response.into_inner()),
+            Err(e) => format!("unavailable: {}\n", e.message()),
+        };
+        entries.push(BundleEntry::new("network.txt", network));
+
+        entries
+    }
+}
+
+/// Bootstrap state recorded at `state_path`, if that bootstrap used the
+/// same API server, node name, kubeconfig and CA, so repeating it would
+/// rewrite identical files
+fn completed_bootstrap(
+    state_path: &str,
+    api_server: &str,
+    node_name: &str,
+    kubeconfig: &str,
+    ca_cert_pem: &str,
+) -> Option<keel_config::bootstrap::BootstrapConfig> {
+    let existing = keel_config::bootstrap::BootstrapConfig::load(state_path).ok()?;
+    let same_file = |path: &str, content: &str| {
+        std::fs::read_to_string(path).is_ok_and(|current| current == content)
+    };
+    (existing.api_server == api_server
+        && existing.node_name == node_name
+        && same_file(&existing.kubeconfig_path, kubeconfig)
+        && (ca_cert_pem.is_empty() || same_file(&existing.ca_cert_path, ca_cert_pem)))
+    .then_some(existing)
+}
+
+/// Reboot shortly, leaving time for the RPC response to reach the client
+fn schedule_reboot(method: RebootMethod) {
+    tokio::spawn(async move {
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        shutdown::reboot_with(method).await;
+    });
 }
 
 /// Parse a raw log line and apply filters.
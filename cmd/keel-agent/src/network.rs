@@ -3,13 +3,16 @@
 //! This module provides the implementation for network configuration RPCs.
 
 use keel_api::node::*;
+use std::path::Path;
 use tonic::{Request, Response, Status};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// Configure network interfaces and DNS
 pub async fn configure_network(
     request: Request<ConfigureNetworkRequest>,
 ) -> Result<Response<ConfigureNetworkResponse>, Status> {
+    // The node address this client reached us on
+    let connected = request.local_addr().map(|addr| addr.ip().to_canonical());
     let req = request.into_inner();
 
     info!("Network configuration request received");
@@ -41,6 +44,11 @@ pub async fn configure_network(
                         Some(static_cfg.ipv6_gateway)
                     },
                     ipv6_auto: static_cfg.ipv6_auto,
+                    gateway_metric: if static_cfg.gateway_metric == 0 {
+                        None
+                    } else {
+                        Some(static_cfg.gateway_metric)
+                    },
                 })
             }
             Some(network_interface::Config::Vlan(vlan_cfg)) => {
@@ -65,6 +73,11 @@ pub async fn configure_network(
                                     Some(s.ipv6_gateway)
                                 },
                                 ipv6_auto: s.ipv6_auto,
+                                gateway_metric: if s.gateway_metric == 0 {
+                                    None
+                                } else {
+                                    Some(s.gateway_metric)
+                                },
                             },
                         )
                     }
@@ -106,6 +119,11 @@ pub async fn configure_network(
                                     Some(s.ipv6_gateway)
                                 },
                                 ipv6_auto: s.ipv6_auto,
+                                gateway_metric: if s.gateway_metric == 0 {
+                                    None
+                                } else {
+                                    Some(s.gateway_metric)
+                                },
                             },
                         )
                     }
@@ -136,6 +154,8 @@ pub async fn configure_network(
         config.dns = Some(keel_config::network::DnsConfig {
             nameservers: dns.nameservers,
             search_domains: dns.search_domains,
+            dns_over_tls: dns.dns_over_tls,
+            tls_servername: (!dns.tls_servername.is_empty()).then_some(dns.tls_servername),
         });
     }
 
@@ -152,33 +172,85 @@ pub async fn configure_network(
         });
     }
 
+    // Interface aliases are only set in the config file, keep them
+    let previous = keel_config::network::NetworkConfig::load().unwrap_or_default();
+    config.interface_aliases = previous.interface_aliases.clone();
+    config.allow_overlapping_subnets = req.allow_overlapping_subnets;
+
     // Validate configuration
-    if let Err(e) = config.validate() {
+    if let Err(e) = config.validate_new() {
         return Err(Status::invalid_argument(format!(
             "Invalid network configuration: {}",
             e
         )));
     }
 
+    check_stub_resolver(&config, Path::new(keel_config::network::STUB_RESOLVER_PATH))?;
+
+    // Work out whether anything changed that only keel-init can apply at boot.
+    // An earlier save may not have been applied yet, so compare against what
+    // is running rather than what was saved.
+    let applied = applied_config(
+        Path::new(keel_config::network::APPLIED_CONFIG_PATH),
+        &previous,
+    );
+    let mut reboot_required = config.requires_reboot(&applied);
+
+    if reboot_required && req.apply_now {
+        check_connected_address(&applied, &config, connected)?;
+    }
+
     // Save configuration
     match config.save() {
         Ok(_) => {
-            info!("Network configuration saved successfully");
+            info!(reboot_required, "Network configuration saved successfully");
+
+            let message = if reboot_required && req.apply_now {
+                // keel-init picks up the marker and re-applies in place
+                request_network_reapply(REAPPLY_NETWORK_MARKER, connected).map_err(|e| {
+                    error!(error = %e, "Failed to request network re-apply");
+                    Status::internal(format!(
+                        "Configuration saved but failed to request re-apply: {}",
+                        e
+                    ))
+                })?;
+                reboot_required = false;
+                "Network configuration saved. keel-init will apply it shortly.".to_string()
+            } else if reboot_required {
+                if let Err(e) = crate::reboot_pending::mark(
+                    crate::reboot_pending::REBOOT_PENDING_MARKER,
+                    "network configuration changed",
+                ) {
+                    warn!(error = %e, "Failed to record pending reboot");
+                }
+                "Network configuration saved. Changes will apply on next boot.".to_string()
+            } else {
+                // Only DNS changed (if anything), which can be applied live
+                if let Some(dns) = &config.dns {
+                    apply_dns_live(dns).map_err(|e| {
+                        error!(error = %e, "Failed to apply DNS configuration");
+                        Status::internal(format!(
+                            "Configuration saved but failed to apply DNS: {}",
+                            e
+                        ))
+                    })?;
+                }
+                "Network configuration saved and applied. No reboot required.".to_string()
+            };
 
-            // Auto-reboot if requested
-            if req.auto_reboot {
+            // Auto-reboot if requested and needed
+            if req.auto_reboot && reboot_required {
                 info!("Auto-reboot requested, scheduling reboot");
                 tokio::spawn(async {
                     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                    let _ = std::process::Command::new("reboot").status();
+                    crate::shutdown::reboot().await;
                 });
             }
 
             Ok(Response::new(ConfigureNetworkResponse {
                 success: true,
-                message: "Network configuration saved. Changes will apply on next boot."
-                    .to_string(),
-                reboot_required: true,
+                message,
+                reboot_required,
             }))
         }
         Err(e) => {
@@ -191,6 +263,87 @@ pub async fn configure_network(
     }
 }
 
+/// Refuse DNS-over-TLS on a node without the stub resolver at `binary`
+///
+/// keel-init would point `/etc/resolv.conf` at a resolver that never
+/// starts, taking down all DNS on the node.
+fn check_stub_resolver(
+    config: &keel_config::network::NetworkConfig,
+    binary: &Path,
+) -> Result<(), Status> {
+    if config.dns.as_ref().is_some_and(|dns| dns.dns_over_tls) && !binary.exists() {
+        return Err(Status::failed_precondition(format!(
+            "dns_over_tls needs the stub resolver at {}, which this image does not include",
+            binary.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Configuration on the interfaces: what keel-init recorded at
+/// `applied_path`, or the `saved` one if it recorded none
+fn applied_config(
+    applied_path: &Path,
+    saved: &keel_config::network::NetworkConfig,
+) -> keel_config::network::NetworkConfig {
+    keel_config::network::NetworkConfig::load_from(applied_path).unwrap_or_else(|e| {
+        debug!(path = %applied_path.display(), error = %e, "No applied network configuration, using the saved one");
+        saved.clone()
+    })
+}
+
+/// Refuse a live re-apply that would remove the address the client is
+/// connected through
+///
+/// keel-init only removes addresses of the `applied` configuration that
+/// `config` drops, so only those are checked. Applying at the next boot
+/// instead is still allowed.
+fn check_connected_address(
+    applied: &keel_config::network::NetworkConfig,
+    config: &keel_config::network::NetworkConfig,
+    connected: Option<std::net::IpAddr>,
+) -> Result<(), Status> {
+    match connected {
+        Some(ip) if applied.has_address(ip) && !config.has_address(ip) => {
+            Err(Status::failed_precondition(format!(
+                "apply_now would remove {}, the address this connection uses; connect through another address or apply at the next boot",
+                ip
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Marker asking keel-init to re-apply the saved network configuration
+pub const REAPPLY_NETWORK_MARKER: &str = "/run/keel/reapply-network";
+
+/// Drop the re-apply marker for keel-init's supervision loop to pick up
+///
+/// The marker holds the `connected` address, which keel-init keeps on the
+/// interface whatever the new configuration says.
+fn request_network_reapply(
+    marker: &str,
+    connected: Option<std::net::IpAddr>,
+) -> std::io::Result<()> {
+    if let Some(parent) = std::path::Path::new(marker).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let contents = connected.map(|ip| ip.to_string()).unwrap_or_default();
+    std::fs::write(marker, contents)?;
+    info!(marker, "Requested in-place network re-apply");
+    Ok(())
+}
+
+/// Path of the resolver configuration rewritten for live DNS changes
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Apply DNS settings immediately by rewriting `/etc/resolv.conf`
+fn apply_dns_live(dns: &keel_config::network::DnsConfig) -> std::io::Result<()> {
+    keel_config::atomic_write(RESOLV_CONF_PATH, dns.to_resolv_conf("keel-agent"))?;
+    info!(path = RESOLV_CONF_PATH, "DNS configuration applied live");
+    Ok(())
+}
+
 /// Get current network configuration
 pub async fn get_network_config(
     _request: Request<GetNetworkConfigRequest>,
@@ -218,6 +371,7 @@ pub async fn get_network_config(
                                 ipv6_addresses: cfg.ipv6_addresses,
                                 ipv6_gateway: cfg.ipv6_gateway.unwrap_or_default(),
                                 ipv6_auto: cfg.ipv6_auto,
+                                gateway_metric: cfg.gateway_metric.unwrap_or(0),
                             }))
                         }
                         keel_config::network::InterfaceType::Vlan(cfg) => {
@@ -233,6 +387,7 @@ pub async fn get_network_config(
                                         ipv6_addresses: s.ipv6_addresses,
                                         ipv6_gateway: s.ipv6_gateway.unwrap_or_default(),
                                         ipv6_auto: s.ipv6_auto,
+                                        gateway_metric: s.gateway_metric.unwrap_or(0),
                                     }))
                                 }
                             };
@@ -256,6 +411,7 @@ pub async fn get_network_config(
                                         ipv6_addresses: s.ipv6_addresses,
                                         ipv6_gateway: s.ipv6_gateway.unwrap_or_default(),
                                         ipv6_auto: s.ipv6_auto,
+                                        gateway_metric: s.gateway_metric.unwrap_or(0),
                                     }))
                                 }
                             };
@@ -278,6 +434,8 @@ pub async fn get_network_config(
             let proto_dns = config.dns.map(|dns| DnsConfig {
                 nameservers: dns.nameservers,
                 search_domains: dns.search_domains,
+                dns_over_tls: dns.dns_over_tls,
+                tls_servername: dns.tls_servername.unwrap_or_default(),
             });
 
             let proto_routes: Vec<NetworkRoute> = config
@@ -308,130 +466,122 @@ pub async fn get_network_config(
     }
 }
 
+/// Sysfs directory with one entry per network interface
+pub const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// Interfaces under `sys_class_net` with their link state, sorted by name
+///
+/// Interfaces are listed whatever their state, so a down link without
+/// addresses still shows up; the state is the lowercased `operstate`, or
+/// `unknown` if it cannot be read. Loopback is skipped unless
+/// `include_loopback` is set.
+pub fn list_interfaces(sys_class_net: &Path, include_loopback: bool) -> Vec<(String, String)> {
+    let Ok(entries) = std::fs::read_dir(sys_class_net) else {
+        return vec![];
+    };
+    let mut interfaces: Vec<(String, String)> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| include_loopback || name != "lo")
+        .map(|name| {
+            let state = std::fs::read_to_string(sys_class_net.join(&name).join("operstate"))
+                .map(|state| state.trim().to_lowercase())
+                .unwrap_or_else(|_| "unknown".to_string());
+            (name, state)
+        })
+        .collect();
+    interfaces.sort();
+    interfaces
+}
+
 /// Get runtime network status
 pub async fn get_network_status(
-    _request: Request<GetNetworkStatusRequest>,
+    request: Request<GetNetworkStatusRequest>,
 ) -> Result<Response<GetNetworkStatusResponse>, Status> {
-    debug!("Get network status requested");
+    let req = request.into_inner();
+    let with_rates = req.with_rates;
+    debug!(
+        with_rates,
+        include_loopback = req.include_loopback,
+        "Get network status requested"
+    );
 
     let mut interfaces = Vec::new();
 
-    // Read /sys/class/net to get all network interfaces
-    if let Ok(entries) = std::fs::read_dir("/sys/class/net") {
-        for entry in entries.flatten() {
-            if let Some(iface_name) = entry.file_name().to_str() {
-                // Skip loopback
-                if iface_name == "lo" {
-                    continue;
-                }
-
-                let mut iface_status = InterfaceStatus {
-                    name: iface_name.to_string(),
-                    state: "unknown".to_string(),
-                    ipv4_addresses: vec![],
-                    mac_address: String::new(),
-                    mtu: 0,
-                    statistics: None,
-                    ipv6_addresses: vec![],
-                    ipv6_address_info: vec![],
-                };
-
-                // Read operstate
-                let operstate_path = format!("/sys/class/net/{}/operstate", iface_name);
-                if let Ok(state) = std::fs::read_to_string(&operstate_path) {
-                    iface_status.state = state.trim().to_lowercase();
-                }
+    for (name, state) in list_interfaces(Path::new(SYS_CLASS_NET), req.include_loopback) {
+        let iface_name = name.as_str();
+        let mut iface_status = InterfaceStatus {
+            name: name.clone(),
+            state,
+            ipv4_addresses: vec![],
+            mac_address: String::new(),
+            mtu: 0,
+            statistics: None,
+            ipv6_addresses: vec![],
+            ipv6_address_info: vec![],
+        };
 
-                // Read MAC address
-                let mac_path = format!("/sys/class/net/{}/address", iface_name);
-                if let Ok(mac) = std::fs::read_to_string(&mac_path) {
-                    iface_status.mac_address = mac.trim().to_string();
-                }
+        // Read MAC address
+        let mac_path = format!("{}/{}/address", SYS_CLASS_NET, iface_name);
+        if let Ok(mac) = std::fs::read_to_string(&mac_path) {
+            iface_status.mac_address = mac.trim().to_string();
+        }
 
-                // Read MTU
-                let mtu_path = format!("/sys/class/net/{}/mtu", iface_name);
-                if let Ok(mtu_str) = std::fs::read_to_string(&mtu_path) {
-                    if let Ok(mtu) = mtu_str.trim().parse::<u32>() {
-                        iface_status.mtu = mtu;
-                    }
-                }
+        // Read MTU
+        let mtu_path = format!("{}/{}/mtu", SYS_CLASS_NET, iface_name);
+        if let Ok(mtu_str) = std::fs::read_to_string(&mtu_path) {
+            if let Ok(mtu) = mtu_str.trim().parse::<u32>() {
+                iface_status.mtu = mtu;
+            }
+        }
 
-                // Get IP addresses using ip command
-                if let Ok(output) = std::process::Command::new("/bin/ip")
-                    .args(["-4", "addr", "show", iface_name])
-                    .output()
-                {
-                    if let Ok(stdout) = String::from_utf8(output.stdout) {
-                        for line in stdout.lines() {
-                            if line.trim().starts_with("inet ") {
-                                if let Some(addr) = line.split_whitespace().nth(1) {
-                                    iface_status.ipv4_addresses.push(addr.to_string());
-                                }
-                            }
+        // Get IP addresses using ip command
+        if let Ok(output) = std::process::Command::new("/bin/ip")
+            .args(["-4", "addr", "show", iface_name])
+            .output()
+        {
+            if let Ok(stdout) = String::from_utf8(output.stdout) {
+                for line in stdout.lines() {
+                    if line.trim().starts_with("inet ") {
+                        if let Some(addr) = line.split_whitespace().nth(1) {
+                            iface_status.ipv4_addresses.push(addr.to_string());
                         }
                     }
                 }
+            }
+        }
 
-                // Get IPv6 addresses with detailed info using ip command
-                if let Ok(output) = std::process::Command::new("/bin/ip")
-                    .args(["-6", "addr", "show", iface_name])
-                    .output()
-                {
-                    if let Ok(stdout) = String::from_utf8(output.stdout) {
-                        for line in stdout.lines() {
-                            if let Some(info) = parse_ipv6_address_line(line) {
-                                iface_status.ipv6_addresses.push(info.address.clone());
-                                iface_status.ipv6_address_info.push(info);
-                            }
-                        }
+        // Get IPv6 addresses with detailed info using ip command
+        if let Ok(output) = std::process::Command::new("/bin/ip")
+            .args(["-6", "addr", "show", iface_name])
+            .output()
+        {
+            if let Ok(stdout) = String::from_utf8(output.stdout) {
+                for line in stdout.lines() {
+                    if let Some(info) = parse_ipv6_address_line(line) {
+                        iface_status.ipv6_addresses.push(info.address.clone());
+                        iface_status.ipv6_address_info.push(info);
                     }
                 }
+            }
+        }
 
-                // Read statistics
-                let rx_bytes_path = format!("/sys/class/net/{}/statistics/rx_bytes", iface_name);
-                let tx_bytes_path = format!("/sys/class/net/{}/statistics/tx_bytes", iface_name);
-                let rx_packets_path =
-                    format!("/sys/class/net/{}/statistics/rx_packets", iface_name);
-                let tx_packets_path =
-                    format!("/sys/class/net/{}/statistics/tx_packets", iface_name);
-                let rx_errors_path = format!("/sys/class/net/{}/statistics/rx_errors", iface_name);
-                let tx_errors_path = format!("/sys/class/net/{}/statistics/tx_errors", iface_name);
-
-                let rx_bytes = std::fs::read_to_string(&rx_bytes_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-                let tx_bytes = std::fs::read_to_string(&tx_bytes_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-                let rx_packets = std::fs::read_to_string(&rx_packets_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-                let tx_packets = std::fs::read_to_string(&tx_packets_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-                let rx_errors = std::fs::read_to_string(&rx_errors_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-                let tx_errors = std::fs::read_to_string(&tx_errors_path)
-                    .ok()
-                    .and_then(|s| s.trim().parse::<u64>().ok())
-                    .unwrap_or(0);
-
-                iface_status.statistics = Some(InterfaceStatistics {
-                    rx_bytes,
-                    tx_bytes,
-                    rx_packets,
-                    tx_packets,
-                    rx_errors,
-                    tx_errors,
-                });
+        // Read statistics
+        iface_status.statistics = Some(read_interface_statistics(iface_name));
+
+        interfaces.push(iface_status);
+    }
 
-                interfaces.push(iface_status);
+    if with_rates {
+        let started = std::time::Instant::now();
+        tokio::time::sleep(std::time::Duration::from_millis(RATE_SAMPLE_INTERVAL_MS)).await;
+        let elapsed = started.elapsed();
+
+        for iface in &mut interfaces {
+            let after = read_interface_statistics(&iface.name);
+            if let Some(before) = iface.statistics.as_mut() {
+                before.rx_bps = compute_rate_bps(before.rx_bytes, after.rx_bytes, elapsed);
+                before.tx_bps = compute_rate_bps(before.tx_bytes, after.tx_bytes, elapsed);
             }
         }
     }
@@ -439,6 +589,47 @@ pub async fn get_network_status(
     Ok(Response::new(GetNetworkStatusResponse { interfaces }))
 }
 
+/// Interval between the two counter samples used to compute rates
+const RATE_SAMPLE_INTERVAL_MS: u64 = 1000;
+
+/// Read the cumulative counters for an interface from sysfs
+///
+/// Missing or unreadable counters are reported as 0.
+fn read_interface_statistics(iface_name: &str) -> InterfaceStatistics {
+    let read_counter = |name: &str| {
+        std::fs::read_to_string(format!(
+            "{}/{}/statistics/{}",
+            SYS_CLASS_NET, iface_name, name
+        ))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(0)
+    };
+
+    InterfaceStatistics {
+        rx_bytes: read_counter("rx_bytes"),
+        tx_bytes: read_counter("tx_bytes"),
+        rx_packets: read_counter("rx_packets"),
+        tx_packets: read_counter("tx_packets"),
+        rx_errors: read_counter("rx_errors"),
+        tx_errors: read_counter("tx_errors"),
+        rx_bps: 0,
+        tx_bps: 0,
+    }
+}
+
+/// Compute a rate in bits per second from two byte-counter snapshots
+///
+/// Returns 0 if the counter went backwards (interface reset or counter
+/// wrap) or if no time elapsed between the samples.
+fn compute_rate_bps(before_bytes: u64, after_bytes: u64, elapsed: std::time::Duration) -> u64 {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 || after_bytes < before_bytes {
+        return 0;
+    }
+    ((after_bytes - before_bytes) as f64 * 8.0 / secs) as u64
+}
+
 /// Parse a single line from `ip -6 addr show` output
 /// Example: "    inet6 2001:db8::1/64 scope global dynamic"
 /// Example: "    inet6 fe80::a00:27ff:fe4e:66a1/64 scope link"
@@ -489,3 +680,154 @@ fn parse_ipv6_address_line(line: &str) -> Option<IPv6AddressInfo> {
         flags,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_compute_rate_bps() {
+        // 125_000 bytes over one second is 1 Mbit/s
+        assert_eq!(
+            compute_rate_bps(1_000, 126_000, Duration::from_secs(1)),
+            1_000_000
+        );
+        // Same delta over half a second doubles the rate
+        assert_eq!(
+            compute_rate_bps(1_000, 126_000, Duration::from_millis(500)),
+            2_000_000
+        );
+    }
+
+    #[test]
+    fn test_list_interfaces_sorted_with_down_links() {
+        let sys = tempfile::TempDir::new().unwrap();
+        for (name, operstate) in [
+            ("lo", Some("unknown\n")),
+            ("eth1", Some("down\n")),
+            ("eth0", Some("up\n")),
+            ("bond0", None),
+        ] {
+            let dir = sys.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            if let Some(operstate) = operstate {
+                std::fs::write(dir.join("operstate"), operstate).unwrap();
+            }
+        }
+
+        let state = |name: &str, state: &str| (name.to_string(), state.to_string());
+        assert_eq!(
+            list_interfaces(sys.path(), false),
+            [
+                state("bond0", "unknown"),
+                state("eth0", "up"),
+                state("eth1", "down"),
+            ]
+        );
+        let all = list_interfaces(sys.path(), true);
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[3], state("lo", "unknown"));
+
+        assert!(list_interfaces(&sys.path().join("missing"), true).is_empty());
+    }
+
+    #[test]
+    fn test_compute_rate_bps_counter_reset() {
+        assert_eq!(compute_rate_bps(5_000, 100, Duration::from_secs(1)), 0);
+    }
+
+    #[test]
+    fn test_compute_rate_bps_zero_elapsed() {
+        assert_eq!(compute_rate_bps(0, 1_000, Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_dns_over_tls_requires_stub_resolver() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let binary = dir.path().join("unbound");
+        let mut config = keel_config::network::NetworkConfig::new();
+        config.dns = Some(keel_config::network::DnsConfig {
+            nameservers: vec!["1.1.1.1".to_string()],
+            search_domains: vec![],
+            dns_over_tls: false,
+            tls_servername: None,
+        });
+
+        // Plain DNS does not need it
+        assert!(check_stub_resolver(&config, &binary).is_ok());
+
+        config.dns.as_mut().unwrap().dns_over_tls = true;
+        let err = check_stub_resolver(&config, &binary).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        std::fs::write(&binary, b"").unwrap();
+        assert!(check_stub_resolver(&config, &binary).is_ok());
+    }
+
+    fn static_eth0(address: &str) -> keel_config::network::NetworkConfig {
+        use keel_config::network::*;
+        let mut config = NetworkConfig::new();
+        config.interfaces.push(InterfaceConfig {
+            name: "eth0".to_string(),
+            config: InterfaceType::Static(StaticConfig {
+                ipv4_address: address.to_string(),
+                gateway: None,
+                mtu: 1500,
+                ipv6_addresses: vec![],
+                ipv6_gateway: None,
+                ipv6_auto: false,
+                gateway_metric: None,
+            }),
+        });
+        config
+    }
+
+    #[test]
+    fn test_live_apply_keeps_connected_address() {
+        let applied = static_eth0("10.0.0.5/24");
+        let connected = Some("10.0.0.5".parse().unwrap());
+
+        // Keeping the address, or connecting through one keel does not own
+        assert!(check_connected_address(&applied, &applied, connected).is_ok());
+        let moved = static_eth0("10.0.0.6/24");
+        assert!(
+            check_connected_address(&applied, &moved, Some("10.0.0.200".parse().unwrap())).is_ok()
+        );
+        assert!(check_connected_address(&applied, &moved, None).is_ok());
+
+        let err = check_connected_address(&applied, &moved, connected).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn test_reboot_required_against_applied_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let applied_path = dir.path().join("network-applied.json");
+        let running = static_eth0("10.0.0.5/24");
+        let saved = static_eth0("10.0.0.6/24");
+
+        // Without a record of the running state the saved one is used
+        assert_eq!(applied_config(&applied_path, &saved), saved);
+
+        // Saving the same pending change again still needs the reboot
+        running.save_to(&applied_path).unwrap();
+        let applied = applied_config(&applied_path, &saved);
+        assert_eq!(applied, running);
+        assert!(saved.requires_reboot(&applied));
+        assert!(!running.requires_reboot(&applied));
+    }
+
+    #[test]
+    fn test_reapply_marker_names_connected_address() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let marker = dir.path().join("keel").join("reapply-network");
+        let marker = marker.to_str().unwrap();
+
+        request_network_reapply(marker, Some("10.0.0.5".parse().unwrap())).unwrap();
+        assert_eq!(std::fs::read_to_string(marker).unwrap(), "10.0.0.5");
+
+        request_network_reapply(marker, None).unwrap();
+        assert_eq!(std::fs::read_to_string(marker).unwrap(), "");
+    }
+}
@@ -0,0 +1,477 @@
+//! Node labels and taints from `NodeConfig.kubernetes`
+//!
+//! After a bootstrap the agent waits for kubelet to register the Node object
+//! and then patches it with the configured labels, using kubelet's own
+//! client credential. NodeRestriction only lets that identity set labels
+//! outside the `kubernetes.io`/`k8s.io` namespaces (bar a few well-known
+//! ones) and never lets it change taints, so restricted labels are rejected
+//! up front and taints are handed to kubelet as `--register-with-taints` by
+//! keel-init. Labels use the kubelet `--node-labels` syntax (`key=value`),
+//! taints the `--register-with-taints` syntax (`key[=value]:Effect`).
+
+use k8s_openapi::api::core::v1::{Node, Taint};
+use keel_config::bootstrap::KUBELET_KUBECONFIG_PATH;
+use kube::api::{Api, Patch, PatchParams};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::Client;
+use std::collections::BTreeMap;
+use tracing::{debug, info, warn};
+
+/// How often the Node object is looked for while kubelet registers
+const REGISTRATION_POLL_SECS: u64 = 10;
+
+/// How many times the Node object is looked for before giving up
+const REGISTRATION_ATTEMPTS: u32 = 60;
+
+const TAINT_EFFECTS: &[&str] = &["NoSchedule", "PreferNoSchedule", "NoExecute"];
+
+/// Label namespaces under `kubernetes.io`/`k8s.io` a node may set on itself
+const NODE_ALLOWED_LABEL_NAMESPACES: &[&str] = &["kubelet.kubernetes.io", "node.kubernetes.io"];
+
+/// Individual `kubernetes.io` labels a node may set on itself
+const NODE_ALLOWED_LABELS: &[&str] = &[
+    "kubernetes.io/hostname",
+    "kubernetes.io/arch",
+    "kubernetes.io/os",
+    "beta.kubernetes.io/arch",
+    "beta.kubernetes.io/os",
+    "beta.kubernetes.io/instance-type",
+    "node.kubernetes.io/instance-type",
+    "topology.kubernetes.io/region",
+    "topology.kubernetes.io/zone",
+    "failure-domain.beta.kubernetes.io/region",
+    "failure-domain.beta.kubernetes.io/zone",
+];
+
+/// Whether `namespace` is `domain` or a subdomain of it
+fn in_namespace(namespace: &str, domain: &str) -> bool {
+    namespace == domain
+        || namespace
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Reject labels the NodeRestriction admission plugin forbids a node to set
+/// (e.g. `node-role.kubernetes.io/*`)
+fn check_node_settable(key: &str) -> Result<(), String> {
+    let Some((namespace, _)) = key.split_once('/') else {
+        return Ok(());
+    };
+    let restricted = ["kubernetes.io", "k8s.io"]
+        .iter()
+        .any(|domain| in_namespace(namespace, domain));
+    let allowed = NODE_ALLOWED_LABELS.contains(&key)
+        || NODE_ALLOWED_LABEL_NAMESPACES
+            .iter()
+            .any(|domain| in_namespace(namespace, domain));
+    if restricted && !allowed {
+        return Err(format!(
+            "label '{}' is in the restricted {} namespace, which a node cannot set on itself",
+            key, namespace
+        ));
+    }
+    Ok(())
+}
+
+/// `[A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?`, at most 63 characters
+fn is_qualified_name_part(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= 63
+        && bytes[0].is_ascii_alphanumeric()
+        && bytes[bytes.len() - 1].is_ascii_alphanumeric()
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Lowercase RFC 1123 subdomain, at most 253 characters
+fn is_dns_subdomain(s: &str) -> bool {
+    s.len() <= 253
+        && s.split('.').all(|label| {
+            let bytes = label.as_bytes();
+            !bytes.is_empty()
+                && bytes.len() <= 63
+                && bytes[0].is_ascii_alphanumeric()
+                && bytes[bytes.len() - 1].is_ascii_alphanumeric()
+                && bytes
+                    .iter()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+        })
+}
+
+/// Node name derived from a hostname
+///
+/// Kubernetes only accepts lowercase RFC 1123 names, so the hostname is
+/// lowercased, a trailing dot dropped and underscores turned into hyphens.
+/// Anything still invalid afterwards is rejected rather than guessed at.
+pub fn node_name_from_hostname(hostname: &str) -> Result<String, String> {
+    let name = hostname
+        .trim()
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+        .replace('_', "-");
+    validate_node_name(&name).map(|()| name).map_err(|e| {
+        format!(
+            "hostname '{}' is not usable as a node name: {}",
+            hostname, e
+        )
+    })
+}
+
+/// Check that `name` is a valid Node name (lowercase RFC 1123 subdomain)
+pub fn validate_node_name(name: &str) -> Result<(), String> {
+    if is_dns_subdomain(name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' must consist of lowercase letters, digits, '-' and '.', start and end \
+             with a letter or digit, and be at most 253 characters (63 per label)",
+            name
+        ))
+    }
+}
+
+/// Validate a label or taint key (`[prefix/]name`)
+fn validate_key(key: &str) -> Result<(), String> {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    if prefix.is_some_and(|p| !is_dns_subdomain(p)) {
+        return Err(format!("invalid key prefix in '{}'", key));
+    }
+    if !is_qualified_name_part(name) {
+        return Err(format!("invalid key name in '{}'", key));
+    }
+    Ok(())
+}
+
+/// Validate a label or taint value (may be empty)
+fn validate_value(key: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() || is_qualified_name_part(value) {
+        Ok(())
+    } else {
+        Err(format!("invalid value '{}' for '{}'", value, key))
+    }
+}
+
+/// Parse a `key=value` label
+pub fn parse_label(label: &str) -> Result<(String, String), String> {
+    let (key, value) = label
+        .split_once('=')
+        .ok_or_else(|| format!("label '{}' must be key=value", label))?;
+    validate_key(key)?;
+    validate_value(key, value)?;
+    check_node_settable(key)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse a `key[=value]:Effect` taint
+pub fn parse_taint(taint: &str) -> Result<Taint, String> {
+    let (key_value, effect) = taint
+        .rsplit_once(':')
+        .ok_or_else(|| format!("taint '{}' must be key[=value]:Effect", taint))?;
+    if !TAINT_EFFECTS.contains(&effect) {
+        return Err(format!(
+            "invalid effect '{}' in taint '{}' (expected one of {})",
+            effect,
+            taint,
+            TAINT_EFFECTS.join(", ")
+        ));
+    }
+    let (key, value) = match key_value.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (key_value, None),
+    };
+    validate_key(key)?;
+    if let Some(value) = value {
+        validate_value(key, value)?;
+    }
+
+    Ok(Taint {
+        key: key.to_string(),
+        value: value.filter(|v| !v.is_empty()).map(str::to_string),
+        effect: effect.to_string(),
+        time_added: None,
+    })
+}
+
+/// Parsed labels and taints for the Node
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeMetadata {
+    pub labels: BTreeMap<String, String>,
+    pub taints: Vec<Taint>,
+}
+
+impl NodeMetadata {
+    /// Parse configured labels and taints, failing on the first invalid entry
+    pub fn parse(labels: &[String], taints: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            labels: labels
+                .iter()
+                .map(|l| parse_label(l))
+                .collect::<Result<_, _>>()?,
+            taints: taints
+                .iter()
+                .map(|t| parse_taint(t))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.taints.is_empty()
+    }
+}
+
+/// Merge patch setting `labels` on a Node
+pub fn build_node_patch(labels: &BTreeMap<String, String>) -> serde_json::Value {
+    serde_json::json!({ "metadata": { "labels": labels } })
+}
+
+/// Patch the Node with `labels`
+pub async fn apply(
+    client: Client,
+    node_name: &str,
+    labels: &BTreeMap<String, String>,
+) -> kube::Result<()> {
+    let nodes: Api<Node> = Api::all(client);
+    nodes
+        .patch(
+            node_name,
+            &PatchParams::default(),
+            &Patch::Merge(build_node_patch(labels)),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Client using kubelet's rotated credential, once kubelet has one
+///
+/// The bootstrap kubeconfig only carries a token that may create CSRs;
+/// patching the Node needs the `system:node:<name>` identity kubelet gets
+/// from TLS bootstrap.
+pub(crate) async fn kubelet_client(path: &str) -> Result<Option<Client>, String> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(None);
+    }
+    client_from_kubeconfig(path)
+        .await
+        .map(Some)
+        .map_err(|e| format!("invalid kubelet kubeconfig {}: {}", path, e))
+}
+
+/// Wait for kubelet to join and register `node_name`, then apply the labels
+///
+/// Gives up after [`REGISTRATION_ATTEMPTS`] polls; errors are logged, not
+/// returned.
+pub async fn apply_after_bootstrap(node_name: String, labels: BTreeMap<String, String>) {
+    let mut client = None;
+    for attempt in 1..=REGISTRATION_ATTEMPTS {
+        if client.is_none() {
+            match kubelet_client(KUBELET_KUBECONFIG_PATH).await {
+                Ok(Some(c)) => client = Some(c),
+                Ok(None) => debug!(attempt, "Kubelet has no client credential yet"),
+                Err(e) => {
+                    warn!(error = %e, "Cannot apply node labels");
+                    return;
+                }
+            }
+        }
+        if let Some(client) = &client {
+            match apply(client.clone(), &node_name, &labels).await {
+                Ok(()) => {
+                    info!(node = %node_name, labels = labels.len(), "Applied node labels");
+                    return;
+                }
+                Err(kube::Error::Api(e)) if e.code == 404 => {
+                    debug!(node = %node_name, attempt, "Node not registered yet");
+                }
+                Err(e) => {
+                    warn!(node = %node_name, attempt, error = %e, "Failed to apply node labels");
+                }
+            }
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(REGISTRATION_POLL_SECS)).await;
+    }
+    warn!(node = %node_name, "Gave up applying node labels");
+}
+
+pub(crate) async fn client_from_kubeconfig(
+    path: &str,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let kubeconfig = Kubeconfig::read_from(path)?;
+    let config =
+        kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default()).await?;
+    Ok(Client::try_from(config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taint(key: &str, value: Option<&str>, effect: &str) -> Taint {
+        Taint {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            effect: effect.to_string(),
+            time_added: None,
+        }
+    }
+
+    #[test]
+    fn test_node_name_from_mixed_case_hostname() {
+        assert_eq!(node_name_from_hostname("Worker-01").unwrap(), "worker-01");
+        assert_eq!(
+            node_name_from_hostname("EDGE_Node.Example.COM.").unwrap(),
+            "edge-node.example.com"
+        );
+        assert_eq!(node_name_from_hostname(" node-1\n").unwrap(), "node-1");
+    }
+
+    #[test]
+    fn test_node_name_rejects_invalid_hostnames() {
+        for hostname in [
+            "",
+            "-node",
+            "node-",
+            "node..local",
+            "node@1",
+            "nöde",
+            &"a".repeat(64),
+        ] {
+            let err = node_name_from_hostname(hostname).unwrap_err();
+            assert!(
+                err.contains("not usable as a node name"),
+                "{hostname}: {err}"
+            );
+        }
+        assert!(validate_node_name("Worker-01").is_err());
+        assert!(validate_node_name("worker-01").is_ok());
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            parse_label("topology.kubernetes.io/zone=eu-1a").unwrap(),
+            (
+                "topology.kubernetes.io/zone".to_string(),
+                "eu-1a".to_string()
+            )
+        );
+        assert_eq!(
+            parse_label("example.com/edge=").unwrap(),
+            ("example.com/edge".to_string(), String::new())
+        );
+
+        for bad in [
+            "no-equals",
+            "=value",
+            "Bad.Prefix/name=x",
+            "example.com/-name=x",
+            "key=has space",
+            "key=-leading",
+            &format!("{}=x", "a".repeat(64)),
+        ] {
+            assert!(parse_label(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_parse_taint() {
+        assert_eq!(
+            parse_taint("dedicated=edge:NoSchedule").unwrap(),
+            taint("dedicated", Some("edge"), "NoSchedule")
+        );
+        assert_eq!(
+            parse_taint("example.com/gpu:NoExecute").unwrap(),
+            taint("example.com/gpu", None, "NoExecute")
+        );
+
+        for bad in [
+            "dedicated=edge",
+            "dedicated=edge:Sometimes",
+            ":NoSchedule",
+            "bad key:NoSchedule",
+            "key=bad value:NoSchedule",
+        ] {
+            assert!(parse_taint(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_node_metadata_parse_reports_first_error() {
+        let metadata = NodeMetadata::parse(
+            &["role=edge".to_string()],
+            &["dedicated=edge:NoSchedule".to_string()],
+        )
+        .unwrap();
+        assert_eq!(metadata.labels["role"], "edge");
+        assert_eq!(metadata.taints.len(), 1);
+
+        let err =
+            NodeMetadata::parse(&["role=edge".to_string(), "broken".to_string()], &[]).unwrap_err();
+        assert!(err.contains("broken"), "{err}");
+        assert!(NodeMetadata::parse(&[], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restricted_labels_rejected() {
+        for label in [
+            "node-role.kubernetes.io/edge=",
+            "kubernetes.io/role=edge",
+            "node-restriction.kubernetes.io/pool=a",
+            "example.k8s.io/team=a",
+        ] {
+            let err = parse_label(label).unwrap_err();
+            assert!(err.contains("cannot set on itself"), "{label}: {err}");
+        }
+        for label in [
+            "topology.kubernetes.io/zone=eu-1a",
+            "node.kubernetes.io/instance-type=m5",
+            "pool.node.kubernetes.io/name=edge",
+            "kubelet.kubernetes.io/managed=true",
+            "example.com/role=edge",
+            "notkubernetes.io/role=edge",
+            "role=edge",
+        ] {
+            assert!(parse_label(label).is_ok(), "{label} should be allowed");
+        }
+    }
+
+    #[test]
+    fn test_build_node_patch() {
+        let metadata = NodeMetadata::parse(
+            &[
+                "example.com/role=edge".to_string(),
+                "topology.kubernetes.io/zone=eu-1a".to_string(),
+            ],
+            &["dedicated=edge:NoSchedule".to_string()],
+        )
+        .unwrap();
+
+        // Taints are registered by kubelet, never patched
+        assert_eq!(
+            build_node_patch(&metadata.labels),
+            serde_json::json!({
+                "metadata": {
+                    "labels": {
+                        "example.com/role": "edge",
+                        "topology.kubernetes.io/zone": "eu-1a"
+                    }
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kubelet_client_waits_for_credential() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kubeconfig");
+        let path = path.to_str().unwrap();
+
+        assert!(kubelet_client(path).await.unwrap().is_none());
+        std::fs::write(path, "not: [a kubeconfig").unwrap();
+        assert!(kubelet_client(path).await.is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Pre-flight checks before joining a Kubernetes cluster
+//!
+//! A bootstrap with an unreachable API server only fails once kubelet tries
+//! its TLS bootstrap, long after the RPC reported success. The agent first
+//! opens a TCP connection to the endpoint so a typo or a firewall surfaces
+//! as an immediate error, before any bootstrap state is written.
+
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::debug;
+
+/// How long the API server gets to accept a connection
+pub const API_SERVER_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `host:port` to connect to for an API server endpoint
+///
+/// Accepts `https://host[:port][/path]` as well as a bare `host:port`;
+/// the port defaults to 443. IPv6 hosts must be bracketed.
+pub fn api_server_address(endpoint: &str) -> Result<String, String> {
+    let rest = match endpoint.split_once("://") {
+        Some(("https", rest)) => rest,
+        Some((scheme, _)) => return Err(format!("unsupported scheme '{}'", scheme)),
+        None => endpoint,
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit('@').next().unwrap_or_default();
+    if authority.is_empty() {
+        return Err(format!("no host in '{}'", endpoint));
+    }
+
+    // The port follows the last ':' unless that ':' is inside an IPv6 literal
+    let has_port = match authority.rfind(':') {
+        Some(colon) => !authority[colon..].contains(']'),
+        None => false,
+    };
+    if !has_port {
+        return Ok(format!("{}:443", authority));
+    }
+    let (host, port) = authority.rsplit_once(':').unwrap_or_default();
+    if host.is_empty() || port.parse::<u16>().is_err() {
+        return Err(format!("invalid host or port in '{}'", endpoint));
+    }
+    Ok(authority.to_string())
+}
+
+/// Check that the API server at `endpoint` accepts TCP connections
+pub async fn probe_api_server(endpoint: &str, timeout: Duration) -> Result<(), String> {
+    let address = api_server_address(endpoint)?;
+    debug!(address = %address, "Probing API server");
+    match tokio::time::timeout(timeout, TcpStream::connect(&address)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("{}: {}", address, e)),
+        Err(_) => Err(format!(
+            "{}: no answer within {}s",
+            address,
+            timeout.as_secs()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_server_address() {
+        assert_eq!(
+            api_server_address("https://k8s.example.com:6443").unwrap(),
+            "k8s.example.com:6443"
+        );
+        assert_eq!(
+            api_server_address("https://k8s.example.com/").unwrap(),
+            "k8s.example.com:443"
+        );
+        assert_eq!(
+            api_server_address("10.0.0.1:6443").unwrap(),
+            "10.0.0.1:6443"
+        );
+        assert_eq!(
+            api_server_address("https://[fd00::1]:6443/healthz").unwrap(),
+            "[fd00::1]:6443"
+        );
+        assert_eq!(
+            api_server_address("https://[fd00::1]").unwrap(),
+            "[fd00::1]:443"
+        );
+        assert!(api_server_address("http://k8s.example.com:6443").is_err());
+        assert!(api_server_address("https://k8s.example.com:port").is_err());
+        assert!(api_server_address("https://").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_reachable_listener() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("https://{}", listener.local_addr().unwrap());
+        probe_api_server(&endpoint, API_SERVER_PROBE_TIMEOUT)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_probe_unused_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("https://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let err = probe_api_server(&endpoint, API_SERVER_PROBE_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("127.0.0.1:"), "{}", err);
+    }
+}
@@ -0,0 +1,85 @@
+//! Phases reported in `UpdateProgress`
+//!
+//! Clients such as osctl switch on `UpdateProgress.phase`, so the agent only
+//! ever sends the values defined here.
+
+use keel_api::node::UpdateProgress;
+
+/// Stage of an update, sent as `UpdateProgress.phase`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Preparing,
+    Downloading,
+    Verifying,
+    Completed,
+}
+
+impl Phase {
+    /// Every phase, in the order an update goes through them
+    pub const ALL: [Phase; 4] = [
+        Phase::Preparing,
+        Phase::Downloading,
+        Phase::Verifying,
+        Phase::Completed,
+    ];
+
+    /// Wire value of the phase
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Preparing => "preparing",
+            Phase::Downloading => "downloading",
+            Phase::Verifying => "verifying",
+            Phase::Completed => "completed",
+        }
+    }
+
+    /// Phase for a wire value, `None` if unknown
+    pub fn from_wire(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == value)
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Progress message for `phase`; only [`Phase::Completed`] reports success
+pub fn update_progress(
+    phase: Phase,
+    percentage: u32,
+    message: impl Into<String>,
+) -> UpdateProgress {
+    UpdateProgress {
+        percentage,
+        message: message.into(),
+        success: phase == Phase::Completed,
+        download_speed_bps: 0,
+        eta_seconds: 0,
+        phase: phase.to_string(),
+        bytes_saved: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_wire_values_round_trip() {
+        for phase in Phase::ALL {
+            assert_eq!(Phase::from_wire(phase.as_str()), Some(phase));
+        }
+        assert_eq!(Phase::from_wire("flashing"), None);
+        assert_eq!(Phase::from_wire(""), None);
+    }
+
+    #[test]
+    fn test_update_progress_success_only_when_completed() {
+        let progress = update_progress(Phase::Downloading, 20, "Downloading...");
+        assert_eq!(progress.phase, "downloading");
+        assert!(!progress.success);
+        assert!(update_progress(Phase::Completed, 100, "Done").success);
+    }
+}
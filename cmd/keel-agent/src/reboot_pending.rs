@@ -0,0 +1,86 @@
+//! Whether the node is waiting on a reboot
+//!
+//! Changes that only take effect on the next boot leave a trace: a network
+//! change that needs a reboot writes [`REBOOT_PENDING_MARKER`] with the
+//! reason, and switching the boot flags writes [`disk::BOOT_NEXT_MARKER`].
+//! Both live on tmpfs, so the reboot itself clears them.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::info;
+
+use crate::disk;
+
+/// Marker holding the reason a reboot is pending
+pub const REBOOT_PENDING_MARKER: &str = "/run/keel/reboot-pending";
+
+/// Record that a reboot is needed to apply `reason`
+pub fn mark<P: AsRef<Path>>(marker: P, reason: &str) -> io::Result<()> {
+    let marker = marker.as_ref();
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(marker, reason)?;
+    info!(reason, "Reboot pending");
+    Ok(())
+}
+
+/// Why a reboot is pending, or `None` if the node boots what it runs
+///
+/// The reason in `marker` wins; otherwise a boot flag switch recorded in
+/// `boot_next_marker` counts if it points away from the partition booted
+/// according to `cmdline_path`.
+pub fn pending_reason<P, Q, R>(marker: P, boot_next_marker: Q, cmdline_path: R) -> Option<String>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    if let Ok(reason) = fs::read_to_string(marker) {
+        let reason = reason.trim();
+        return Some(if reason.is_empty() {
+            "reboot requested".to_string()
+        } else {
+            reason.to_string()
+        });
+    }
+
+    let active = disk::get_active_partition_from(&cmdline_path).ok()?;
+    let next = disk::next_boot_partition(boot_next_marker, &cmdline_path).ok()?;
+    (next.index != active.index).then(|| {
+        format!(
+            "boot partition switched from {} to {}",
+            active.device, next.device
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_reason() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let marker = dir.path().join("reboot-pending");
+        let boot_next = dir.path().join("boot.next");
+        let cmdline = dir.path().join("cmdline");
+        fs::write(&cmdline, "root=/dev/sda2 ro\n").unwrap();
+        let reason = || pending_reason(&marker, &boot_next, &cmdline);
+
+        assert_eq!(reason(), None);
+
+        // Switching to the partition already booted is not a pending reboot
+        fs::write(&boot_next, "2").unwrap();
+        assert_eq!(reason(), None);
+        fs::write(&boot_next, "3").unwrap();
+        assert_eq!(
+            reason().as_deref(),
+            Some("boot partition switched from /dev/sda2 to /dev/sda3")
+        );
+
+        mark(&marker, "network configuration changed").unwrap();
+        assert_eq!(reason().as_deref(), Some("network configuration changed"));
+    }
+}
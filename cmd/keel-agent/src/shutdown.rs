@@ -0,0 +1,233 @@
+//! Orderly service shutdown before a reboot
+//!
+//! containerd is supervised by `keel-init`, so a bare `reboot` tears it down
+//! with no chance to flush its content store. Every reboot the agent issues
+//! goes through [`reboot`], which first asks the services in
+//! [`PRE_REBOOT_STOP_ORDER`] to exit with SIGTERM and only resorts to SIGKILL
+//! once a bounded grace period has passed. The services are listed in
+//! [`SERVICE_STOP_MARKER`] beforehand, so keel-init lets them stay down
+//! instead of respawning them and counting the exit as a crash. [`reboot_with`] can then kexec
+//! into the next slot instead of going through firmware.
+
+use keel_config::service_stop::{self, SERVICE_STOP_MARKER};
+use keel_config::RebootMethod;
+use nix::errno::Errno;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Services stopped before a reboot, in this order
+pub const PRE_REBOOT_STOP_ORDER: &[&str] = &["containerd"];
+
+/// How long a service gets to exit after SIGTERM before it is killed
+pub const SERVICE_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a stopping process is checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a service stop ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// No such process was running
+    NotRunning,
+    /// The process exited within the timeout after SIGTERM
+    Exited,
+    /// The process ignored SIGTERM and was killed with SIGKILL
+    Killed,
+}
+
+/// PID of the first process under `proc_root` whose `comm` is `name`
+pub fn find_process(proc_root: &Path, name: &str) -> Option<i32> {
+    std::fs::read_dir(proc_root)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<i32>().ok()?;
+            let comm = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+            (comm.trim_end() == name).then_some(pid)
+        })
+        .min()
+}
+
+/// Whether `pid` exists and has not exited
+///
+/// A zombie counts as exited: it is gone as far as its files are concerned
+/// and only waits for its parent to reap it.
+fn is_running(pid: i32) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+        return false;
+    };
+    // The state follows the parenthesised command name, which may contain spaces
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next());
+    !matches!(state, Some("Z" | "X") | None)
+}
+
+/// Send SIGTERM to `pid` and wait up to `timeout` for it to exit,
+/// falling back to SIGKILL
+pub async fn stop_process(pid: i32, timeout: Duration) -> io::Result<StopOutcome> {
+    let target = Pid::from_raw(pid);
+    match kill(target, Signal::SIGTERM) {
+        Ok(()) => {}
+        Err(Errno::ESRCH) => return Ok(StopOutcome::NotRunning),
+        Err(e) => return Err(e.into()),
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if !is_running(pid) {
+            return Ok(StopOutcome::Exited);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    if !is_running(pid) {
+        return Ok(StopOutcome::Exited);
+    }
+
+    match kill(target, Signal::SIGKILL) {
+        Ok(()) => Ok(StopOutcome::Killed),
+        Err(Errno::ESRCH) => Ok(StopOutcome::Exited),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stop the named services one after another
+///
+/// Failures are logged and do not prevent the remaining services from
+/// being stopped. keel-init is told first that the stop is intentional; if
+/// that fails the services are left running, since keel-init would only
+/// restart them.
+pub async fn stop_services(names: &[&str], timeout: Duration) {
+    if let Err(e) = service_stop::mark_stopping(SERVICE_STOP_MARKER, names) {
+        warn!(error = %e, "Failed to tell keel-init services are stopping, leaving them running");
+        return;
+    }
+    for name in names {
+        let Some(pid) = find_process(Path::new("/proc"), name) else {
+            debug!(service = %name, "Service not running, nothing to stop");
+            continue;
+        };
+
+        info!(service = %name, pid, "Stopping service");
+        match stop_process(pid, timeout).await {
+            Ok(StopOutcome::Killed) => {
+                warn!(service = %name, pid, timeout_secs = timeout.as_secs(), "Service did not exit after SIGTERM, killed");
+            }
+            Ok(outcome) => debug!(service = %name, pid, ?outcome, "Service stopped"),
+            Err(e) => warn!(service = %name, pid, error = %e, "Failed to stop service"),
+        }
+    }
+}
+
+/// Stop node services gracefully, then reboot
+pub async fn reboot() {
+    reboot_with(RebootMethod::Reboot).await;
+}
+
+/// Stop node services gracefully, then reboot using `method`
+///
+/// A kexec that cannot be loaded or executed falls back to a normal reboot.
+pub async fn reboot_with(method: RebootMethod) {
+    stop_services(PRE_REBOOT_STOP_ORDER, SERVICE_STOP_TIMEOUT).await;
+
+    let kexec = crate::kexec::find_kexec_binary(crate::kexec::KEXEC_PATHS);
+    if let (RebootMethod::Kexec, Some(kexec)) =
+        (crate::kexec::select_method(method, kexec.as_deref()), kexec)
+    {
+        let result =
+            tokio::task::spawn_blocking(move || match crate::kexec::load_next_slot(&kexec) {
+                Ok(()) => {
+                    info!("Rebooting with kexec");
+                    crate::kexec::execute(&kexec)
+                }
+                Err(e) => e,
+            })
+            .await;
+        match result {
+            Ok(e) => warn!(error = %e, "kexec failed, falling back to a normal reboot"),
+            Err(e) => warn!(error = %e, "kexec task failed, falling back to a normal reboot"),
+        }
+    }
+
+    info!("Rebooting");
+    let rebooting = match std::process::Command::new("reboot").status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            warn!(error = %e, "Failed to run reboot");
+            false
+        }
+    };
+    if !rebooting {
+        // Let keel-init bring the stopped services back
+        if let Err(e) = service_stop::clear(SERVICE_STOP_MARKER) {
+            warn!(error = %e, "Failed to clear service stop marker");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn test_find_process_by_comm() {
+        let proc_root = tempfile::TempDir::new().unwrap();
+        for (pid, comm) in [
+            ("1", "keel-init\n"),
+            ("42", "containerd\n"),
+            ("77", "containerd\n"),
+        ] {
+            std::fs::create_dir(proc_root.path().join(pid)).unwrap();
+            std::fs::write(proc_root.path().join(pid).join("comm"), comm).unwrap();
+        }
+        std::fs::create_dir(proc_root.path().join("self")).unwrap();
+
+        assert_eq!(find_process(proc_root.path(), "containerd"), Some(42));
+        assert_eq!(find_process(proc_root.path(), "kubelet"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_process_exits_on_sigterm() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id() as i32;
+
+        let outcome = stop_process(pid, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(outcome, StopOutcome::Exited);
+        child.wait().unwrap();
+
+        // Once reaped, there is nothing left to stop
+        assert_eq!(
+            stop_process(pid, Duration::from_secs(1)).await.unwrap(),
+            StopOutcome::NotRunning
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_process_kills_after_timeout() {
+        // SIGTERM stays ignored across exec
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' TERM; echo ready; exec sleep 30"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut ready = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut ready)
+            .unwrap();
+        let pid = child.id() as i32;
+
+        let outcome = stop_process(pid, Duration::from_millis(300)).await.unwrap();
+        assert_eq!(outcome, StopOutcome::Killed);
+
+        let status = child.wait().unwrap();
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(status.signal(), Some(Signal::SIGKILL as i32));
+    }
+}
@@ -0,0 +1,367 @@
+//! Pre-staging of scheduled update images
+//!
+//! When a schedule is created with `prestage` and a future `scheduled_at`,
+//! the image is downloaded and verified into the staging directory ahead of
+//! time. At execution the scheduled update flashes from the local copy, so
+//! a fleet does not hit the image server all at once when the maintenance
+//! window opens.
+
+use futures::StreamExt;
+use keel_config::UpdateConfig;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+use crate::update_scheduler::UpdateSchedule;
+use crate::{disk, download, image_metadata};
+
+/// Default directory for pre-staged images
+pub const STAGING_DIR: &str = "/var/lib/keel/staging";
+
+/// Free space left on the staging filesystem beyond the download itself
+pub const STAGING_RESERVE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Staging directory configured for the node
+pub fn staging_dir(config: &UpdateConfig) -> PathBuf {
+    PathBuf::from(config.staging_dir.as_deref().unwrap_or(STAGING_DIR))
+}
+
+/// Create `dir` if needed and restrict it to root
+///
+/// Staged images are flashed to disk later, so nobody else may be able to
+/// swap them out.
+pub fn prepare_staging_dir(dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+}
+
+/// Refuse to download `required` bytes into `dir` unless the filesystem
+/// keeps [`STAGING_RESERVE_BYTES`] free afterwards
+pub fn ensure_free_space(dir: &Path, required: u64) -> io::Result<()> {
+    let stat = nix::sys::statvfs::statvfs(dir).map_err(io::Error::from)?;
+    let available = (stat.blocks_available() as u64).saturating_mul(stat.fragment_size() as u64);
+    let needed = required.saturating_add(STAGING_RESERVE_BYTES);
+    if available < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "Not enough space in {}: {} bytes needed, {} available",
+                dir.display(),
+                needed,
+                available
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Path of the staged image for a schedule (`<staging_dir>/<id>.img`)
+pub fn staged_image_path(staging_dir: impl AsRef<Path>, schedule_id: &str) -> PathBuf {
+    staging_dir.as_ref().join(format!("{schedule_id}.img"))
+}
+
+/// Path used while a staged image is still being downloaded
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// Download `source_url` to `dest`, verifying the SHA256 if provided.
+///
+/// The image is written to `<dest>.partial` first and only renamed into
+/// place once the download completed and the checksum matched, so a
+/// present `dest` is always a complete, verified image.
+pub async fn stage_image(
+    source_url: &str,
+    expected_sha256: Option<&str>,
+    dest: &Path,
+) -> io::Result<u64> {
+    let staging_dir = dest.parent().unwrap_or(Path::new("."));
+    prepare_staging_dir(staging_dir)?;
+
+    let partial = partial_path(dest);
+    let auth_header = download::resolve_auth_header(None, source_url);
+    image_metadata::verify_image(source_url, auth_header.as_deref()).await?;
+    info!(url = %download::redact_url(source_url), dest = %dest.display(), "Pre-staging update image");
+
+    let response = download::get(source_url, auth_header.as_deref())
+        .await
+        .map_err(|e| io::Error::other(format!("Download failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Server returned error: {}",
+            response.status()
+        )));
+    }
+
+    ensure_free_space(staging_dir, response.content_length().unwrap_or(0))?;
+
+    let mut file = tokio::fs::File::create(&partial).await?;
+    let mut hasher = Sha256::new();
+    let written = async {
+        let mut bytes_written: u64 = 0;
+        let mut stream = response.bytes_stream();
+        while let Some(item) = stream.next().await {
+            let chunk = item.map_err(|e| io::Error::other(format!("Stream error: {}", e)))?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            bytes_written += chunk.len() as u64;
+        }
+        file.flush().await?;
+        file.sync_all().await?;
+        Ok::<_, io::Error>(bytes_written)
+    }
+    .await;
+    drop(file);
+
+    // Never leave a partial image behind, e.g. after the disk filled up
+    let bytes_written = match written {
+        Ok(bytes_written) => bytes_written,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+    };
+
+    if let Some(expected) = expected_sha256.filter(|e| !e.is_empty()) {
+        let actual = format!("{:x}", hasher.finalize());
+        if actual != expected.to_lowercase() {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("SHA256 mismatch: expected {}, got {}", expected, actual),
+            ));
+        }
+        debug!(hash = %actual, "Staged image SHA256 verified");
+    }
+
+    if let Err(e) = tokio::fs::rename(&partial, dest).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    info!(bytes = bytes_written, dest = %dest.display(), "Update image staged");
+    Ok(bytes_written)
+}
+
+/// Remove a staged image and any leftover partial download
+pub fn remove_staged_image(path: &Path) {
+    for p in [path.to_path_buf(), partial_path(path)] {
+        match std::fs::remove_file(&p) {
+            Ok(()) => debug!(path = %p.display(), "Removed staged image"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!(path = %p.display(), error = %e, "Failed to remove staged image"),
+        }
+    }
+}
+
+/// The staged image for a schedule, if one was recorded and still exists
+pub fn usable_staged_image(schedule: &UpdateSchedule) -> Option<&Path> {
+    schedule
+        .staged_image_path
+        .as_deref()
+        .map(Path::new)
+        .filter(|p| p.is_file())
+}
+
+/// Flash a scheduled update, preferring the pre-staged image when present
+///
+/// Falls back to downloading from the schedule's source URL if nothing was
+/// staged or the staged file has disappeared. Downloads are limited to
+/// `max_bytes_per_sec` (zero means unlimited) and written in blocks of
+/// `write_block_bytes` (zero for the default); delta files are kept in
+/// `staging_dir` while they are applied. `allow_non_block` accepts a
+/// regular file as `target_device` (see [`disk::check_flash_target`]).
+pub async fn flash_scheduled_image(
+    schedule: &UpdateSchedule,
+    target_device: &str,
+    max_bytes_per_sec: u64,
+    write_block_bytes: u64,
+    staging_dir: &Path,
+    allow_non_block: bool,
+) -> io::Result<disk::Flashed> {
+    if let Some(staged) = usable_staged_image(schedule) {
+        info!(path = %staged.display(), "Flashing from pre-staged image");
+        return disk::flash_local_image(
+            staged,
+            target_device,
+            schedule.expected_sha256.as_deref(),
+            allow_non_block,
+        )
+        .await;
+    }
+
+    if schedule.staged_image_path.is_some() {
+        warn!(schedule_id = %schedule.id, "Staged image missing, downloading from source");
+    }
+
+    disk::flash_image(
+        &schedule.source_url,
+        target_device,
+        schedule.expected_sha256.as_deref(),
+        schedule.is_delta,
+        schedule
+            .fallback_to_full
+            .then_some(schedule.full_image_url.as_deref())
+            .flatten(),
+        download::resolve_auth_header(None, &schedule.source_url).as_deref(),
+        max_bytes_per_sec,
+        write_block_bytes,
+        staging_dir,
+        allow_non_block,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update_scheduler::pending_schedule;
+    use tempfile::TempDir;
+
+    fn schedule_with_staged(path: Option<String>) -> UpdateSchedule {
+        UpdateSchedule {
+            // Unresolvable on purpose: the test must never hit the network
+            source_url: "http://staging-test.invalid/image.img".to_string(),
+            prestage: true,
+            staged_image_path: path,
+            ..pending_schedule("stage-test", None)
+        }
+    }
+
+    #[test]
+    fn test_staged_image_path() {
+        assert_eq!(
+            staged_image_path(STAGING_DIR, "abc-123"),
+            PathBuf::from("/var/lib/keel/staging/abc-123.img")
+        );
+        assert_eq!(
+            partial_path(Path::new("/var/lib/keel/staging/abc-123.img")),
+            PathBuf::from("/var/lib/keel/staging/abc-123.img.partial")
+        );
+    }
+
+    #[test]
+    fn test_staging_dir_from_config() {
+        assert_eq!(
+            staging_dir(&UpdateConfig::default()),
+            PathBuf::from(STAGING_DIR)
+        );
+        let config = UpdateConfig {
+            staging_dir: Some("/data/keel-staging".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(staging_dir(&config), PathBuf::from("/data/keel-staging"));
+    }
+
+    #[test]
+    fn test_prepare_staging_dir_is_private() {
+        let dir = TempDir::new().unwrap();
+        let staging = dir.path().join("var/lib/keel/staging");
+
+        prepare_staging_dir(&staging).unwrap();
+        let mode = std::fs::metadata(&staging).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // An existing, too open directory is tightened
+        std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(0o777)).unwrap();
+        prepare_staging_dir(&staging).unwrap();
+        let mode = std::fs::metadata(&staging).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn test_ensure_free_space() {
+        let dir = TempDir::new().unwrap();
+        let err = ensure_free_space(dir.path(), u64::MAX).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        assert!(err.to_string().contains("Not enough space"), "{err}");
+    }
+
+    #[test]
+    fn test_remove_staged_image() {
+        let dir = TempDir::new().unwrap();
+        let path = staged_image_path(dir.path(), "rm");
+        std::fs::write(&path, b"image").unwrap();
+        std::fs::write(partial_path(&path), b"part").unwrap();
+
+        remove_staged_image(&path);
+        assert!(!path.exists());
+        assert!(!partial_path(&path).exists());
+
+        // Removing again is a no-op
+        remove_staged_image(&path);
+    }
+
+    #[test]
+    fn test_usable_staged_image_requires_file() {
+        let dir = TempDir::new().unwrap();
+        let path = staged_image_path(dir.path(), "missing");
+        let schedule = schedule_with_staged(Some(path.to_string_lossy().into_owned()));
+        assert!(usable_staged_image(&schedule).is_none());
+
+        std::fs::write(&path, b"image").unwrap();
+        assert_eq!(usable_staged_image(&schedule), Some(path.as_path()));
+
+        assert!(usable_staged_image(&schedule_with_staged(None)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stage_image_removes_partial_on_write_error() {
+        // Also served as the metadata sidecar: a version newer than any node
+        let url = download::test_server::serve_payload(br#"{"version":"9999.0.0"}"#.to_vec()).await;
+        let dir = TempDir::new().unwrap();
+        let dest = staged_image_path(dir.path(), "full");
+        // Every write to /dev/full fails with ENOSPC
+        std::os::unix::fs::symlink("/dev/full", partial_path(&dest)).unwrap();
+
+        let err = stage_image(&url, None, &dest).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull, "{err}");
+        assert!(!partial_path(&dest).exists());
+        assert!(!dest.exists());
+    }
+
+    #[tokio::test]
+    async fn test_flash_scheduled_image_uses_staged_file() {
+        let dir = TempDir::new().unwrap();
+        let staged = staged_image_path(dir.path(), "flash");
+        let image = b"staged image contents".repeat(1024);
+        std::fs::write(&staged, &image).unwrap();
+
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"").unwrap();
+
+        let mut schedule = schedule_with_staged(Some(staged.to_string_lossy().into_owned()));
+        schedule.expected_sha256 = Some(format!("{:x}", Sha256::digest(&image)));
+
+        flash_scheduled_image(&schedule, target.to_str().unwrap(), 0, 0, dir.path(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&target).unwrap(), image);
+    }
+
+    #[tokio::test]
+    async fn test_flash_scheduled_image_rejects_corrupt_staged_file() {
+        let dir = TempDir::new().unwrap();
+        let staged = staged_image_path(dir.path(), "corrupt");
+        std::fs::write(&staged, b"tampered").unwrap();
+        let target = dir.path().join("target.img");
+        std::fs::write(&target, b"previous image").unwrap();
+
+        let mut schedule = schedule_with_staged(Some(staged.to_string_lossy().into_owned()));
+        schedule.expected_sha256 = Some("00".repeat(32));
+
+        let err =
+            flash_scheduled_image(&schedule, target.to_str().unwrap(), 0, 0, dir.path(), true)
+                .await
+                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // Checked before writing: the partition is left as it was
+        assert_eq!(std::fs::read(&target).unwrap(), b"previous image");
+    }
+}
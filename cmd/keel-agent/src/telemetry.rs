@@ -4,23 +4,128 @@
 //! - Distributed tracing
 //! - Metrics collection (system and application)
 //! - OTLP export to collectors
+//!
+//! Exporters are configured from the environment (see [`OtlpSettings`]):
+//! `OTLP_ENDPOINT` sends traces to a collector, overridden by
+//! `OTLP_TRACES_ENDPOINT`. Metrics are only pushed when
+//! `OTLP_METRICS_ENDPOINT` is set. `OTLP_HEADERS` (`k=v,k2=v2`) is sent with
+//! every export, e.g. for auth. Bad settings are logged and leave the agent
+//! running without exporters.
+//!
+//! Log output honors `KEEL_LOG_LEVEL`/`RUST_LOG` and `KEEL_LOG_FORMAT`
+//! (see [`keel_config::logging`]).
 
+use keel_config::logging::{LogFormat, LogSettings, DEFAULT_LOG_LEVEL};
 use opentelemetry::{global, trace::TracerProvider, KeyValue};
-use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use opentelemetry_otlp::{WithExportConfig, WithTonicConfig};
+use opentelemetry_sdk::{metrics::SdkMeterProvider, trace::SdkTracerProvider, Resource};
 use sysinfo::System;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-/// Initialize OpenTelemetry with OTLP exporter
+/// Collector for traces
+pub const OTLP_ENDPOINT_ENV: &str = "OTLP_ENDPOINT";
+
+/// Collector for traces, overriding [`OTLP_ENDPOINT_ENV`]
+pub const OTLP_TRACES_ENDPOINT_ENV: &str = "OTLP_TRACES_ENDPOINT";
+
+/// Collector metrics are pushed to; there is no push without it
+pub const OTLP_METRICS_ENDPOINT_ENV: &str = "OTLP_METRICS_ENDPOINT";
+
+/// Headers sent with every export, as comma-separated `key=value` pairs
+pub const OTLP_HEADERS_ENV: &str = "OTLP_HEADERS";
+
+/// Where and how to export telemetry over OTLP
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OtlpSettings {
+    /// Trace collector; tracing is not exported when `None`
+    pub traces_endpoint: Option<String>,
+    /// Metrics collector; metrics are not exported when `None`
+    pub metrics_endpoint: Option<String>,
+    /// gRPC metadata added to every export
+    pub headers: Vec<(String, String)>,
+}
+
+impl OtlpSettings {
+    /// Settings from the process environment
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Settings from `lookup`, which returns a variable's value if set
+    ///
+    /// Empty variables count as unset. Headers that are not valid gRPC
+    /// metadata are an error.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let var = |name: &str| lookup(name).filter(|v| !v.trim().is_empty());
+        let settings = Self {
+            traces_endpoint: var(OTLP_TRACES_ENDPOINT_ENV).or_else(|| var(OTLP_ENDPOINT_ENV)),
+            metrics_endpoint: var(OTLP_METRICS_ENDPOINT_ENV),
+            headers: match var(OTLP_HEADERS_ENV) {
+                Some(headers) => parse_headers(&headers)?,
+                None => Vec::new(),
+            },
+        };
+        settings.metadata()?;
+        Ok(settings)
+    }
+
+    /// Headers as gRPC metadata
+    fn metadata(&self) -> Result<MetadataMap, String> {
+        let mut metadata = MetadataMap::new();
+        for (key, value) in &self.headers {
+            let key = MetadataKey::from_bytes(key.to_ascii_lowercase().as_bytes())
+                .map_err(|_| format!("invalid {} key '{}'", OTLP_HEADERS_ENV, key))?;
+            let value = MetadataValue::try_from(value.as_str())
+                .map_err(|_| format!("invalid {} value for '{}'", OTLP_HEADERS_ENV, key))?;
+            metadata.insert(key, value);
+        }
+        Ok(metadata)
+    }
+}
+
+/// Parse `key=value` pairs separated by commas
+///
+/// Whitespace around keys and values is dropped, as are empty entries, so a
+/// trailing comma is fine. Values may contain `=` (base64 tokens).
+pub fn parse_headers(headers: &str) -> Result<Vec<(String, String)>, String> {
+    headers
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!(
+                "invalid {} entry '{}': expected key=value",
+                OTLP_HEADERS_ENV, entry
+            )),
+        })
+        .collect()
+}
+
+/// Initialize OpenTelemetry with OTLP exporters
 ///
 /// This sets up:
-/// - Tracing with OTLP export
-/// - Metrics with Prometheus export
+/// - Tracing with OTLP export, if a traces endpoint is set
+/// - Metrics with OTLP export, if a metrics endpoint is set
 /// - Resource attributes (service name, version)
-pub fn init_telemetry(
-    service_name: &str,
-    otlp_endpoint: Option<String>,
-) -> Result<(), Box<dyn std::error::Error>> {
+/// - Log filtering and format from the environment
+///
+/// Telemetry never stops the agent: if `otlp` is an error or an exporter
+/// cannot be built, a warning is logged and that export is left off.
+pub fn init_telemetry(service_name: &str, otlp: Result<OtlpSettings, String>) {
+    // Logged once the subscriber below is installed
+    let mut problems = Vec::new();
+    let otlp = otlp.unwrap_or_else(|e| {
+        problems.push(format!(
+            "ignoring OTLP settings, not exporting telemetry: {}",
+            e
+        ));
+        OtlpSettings::default()
+    });
+
     // Create resource with service information
     let resource = Resource::builder()
         .with_attributes(vec![
@@ -29,46 +134,85 @@ pub fn init_telemetry(
         ])
         .build();
 
+    // Checked by `OtlpSettings::from_lookup`
+    let metadata = otlp.metadata().unwrap_or_default();
+
+    // Export metrics recorded through the global meter
+    if let Some(endpoint) = otlp.metrics_endpoint {
+        match opentelemetry_otlp::MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .with_metadata(metadata.clone())
+            .build()
+        {
+            Ok(exporter) => {
+                let meter_provider = SdkMeterProvider::builder()
+                    .with_periodic_exporter(exporter)
+                    .with_resource(resource.clone())
+                    .build();
+                global::set_meter_provider(meter_provider);
+            }
+            Err(e) => problems.push(format!("not exporting metrics: {}", e)),
+        }
+    }
+
     // Initialize tracing if OTLP endpoint is provided
-    if let Some(endpoint) = otlp_endpoint {
-        let exporter = opentelemetry_otlp::SpanExporterBuilder::default()
+    let telemetry_layer = match otlp.traces_endpoint.map(|endpoint| {
+        opentelemetry_otlp::SpanExporterBuilder::default()
             .with_tonic()
             .with_endpoint(endpoint)
-            .build()?;
+            .with_metadata(metadata)
+            .build()
+    }) {
+        Some(Ok(exporter)) => {
+            let tracer_provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(resource)
+                .build();
 
-        let tracer_provider = SdkTracerProvider::builder()
-            .with_batch_exporter(exporter)
-            .with_resource(resource)
-            .build();
+            // Set global tracer provider
+            global::set_tracer_provider(tracer_provider.clone());
 
-        // Set global tracer provider
-        global::set_tracer_provider(tracer_provider.clone());
+            let tracer = tracer_provider.tracer("keel-agent");
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        Some(Err(e)) => {
+            problems.push(format!("not exporting traces: {}", e));
+            None
+        }
+        None => None,
+    };
 
-        // Create tracing subscriber with OpenTelemetry layer
-        let tracer = tracer_provider.tracer("keel-agent");
-        let telemetry_layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    let settings = LogSettings::from_env();
+    let env_filter = EnvFilter::try_new(&settings.filter).unwrap_or_else(|e| {
+        eprintln!("Invalid log filter '{}': {}", settings.filter, e);
+        EnvFilter::new(DEFAULT_LOG_LEVEL)
+    });
 
-        // Set up structured logging with OpenTelemetry
-        let env_filter =
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    // Exactly one of the two formatting layers is active
+    let (text_layer, json_layer) = match settings.format {
+        LogFormat::Text => (Some(tracing_subscriber::fmt::layer().compact()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true),
+            ),
+        ),
+    };
 
-        tracing_subscriber::registry()
-            .with(env_filter)
-            .with(tracing_subscriber::fmt::layer().compact())
-            .with(telemetry_layer)
-            .init();
-    } else {
-        // Set up structured logging without OpenTelemetry
-        let env_filter =
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(text_layer)
+        .with(json_layer)
+        .with(telemetry_layer)
+        .init();
 
-        tracing_subscriber::registry()
-            .with(env_filter)
-            .with(tracing_subscriber::fmt::layer().compact())
-            .init();
+    for problem in problems {
+        tracing::warn!("Telemetry: {}", problem);
     }
-
-    Ok(())
 }
 
 /// Shutdown telemetry and flush pending data
@@ -91,8 +235,12 @@ impl SystemMetrics {
     }
 
     /// Update system metrics
+    ///
+    /// Only refreshes what the getters below read (memory and CPU usage);
+    /// walking the process table is skipped.
     pub fn update(&mut self) {
-        self.system.refresh_all();
+        self.system.refresh_memory();
+        self.system.refresh_cpu_usage();
     }
 
     /// Get CPU usage percentage
@@ -128,7 +276,76 @@ impl SystemMetrics {
 
 impl Default for SystemMetrics {
     fn default() -> Self {
-        Self::new().expect("Failed to create SystemMetrics")
+        Self {
+            system: System::new_all(),
+        }
+    }
+}
+
+/// Span names recorded by a test subscriber
+#[cfg(test)]
+pub(crate) mod span_recorder {
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Records the name and fields of every span created while it is the
+    /// default subscriber
+    #[derive(Clone, Default)]
+    pub(crate) struct SpanRecorder(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl SpanRecorder {
+        /// Names of the spans created so far
+        pub(crate) fn names(&self) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect()
+        }
+
+        /// Fields the spans were created with, as `name=value` pairs
+        pub(crate) fn fields(&self) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, fields)| fields.clone())
+                .collect()
+        }
+    }
+
+    /// Formats visited fields as `name=value` pairs
+    struct FieldText(String);
+
+    impl tracing::field::Visit for FieldText {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!("{}={:?} ", field.name(), value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanRecorder {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields = FieldText(String::new());
+            attrs.record(&mut fields);
+            self.0
+                .lock()
+                .unwrap()
+                .push((attrs.metadata().name().to_string(), fields.0));
+        }
+    }
+
+    /// Record spans on this thread until the guard is dropped
+    pub(crate) fn record_spans() -> (SpanRecorder, tracing::subscriber::DefaultGuard) {
+        let recorder = SpanRecorder::default();
+        let guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+        (recorder, guard)
     }
 }
 
@@ -136,6 +353,76 @@ impl Default for SystemMetrics {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_headers("authorization=Bearer abc==, x-scope-orgid = tenant1,").unwrap(),
+            [
+                ("authorization".to_string(), "Bearer abc==".to_string()),
+                ("x-scope-orgid".to_string(), "tenant1".to_string()),
+            ]
+        );
+        assert!(parse_headers("").unwrap().is_empty());
+        assert!(parse_headers("authorization").is_err());
+        assert!(parse_headers("=value").is_err());
+    }
+
+    #[test]
+    fn test_endpoint_selection() {
+        let settings = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            OtlpSettings::from_lookup(|name| {
+                vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+            })
+            .unwrap()
+        };
+
+        // Nothing set: no exporters
+        assert_eq!(settings(&[]), OtlpSettings::default());
+
+        // The shared endpoint only enables traces, metrics need their own
+        let shared = settings(&[(OTLP_ENDPOINT_ENV, "http://otel:4317")]);
+        assert_eq!(shared.traces_endpoint.as_deref(), Some("http://otel:4317"));
+        assert_eq!(shared.metrics_endpoint, None);
+
+        // Per-signal endpoints win
+        let split = settings(&[
+            (OTLP_ENDPOINT_ENV, "http://otel:4317"),
+            (OTLP_TRACES_ENDPOINT_ENV, "http://tempo:4317"),
+            (OTLP_METRICS_ENDPOINT_ENV, ""),
+        ]);
+        assert_eq!(split.traces_endpoint.as_deref(), Some("http://tempo:4317"));
+        assert_eq!(split.metrics_endpoint, None);
+
+        let metrics_only = settings(&[
+            (OTLP_METRICS_ENDPOINT_ENV, "http://mimir:4317"),
+            (OTLP_HEADERS_ENV, "Authorization=Basic dXNlcg=="),
+        ]);
+        assert_eq!(metrics_only.traces_endpoint, None);
+        let metadata = metrics_only.metadata().unwrap();
+        assert_eq!(metadata.get("authorization").unwrap(), "Basic dXNlcg==");
+
+        let bad = OtlpSettings {
+            headers: vec![("bad key".to_string(), "v".to_string())],
+            ..Default::default()
+        };
+        assert!(bad.metadata().is_err());
+
+        // Malformed headers are reported, for the agent to carry on without
+        // exporters
+        assert!(OtlpSettings::from_lookup(|name| {
+            (name == OTLP_HEADERS_ENV).then(|| "bad key=v".to_string())
+        })
+        .is_err());
+        assert!(OtlpSettings::from_lookup(|name| {
+            (name == OTLP_HEADERS_ENV).then(|| "authorization".to_string())
+        })
+        .is_err());
+    }
+
     #[test]
     fn test_system_metrics_creation() {
         let metrics = SystemMetrics::new();
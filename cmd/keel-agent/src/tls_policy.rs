@@ -0,0 +1,199 @@
+//! TLS version and cipher suite policy for the gRPC server
+//!
+//! Translates the `tls_policy` section of the node configuration into the
+//! rustls protocol versions and crypto provider the server is built with.
+//! Combinations that would silently weaken or break TLS are rejected
+//! instead of being applied.
+
+use keel_config::TlsPolicyConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::{ConfigBuilder, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion};
+use std::sync::Arc;
+
+static TLS12_AND_UP: &[&SupportedProtocolVersion] =
+    &[&rustls::version::TLS13, &rustls::version::TLS12];
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// IANA name of a cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`
+fn suite_name(suite: &SupportedCipherSuite) -> &'static str {
+    suite.suite().as_str().unwrap_or("unknown")
+}
+
+/// Protocol versions allowed by `min_version` (default: TLS 1.2 and 1.3)
+pub fn protocol_versions(
+    policy: &TlsPolicyConfig,
+) -> Result<&'static [&'static SupportedProtocolVersion], String> {
+    match policy.min_version.as_deref() {
+        None | Some("1.2") => Ok(TLS12_AND_UP),
+        Some("1.3") => Ok(TLS13_ONLY),
+        Some(other) => Err(format!(
+            "tls_policy.min_version '{}' is not supported (use 1.2 or 1.3)",
+            other
+        )),
+    }
+}
+
+/// Crypto provider restricted to the policy's cipher suites
+///
+/// Rejects unknown suite names, TLS 1.2 suites under a TLS 1.3 minimum, and
+/// allowlists without a TLS 1.3 suite, which would quietly cap every
+/// connection at TLS 1.2.
+pub fn crypto_provider(policy: &TlsPolicyConfig) -> Result<CryptoProvider, String> {
+    let versions = protocol_versions(policy)?;
+    let mut provider = rustls::crypto::aws_lc_rs::default_provider();
+
+    if policy.cipher_suites.is_empty() {
+        provider
+            .cipher_suites
+            .retain(|suite| versions.contains(&suite.version()));
+        return Ok(provider);
+    }
+
+    let mut allowed = Vec::new();
+    for name in &policy.cipher_suites {
+        let suite = provider
+            .cipher_suites
+            .iter()
+            .find(|suite| suite_name(suite) == name)
+            .ok_or_else(|| format!("Unknown or unsupported cipher suite '{}'", name))?;
+        if !versions.contains(&suite.version()) {
+            return Err(format!(
+                "Cipher suite '{}' is TLS 1.2 only but tls_policy.min_version is 1.3",
+                name
+            ));
+        }
+        allowed.push(*suite);
+    }
+    if !allowed.iter().any(|suite| suite.tls13().is_some()) {
+        return Err("tls_policy.cipher_suites must include a TLS 1.3 cipher suite".to_string());
+    }
+
+    provider.cipher_suites = allowed;
+    Ok(provider)
+}
+
+/// Server config builder with the policy applied, ready for client auth
+pub fn server_config_builder(
+    policy: &TlsPolicyConfig,
+) -> Result<ConfigBuilder<ServerConfig, rustls::WantsVerifier>, String> {
+    ServerConfig::builder_with_provider(Arc::new(crypto_provider(policy)?))
+        .with_protocol_versions(protocol_versions(policy)?)
+        .map_err(|e| format!("Invalid TLS policy: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+    use rustls::{ClientConfig, ProtocolVersion, RootCertStore};
+
+    fn policy(min_version: Option<&str>, suites: &[&str]) -> TlsPolicyConfig {
+        TlsPolicyConfig {
+            min_version: min_version.map(str::to_string),
+            cipher_suites: suites.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Handshake over an in-memory pipe, returning the negotiated version
+    async fn handshake(
+        policy: &TlsPolicyConfig,
+        client_versions: &[&'static SupportedProtocolVersion],
+    ) -> Result<ProtocolVersion, String> {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let cert_der = CertificateDer::from(cert.der().to_vec());
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+
+        let server = server_config_builder(policy)?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der.clone()], key_der)
+            .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert_der).unwrap();
+        let client = ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::aws_lc_rs::default_provider(),
+        ))
+        .with_protocol_versions(client_versions)
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server));
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client));
+        let name = ServerName::try_from("localhost").unwrap();
+
+        let (client_result, _server_result) = tokio::join!(
+            connector.connect(name, client_io),
+            acceptor.accept(server_io)
+        );
+        let stream = client_result.map_err(|e| e.to_string())?;
+        Ok(stream.get_ref().1.protocol_version().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tls13_only_policy_is_honored() {
+        let tls13 = policy(Some("1.3"), &[]);
+        assert_eq!(
+            handshake(&tls13, TLS12_AND_UP).await.unwrap(),
+            ProtocolVersion::TLSv1_3
+        );
+        assert!(handshake(&tls13, &[&rustls::version::TLS12]).await.is_err());
+
+        // The default policy still serves TLS 1.2 clients
+        let default = TlsPolicyConfig::default();
+        assert_eq!(
+            handshake(&default, &[&rustls::version::TLS12])
+                .await
+                .unwrap(),
+            ProtocolVersion::TLSv1_2
+        );
+    }
+
+    #[test]
+    fn test_cipher_allowlist_translation() {
+        let provider = crypto_provider(&policy(
+            None,
+            &[
+                "TLS13_AES_256_GCM_SHA384",
+                "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+            ],
+        ))
+        .unwrap();
+        let names: Vec<_> = provider.cipher_suites.iter().map(suite_name).collect();
+        assert_eq!(
+            names,
+            [
+                "TLS13_AES_256_GCM_SHA384",
+                "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"
+            ]
+        );
+
+        let provider = crypto_provider(&policy(Some("1.3"), &[])).unwrap();
+        assert!(provider.cipher_suites.iter().all(|s| s.tls13().is_some()));
+    }
+
+    #[test]
+    fn test_insecure_combinations_rejected() {
+        // Unknown names and old versions
+        assert!(crypto_provider(&policy(None, &["TLS_RSA_WITH_RC4_128_SHA"])).is_err());
+        assert!(protocol_versions(&policy(Some("1.1"), &[])).is_err());
+        // A TLS 1.2 suite under a TLS 1.3 minimum
+        assert!(crypto_provider(&policy(
+            Some("1.3"),
+            &[
+                "TLS13_AES_128_GCM_SHA256",
+                "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"
+            ],
+        ))
+        .is_err());
+        // Only TLS 1.2 suites would cap every connection at TLS 1.2
+        assert!(
+            crypto_provider(&policy(None, &["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"])).is_err()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Uncordoning the Node after a healthy post-update boot
+//!
+//! Drain hooks cordon the Node before an update. Once the node has booted
+//! the new image and passed its health checks, the agent can mark it
+//! schedulable again. Uncordoning before the node's networking pods are up
+//! would schedule workloads onto a broken node, so the agent first waits for
+//! the DaemonSet pods matched by `kubernetes.auto_uncordon.ready_selector`
+//! to be `Ready`.
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::api::{Api, ListParams, Patch, PatchParams};
+use kube::Client;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use keel_config::bootstrap::KUBELET_KUBECONFIG_PATH;
+use keel_config::UncordonConfig;
+
+/// Seconds to wait for required pods when no timeout is configured
+pub const DEFAULT_READY_TIMEOUT_SECS: u64 = 300;
+
+/// How often pod readiness is polled
+const READY_POLL_SECS: u64 = 5;
+
+/// Whether `pod` is owned by a DaemonSet
+fn is_daemonset_pod(pod: &Pod) -> bool {
+    pod.metadata
+        .owner_references
+        .as_ref()
+        .is_some_and(|owners| owners.iter().any(|o| o.kind == "DaemonSet"))
+}
+
+/// Whether `pod` reports the `Ready` condition as `True`
+fn is_pod_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|c| c.type_ == "Ready" && c.status == "True")
+        })
+}
+
+/// Names (`namespace/name`) of required pods that are not `Ready`
+///
+/// Only DaemonSet pods count, and pods being deleted are ignored. If no
+/// required pod exists yet the gate is not passed either: after a reboot
+/// kubelet may not have recreated them.
+pub fn check_pods_ready(pods: &[Pod]) -> Result<(), Vec<String>> {
+    let required: Vec<&Pod> = pods
+        .iter()
+        .filter(|p| is_daemonset_pod(p) && p.metadata.deletion_timestamp.is_none())
+        .collect();
+    if required.is_empty() {
+        return Err(Vec::new());
+    }
+
+    let unready: Vec<String> = required
+        .iter()
+        .filter(|p| !is_pod_ready(p))
+        .map(|p| {
+            format!(
+                "{}/{}",
+                p.metadata.namespace.as_deref().unwrap_or_default(),
+                p.metadata.name.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    if unready.is_empty() {
+        Ok(())
+    } else {
+        Err(unready)
+    }
+}
+
+/// Poll until the pods on `node_name` matching `selector` are all `Ready`
+pub async fn wait_for_pods_ready(
+    client: Client,
+    node_name: &str,
+    selector: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let pods: Api<Pod> = Api::all(client);
+    let params = ListParams::default()
+        .labels(selector)
+        .fields(&format!("spec.nodeName={}", node_name));
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        let last = match pods.list(&params).await {
+            Ok(list) => match check_pods_ready(&list.items) {
+                Ok(()) => return Ok(()),
+                Err(unready) if unready.is_empty() => "no matching pods yet".to_string(),
+                Err(unready) => format!("not ready: {}", unready.join(", ")),
+            },
+            Err(e) => format!("failed to list pods: {}", e),
+        };
+        debug!(node = %node_name, selector = %selector, status = %last, "Waiting for required pods");
+
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!(
+                "required pods not ready after {}s ({})",
+                timeout.as_secs(),
+                last
+            ));
+        }
+        tokio::time::sleep(Duration::from_secs(READY_POLL_SECS)).await;
+    }
+}
+
+/// Uncordon `node_name` if it is cordoned, after the readiness gate passes
+///
+/// Returns `Ok(false)` if the node was schedulable already. On a gate
+/// timeout the node stays cordoned.
+pub async fn uncordon_when_ready(
+    client: Client,
+    node_name: &str,
+    config: &UncordonConfig,
+) -> Result<bool, String> {
+    let nodes: Api<Node> = Api::all(client.clone());
+    let node = nodes
+        .get(node_name)
+        .await
+        .map_err(|e| format!("failed to get node: {}", e))?;
+    if !node.spec.and_then(|s| s.unschedulable).unwrap_or(false) {
+        return Ok(false);
+    }
+
+    if let Some(selector) = config.ready_selector.as_deref().filter(|s| !s.is_empty()) {
+        let timeout = Duration::from_secs(
+            config
+                .ready_timeout_secs
+                .unwrap_or(DEFAULT_READY_TIMEOUT_SECS),
+        );
+        wait_for_pods_ready(client, node_name, selector, timeout).await?;
+        info!(node = %node_name, selector = %selector, "Required pods are ready");
+    }
+
+    let patch = serde_json::json!({ "spec": { "unschedulable": false } });
+    nodes
+        .patch(node_name, &PatchParams::default(), &Patch::Merge(patch))
+        .await
+        .map_err(|e| format!("failed to uncordon node: {}", e))?;
+    Ok(true)
+}
+
+/// Uncordon the bootstrapped Node after a healthy post-update boot
+///
+/// Runs as the node itself, with kubelet's rotated credential; the bootstrap
+/// kubeconfig can only create CSRs. Does nothing if the node was never
+/// bootstrapped; errors are logged, not returned.
+pub async fn uncordon_after_update(bootstrap_state_path: &str, config: &UncordonConfig) {
+    if !keel_config::bootstrap::BootstrapConfig::is_bootstrapped(bootstrap_state_path) {
+        debug!("Node not bootstrapped, nothing to uncordon");
+        return;
+    }
+    let bootstrap = match keel_config::bootstrap::BootstrapConfig::load(bootstrap_state_path) {
+        Ok(bootstrap) => bootstrap,
+        Err(e) => {
+            warn!(error = %e, "Cannot uncordon: failed to load bootstrap configuration");
+            return;
+        }
+    };
+    let client = match crate::node_metadata::kubelet_client(KUBELET_KUBECONFIG_PATH).await {
+        Ok(Some(client)) => client,
+        Ok(None) => {
+            warn!(
+                path = KUBELET_KUBECONFIG_PATH,
+                "Cannot uncordon: kubelet has no client credential"
+            );
+            return;
+        }
+        Err(e) => {
+            warn!(error = %e, "Cannot uncordon");
+            return;
+        }
+    };
+
+    match uncordon_when_ready(client, &bootstrap.node_name, config).await {
+        Ok(true) => info!(node = %bootstrap.node_name, "Node uncordoned after update"),
+        Ok(false) => debug!(node = %bootstrap.node_name, "Node is not cordoned"),
+        Err(e) => warn!(node = %bootstrap.node_name, error = %e, "Leaving node cordoned"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodCondition, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference, Time};
+
+    fn pod(name: &str, owner_kind: &str, ready: Option<bool>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("kube-system".to_string()),
+                owner_references: Some(vec![OwnerReference {
+                    kind: owner_kind.to_string(),
+                    name: name.to_string(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                conditions: ready.map(|ready| {
+                    vec![PodCondition {
+                        type_: "Ready".to_string(),
+                        status: if ready { "True" } else { "False" }.to_string(),
+                        ..Default::default()
+                    }]
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_all_required_pods_ready() {
+        let pods = vec![
+            pod("calico-node-x", "DaemonSet", Some(true)),
+            pod("kube-proxy-x", "DaemonSet", Some(true)),
+            // Not a DaemonSet pod, ignored
+            pod("coredns-x", "ReplicaSet", Some(false)),
+        ];
+        assert_eq!(check_pods_ready(&pods), Ok(()));
+    }
+
+    #[test]
+    fn test_unready_pods_reported() {
+        let pods = vec![
+            pod("calico-node-x", "DaemonSet", Some(true)),
+            pod("kube-proxy-x", "DaemonSet", Some(false)),
+            // No conditions yet counts as not ready
+            pod("cilium-x", "DaemonSet", None),
+        ];
+        assert_eq!(
+            check_pods_ready(&pods),
+            Err(vec![
+                "kube-system/kube-proxy-x".to_string(),
+                "kube-system/cilium-x".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_no_required_pods_is_not_ready() {
+        assert_eq!(check_pods_ready(&[]), Err(Vec::new()));
+        assert_eq!(
+            check_pods_ready(&[pod("coredns-x", "ReplicaSet", Some(true))]),
+            Err(Vec::new())
+        );
+
+        let mut terminating = pod("kube-proxy-old", "DaemonSet", Some(false));
+        terminating.metadata.deletion_timestamp =
+            Some(Time(k8s_openapi::jiff::Timestamp::UNIX_EPOCH));
+        assert_eq!(
+            check_pods_ready(&[terminating, pod("kube-proxy-x", "DaemonSet", Some(true))]),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Append-only journal of update attempts
+//!
+//! Every update, whether installed directly or run from a schedule, adds
+//! one JSON line to [`UPDATE_JOURNAL_PATH`] recording where the image came
+//! from, which partition it went to, how it ended, and the OS version
+//! before and after. Lines are never rewritten: once the file would grow
+//! past [`MAX_JOURNAL_BYTES`] it is renamed to `<path>.1`, replacing the
+//! previous generation, and a new file is started.
+//!
+//! Source URLs are stored with their query string redacted, since the
+//! journal is readable by viewers and pre-signed URLs carry credentials.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::{download, image_metadata};
+
+/// Where the agent keeps its update journal
+pub const UPDATE_JOURNAL_PATH: &str = "/var/lib/keel/update-journal.jsonl";
+
+/// Size at which the journal is rotated
+pub const MAX_JOURNAL_BYTES: u64 = 1024 * 1024;
+
+/// One update attempt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub source_url: String,
+    /// Schedule that ran the update; `None` for `InstallUpdate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_id: Option<String>,
+    /// Partition the image was written to; `None` if the attempt failed
+    /// before one was chosen
+    pub target_partition: Option<u32>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// OS version running when the update started (`VERSION_ID` from
+    /// os-release); empty if it names none
+    pub version_before: String,
+    /// OS version in the image, if its metadata sidecar names one
+    pub version_after: Option<String>,
+}
+
+impl JournalEntry {
+    /// Entry for an attempt starting now; finish it with [`Self::finish`]
+    pub fn start(source_url: &str, schedule_id: Option<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            started_at: now,
+            finished_at: now,
+            source_url: download::redact_url(source_url),
+            schedule_id,
+            target_partition: None,
+            success: false,
+            error: None,
+            version_before: image_metadata::running_os_version().unwrap_or_default(),
+            version_after: None,
+        }
+    }
+
+    /// Record the outcome of the attempt
+    pub fn finish(&mut self, result: Result<(), String>) {
+        self.finished_at = Utc::now();
+        self.success = result.is_ok();
+        self.error = result.err();
+    }
+}
+
+/// Path the journal at `path` is rotated to
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Append `entry` to the journal at `path`, rotating it first if the new
+/// line would take it past `max_bytes`
+pub fn append<P: AsRef<Path>>(path: P, entry: &JournalEntry, max_bytes: u64) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut line =
+        serde_json::to_vec(entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.push(b'\n');
+
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    if size > 0 && size + line.len() as u64 > max_bytes {
+        debug!(path = %path.display(), size, "Rotating update journal");
+        fs::rename(path, rotated_path(path))?;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    // A single write keeps concurrent appends from interleaving
+    file.write_all(&line)?;
+    file.sync_data()
+}
+
+/// Append `entry` to the agent's journal, logging rather than failing
+pub fn record<P: AsRef<Path>>(path: P, entry: &JournalEntry) {
+    let path = path.as_ref();
+    if let Err(e) = append(path, entry, MAX_JOURNAL_BYTES) {
+        warn!(error = %e, path = %path.display(), "Failed to write update journal");
+    }
+}
+
+/// Error recorded for an attempt whose client went away mid-update
+pub const INTERRUPTED_ERROR: &str = "client disconnected before the update finished";
+
+/// Journals a streamed update attempt exactly once
+///
+/// Dropping the response stream of `InstallUpdate` also drops the update,
+/// so an attempt that was never [finished](Self::finish) is recorded as
+/// interrupted when the guard is dropped.
+pub struct JournalGuard {
+    path: PathBuf,
+    entry: Arc<tokio::sync::Mutex<JournalEntry>>,
+    recorded: bool,
+}
+
+impl JournalGuard {
+    /// Guard for `entry`, which is journaled to `path`
+    pub fn new(path: impl Into<PathBuf>, entry: Arc<tokio::sync::Mutex<JournalEntry>>) -> Self {
+        Self {
+            path: path.into(),
+            entry,
+            recorded: false,
+        }
+    }
+
+    /// Record the outcome; later calls and the drop do nothing
+    pub async fn finish(&mut self, result: Result<(), String>) {
+        if self.recorded {
+            return;
+        }
+        let mut entry = self.entry.lock().await.clone();
+        entry.finish(result);
+        record(&self.path, &entry);
+        self.recorded = true;
+    }
+}
+
+impl Drop for JournalGuard {
+    fn drop(&mut self) {
+        if self.recorded {
+            return;
+        }
+        match self.entry.try_lock() {
+            Ok(entry) => {
+                let mut entry = entry.clone();
+                entry.finish(Err(INTERRUPTED_ERROR.to_string()));
+                record(&self.path, &entry);
+            }
+            Err(_) => {
+                warn!(path = %self.path.display(), "Update entry busy, interrupted attempt not journaled")
+            }
+        }
+    }
+}
+
+/// All entries in the journal at `path`, oldest first, including the
+/// rotated generation
+///
+/// Lines that do not parse are skipped.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    let mut entries = Vec::new();
+    for file in [rotated_path(path), path.to_path_buf()] {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!(error = %e, path = %file.display(), "Skipping bad journal line"),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, result: Result<(), String>) -> JournalEntry {
+        let mut entry = JournalEntry::start(source, None);
+        entry.target_partition = Some(3);
+        entry.version_after = Some("1.4.0".to_string());
+        entry.finish(result);
+        entry
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        assert!(read(&path).unwrap().is_empty());
+
+        let ok = entry("http://example.com/a.squashfs", Ok(()));
+        let failed = entry(
+            "http://example.com/b.squashfs",
+            Err("Flash error".to_string()),
+        );
+        append(&path, &ok, MAX_JOURNAL_BYTES).unwrap();
+        append(&path, &failed, MAX_JOURNAL_BYTES).unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries, [ok.clone(), failed.clone()]);
+        assert!(entries[0].success);
+        assert_eq!(entries[1].error.as_deref(), Some("Flash error"));
+        assert_eq!(
+            entries[0].version_before,
+            image_metadata::running_os_version().unwrap_or_default()
+        );
+
+        // Earlier lines are left untouched by later appends
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content.lines().next().unwrap(),
+            serde_json::to_string(&ok).unwrap()
+        );
+
+        // Damaged lines are skipped
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{not json\n")
+            .unwrap();
+        assert_eq!(read(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_source_url_query_redacted() {
+        let entry = JournalEntry::start("https://images.example.com/keel.img?sig=secret", None);
+        assert_eq!(
+            entry.source_url,
+            "https://images.example.com/keel.img?[REDACTED]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_guard_records_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let entry = || {
+            Arc::new(tokio::sync::Mutex::new(JournalEntry::start(
+                "http://example.com/a.squashfs",
+                None,
+            )))
+        };
+
+        let mut guard = JournalGuard::new(&path, entry());
+        guard.finish(Ok(())).await;
+        guard.finish(Err("late".to_string())).await;
+        drop(guard);
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].success);
+
+        // Dropped mid-update, as when the client cancels the stream
+        drop(JournalGuard::new(&path, entry()));
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries[1].success);
+        assert_eq!(entries[1].error.as_deref(), Some(INTERRUPTED_ERROR));
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let line_len = serde_json::to_vec(&entry("http://example.com/0", Ok(())))
+            .unwrap()
+            .len() as u64
+            + 1;
+        // Room for two lines per file but not three; timestamps vary in
+        // length by a few bytes
+        let max_bytes = line_len * 2 + line_len / 2;
+
+        for i in 0..5 {
+            let e = entry(&format!("http://example.com/{}", i), Ok(()));
+            append(&path, &e, max_bytes).unwrap();
+            assert!(fs::metadata(&path).unwrap().len() <= max_bytes);
+        }
+
+        // 0,1 went to the rotated file, which 2,3 then replaced
+        let sources: Vec<_> = read(&path)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.source_url)
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                "http://example.com/2",
+                "http://example.com/3",
+                "http://example.com/4"
+            ]
+        );
+        assert!(rotated_path(&path).exists());
+    }
+}
@@ -5,12 +5,13 @@
 //! - Maintenance window support
 //! - Auto-rollback configuration
 //! - Update hooks (pre/post)
+//! - A cap on in-flight schedules and pruning of finished ones
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
@@ -32,12 +33,20 @@ pub struct UpdateSchedule {
     pub is_delta: bool,
     pub fallback_to_full: bool,
     pub full_image_url: Option<String>,
+    // Pre-staging support
+    #[serde(default)]
+    pub prestage: bool,
+    #[serde(default)]
+    pub staged_image_path: Option<String>,
     pub rollback_triggered: bool,
     pub rollback_reason: Option<String>,
     pub status: ScheduleStatus,
     pub created_at: DateTime<Utc>,
     pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
+    /// Set when an operator confirmed the update, disarming auto-rollback
+    #[serde(default)]
+    pub confirmed_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
 }
 
@@ -64,10 +73,76 @@ impl std::fmt::Display for ScheduleStatus {
     }
 }
 
+impl std::str::FromStr for ScheduleStatus {
+    type Err = String;
+
+    /// Parse the [`Display`](std::fmt::Display) form (`rolled_back`); the
+    /// serialized variant names (`RolledBack`) are accepted as well
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "").as_str() {
+            "pending" => Ok(ScheduleStatus::Pending),
+            "running" => Ok(ScheduleStatus::Running),
+            "completed" => Ok(ScheduleStatus::Completed),
+            "failed" => Ok(ScheduleStatus::Failed),
+            "cancelled" => Ok(ScheduleStatus::Cancelled),
+            "rolledback" => Ok(ScheduleStatus::RolledBack),
+            _ => Err(format!("unknown schedule status '{}'", s)),
+        }
+    }
+}
+
+/// Pending or running schedules allowed at once unless configured otherwise
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 100;
+
+/// Why a schedule could not be created
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleError {
+    /// The in-flight cap is reached
+    TooManySchedules { in_flight: usize, limit: usize },
+    /// The requested combination of options is not supported
+    Invalid(String),
+    /// The schedules could not be persisted
+    Storage(String),
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::TooManySchedules { in_flight, limit } => write!(
+                f,
+                "Too many in-flight schedules ({} pending or running, limit {}); cancel some first",
+                in_flight, limit
+            ),
+            ScheduleError::Invalid(msg) | ScheduleError::Storage(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Outcome of [`UpdateScheduler::schedule_update`]
+#[derive(Debug, Clone)]
+pub enum Scheduled {
+    /// A new schedule was created
+    Created(UpdateSchedule),
+    /// A pending schedule for the same source already existed
+    Existing(UpdateSchedule),
+}
+
+impl Scheduled {
+    /// The created or existing schedule
+    pub fn into_schedule(self) -> UpdateSchedule {
+        match self {
+            Self::Created(schedule) | Self::Existing(schedule) => schedule,
+        }
+    }
+}
+
 /// Update scheduler
 pub struct UpdateScheduler {
     schedules: Arc<RwLock<HashMap<String, UpdateSchedule>>>,
     storage_path: String,
+    max_in_flight: AtomicUsize,
+    /// Finished schedules kept when a new one is created (`usize::MAX`: all)
+    keep_finished: AtomicUsize,
 }
 
 impl UpdateScheduler {
@@ -79,10 +154,41 @@ impl UpdateScheduler {
         Self {
             schedules: Arc::new(RwLock::new(schedules)),
             storage_path,
+            max_in_flight: AtomicUsize::new(DEFAULT_MAX_IN_FLIGHT),
+            keep_finished: AtomicUsize::new(usize::MAX),
         }
     }
 
+    /// Limit how many schedules may be pending or running at once
+    pub fn set_max_in_flight(&self, max_in_flight: usize) {
+        self.max_in_flight.store(max_in_flight, Ordering::Relaxed);
+    }
+
+    /// Drop all but the newest `keep` finished schedules whenever a schedule
+    /// is created; `None` keeps them all
+    pub fn set_keep_finished(&self, keep: Option<usize>) {
+        self.keep_finished
+            .store(keep.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// Apply the `scheduler` section of the node configuration
+    ///
+    /// Called at startup and after every config reload.
+    pub fn apply_config(&self, config: &keel_config::SchedulerConfig) {
+        self.set_max_in_flight(config.max_in_flight.unwrap_or(DEFAULT_MAX_IN_FLIGHT));
+        self.set_keep_finished(config.keep_finished);
+    }
+
     /// Schedule an update
+    ///
+    /// Unless `force` is set, a pending schedule for the same `source_url`
+    /// is returned as [`Scheduled::Existing`] instead of creating another;
+    /// the lookup and the insert happen under one lock, so concurrent
+    /// requests cannot both create one.
+    ///
+    /// Fails with [`ScheduleError::TooManySchedules`] once the in-flight cap
+    /// is reached. If `scheduler.keep_finished` is set, older finished
+    /// schedules are dropped to keep the persisted file small.
     #[allow(clippy::too_many_arguments)]
     pub async fn schedule_update(
         &self,
@@ -98,7 +204,15 @@ impl UpdateScheduler {
         is_delta: bool,
         fallback_to_full: bool,
         full_image_url: Option<String>,
-    ) -> Result<UpdateSchedule, String> {
+        prestage: bool,
+        force: bool,
+    ) -> Result<Scheduled, ScheduleError> {
+        if prestage && is_delta {
+            return Err(ScheduleError::Invalid(
+                "Pre-staging is not supported for delta updates".to_string(),
+            ));
+        }
+
         let schedule = UpdateSchedule {
             id: Uuid::new_v4().to_string(),
             source_url,
@@ -114,26 +228,70 @@ impl UpdateScheduler {
             is_delta,
             fallback_to_full,
             full_image_url,
+            prestage,
+            staged_image_path: None,
             status: ScheduleStatus::Pending,
             created_at: Utc::now(),
             started_at: None,
             completed_at: None,
+            confirmed_at: None,
             error_message: None,
         };
 
+        let mut schedules = self.schedules.write().await;
+        if !force {
+            if let Some(existing) = oldest_pending_by_source(&schedules, &schedule.source_url) {
+                return Ok(Scheduled::Existing(existing.clone()));
+            }
+        }
+
+        let max_in_flight = self.max_in_flight.load(Ordering::Relaxed);
+        let in_flight = schedules
+            .values()
+            .filter(|s| matches!(s.status, ScheduleStatus::Pending | ScheduleStatus::Running))
+            .count();
+        if in_flight >= max_in_flight {
+            return Err(ScheduleError::TooManySchedules {
+                in_flight,
+                limit: max_in_flight,
+            });
+        }
+
         info!(
             schedule_id = %schedule.id,
             scheduled_at = ?schedule.scheduled_at,
             "Created update schedule"
         );
 
-        let mut schedules = self.schedules.write().await;
         schedules.insert(schedule.id.clone(), schedule.clone());
+        let keep = self.keep_finished.load(Ordering::Relaxed);
+        let pruned = prune_finished(&mut schedules, keep);
         drop(schedules);
+        if !pruned.is_empty() {
+            debug!(count = pruned.len(), keep, "Pruned finished schedules");
+        }
 
-        self.persist_schedules().await?;
+        self.persist_schedules()
+            .await
+            .map_err(ScheduleError::Storage)?;
+
+        Ok(Scheduled::Created(schedule))
+    }
+
+    /// Drop all but the newest `keep` completed, failed, or cancelled
+    /// schedules, returning the IDs removed
+    ///
+    /// Rolled-back schedules are kept as rollback history.
+    pub async fn prune(&self, keep: usize) -> Result<Vec<String>, String> {
+        let mut schedules = self.schedules.write().await;
+        let pruned = prune_finished(&mut schedules, keep);
+        drop(schedules);
 
-        Ok(schedule)
+        if !pruned.is_empty() {
+            info!(count = pruned.len(), keep, "Pruned finished schedules");
+            self.persist_schedules().await?;
+        }
+        Ok(pruned)
     }
 
     /// Get all schedules
@@ -242,6 +400,58 @@ impl UpdateScheduler {
         }
     }
 
+    /// Confirm that an applied update works, so it is never rolled back
+    /// automatically
+    ///
+    /// Only running or completed schedules can be confirmed; confirming
+    /// again keeps the original confirmation time.
+    pub async fn confirm_schedule(&self, id: &str) -> Result<UpdateSchedule, String> {
+        let mut schedules = self.schedules.write().await;
+
+        let schedule = schedules
+            .get_mut(id)
+            .ok_or_else(|| format!("Schedule not found: {}", id))?;
+        if !matches!(
+            schedule.status,
+            ScheduleStatus::Running | ScheduleStatus::Completed
+        ) {
+            return Err(format!(
+                "Cannot confirm schedule in status: {}",
+                schedule.status
+            ));
+        }
+
+        if schedule.confirmed_at.is_none() {
+            schedule.confirmed_at = Some(Utc::now());
+            info!(schedule_id = %id, "Update confirmed, auto-rollback disarmed");
+        }
+        let confirmed = schedule.clone();
+        drop(schedules);
+        self.persist_schedules().await?;
+        Ok(confirmed)
+    }
+
+    /// Record the pre-staged image for a pending schedule
+    ///
+    /// Fails if the schedule no longer exists or has left the `Pending`
+    /// state, in which case the caller should discard the staged file.
+    pub async fn set_staged_image(&self, id: &str, path: String) -> Result<(), String> {
+        let mut schedules = self.schedules.write().await;
+
+        match schedules.get_mut(id) {
+            Some(schedule) if schedule.status == ScheduleStatus::Pending => {
+                schedule.staged_image_path = Some(path);
+                drop(schedules);
+                self.persist_schedules().await
+            }
+            Some(schedule) => Err(format!(
+                "Schedule {} is no longer pending (status: {})",
+                id, schedule.status
+            )),
+            None => Err(format!("Schedule not found: {}", id)),
+        }
+    }
+
     /// Update schedule status
     pub async fn update_status(
         &self,
@@ -289,6 +499,12 @@ impl UpdateScheduler {
             .collect()
     }
 
+    /// Time until the nearest pending schedule becomes due
+    pub async fn time_until_next_due(&self) -> Option<std::time::Duration> {
+        let schedules = self.schedules.read().await;
+        time_until_next_due(schedules.values(), Utc::now())
+    }
+
     /// Trigger rollback for a schedule
     #[allow(dead_code)]
     pub async fn trigger_rollback(
@@ -314,20 +530,34 @@ impl UpdateScheduler {
         }
     }
 
+    /// Reset schedules a restart left in `Running`
+    ///
+    /// Call once at startup, before the executor runs: nothing can still be
+    /// executing them. Returns the IDs of the schedules that were reset.
+    pub async fn recover_interrupted(
+        &self,
+        resume_on_restart: bool,
+    ) -> Result<Vec<String>, String> {
+        let mut schedules = self.schedules.write().await;
+        let recovered = recover_interrupted(&mut schedules, resume_on_restart);
+        drop(schedules);
+
+        if !recovered.is_empty() {
+            for id in &recovered {
+                warn!(schedule_id = %id, resume_on_restart, "Recovered update interrupted by restart");
+            }
+            self.persist_schedules().await?;
+        }
+        Ok(recovered)
+    }
+
     /// Persist schedules to disk
     async fn persist_schedules(&self) -> Result<(), String> {
         let schedules = self.schedules.read().await;
         let json = serde_json::to_string_pretty(&*schedules)
             .map_err(|e| format!("Failed to serialize schedules: {}", e))?;
 
-        // Ensure parent directory exists
-        if let Some(parent) = Path::new(&self.storage_path).parent() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                warn!(error = %e, "Failed to create storage directory");
-            }
-        }
-
-        fs::write(&self.storage_path, json)
+        keel_config::atomic_write(&self.storage_path, json)
             .map_err(|e| format!("Failed to write schedules: {}", e))?;
 
         debug!(path = %self.storage_path, "Persisted schedules");
@@ -355,10 +585,144 @@ impl UpdateScheduler {
     }
 }
 
+/// Reason recorded on schedules found `Running` at startup
+pub const INTERRUPTED_BY_RESTART: &str = "Interrupted by restart";
+
+/// Re-queue (`Pending`) or fail every schedule left `Running` without a
+/// `completed_at`, returning their IDs
+pub fn recover_interrupted(
+    schedules: &mut HashMap<String, UpdateSchedule>,
+    resume_on_restart: bool,
+) -> Vec<String> {
+    let mut recovered = Vec::new();
+    for schedule in schedules.values_mut() {
+        if schedule.status != ScheduleStatus::Running || schedule.completed_at.is_some() {
+            continue;
+        }
+        if resume_on_restart {
+            schedule.status = ScheduleStatus::Pending;
+            schedule.started_at = None;
+            schedule.error_message = Some(format!("{}; re-queued", INTERRUPTED_BY_RESTART));
+        } else {
+            schedule.status = ScheduleStatus::Failed;
+            schedule.completed_at = Some(Utc::now());
+            schedule.error_message = Some(INTERRUPTED_BY_RESTART.to_string());
+        }
+        recovered.push(schedule.id.clone());
+    }
+    recovered
+}
+
+/// Remove all but the newest `keep` schedules in `Completed`, `Failed`, or
+/// `Cancelled`, by completion (or else creation) time, returning their IDs
+pub fn prune_finished(schedules: &mut HashMap<String, UpdateSchedule>, keep: usize) -> Vec<String> {
+    let mut finished: Vec<_> = schedules
+        .values()
+        .filter(|s| {
+            matches!(
+                s.status,
+                ScheduleStatus::Completed | ScheduleStatus::Failed | ScheduleStatus::Cancelled
+            )
+        })
+        .map(|s| (s.completed_at.unwrap_or(s.created_at), s.id.clone()))
+        .collect();
+    finished.sort_unstable_by(|a, b| b.cmp(a));
+
+    let pruned: Vec<String> = finished.into_iter().skip(keep).map(|(_, id)| id).collect();
+    for id in &pruned {
+        schedules.remove(id);
+    }
+    pruned
+}
+
+/// Compute how long from `now` until the earliest pending schedule is due
+///
+/// Returns `Duration::ZERO` if a schedule is already overdue, and `None` if
+/// no pending schedule has a start time.
+pub fn time_until_next_due<'a>(
+    schedules: impl IntoIterator<Item = &'a UpdateSchedule>,
+    now: DateTime<Utc>,
+) -> Option<std::time::Duration> {
+    schedules
+        .into_iter()
+        .filter(|s| s.status == ScheduleStatus::Pending)
+        .filter_map(|s| s.scheduled_at)
+        .min()
+        .map(|next| (next - now).to_std().unwrap_or(std::time::Duration::ZERO))
+}
+
+/// Pending schedule with every optional field unset, for tests
+/// Oldest pending schedule in `schedules` for `source_url`
+fn oldest_pending_by_source<'a>(
+    schedules: &'a HashMap<String, UpdateSchedule>,
+    source_url: &str,
+) -> Option<&'a UpdateSchedule> {
+    schedules
+        .values()
+        .filter(|s| s.status == ScheduleStatus::Pending && s.source_url == source_url)
+        .min_by_key(|s| s.created_at)
+}
+
+#[cfg(test)]
+pub(crate) fn pending_schedule(id: &str, scheduled_at: Option<DateTime<Utc>>) -> UpdateSchedule {
+    UpdateSchedule {
+        id: id.to_string(),
+        source_url: String::new(),
+        expected_sha256: None,
+        scheduled_at,
+        maintenance_window_secs: None,
+        enable_auto_rollback: false,
+        health_check_timeout_secs: None,
+        pre_update_hook: None,
+        post_update_hook: None,
+        is_delta: false,
+        fallback_to_full: false,
+        full_image_url: None,
+        prestage: false,
+        staged_image_path: None,
+        rollback_triggered: false,
+        rollback_reason: None,
+        status: ScheduleStatus::Pending,
+        created_at: Utc::now(),
+        started_at: None,
+        completed_at: None,
+        confirmed_at: None,
+        error_message: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const ALL_STATUSES: [ScheduleStatus; 6] = [
+        ScheduleStatus::Pending,
+        ScheduleStatus::Running,
+        ScheduleStatus::Completed,
+        ScheduleStatus::Failed,
+        ScheduleStatus::Cancelled,
+        ScheduleStatus::RolledBack,
+    ];
+
+    #[test]
+    fn test_schedule_status_display_round_trip() {
+        for status in ALL_STATUSES {
+            let shown = status.to_string();
+            assert_eq!(
+                shown.parse::<ScheduleStatus>(),
+                Ok(status.clone()),
+                "{shown}"
+            );
+
+            // The names persisted in schedules.json parse too
+            let serialized = serde_json::to_value(&status).unwrap();
+            let serialized = serialized.as_str().unwrap();
+            assert_eq!(serialized.parse::<ScheduleStatus>(), Ok(status));
+        }
+        assert_eq!(ScheduleStatus::RolledBack.to_string(), "rolled_back");
+        assert!("paused".parse::<ScheduleStatus>().is_err());
+    }
+
     #[tokio::test]
     async fn test_schedule_update() {
         let scheduler = UpdateScheduler::new("/tmp/test-schedules.json");
@@ -376,9 +740,12 @@ mod tests {
                 false, // is_delta
                 false, // fallback_to_full
                 None,  // full_image_url
+                false, // prestage
+                true,  // force
             )
             .await
-            .unwrap();
+            .unwrap()
+            .into_schedule();
 
         assert_eq!(schedule.status, ScheduleStatus::Pending);
         assert!(schedule.enable_auto_rollback);
@@ -404,9 +771,12 @@ mod tests {
                 false, // is_delta
                 false, // fallback_to_full
                 None,  // full_image_url
+                false, // prestage
+                true,  // force
             )
             .await
-            .unwrap();
+            .unwrap()
+            .into_schedule();
 
         scheduler.cancel_schedule(&schedule.id).await.unwrap();
 
@@ -417,6 +787,118 @@ mod tests {
         let _ = fs::remove_file("/tmp/test-cancel-schedules.json");
     }
 
+    #[tokio::test]
+    async fn test_confirm_schedule() {
+        let path = "/tmp/test-confirm-schedule.json";
+        let scheduler = UpdateScheduler::new(path);
+
+        let schedule = scheduler
+            .schedule_update(
+                "http://example.com/update.squashfs".to_string(),
+                None,
+                Some(Utc::now()),
+                None,
+                true,
+                None,  // health_check_timeout_secs
+                None,  // pre_update_hook
+                None,  // post_update_hook
+                false, // is_delta
+                false, // fallback_to_full
+                None,  // full_image_url
+                false, // prestage
+                true,  // force
+            )
+            .await
+            .unwrap()
+            .into_schedule();
+
+        // Nothing to confirm before the update ran
+        let err = scheduler.confirm_schedule(&schedule.id).await.unwrap_err();
+        assert!(err.contains("pending"), "{err}");
+        assert!(scheduler.confirm_schedule("missing").await.is_err());
+
+        scheduler
+            .update_status(&schedule.id, ScheduleStatus::Completed, None)
+            .await
+            .unwrap();
+        let confirmed = scheduler.confirm_schedule(&schedule.id).await.unwrap();
+        let confirmed_at = confirmed.confirmed_at.unwrap();
+        assert_eq!(confirmed.status, ScheduleStatus::Completed);
+
+        // Idempotent, and persisted across restarts
+        let again = scheduler.confirm_schedule(&schedule.id).await.unwrap();
+        assert_eq!(again.confirmed_at, Some(confirmed_at));
+        let reloaded = UpdateScheduler::new(path);
+        assert_eq!(
+            reloaded
+                .get_schedule(&schedule.id)
+                .await
+                .unwrap()
+                .confirmed_at,
+            Some(confirmed_at)
+        );
+
+        // Cleanup
+        let _ = fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_update_coalesces_pending_source() {
+        let scheduler = UpdateScheduler::new("/tmp/test-coalesce-pending-source.json");
+        let url = "http://example.com/dup.squashfs";
+
+        let schedule = |source: &str, force: bool| {
+            scheduler.schedule_update(
+                source.to_string(),
+                None,
+                Some(Utc::now() + chrono::Duration::hours(1)),
+                None,
+                false,
+                None,  // health_check_timeout_secs
+                None,  // pre_update_hook
+                None,  // post_update_hook
+                false, // is_delta
+                false, // fallback_to_full
+                None,  // full_image_url
+                false, // prestage
+                force,
+            )
+        };
+
+        // Concurrent requests for the same source create one schedule
+        let (a, b) = tokio::join!(schedule(url, false), schedule(url, false));
+        let (first, existing) = match (a.unwrap(), b.unwrap()) {
+            (Scheduled::Created(first), Scheduled::Existing(existing))
+            | (Scheduled::Existing(existing), Scheduled::Created(first)) => (first, existing),
+            other => panic!(
+                "expected one created and one existing schedule, got {:?}",
+                other
+            ),
+        };
+        assert_eq!(existing.id, first.id);
+        assert_eq!(scheduler.get_schedules().await.len(), 1);
+
+        // Other sources and forced requests get their own
+        assert!(matches!(
+            schedule("http://example.com/other.squashfs", false)
+                .await
+                .unwrap(),
+            Scheduled::Created(_)
+        ));
+        let forced = schedule(url, true).await.unwrap();
+        assert!(matches!(forced, Scheduled::Created(_)));
+
+        // Only pending schedules count; the oldest remaining one is returned
+        scheduler.cancel_schedule(&first.id).await.unwrap();
+        match schedule(url, false).await.unwrap() {
+            Scheduled::Existing(existing) => assert_eq!(existing.id, forced.into_schedule().id),
+            other => panic!("expected the forced schedule, got {:?}", other),
+        }
+
+        // Cleanup
+        let _ = fs::remove_file("/tmp/test-coalesce-pending-source.json");
+    }
+
     #[tokio::test]
     async fn test_register_rollback() {
         let scheduler = UpdateScheduler::new("/tmp/test-register-rollback.json");
@@ -434,9 +916,12 @@ mod tests {
                 false,
                 false,
                 None,
+                false,
+                true, // force
             )
             .await
-            .unwrap();
+            .unwrap()
+            .into_schedule();
 
         // Mark as running then completed
         scheduler
@@ -466,6 +951,77 @@ mod tests {
         let _ = fs::remove_file("/tmp/test-register-rollback.json");
     }
 
+    #[tokio::test]
+    async fn test_set_staged_image_only_while_pending() {
+        let scheduler = UpdateScheduler::new("/tmp/test-staged-image.json");
+
+        let schedule = scheduler
+            .schedule_update(
+                "http://example.com/update.squashfs".to_string(),
+                None,
+                Some(Utc::now() + chrono::Duration::hours(1)),
+                None,
+                false,
+                None,
+                None,
+                None,
+                false,
+                false,
+                None,
+                true,
+                true, // force
+            )
+            .await
+            .unwrap()
+            .into_schedule();
+        assert!(schedule.prestage);
+
+        scheduler
+            .set_staged_image(&schedule.id, "/tmp/staged.img".to_string())
+            .await
+            .unwrap();
+        let updated = scheduler.get_schedule(&schedule.id).await.unwrap();
+        assert_eq!(
+            updated.staged_image_path.as_deref(),
+            Some("/tmp/staged.img")
+        );
+
+        scheduler.cancel_schedule(&schedule.id).await.unwrap();
+        assert!(scheduler
+            .set_staged_image(&schedule.id, "/tmp/staged.img".to_string())
+            .await
+            .is_err());
+
+        // Cleanup
+        let _ = fs::remove_file("/tmp/test-staged-image.json");
+    }
+
+    #[tokio::test]
+    async fn test_prestage_rejected_for_delta() {
+        let scheduler = UpdateScheduler::new("/tmp/test-prestage-delta.json");
+
+        let result = scheduler
+            .schedule_update(
+                "http://example.com/update.delta".to_string(),
+                None,
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+                true,
+                false,
+                None,
+                true,
+                true, // force
+            )
+            .await;
+        assert!(result.is_err());
+
+        let _ = fs::remove_file("/tmp/test-prestage-delta.json");
+    }
+
     #[tokio::test]
     async fn test_register_rollback_no_schedule() {
         let scheduler = UpdateScheduler::new("/tmp/test-register-rollback-none.json");
@@ -494,9 +1050,12 @@ mod tests {
                 false,
                 false,
                 None,
+                false,
+                true, // force
             )
             .await
-            .unwrap();
+            .unwrap()
+            .into_schedule();
 
         let s2 = scheduler
             .schedule_update(
@@ -511,9 +1070,12 @@ mod tests {
                 false,
                 false,
                 None,
+                false,
+                true, // force
             )
             .await
-            .unwrap();
+            .unwrap()
+            .into_schedule();
 
         // No completed schedules yet
         assert!(scheduler.get_latest_active_schedule().await.is_none());
@@ -547,28 +1109,261 @@ mod tests {
         let _ = fs::remove_file("/tmp/test-latest-active.json");
     }
 
+    /// Persist one `Running` and one `Completed` schedule at `path`
+    async fn persist_interrupted(path: &str) -> (String, String) {
+        let scheduler = UpdateScheduler::new(path);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let schedule = scheduler
+                .schedule_update(
+                    "http://example.com/update.squashfs".to_string(),
+                    None,
+                    Some(Utc::now()),
+                    None,
+                    false,
+                    None,
+                    None,
+                    None,
+                    false,
+                    false,
+                    None,
+                    false,
+                    true, // force
+                )
+                .await
+                .unwrap()
+                .into_schedule();
+            scheduler
+                .update_status(&schedule.id, ScheduleStatus::Running, None)
+                .await
+                .unwrap();
+            ids.push(schedule.id);
+        }
+        scheduler
+            .update_status(&ids[1], ScheduleStatus::Completed, None)
+            .await
+            .unwrap();
+        (ids[0].clone(), ids[1].clone())
+    }
+
+    /// Create a pending schedule due now
+    async fn schedule_now(scheduler: &UpdateScheduler) -> Result<UpdateSchedule, ScheduleError> {
+        scheduler
+            .schedule_update(
+                "http://example.com/update.squashfs".to_string(),
+                None,
+                Some(Utc::now()),
+                None,
+                false,
+                None,
+                None,
+                None,
+                false,
+                false,
+                None,
+                false,
+                true, // force
+            )
+            .await
+            .map(Scheduled::into_schedule)
+    }
+
+    #[tokio::test]
+    async fn test_schedule_update_enforces_in_flight_cap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("schedules.json");
+        let scheduler = UpdateScheduler::new(path.to_str().unwrap());
+        scheduler.set_max_in_flight(2);
+
+        let first = schedule_now(&scheduler).await.unwrap();
+        let second = schedule_now(&scheduler).await.unwrap();
+        scheduler
+            .update_status(&second.id, ScheduleStatus::Running, None)
+            .await
+            .unwrap();
+
+        // Pending and running both count
+        let err = schedule_now(&scheduler).await.unwrap_err();
+        assert_eq!(
+            err,
+            ScheduleError::TooManySchedules {
+                in_flight: 2,
+                limit: 2
+            }
+        );
+        assert_eq!(scheduler.get_schedules().await.len(), 2);
+
+        // Finishing one frees a slot
+        scheduler.cancel_schedule(&first.id).await.unwrap();
+        schedule_now(&scheduler).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auto_prune_is_opt_in() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("schedules.json");
+        let scheduler = UpdateScheduler::new(path.to_str().unwrap());
+
+        for _ in 0..3 {
+            let schedule = schedule_now(&scheduler).await.unwrap();
+            scheduler.cancel_schedule(&schedule.id).await.unwrap();
+        }
+        schedule_now(&scheduler).await.unwrap();
+        assert_eq!(scheduler.get_schedules().await.len(), 4);
+
+        scheduler.apply_config(&keel_config::SchedulerConfig {
+            keep_finished: Some(1),
+            ..Default::default()
+        });
+        schedule_now(&scheduler).await.unwrap();
+        let schedules = scheduler.get_schedules().await;
+        assert_eq!(schedules.len(), 3);
+        assert_eq!(
+            schedules
+                .iter()
+                .filter(|s| s.status == ScheduleStatus::Cancelled)
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune_keeps_newest_finished() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("schedules.json");
+        let path = path.to_str().unwrap();
+        let scheduler = UpdateScheduler::new(path);
+
+        let mut finished = Vec::new();
+        let base = Utc::now();
+        for (i, status) in [
+            ScheduleStatus::Completed,
+            ScheduleStatus::Failed,
+            ScheduleStatus::Cancelled,
+            ScheduleStatus::Completed,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let schedule = schedule_now(&scheduler).await.unwrap();
+            scheduler
+                .update_status(&schedule.id, status, None)
+                .await
+                .unwrap();
+            // Distinct completion times, oldest first
+            scheduler
+                .schedules
+                .write()
+                .await
+                .get_mut(&schedule.id)
+                .unwrap()
+                .completed_at = Some(base + chrono::Duration::seconds(i as i64));
+            finished.push(schedule.id);
+        }
+        let pending = schedule_now(&scheduler).await.unwrap();
+        let rolled_back = schedule_now(&scheduler).await.unwrap();
+        scheduler
+            .trigger_rollback(&rolled_back.id, "health check failed")
+            .await
+            .unwrap();
+
+        let mut pruned = scheduler.prune(2).await.unwrap();
+        pruned.sort();
+        let mut oldest = finished[..2].to_vec();
+        oldest.sort();
+        assert_eq!(pruned, oldest);
+
+        // Pending and rolled-back schedules are never pruned, and the
+        // result is persisted
+        let reloaded = UpdateScheduler::new(path);
+        let mut remaining: Vec<_> = reloaded
+            .get_schedules()
+            .await
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        remaining.sort();
+        let mut expected = vec![
+            finished[2].clone(),
+            finished[3].clone(),
+            pending.id,
+            rolled_back.id,
+        ];
+        expected.sort();
+        assert_eq!(remaining, expected);
+
+        assert!(reloaded.prune(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recover_interrupted_fails_running_schedule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("schedules.json");
+        let path = path.to_str().unwrap();
+        let (running, completed) = persist_interrupted(path).await;
+
+        let scheduler = UpdateScheduler::new(path);
+        assert_eq!(
+            scheduler.recover_interrupted(false).await.unwrap(),
+            std::slice::from_ref(&running)
+        );
+
+        let failed = scheduler.get_schedule(&running).await.unwrap();
+        assert_eq!(failed.status, ScheduleStatus::Failed);
+        assert!(failed.completed_at.is_some());
+        assert_eq!(
+            failed.error_message.as_deref(),
+            Some(INTERRUPTED_BY_RESTART)
+        );
+        assert_eq!(
+            scheduler.get_schedule(&completed).await.unwrap().status,
+            ScheduleStatus::Completed
+        );
+
+        // Persisted, and nothing left to recover on the next start
+        let reloaded = UpdateScheduler::new(path);
+        assert_eq!(
+            reloaded.get_schedule(&running).await.unwrap().status,
+            ScheduleStatus::Failed
+        );
+        assert!(reloaded
+            .recover_interrupted(false)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recover_interrupted_requeues_when_resuming() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("schedules.json");
+        let path = path.to_str().unwrap();
+        let (running, _) = persist_interrupted(path).await;
+
+        let scheduler = UpdateScheduler::new(path);
+        assert_eq!(
+            scheduler.recover_interrupted(true).await.unwrap(),
+            std::slice::from_ref(&running)
+        );
+
+        let requeued = scheduler.get_schedule(&running).await.unwrap();
+        assert_eq!(requeued.status, ScheduleStatus::Pending);
+        assert!(requeued.started_at.is_none());
+        assert!(requeued.completed_at.is_none());
+        let due: Vec<_> = scheduler
+            .get_due_schedules()
+            .await
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(due, [running]);
+    }
+
     #[test]
     fn test_maintenance_window_no_schedule_time() {
         let schedule = UpdateSchedule {
-            id: "test".to_string(),
-            source_url: String::new(),
-            expected_sha256: None,
-            scheduled_at: None,
             maintenance_window_secs: Some(3600),
-            enable_auto_rollback: false,
-            health_check_timeout_secs: None,
-            pre_update_hook: None,
-            post_update_hook: None,
-            is_delta: false,
-            fallback_to_full: false,
-            full_image_url: None,
-            rollback_triggered: false,
-            rollback_reason: None,
-            status: ScheduleStatus::Pending,
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            error_message: None,
+            ..pending_schedule("test", None)
         };
 
         // No scheduled_at means always within window
@@ -577,27 +1372,7 @@ mod tests {
 
     #[test]
     fn test_maintenance_window_no_window_configured() {
-        let schedule = UpdateSchedule {
-            id: "test".to_string(),
-            source_url: String::new(),
-            expected_sha256: None,
-            scheduled_at: Some(Utc::now() - chrono::Duration::hours(2)),
-            maintenance_window_secs: None,
-            enable_auto_rollback: false,
-            health_check_timeout_secs: None,
-            pre_update_hook: None,
-            post_update_hook: None,
-            is_delta: false,
-            fallback_to_full: false,
-            full_image_url: None,
-            rollback_triggered: false,
-            rollback_reason: None,
-            status: ScheduleStatus::Pending,
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            error_message: None,
-        };
+        let schedule = pending_schedule("test", Some(Utc::now() - chrono::Duration::hours(2)));
 
         // No maintenance_window_secs means no restriction
         assert!(UpdateScheduler::is_within_maintenance_window(&schedule));
@@ -606,25 +1381,8 @@ mod tests {
     #[test]
     fn test_maintenance_window_within() {
         let schedule = UpdateSchedule {
-            id: "test".to_string(),
-            source_url: String::new(),
-            expected_sha256: None,
-            scheduled_at: Some(Utc::now() - chrono::Duration::minutes(10)),
             maintenance_window_secs: Some(3600),
-            enable_auto_rollback: false,
-            health_check_timeout_secs: None,
-            pre_update_hook: None,
-            post_update_hook: None,
-            is_delta: false,
-            fallback_to_full: false,
-            full_image_url: None,
-            rollback_triggered: false,
-            rollback_reason: None,
-            status: ScheduleStatus::Pending,
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            error_message: None,
+            ..pending_schedule("test", Some(Utc::now() - chrono::Duration::minutes(10)))
         };
 
         // 10 minutes into a 1-hour window
@@ -634,28 +1392,49 @@ mod tests {
     #[test]
     fn test_maintenance_window_expired() {
         let schedule = UpdateSchedule {
-            id: "test".to_string(),
-            source_url: String::new(),
-            expected_sha256: None,
-            scheduled_at: Some(Utc::now() - chrono::Duration::hours(2)),
             maintenance_window_secs: Some(3600),
-            enable_auto_rollback: false,
-            health_check_timeout_secs: None,
-            pre_update_hook: None,
-            post_update_hook: None,
-            is_delta: false,
-            fallback_to_full: false,
-            full_image_url: None,
-            rollback_triggered: false,
-            rollback_reason: None,
-            status: ScheduleStatus::Pending,
-            created_at: Utc::now(),
-            started_at: None,
-            completed_at: None,
-            error_message: None,
+            ..pending_schedule("test", Some(Utc::now() - chrono::Duration::hours(2)))
         };
 
         // 2 hours past a 1-hour window
         assert!(!UpdateScheduler::is_within_maintenance_window(&schedule));
     }
+
+    #[test]
+    fn test_time_until_next_due_picks_earliest_pending() {
+        let now = Utc::now();
+        let mut done = pending_schedule("done", Some(now + chrono::Duration::seconds(5)));
+        done.status = ScheduleStatus::Completed;
+        let schedules = [
+            pending_schedule("later", Some(now + chrono::Duration::seconds(120))),
+            pending_schedule("soon", Some(now + chrono::Duration::seconds(45))),
+            pending_schedule("unscheduled", None),
+            done,
+        ];
+
+        assert_eq!(
+            time_until_next_due(&schedules, now),
+            Some(std::time::Duration::from_secs(45))
+        );
+    }
+
+    #[test]
+    fn test_time_until_next_due_overdue_is_zero() {
+        let now = Utc::now();
+        let schedules = [pending_schedule(
+            "overdue",
+            Some(now - chrono::Duration::seconds(10)),
+        )];
+
+        assert_eq!(
+            time_until_next_due(&schedules, now),
+            Some(std::time::Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_time_until_next_due_none_pending() {
+        let schedules = [pending_schedule("unscheduled", None)];
+        assert_eq!(time_until_next_due(&schedules, Utc::now()), None);
+    }
 }
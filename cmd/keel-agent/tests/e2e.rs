@@ -6,8 +6,9 @@
 use keel_api::node::node_service_client::NodeServiceClient;
 use keel_api::node::node_service_server::NodeServiceServer;
 use keel_api::node::{
-    CancelScheduledUpdateRequest, GetHealthRequest, GetRollbackHistoryRequest, GetStatusRequest,
-    GetUpdateScheduleRequest, RebootRequest, ScheduleUpdateRequest,
+    CancelScheduledUpdateRequest, ConfirmUpdateRequest, GetHealthRequest,
+    GetRollbackHistoryRequest, GetStatusRequest, GetUpdateScheduleRequest, RebootRequest,
+    ScheduleUpdateRequest,
 };
 use std::net::SocketAddr;
 use tonic::transport::{Channel, Server};
@@ -30,10 +31,29 @@ async fn start_test_server() -> Result<SocketAddr, Box<dyn std::error::Error>> {
 
     let diagnostics = std::sync::Arc::new(keel_agent::DiagnosticsManager::new());
 
+    let os_release_path = format!("/tmp/keel-e2e-{}-os-release", addr.port());
+    std::fs::write(&os_release_path, "ID=keelos\nVERSION_ID=\"1.4.0\"\n")?;
+
     let service = keel_agent::HelperNodeService {
         scheduler,
         health_checker,
         diagnostics,
+        config: std::sync::Arc::new(tokio::sync::RwLock::new(
+            keel_config::NodeConfig::default_config(),
+        )),
+        cmdline_path: keel_agent::disk::PROC_CMDLINE.to_string(),
+        ca_cert_path: keel_agent::mtls::CA_CERT_PATH.to_string(),
+        degraded_state_path: keel_config::degraded::DEGRADED_STATE_PATH.to_string(),
+        node_id_path: keel_config::node_id::NODE_ID_PATH.to_string(),
+        os_release_path,
+        bootstrap_base_path: keel_config::bootstrap::BOOTSTRAP_BASE_PATH.to_string(),
+        kubelet_restart_signal_path: keel_config::bootstrap::KUBELET_RESTART_SIGNAL_PATH
+            .to_string(),
+        bootstrap_lock: std::sync::Arc::new(tokio::sync::Mutex::new(())),
+        update_journal_path: keel_agent::update_journal::UPDATE_JOURNAL_PATH.to_string(),
+        reboot_pending_path: keel_agent::reboot_pending::REBOOT_PENDING_MARKER.to_string(),
+        boot_next_marker_path: keel_agent::disk::BOOT_NEXT_MARKER.to_string(),
+        printk_path: keel_agent::kernel_log::PRINTK_PATH.to_string(),
     };
 
     tokio::spawn(async move {
@@ -63,6 +83,7 @@ async fn connect_client(
 /// Cleanup helper that removes the schedule file used by a test server.
 fn cleanup_schedule_file(port: u16) {
     let _ = std::fs::remove_file(format!("/tmp/keel-e2e-{port}.json"));
+    let _ = std::fs::remove_file(format!("/tmp/keel-e2e-{port}-os-release"));
 }
 
 // ---- Tests ----
@@ -76,7 +97,7 @@ async fn e2e_get_status() -> Result<(), Box<dyn std::error::Error>> {
     let status = response.into_inner();
 
     assert_eq!(status.hostname, "keel-node");
-    assert_eq!(status.os_version, "0.1.0");
+    assert_eq!(status.os_version, "1.4.0");
     assert!(!status.kernel_version.is_empty());
 
     cleanup_schedule_file(addr.port());
@@ -105,7 +126,7 @@ async fn e2e_get_health() -> Result<(), Box<dyn std::error::Error>> {
     let addr = start_test_server().await?;
     let mut client = connect_client(addr).await?;
 
-    let response = client.get_health(GetHealthRequest {}).await?;
+    let response = client.get_health(GetHealthRequest::default()).await?;
     let health = response.into_inner();
 
     // Status should be one of the valid values
@@ -116,6 +137,12 @@ async fn e2e_get_health() -> Result<(), Box<dyn std::error::Error>> {
     assert!(!health.checks.is_empty());
     // last_update_time should be populated
     assert!(!health.last_update_time.is_empty());
+    // The summary explains the status
+    if health.status == "healthy" {
+        assert_eq!(health.summary, "all checks passing");
+    } else {
+        assert!(health.summary.contains("failing") || health.summary.contains("degraded"));
+    }
 
     cleanup_schedule_file(addr.port());
     Ok(())
@@ -140,6 +167,8 @@ async fn e2e_schedule_update_lifecycle() -> Result<(), Box<dyn std::error::Error
             is_delta: false,
             fallback_to_full: false,
             full_image_url: String::new(),
+            prestage: false,
+            force: false,
         })
         .await?;
 
@@ -241,6 +270,8 @@ async fn e2e_schedule_update_invalid_time() -> Result<(), Box<dyn std::error::Er
             is_delta: false,
             fallback_to_full: false,
             full_image_url: String::new(),
+            prestage: false,
+            force: false,
         })
         .await;
 
@@ -253,6 +284,106 @@ async fn e2e_schedule_update_invalid_time() -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
+#[tokio::test]
+async fn e2e_confirm_update_requires_applied_schedule() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_test_server().await?;
+    let mut client = connect_client(addr).await?;
+
+    let missing = client
+        .confirm_update(ConfirmUpdateRequest {
+            schedule_id: "nonexistent-id".to_string(),
+        })
+        .await?
+        .into_inner();
+    assert!(!missing.success);
+    assert!(missing.message.contains("not found"));
+
+    let schedule = client
+        .schedule_update(ScheduleUpdateRequest {
+            source_url: "http://example.com/confirm.squashfs".to_string(),
+            expected_sha256: String::new(),
+            scheduled_at: "2099-06-01T00:00:00Z".to_string(),
+            maintenance_window_secs: 0,
+            enable_auto_rollback: true,
+            health_check_timeout_secs: 0,
+            pre_update_hook: String::new(),
+            post_update_hook: String::new(),
+            is_delta: false,
+            fallback_to_full: false,
+            full_image_url: String::new(),
+            prestage: false,
+            force: false,
+        })
+        .await?
+        .into_inner();
+
+    // A pending update has nothing to confirm yet
+    let pending = client
+        .confirm_update(ConfirmUpdateRequest {
+            schedule_id: schedule.schedule_id.clone(),
+        })
+        .await?
+        .into_inner();
+    assert!(!pending.success);
+    assert!(pending.message.contains("pending"));
+
+    let schedules = client
+        .get_update_schedule(GetUpdateScheduleRequest {})
+        .await?
+        .into_inner()
+        .schedules;
+    assert!(schedules[0].confirmed_at.is_empty());
+
+    cleanup_schedule_file(addr.port());
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_schedule_same_source_is_coalesced() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_test_server().await?;
+    let mut client = connect_client(addr).await?;
+
+    let request = |force: bool| ScheduleUpdateRequest {
+        source_url: "http://example.com/dup.squashfs".to_string(),
+        expected_sha256: String::new(),
+        scheduled_at: "2099-06-01T00:00:00Z".to_string(),
+        maintenance_window_secs: 0,
+        enable_auto_rollback: false,
+        health_check_timeout_secs: 0,
+        pre_update_hook: String::new(),
+        post_update_hook: String::new(),
+        is_delta: false,
+        fallback_to_full: false,
+        full_image_url: String::new(),
+        prestage: false,
+        force,
+    };
+
+    let first = client.schedule_update(request(false)).await?.into_inner();
+    assert!(!first.duplicate);
+
+    // Same source again returns the existing schedule
+    let second = client.schedule_update(request(false)).await?.into_inner();
+    assert!(second.duplicate);
+    assert_eq!(second.schedule_id, first.schedule_id);
+    assert!(second.message.contains(&first.schedule_id));
+
+    // force schedules it a second time
+    let forced = client.schedule_update(request(true)).await?.into_inner();
+    assert!(!forced.duplicate);
+    assert_ne!(forced.schedule_id, first.schedule_id);
+
+    let list = client
+        .get_update_schedule(GetUpdateScheduleRequest {})
+        .await?
+        .into_inner()
+        .schedules;
+    assert_eq!(list.len(), 2);
+
+    cleanup_schedule_file(addr.port());
+    Ok(())
+}
+
 #[tokio::test]
 async fn e2e_schedule_multiple_updates() -> Result<(), Box<dyn std::error::Error>> {
     let addr = start_test_server().await?;
@@ -272,6 +403,8 @@ async fn e2e_schedule_multiple_updates() -> Result<(), Box<dyn std::error::Error
             is_delta: false,
             fallback_to_full: false,
             full_image_url: String::new(),
+            prestage: false,
+            force: false,
         })
         .await?;
 
@@ -288,6 +421,8 @@ async fn e2e_schedule_multiple_updates() -> Result<(), Box<dyn std::error::Error
             is_delta: true,
             fallback_to_full: true,
             full_image_url: "http://example.com/v2-full.squashfs".to_string(),
+            prestage: false,
+            force: false,
         })
         .await?;
 
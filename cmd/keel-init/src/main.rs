@@ -10,6 +10,9 @@
 //! All errors are handled gracefully - the system will continue running
 //! in a degraded/maintenance mode rather than crashing.
 
+use keel_config::degraded::{DegradedState, DEGRADED_STATE_PATH};
+use keel_config::logging::{LogFormat, LogSettings, DEFAULT_LOG_LEVEL};
+use keel_config::service_stop::{self, SERVICE_STOP_MARKER};
 use nix::mount::{mount, MsFlags};
 use nix::sys::stat::{umask, Mode};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
@@ -17,38 +20,64 @@ use nix::unistd::Pid;
 use std::fs;
 use std::process::{Child, Command, Stdio};
 use std::{thread, time};
-use tracing::{debug, error, info, warn, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 mod telemetry;
 
 /// Entry point - wraps run() to ensure PID 1 never exits unexpectedly
 fn main() {
-    // Initialize tracing subscriber for structured logging
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .with_target(false)
-        .with_ansi(false) // No ANSI colors for serial console
-        .compact()
-        .finish();
-
-    // Ignore errors if subscriber is already set (shouldn't happen for PID 1)
-    let _ = tracing::subscriber::set_global_default(subscriber);
+    init_logging();
 
     info!("Welcome to KeelOS v0.1");
     info!("Init process started (PID 1)");
 
-    if let Err(e) = run() {
+    let mut boot_tracker = telemetry::BootPhaseTracker::new();
+    if let Err(e) = run(&mut boot_tracker) {
         error!(error = %e, "Init encountered a fatal error");
         error!("System entering maintenance mode");
+        record_degraded(
+            DEGRADED_STATE_PATH,
+            &e.to_string(),
+            boot_tracker.current_phase().unwrap_or("init"),
+        );
     }
 
     // PID 1 must never exit - enter infinite maintenance loop
     maintenance_loop();
 }
 
+/// Initialize the tracing subscriber from `KEEL_LOG_LEVEL`/`RUST_LOG` and
+/// `KEEL_LOG_FORMAT`, which PID 1 receives from the kernel command line
+fn init_logging() {
+    let settings = LogSettings::from_env();
+    let filter =
+        EnvFilter::try_new(&settings.filter).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL));
+
+    let builder = FmtSubscriber::builder()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_ansi(false); // No ANSI colors for serial console
+
+    // Ignore errors if subscriber is already set (shouldn't happen for PID 1)
+    let _ = match settings.format {
+        LogFormat::Text => tracing::subscriber::set_global_default(builder.compact().finish()),
+        LogFormat::Json => {
+            tracing::subscriber::set_global_default(builder.json().flatten_event(true).finish())
+        }
+    };
+}
+
+/// Tell keel-agent why the node is in maintenance mode
+fn record_degraded(path: &str, reason: &str, phase: &str) {
+    match DegradedState::new(reason, phase).save(path) {
+        Ok(()) => info!(path = %path, phase = %phase, "Recorded maintenance mode"),
+        Err(e) => warn!(error = %e, "Failed to record maintenance mode"),
+    }
+}
+
 /// Main init logic - all errors are propagated but never cause a panic
-fn run() -> Result<(), InitError> {
+fn run(boot_tracker: &mut telemetry::BootPhaseTracker) -> Result<(), InitError> {
     // Set safe umask
     umask(Mode::from_bits(0o077).unwrap());
 
@@ -59,16 +88,14 @@ fn run() -> Result<(), InitError> {
         "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
     );
 
-    // Initialize boot phase tracker
-    let mut boot_tracker = telemetry::BootPhaseTracker::new();
-
     // Mount essential filesystems
     boot_tracker.start_phase("filesystem");
     setup_filesystems()?;
 
     // Mount persistent storage for container data
     boot_tracker.start_phase("storage");
-    setup_persistent_storage();
+    let keel_persistent = setup_persistent_storage();
+    setup_node_id(keel_persistent);
 
     // Set up cgroups
     boot_tracker.start_phase("cgroups");
@@ -238,7 +265,9 @@ fn setup_filesystems() -> Result<(), InitError> {
 /// Mount persistent storage disk for container and kubelet data.
 /// Without this, all container images and state live on the rootfs (RAM),
 /// which quickly fills up and causes DiskPressure.
-fn setup_persistent_storage() {
+///
+/// Returns whether /var/lib/keel is backed by the data partition.
+fn setup_persistent_storage() -> bool {
     use std::process::Command;
 
     let data_mount = "/data";
@@ -317,7 +346,7 @@ fn setup_persistent_storage() {
 
     if !mounted {
         warn!("No persistent storage available. Container data will use rootfs (RAM).");
-        return;
+        return false;
     }
 
     // Bind-mount key directories to persistent storage
@@ -326,6 +355,7 @@ fn setup_persistent_storage() {
         ("kubelet", "/var/lib/kubelet"),
         ("keel", "/var/lib/keel"),
     ];
+    let mut keel_persistent = false;
 
     for (subdir, target) in &bind_dirs {
         let source = format!("{}/{}", data_mount, subdir);
@@ -339,12 +369,44 @@ fn setup_persistent_storage() {
             MsFlags::MS_BIND,
             None,
         ) {
-            Ok(()) => info!(source = %source, target = target, "Bind-mounted persistent storage"),
+            Ok(()) => {
+                info!(source = %source, target = target, "Bind-mounted persistent storage");
+                keel_persistent |= *target == "/var/lib/keel";
+            }
             Err(e) => warn!(source = %source, target = target, error = %e, "Failed to bind-mount"),
         }
     }
 
     info!("Persistent storage setup complete");
+    keel_persistent
+}
+
+/// Generate the persistent node ID on first boot
+///
+/// Runs after the data partition is mounted so the ID lands on persistent
+/// storage; an existing ID is kept. Without the data partition an ID written
+/// to /var/lib/keel would live in RAM and change on every boot, so none is
+/// written and the node reports an empty ID.
+fn setup_node_id(persistent: bool) {
+    if !persistent {
+        warn!("/var/lib/keel is not on the data partition, not generating a node ID");
+        return;
+    }
+    match keel_config::node_id::ensure_node_id(keel_config::node_id::NODE_ID_PATH, || {
+        random_bytes().map(keel_config::node_id::uuid_v4)
+    }) {
+        Ok(id) => info!(node_id = %id, "Node ID"),
+        Err(e) => warn!(error = %e, "Failed to set up node ID"),
+    }
+}
+
+/// 16 bytes from /dev/urandom
+fn random_bytes() -> std::io::Result<[u8; 16]> {
+    use std::io::Read;
+    // Read exactly what we need: /dev/urandom is an infinite device
+    let mut buf = [0u8; 16];
+    fs::File::open("/dev/urandom")?.read_exact(&mut buf)?;
+    Ok(buf)
 }
 
 /// Configure networking based on saved configuration or DHCP fallback
@@ -358,10 +420,19 @@ fn setup_networking() {
     match keel_config::network::NetworkConfig::load() {
         Ok(config) => {
             info!("Loading network configuration from file");
-            apply_network_config(&config);
+            apply_interface_aliases(&config);
+            apply_network_config(&config, None);
+            record_applied_network(&config);
+        }
+        Err(keel_config::network::NetworkConfigError::Io(e))
+            if e.kind() == std::io::ErrorKind::NotFound =>
+        {
+            debug!("No network configuration found, using DHCP fallback");
+            // Fallback to DHCP on eth0 (QEMU default primary interface)
+            configure_dhcp_fallback();
         }
         Err(e) => {
-            debug!(error = %e, "No network configuration found, using DHCP fallback");
+            error!(error = %e, "Failed to load network configuration, using DHCP fallback");
             // Fallback to DHCP on eth0 (QEMU default primary interface)
             configure_dhcp_fallback();
         }
@@ -397,6 +468,80 @@ fn setup_networking() {
     info!("Networking initialized");
 }
 
+/// Marker dropped by keel-agent to have the network configuration re-applied
+const REAPPLY_NETWORK_MARKER: &str = "/run/keel/reapply-network";
+
+/// Re-apply the network configuration if the marker at `marker` is present
+///
+/// The marker holds the address the requesting client is connected
+/// through, if keel-agent knew it, and is passed on to `reapply`. It is
+/// removed before `reapply` runs so a failed re-apply is not retried in a
+/// loop. Returns whether a re-apply was triggered.
+fn check_reapply_network(
+    marker: &std::path::Path,
+    reapply: impl FnOnce(Option<std::net::IpAddr>),
+) -> bool {
+    let contents = match fs::read_to_string(marker) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return false,
+        Err(e) => {
+            warn!(error = %e, "Failed to read network re-apply marker");
+            String::new()
+        }
+    };
+    if let Err(e) = fs::remove_file(marker) {
+        warn!(error = %e, "Failed to remove network re-apply marker");
+        return false;
+    }
+    info!("Network re-apply requested");
+    reapply(contents.trim().parse().ok());
+    true
+}
+
+/// State of a live re-apply on a running node, see [`address_commands`]
+struct LiveApply {
+    /// Configuration applied before this re-apply
+    previous: keel_config::network::NetworkConfig,
+    /// Address the client that asked for the re-apply is connected through
+    connected: Option<std::net::IpAddr>,
+}
+
+/// Reload the saved network configuration and apply it in place
+fn reapply_network(connected: Option<std::net::IpAddr>) {
+    use keel_config::network::{NetworkConfig, APPLIED_CONFIG_PATH};
+
+    match NetworkConfig::load() {
+        Ok(config) => {
+            // Without a record of the previous apply no address is known
+            // to be ours, so none are removed
+            let previous = NetworkConfig::load_from(APPLIED_CONFIG_PATH).unwrap_or_else(|e| {
+                warn!(error = %e, "No record of the applied network configuration, keeping existing addresses");
+                NetworkConfig::new()
+            });
+            let live = LiveApply {
+                previous,
+                connected,
+            };
+            apply_interface_aliases(&config);
+            apply_network_config(&config, Some(&live));
+            record_applied_network(&config);
+            info!("Network configuration re-applied");
+        }
+        Err(e) => warn!(error = %e, "Failed to load network configuration for re-apply"),
+    }
+}
+
+/// Record `config` as applied, for the next live re-apply and keel-agent
+fn record_applied_network(config: &keel_config::network::NetworkConfig) {
+    let path = keel_config::network::APPLIED_CONFIG_PATH;
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = config.save_to(path) {
+        warn!(path, error = %e, "Failed to record applied network configuration");
+    }
+}
+
 /// Configure loopback interface
 fn configure_loopback() {
     // Using ip command instead of busybox ifconfig for modern networking
@@ -419,11 +564,67 @@ fn configure_loopback() {
     }
 }
 
+/// Rename interfaces according to the MAC-based aliases in the config
+fn apply_interface_aliases(config: &keel_config::network::NetworkConfig) {
+    let renames = match config.plan_interface_renames("/sys/class/net") {
+        Ok(renames) => renames,
+        Err(e) => {
+            warn!(error = %e, "Failed to read interfaces for alias matching");
+            return;
+        }
+    };
+
+    for rename in renames {
+        // The kernel only renames links that are down
+        let _ = Command::new("/sbin/ip")
+            .args(["link", "set", &rename.current, "down"])
+            .status();
+
+        match Command::new("/sbin/ip")
+            .args(["link", "set", &rename.current, "name", &rename.desired])
+            .status()
+        {
+            Ok(status) if status.success() => {
+                info!(from = %rename.current, to = %rename.desired, "Renamed interface")
+            }
+            Ok(status) => warn!(
+                from = %rename.current,
+                to = %rename.desired,
+                exit_code = ?status.code(),
+                "Failed to rename interface"
+            ),
+            Err(e) => warn!(
+                from = %rename.current,
+                to = %rename.desired,
+                error = %e,
+                "Failed to rename interface"
+            ),
+        }
+    }
+}
+
 /// Apply network configuration from config file
-fn apply_network_config(config: &keel_config::network::NetworkConfig) {
+///
+/// `live` is set when re-applying on a running node, where addresses that
+/// stay configured must not be removed (see [`address_commands`]) and
+/// routes the new config dropped must be (see [`stale_route_commands`]).
+fn apply_network_config(config: &keel_config::network::NetworkConfig, live: Option<&LiveApply>) {
+    if let Some(live) = live {
+        for args in stale_route_commands(&live.previous, config) {
+            let command = args.join(" ");
+            match Command::new("/sbin/ip").args(&args).status() {
+                Ok(status) if status.success() => info!(command = %command, "Stale route removed"),
+                Ok(status) => {
+                    warn!(command = %command, exit_code = ?status.code(), "Failed to remove stale route")
+                }
+                Err(e) => warn!(command = %command, error = %e, "Failed to remove stale route"),
+            }
+        }
+    }
+
     // Configure each interface
     for iface in &config.interfaces {
-        configure_interface(iface);
+        configure_interface(iface, live);
     }
 
     // Configure DNS if present
@@ -437,34 +638,438 @@ fn apply_network_config(config: &keel_config::network::NetworkConfig) {
     }
 }
 
+/// `ip` arguments running `action` (`replace`, `del`) on a default route
+///
+/// The route names the device, as IPv6 gateways are often link-local.
+fn default_route_args(
+    action: &str,
+    iface_name: &str,
+    gateway: &str,
+    metric: Option<u32>,
+) -> Vec<String> {
+    let mut args = if gateway.contains(':') {
+        vec!["-6".to_string()]
+    } else {
+        Vec::new()
+    };
+    args.extend(
+        [
+            "route", action, "default", "via", gateway, "dev", iface_name,
+        ]
+        .map(String::from),
+    );
+    if let Some(metric) = metric {
+        args.extend(["metric".to_string(), metric.to_string()]);
+    }
+    args
+}
+
+/// `ip` arguments setting the IPv4 default route of a static interface
+///
+/// `replace` rather than `add`, so a live re-apply updates the route
+/// instead of failing because it already exists.
+fn ipv4_default_route_args(
+    iface_name: &str,
+    cfg: &keel_config::network::StaticConfig,
+) -> Option<Vec<String>> {
+    let gateway = cfg.gateway.as_deref()?;
+    Some(default_route_args(
+        "replace",
+        iface_name,
+        gateway,
+        cfg.gateway_metric,
+    ))
+}
+
+/// `ip` arguments setting the IPv6 default route of a static interface
+fn ipv6_default_route_args(
+    iface_name: &str,
+    cfg: &keel_config::network::StaticConfig,
+) -> Option<Vec<String>> {
+    let gateway = cfg.ipv6_gateway.as_deref()?;
+    Some(default_route_args(
+        "replace",
+        iface_name,
+        gateway,
+        cfg.gateway_metric,
+    ))
+}
+
+/// `ip` arguments running `action` (`replace`, `del`) on a custom route
+fn route_args(action: &str, route: &keel_config::network::RouteConfig) -> Vec<String> {
+    let mut args = ["route", action, &route.destination, "via", &route.gateway]
+        .map(String::from)
+        .to_vec();
+    if let Some(metric) = route.metric {
+        args.extend(["metric".to_string(), metric.to_string()]);
+    }
+    args
+}
+
+/// `ip` argument lists deleting the default routes and custom routes that
+/// `previous` set up and `config` no longer has
+///
+/// Routes that stay are left alone; `replace` updates them in place.
+fn stale_route_commands(
+    previous: &keel_config::network::NetworkConfig,
+    config: &keel_config::network::NetworkConfig,
+) -> Vec<Vec<String>> {
+    let gateways =
+        |config: &keel_config::network::NetworkConfig| -> Vec<(String, String, Option<u32>)> {
+            config
+                .interfaces
+                .iter()
+                .filter_map(|iface| Some((iface, iface.static_config()?)))
+                .flat_map(|(iface, cfg)| {
+                    [cfg.gateway.as_deref(), cfg.ipv6_gateway.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .map(move |gateway| {
+                            (iface.name.clone(), gateway.to_string(), cfg.gateway_metric)
+                        })
+                })
+                .collect()
+        };
+
+    let wanted = gateways(config);
+    let mut commands: Vec<Vec<String>> = gateways(previous)
+        .into_iter()
+        .filter(|gateway| !wanted.contains(gateway))
+        .map(|(iface, gateway, metric)| default_route_args("del", &iface, &gateway, metric))
+        .collect();
+    commands.extend(
+        previous
+            .routes
+            .iter()
+            .filter(|route| !config.routes.contains(route))
+            .map(|route| route_args("del", route)),
+    );
+    commands
+}
+
+/// `ip` arguments creating the VLAN or bond link of `iface`
+///
+/// `None` for plain interfaces, and for links that already `exist`, as on
+/// a live re-apply, where `ip link add` would fail.
+fn link_add_args(
+    iface: &keel_config::network::InterfaceConfig,
+    exists: bool,
+) -> Option<Vec<String>> {
+    use keel_config::network::InterfaceType;
+
+    if exists {
+        return None;
+    }
+    let args = match &iface.config {
+        InterfaceType::Vlan(vlan_cfg) => vec![
+            "link".to_string(),
+            "add".to_string(),
+            "link".to_string(),
+            vlan_cfg.parent.clone(),
+            "name".to_string(),
+            iface.name.clone(),
+            "type".to_string(),
+            "vlan".to_string(),
+            "id".to_string(),
+            vlan_cfg.vlan_id.to_string(),
+        ],
+        InterfaceType::Bond(bond_cfg) => vec![
+            "link".to_string(),
+            "add".to_string(),
+            iface.name.clone(),
+            "type".to_string(),
+            "bond".to_string(),
+            "mode".to_string(),
+            bond_cfg.mode.as_str().to_string(),
+        ],
+        InterfaceType::Dhcp | InterfaceType::Static(_) => return None,
+    };
+    Some(args)
+}
+
+/// Whether the network link `name` exists
+fn link_exists(name: &str) -> bool {
+    std::path::Path::new("/sys/class/net").join(name).exists()
+}
+
+/// Name of the bond or bridge the link `name` is enslaved to, if any
+fn link_master(name: &str) -> Option<String> {
+    let master = fs::read_link(
+        std::path::Path::new("/sys/class/net")
+            .join(name)
+            .join("master"),
+    )
+    .ok()?;
+    Some(master.file_name()?.to_string_lossy().into_owned())
+}
+
+/// `/proc/sys` writes enabling SLAAC on an interface, if `ipv6_auto` is set
+///
+/// `accept_ra` is 2 rather than 1: the kernel ignores router advertisements
+/// on interfaces with forwarding enabled unless told otherwise, and
+/// Kubernetes nodes forward.
+fn ipv6_auto_sysctls(
+    iface_name: &str,
+    cfg: &keel_config::network::StaticConfig,
+) -> Vec<(String, &'static str)> {
+    if !cfg.ipv6_auto {
+        return Vec::new();
+    }
+    let conf = format!("/proc/sys/net/ipv6/conf/{}", iface_name);
+    vec![
+        (format!("{}/accept_ra", conf), "2"),
+        (format!("{}/autoconf", conf), "1"),
+    ]
+}
+
+/// Outcome of an ARP duplicate address probe
+#[derive(Debug, PartialEq, Eq)]
+enum ArpProbe {
+    /// Nobody answered for the address
+    Free,
+    /// Another host answered, with its MAC address if arping printed one
+    Conflict(Option<String>),
+    /// The probe could not run (missing arping, interface error, ...)
+    Inconclusive,
+}
+
+/// Interpret the exit status and output of `arping -D`
+///
+/// In duplicate address detection mode arping exits 0 when no reply
+/// arrived and non-zero when one did, but also on errors, so a failure
+/// only counts as a conflict if a reply was actually printed, e.g.
+/// `Unicast reply from 192.168.1.10 [52:54:00:12:34:56]  0.612ms`.
+fn interpret_arp_probe(success: bool, output: &str) -> ArpProbe {
+    if success {
+        return ArpProbe::Free;
+    }
+    match output.lines().find(|line| line.contains("reply from")) {
+        Some(line) => ArpProbe::Conflict(
+            line.split_once('[')
+                .and_then(|(_, rest)| rest.split_once(']'))
+                .map(|(mac, _)| mac.to_string()),
+        ),
+        None => ArpProbe::Inconclusive,
+    }
+}
+
+/// Probe for another host using the address of `ipv4_cidr` on `iface_name`
+fn probe_ip_conflict(iface_name: &str, ipv4_cidr: &str, timeout_secs: u64) -> ArpProbe {
+    let ip = ipv4_cidr.split('/').next().unwrap_or(ipv4_cidr);
+    match Command::new("/sbin/arping")
+        .args([
+            "-D",
+            "-c",
+            "2",
+            "-w",
+            &timeout_secs.to_string(),
+            "-I",
+            iface_name,
+            ip,
+        ])
+        .output()
+    {
+        Ok(output) => interpret_arp_probe(
+            output.status.success(),
+            &String::from_utf8_lossy(&output.stdout),
+        ),
+        Err(e) => {
+            warn!(interface = %iface_name, error = %e, "Failed to run arping");
+            ArpProbe::Inconclusive
+        }
+    }
+}
+
+/// IP conflict probe settings from the node config, defaults if absent
+fn ip_conflict_config(config_path: &str) -> keel_config::IpConflictConfig {
+    if !std::path::Path::new(config_path).exists() {
+        return keel_config::IpConflictConfig::default();
+    }
+    match keel_config::NodeConfig::load(config_path) {
+        Ok(config) => config.ip_conflict,
+        Err(e) => {
+            warn!(path = config_path, error = %e, "Failed to read IP conflict settings from node config");
+            keel_config::IpConflictConfig::default()
+        }
+    }
+}
+
+/// Whether the static IPv4 address may be assigned, probing for a
+/// conflicting host first if the node config asks for it
+fn ipv4_address_is_usable(iface_name: &str, ipv4_cidr: &str) -> bool {
+    use keel_config::IpConflictAction;
+
+    let config = ip_conflict_config(NODE_CONFIG_PATH);
+    if config.action == IpConflictAction::Off {
+        return true;
+    }
+    let timeout_secs = config.probe_timeout_secs.unwrap_or(1);
+    match probe_ip_conflict(iface_name, ipv4_cidr, timeout_secs) {
+        ArpProbe::Free => {
+            debug!(interface = %iface_name, ip = %ipv4_cidr, "No IP conflict detected");
+            true
+        }
+        ArpProbe::Conflict(mac) if config.action == IpConflictAction::Abort => {
+            error!(interface = %iface_name, ip = %ipv4_cidr, other_host = ?mac, "IPv4 address is already in use, not assigning it");
+            false
+        }
+        ArpProbe::Conflict(mac) => {
+            warn!(interface = %iface_name, ip = %ipv4_cidr, other_host = ?mac, "IPv4 address is already in use by another host");
+            true
+        }
+        ArpProbe::Inconclusive => {
+            warn!(interface = %iface_name, ip = %ipv4_cidr, "IP conflict probe inconclusive, assigning address");
+            true
+        }
+    }
+}
+
+/// Parse an `ip` address (`10.0.0.5/24`, `fd00::5/64`) so that equal
+/// addresses compare equal whatever their spelling
+fn parse_cidr(address: &str) -> Option<(std::net::IpAddr, u8)> {
+    let (ip, prefix) = address.split_once('/')?;
+    Some((ip.parse().ok()?, prefix.parse().ok()?))
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    match (parse_cidr(a), parse_cidr(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Addresses listed by `ip -o addr show`, in CIDR form
+fn parse_ip_addr_show(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.find(|f| *f == "inet" || *f == "inet6")?;
+            fields.next().map(str::to_string)
+        })
+        .collect()
+}
+
+/// Global-scope addresses currently on `iface_name`
+fn global_addresses(iface_name: &str) -> Vec<String> {
+    match Command::new("/sbin/ip")
+        .args(["-o", "addr", "show", "dev", iface_name, "scope", "global"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            parse_ip_addr_show(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            warn!(interface = %iface_name, exit_code = ?output.status.code(), "Failed to list interface addresses");
+            Vec::new()
+        }
+        Err(e) => {
+            warn!(interface = %iface_name, error = %e, "Failed to list interface addresses");
+            Vec::new()
+        }
+    }
+}
+
+/// `ip` argument lists giving `iface_name` the `wanted` addresses
+///
+/// At boot all global addresses are flushed first, so nothing left over
+/// from an earlier apply survives. A live re-apply instead adds only the
+/// missing addresses and deletes only those the previous apply put there
+/// and the new config dropped: other addresses belong to something else,
+/// like the VIPs of kube-vip, MetalLB or keepalived. The address the
+/// requesting client is connected through is never deleted, and neither
+/// are link-local addresses, as IPv6 gateways are often reached over them.
+fn address_commands(
+    iface_name: &str,
+    wanted: &[String],
+    current: &[String],
+    live: Option<&LiveApply>,
+) -> Vec<Vec<String>> {
+    let family = |address: &str| -> Vec<String> {
+        if address.contains(':') {
+            vec!["-6".to_string()]
+        } else {
+            Vec::new()
+        }
+    };
+    let command = |action: &str, address: &str| {
+        let mut args = family(address);
+        args.extend(["addr", action, address, "dev", iface_name].map(String::from));
+        args
+    };
+
+    let mut commands = Vec::new();
+    if let Some(live) = live {
+        let previous = live.previous.addresses_on(iface_name);
+        for address in current {
+            let ours = previous.iter().any(|p| same_address(p, address));
+            let connected =
+                live.connected.is_some() && parse_cidr(address).map(|(ip, _)| ip) == live.connected;
+            if ours && !connected && !wanted.iter().any(|w| same_address(w, address)) {
+                commands.push(command("del", address));
+            }
+        }
+        for address in wanted {
+            if !current.iter().any(|c| same_address(c, address)) {
+                commands.push(command("add", address));
+            }
+        }
+    } else {
+        commands.push(
+            ["addr", "flush", "dev", iface_name, "scope", "global"]
+                .map(String::from)
+                .to_vec(),
+        );
+        commands.extend(wanted.iter().map(|address| command("add", address)));
+    }
+    commands
+}
+
 /// Apply static IP configuration to an interface
 /// This helper is used for regular interfaces, VLANs, and Bonds
-fn apply_static_ip_config(iface_name: &str, cfg: &keel_config::network::StaticConfig) {
-    // Add IPv4 address if present
-    if !cfg.ipv4_address.is_empty() {
-        match Command::new("/sbin/ip")
-            .args(["addr", "add", &cfg.ipv4_address, "dev", iface_name])
-            .status()
-        {
+fn apply_static_ip_config(
+    iface_name: &str,
+    cfg: &keel_config::network::StaticConfig,
+    live: Option<&LiveApply>,
+) {
+    let current = if live.is_some() {
+        global_addresses(iface_name)
+    } else {
+        Vec::new()
+    };
+
+    // IPv4 address if present and not taken by another host; an address
+    // the interface already holds is not probed again
+    let mut wanted = Vec::new();
+    let ipv4 = !cfg.ipv4_address.is_empty()
+        && (current.iter().any(|c| same_address(c, &cfg.ipv4_address))
+            || ipv4_address_is_usable(iface_name, &cfg.ipv4_address));
+    if ipv4 {
+        wanted.push(cfg.ipv4_address.clone());
+    }
+    wanted.extend(cfg.ipv6_addresses.iter().cloned());
+
+    for args in address_commands(iface_name, &wanted, &current, live) {
+        let command = args.join(" ");
+        match Command::new("/sbin/ip").args(&args).status() {
             Ok(status) if status.success() => {
-                info!(interface = %iface_name, ip = %cfg.ipv4_address, "Static IPv4 configured");
+                info!(interface = %iface_name, command = %command, "Address configured");
             }
             Ok(status) => {
-                warn!(interface = %iface_name, exit_code = ?status.code(), "Failed to set IPv4 address");
+                warn!(interface = %iface_name, command = %command, exit_code = ?status.code(), "Failed to configure address");
             }
             Err(e) => {
-                warn!(interface = %iface_name, error = %e, "Failed to set IPv4 address");
+                warn!(interface = %iface_name, command = %command, error = %e, "Failed to configure address");
             }
         }
+    }
 
+    if ipv4 {
         // Set IPv4 gateway if present
-        if let Some(ref gateway) = cfg.gateway {
-            match Command::new("/sbin/ip")
-                .args(["route", "add", "default", "via", gateway, "dev", iface_name])
-                .status()
-            {
+        if let Some(args) = ipv4_default_route_args(iface_name, cfg) {
+            match Command::new("/sbin/ip").args(&args).status() {
                 Ok(status) if status.success() => {
-                    debug!(interface = %iface_name, gateway = %gateway, "IPv4 default route configured");
+                    debug!(interface = %iface_name, gateway = ?cfg.gateway, "IPv4 default route configured");
                 }
                 Ok(status) => {
                     warn!(exit_code = ?status.code(), "Failed to set IPv4 default route");
@@ -476,34 +1081,11 @@ fn apply_static_ip_config(iface_name: &str, cfg: &keel_config::network::StaticCo
         }
     }
 
-    // Add IPv6 addresses
-    for ipv6_addr in &cfg.ipv6_addresses {
-        match Command::new("/sbin/ip")
-            .args(["-6", "addr", "add", ipv6_addr, "dev", iface_name])
-            .status()
-        {
-            Ok(status) if status.success() => {
-                info!(interface = %iface_name, ip = %ipv6_addr, "Static IPv6 configured");
-            }
-            Ok(status) => {
-                warn!(interface = %iface_name, exit_code = ?status.code(), "Failed to set IPv6 address");
-            }
-            Err(e) => {
-                warn!(interface = %iface_name, error = %e, "Failed to set IPv6 address");
-            }
-        }
-    }
-
     // Set IPv6 gateway if present
-    if let Some(ref gateway6) = cfg.ipv6_gateway {
-        match Command::new("/sbin/ip")
-            .args([
-                "-6", "route", "add", "default", "via", gateway6, "dev", iface_name,
-            ])
-            .status()
-        {
+    if let Some(args) = ipv6_default_route_args(iface_name, cfg) {
+        match Command::new("/sbin/ip").args(&args).status() {
             Ok(status) if status.success() => {
-                debug!(interface = %iface_name, gateway = %gateway6, "IPv6 default route configured");
+                debug!(interface = %iface_name, gateway = ?cfg.ipv6_gateway, "IPv6 default route configured");
             }
             Ok(status) => {
                 warn!(exit_code = ?status.code(), "Failed to set IPv6 default route");
@@ -515,23 +1097,15 @@ fn apply_static_ip_config(iface_name: &str, cfg: &keel_config::network::StaticCo
     }
 
     // Enable IPv6 SLAAC (auto-configuration) if requested
-    if cfg.ipv6_auto {
-        // Enable Router Advertisement acceptance
-        let accept_ra_path = format!("/proc/sys/net/ipv6/conf/{}/accept_ra", iface_name);
-        if let Err(e) = fs::write(&accept_ra_path, "1") {
-            warn!(interface = %iface_name, error = %e, "Failed to enable accept_ra");
-        } else {
-            debug!(interface = %iface_name, "Enabled IPv6 accept_ra");
-        }
-
-        // Enable IPv6 autoconfiguration
-        let autoconf_path = format!("/proc/sys/net/ipv6/conf/{}/autoconf", iface_name);
-        if let Err(e) = fs::write(&autoconf_path, "1") {
-            warn!(interface = %iface_name, error = %e, "Failed to enable autoconf");
+    let sysctls = ipv6_auto_sysctls(iface_name, cfg);
+    for (path, value) in &sysctls {
+        if let Err(e) = fs::write(path, value) {
+            warn!(interface = %iface_name, path = %path, error = %e, "Failed to set IPv6 sysctl");
         } else {
-            debug!(interface = %iface_name, "Enabled IPv6 autoconf");
+            debug!(interface = %iface_name, path = %path, value = %value, "Set IPv6 sysctl");
         }
-
+    }
+    if !sysctls.is_empty() {
         info!(interface = %iface_name, "IPv6 SLAAC enabled");
     }
 
@@ -555,11 +1129,37 @@ fn apply_static_ip_config(iface_name: &str, cfg: &keel_config::network::StaticCo
 }
 
 /// Configure a single network interface
-fn configure_interface(iface: &keel_config::network::InterfaceConfig) {
-    use keel_config::network::InterfaceType;
+///
+/// VLAN and bond links are only created if missing, so a live re-apply
+/// still reaches their addresses and routes.
+fn configure_interface(iface: &keel_config::network::InterfaceConfig, live: Option<&LiveApply>) {
+    use keel_config::network::{BondIpConfig, InterfaceType, VlanIpConfig};
 
     info!(interface = %iface.name, "Configuring network interface");
 
+    let exists = link_exists(&iface.name);
+    if exists
+        && matches!(
+            iface.config,
+            InterfaceType::Vlan(_) | InterfaceType::Bond(_)
+        )
+    {
+        debug!(interface = %iface.name, "Link already exists, not creating it");
+    }
+    if let Some(args) = link_add_args(iface, exists) {
+        match Command::new("/sbin/ip").args(&args).status() {
+            Ok(status) if status.success() => debug!(interface = %iface.name, "Link created"),
+            Ok(status) => {
+                warn!(interface = %iface.name, exit_code = ?status.code(), "Failed to create link");
+                return;
+            }
+            Err(e) => {
+                warn!(interface = %iface.name, error = %e, "Failed to create link");
+                return;
+            }
+        }
+    }
+
     // Bring interface up
     match Command::new("/sbin/ip")
         .args(["link", "set", &iface.name, "up"])
@@ -584,162 +1184,212 @@ fn configure_interface(iface: &keel_config::network::InterfaceConfig) {
             debug!(interface = %iface.name, "DHCP configuration (client not implemented)");
         }
         InterfaceType::Static(cfg) => {
-            apply_static_ip_config(&iface.name, cfg);
+            apply_static_ip_config(&iface.name, cfg, live);
         }
         InterfaceType::Vlan(vlan_cfg) => {
             info!(interface = %iface.name, vlan_id = vlan_cfg.vlan_id, parent = %vlan_cfg.parent, "Configuring VLAN");
 
-            // Create VLAN interface using ip link
-            match Command::new("/sbin/ip")
-                .args([
-                    "link",
-                    "add",
-                    "link",
-                    &vlan_cfg.parent,
-                    "name",
-                    &iface.name,
-                    "type",
-                    "vlan",
-                    "id",
-                    &vlan_cfg.vlan_id.to_string(),
-                ])
-                .status()
-            {
-                Ok(status) if status.success() => {
-                    debug!(interface = %iface.name, "VLAN interface created");
-
-                    // Bring VLAN interface up
-                    if let Err(e) = Command::new("/sbin/ip")
-                        .args(["link", "set", &iface.name, "up"])
-                        .status()
-                    {
-                        warn!(interface = %iface.name, error = ?e, "Failed to bring up VLAN");
-                        return;
-                    }
-
-                    // Configure IP based on VLAN config type
-                    match &vlan_cfg.ip_config {
-                        keel_config::network::VlanIpConfig::Dhcp => {
-                            debug!(interface = %iface.name, "VLAN DHCP configuration (client not implemented)");
-                        }
-                        keel_config::network::VlanIpConfig::Static(cfg) => {
-                            apply_static_ip_config(&iface.name, cfg);
-                        }
-                    }
-                }
-                Ok(status) => {
-                    warn!(interface = %iface.name, exit_code = ?status.code(), "Failed to create VLAN");
+            // Configure IP based on VLAN config type
+            match &vlan_cfg.ip_config {
+                VlanIpConfig::Dhcp => {
+                    debug!(interface = %iface.name, "VLAN DHCP configuration (client not implemented)");
                 }
-                Err(e) => {
-                    warn!(interface = %iface.name, error = %e, "Failed to create VLAN");
+                VlanIpConfig::Static(cfg) => {
+                    apply_static_ip_config(&iface.name, cfg, live);
                 }
             }
         }
         InterfaceType::Bond(bond_cfg) => {
             info!(interface = %iface.name, mode = %bond_cfg.mode.as_str(), "Configuring Bond");
 
-            // Create bond interface
-            match Command::new("/sbin/ip")
-                .args([
-                    "link",
-                    "add",
-                    &iface.name,
-                    "type",
-                    "bond",
-                    "mode",
-                    bond_cfg.mode.as_str(),
-                ])
-                .status()
-            {
-                Ok(status) if status.success() => {
-                    debug!(interface = %iface.name, "Bond interface created");
-
-                    // Bring bond interface up
-                    if let Err(e) = Command::new("/sbin/ip")
-                        .args(["link", "set", &iface.name, "up"])
-                        .status()
-                    {
-                        warn!(interface = %iface.name, error = ?e, "Failed to bring up bond");
-                        return;
-                    }
+            // Enslave member interfaces; members already in the bond are
+            // left up rather than bounced
+            for slave in &bond_cfg.slaves {
+                if link_master(slave).as_deref() == Some(iface.name.as_str()) {
+                    debug!(interface = %iface.name, slave = %slave, "Interface already enslaved to bond");
+                    continue;
+                }
+
+                // Bring slave down first
+                let _ = Command::new("/sbin/ip")
+                    .args(["link", "set", slave, "down"])
+                    .status();
 
-                    // Enslave member interfaces
-                    for slave in &bond_cfg.slaves {
-                        // Bring slave down first
+                // Add to bond
+                match Command::new("/sbin/ip")
+                    .args(["link", "set", slave, "master", &iface.name])
+                    .status()
+                {
+                    Ok(status) if status.success() => {
+                        debug!(interface = %iface.name, slave = %slave, "Enslaved interface to bond");
+
+                        // Bring slave back up
                         let _ = Command::new("/sbin/ip")
-                            .args(["link", "set", slave, "down"])
+                            .args(["link", "set", slave, "up"])
                             .status();
-
-                        // Add to bond
-                        match Command::new("/sbin/ip")
-                            .args(["link", "set", slave, "master", &iface.name])
-                            .status()
-                        {
-                            Ok(status) if status.success() => {
-                                debug!(interface = %iface.name, slave = %slave, "Enslaved interface to bond");
-
-                                // Bring slave back up
-                                let _ = Command::new("/sbin/ip")
-                                    .args(["link", "set", slave, "up"])
-                                    .status();
-                            }
-                            Ok(status) => {
-                                warn!(slave = %slave, exit_code = ?status.code(), "Failed to enslave interface");
-                            }
-                            Err(e) => {
-                                warn!(slave = %slave, error = %e, "Failed to enslave interface");
-                            }
-                        }
                     }
-
-                    // Configure IP based on bond config type
-                    match &bond_cfg.ip_config {
-                        keel_config::network::BondIpConfig::Dhcp => {
-                            debug!(interface = %iface.name, "Bond DHCP configuration (client not implemented)");
-                        }
-                        keel_config::network::BondIpConfig::Static(cfg) => {
-                            apply_static_ip_config(&iface.name, cfg);
-                        }
+                    Ok(status) => {
+                        warn!(slave = %slave, exit_code = ?status.code(), "Failed to enslave interface");
+                    }
+                    Err(e) => {
+                        warn!(slave = %slave, error = %e, "Failed to enslave interface");
                     }
                 }
-                Ok(status) => {
-                    warn!(interface = %iface.name, exit_code = ?status.code(), "Failed to create bond");
+            }
+
+            // Configure IP based on bond config type
+            match &bond_cfg.ip_config {
+                BondIpConfig::Dhcp => {
+                    debug!(interface = %iface.name, "Bond DHCP configuration (client not implemented)");
                 }
-                Err(e) => {
-                    warn!(interface = %iface.name, error = %e, "Failed to create bond");
+                BondIpConfig::Static(cfg) => {
+                    apply_static_ip_config(&iface.name, cfg, live);
                 }
             }
         }
     }
 }
 
+/// Configuration written for the stub resolver
+const UNBOUND_CONF_PATH: &str = "/etc/unbound/unbound.conf";
+
+/// Stub resolver started by [`start_stub_resolver`], respawned by the
+/// supervision loop if it exits
+static STUB_RESOLVER: std::sync::Mutex<Option<Child>> = std::sync::Mutex::new(None);
+
+/// Start the stub resolver with the configuration already written
+fn spawn_stub_resolver() -> Option<Child> {
+    spawn_service(
+        "unbound",
+        keel_config::network::STUB_RESOLVER_PATH,
+        &["-c", UNBOUND_CONF_PATH],
+    )
+}
+
 /// Configure DNS resolvers
+///
+/// With `dns_over_tls`, unbound is started as a local stub resolver and
+/// `/etc/resolv.conf` points at it. If unbound cannot be started, lookups
+/// fail rather than falling back to plain DNS, which would send the queries
+/// in the clear.
 fn configure_dns(dns: &keel_config::network::DnsConfig) {
-    let mut resolv_conf = String::from("# Generated by keel-init\n");
+    // A re-apply replaces any resolver started earlier
+    stop_stub_resolver();
+    if dns.dns_over_tls {
+        start_stub_resolver(dns);
+    }
 
-    for ns in &dns.nameservers {
-        resolv_conf.push_str(&format!("nameserver {}\n", ns));
+    match fs::write("/etc/resolv.conf", dns.to_resolv_conf("keel-init")) {
+        Ok(_) => info!("DNS configuration written to /etc/resolv.conf"),
+        Err(e) => warn!(error = %e, "Failed to write /etc/resolv.conf"),
     }
+}
 
-    if !dns.search_domains.is_empty() {
-        resolv_conf.push_str(&format!("search {}\n", dns.search_domains.join(" ")));
+/// Write the unbound configuration for `dns` and start unbound
+fn start_stub_resolver(dns: &keel_config::network::DnsConfig) {
+    if let Some(parent) = std::path::Path::new(UNBOUND_CONF_PATH).parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            warn!(error = %e, "Failed to create unbound configuration directory");
+        }
+    }
+    if let Err(e) = fs::write(UNBOUND_CONF_PATH, dns.to_unbound_conf("keel-init")) {
+        error!(error = %e, "Failed to write unbound configuration; DNS lookups will fail");
+        return;
     }
 
-    match fs::write("/etc/resolv.conf", resolv_conf) {
-        Ok(_) => info!("DNS configuration written to /etc/resolv.conf"),
-        Err(e) => warn!(error = %e, "Failed to write /etc/resolv.conf"),
+    let child = spawn_stub_resolver();
+    match child {
+        Some(_) => info!(
+            servername = dns.tls_servername.as_deref().unwrap_or_default(),
+            "DNS-over-TLS stub resolver started"
+        ),
+        None => error!("DNS-over-TLS stub resolver not running; DNS lookups will fail"),
+    }
+    if let Ok(mut resolver) = STUB_RESOLVER.lock() {
+        *resolver = child;
     }
 }
 
-/// Configure a custom route
-fn configure_route(route: &keel_config::network::RouteConfig) {
-    let mut args = vec!["route", "add", &route.destination, "via", &route.gateway];
+/// Stop a stub resolver started by an earlier [`start_stub_resolver`]
+fn stop_stub_resolver() {
+    use nix::sys::signal::{kill, Signal};
+
+    let pid_file = keel_config::network::STUB_RESOLVER_PID_FILE;
+    let tracked = STUB_RESOLVER.lock().ok().and_then(|mut r| r.take());
+    if let Some(mut child) = tracked {
+        let pid = child.id();
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+        let _ = child.wait();
+        info!(pid, "Stopped DNS-over-TLS stub resolver");
+    } else if let Some(pid) = fs::read_to_string(pid_file)
+        .ok()
+        .and_then(|s| s.trim().parse::<i32>().ok())
+    {
+        match kill(Pid::from_raw(pid), Signal::SIGTERM) {
+            Ok(()) => info!(pid, "Stopped DNS-over-TLS stub resolver"),
+            Err(e) => debug!(pid, error = %e, "Stub resolver already gone"),
+        }
+    }
+    let _ = fs::remove_file(pid_file);
+}
 
-    let metric_str;
-    if let Some(metric) = route.metric {
-        metric_str = metric.to_string();
-        args.extend_from_slice(&["metric", &metric_str]);
+/// Respawn the stub resolver in `resolver` if it exited, until it
+/// crash-loops and the node is marked degraded at `degraded_path`
+///
+/// Returns whether it was found exited.
+fn supervise_stub_resolver(
+    resolver: &std::sync::Mutex<Option<Child>>,
+    crashes: &mut Vec<time::Instant>,
+    policy: &RestartPolicy,
+    degraded_path: &str,
+    respawn: impl FnOnce() -> Option<Child>,
+) -> bool {
+    let Ok(mut resolver) = resolver.lock() else {
+        return false;
+    };
+    let Some(child) = resolver.as_mut() else {
+        return false;
+    };
+    // reap_zombies may already have collected it, which try_wait reports
+    // as an error
+    let status = match child.try_wait() {
+        Ok(None) => return false,
+        Ok(Some(status)) => status.to_string(),
+        Err(e) => e.to_string(),
+    };
+    *resolver = None;
+    error!(service = "unbound", exit_status = %status, "DNS-over-TLS stub resolver exited");
+
+    match restart_decision(crashes, time::Instant::now(), policy, random_seed()) {
+        RestartDecision::Restart(delay) => {
+            warn!(
+                service = "unbound",
+                attempt = crashes.len(),
+                backoff_ms = delay.as_millis() as u64,
+                "Service exited, restarting with backoff"
+            );
+            thread::sleep(delay);
+            *resolver = respawn();
+            if resolver.is_none() {
+                error!("DNS-over-TLS stub resolver restart failed; DNS lookups will fail");
+            }
+        }
+        RestartDecision::GiveUp => {
+            let reason = format!(
+                "unbound crashed {} times within {}s; not restarting it",
+                crashes.len(),
+                policy.crash_loop_window.as_secs()
+            );
+            error!(service = "unbound", "{}", reason);
+            record_degraded(degraded_path, &reason, "services");
+        }
     }
+    true
+}
+
+/// Configure a custom route
+fn configure_route(route: &keel_config::network::RouteConfig) {
+    let args = route_args("replace", route);
 
     match Command::new("/sbin/ip").args(&args).status() {
         Ok(status) if status.success() => {
@@ -852,6 +1502,128 @@ fn uuid_like_id() -> String {
     "00000000".to_string()
 }
 
+/// Exponential restart backoff with ±20% jitter
+///
+/// The base delay is `2^attempt` seconds capped at `max_secs`; the jitter is
+/// derived from `seed` so that a fleet restarting a crashed agent at the
+/// same moment spreads out instead of retrying in lockstep. The jittered
+/// delay never exceeds `max_secs`.
+fn jittered_backoff(attempt: u32, max_secs: u64, seed: u64) -> time::Duration {
+    let base_ms = 1u64
+        .checked_shl(attempt)
+        .unwrap_or(u64::MAX)
+        .min(max_secs)
+        .saturating_mul(1000);
+
+    // splitmix64: cheap, well-distributed, no extra dependency in PID 1
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    // Map to a factor in [0.8, 1.2]
+    let factor = 0.8 + 0.4 * (z as f64 / u64::MAX as f64);
+    let jittered_ms = (base_ms as f64 * factor) as u64;
+    time::Duration::from_millis(jittered_ms.min(max_secs.saturating_mul(1000)))
+}
+
+/// Seed for restart jitter, from /dev/urandom with a clock fallback
+fn random_seed() -> u64 {
+    use std::io::Read;
+    if let Ok(mut f) = std::fs::File::open("/dev/urandom") {
+        let mut buf = [0u8; 8];
+        if f.read_exact(&mut buf).is_ok() {
+            return u64::from_le_bytes(buf);
+        }
+    }
+
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+        ^ u64::from(std::process::id())
+}
+
+/// Restart policy for containerd, from the `containerd` node config section
+#[derive(Debug, Clone, PartialEq)]
+struct RestartPolicy {
+    max_delay_secs: u64,
+    crash_loop_threshold: u32,
+    crash_loop_window: time::Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_delay_secs: 60,
+            crash_loop_threshold: 5,
+            crash_loop_window: time::Duration::from_secs(300),
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn from_config(config: &keel_config::ContainerdConfig) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_delay_secs: config
+                .max_restart_delay_secs
+                .unwrap_or(defaults.max_delay_secs),
+            crash_loop_threshold: config
+                .crash_loop_threshold
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.crash_loop_threshold),
+            crash_loop_window: config
+                .crash_loop_window_secs
+                .map(time::Duration::from_secs)
+                .unwrap_or(defaults.crash_loop_window),
+        }
+    }
+}
+
+/// What to do after a supervised service exits
+#[derive(Debug, PartialEq)]
+enum RestartDecision {
+    /// Respawn after the given backoff
+    Restart(time::Duration),
+    /// The service is crash-looping; stop respawning it
+    GiveUp,
+}
+
+/// Record a crash at `now` and decide whether to respawn
+///
+/// Only crashes within the policy window count, so a service that has run
+/// cleanly for a while restarts with a short backoff again.
+fn restart_decision(
+    crashes: &mut Vec<time::Instant>,
+    now: time::Instant,
+    policy: &RestartPolicy,
+    seed: u64,
+) -> RestartDecision {
+    crashes.retain(|&at| now.saturating_duration_since(at) < policy.crash_loop_window);
+    crashes.push(now);
+
+    let recent = crashes.len() as u32;
+    if recent >= policy.crash_loop_threshold {
+        return RestartDecision::GiveUp;
+    }
+    RestartDecision::Restart(jittered_backoff(recent - 1, policy.max_delay_secs, seed))
+}
+
+/// containerd restart policy from the node config, defaults if absent
+fn containerd_restart_policy(config_path: &str) -> RestartPolicy {
+    if !std::path::Path::new(config_path).exists() {
+        return RestartPolicy::default();
+    }
+    match keel_config::NodeConfig::load(config_path) {
+        Ok(config) => RestartPolicy::from_config(&config.containerd),
+        Err(e) => {
+            warn!(path = config_path, error = %e, "Failed to read containerd restart policy from node config");
+            RestartPolicy::default()
+        }
+    }
+}
+
 /// Check for test mode flags in kernel cmdline
 fn check_test_mode() {
     let cmdline = match fs::read_to_string("/proc/cmdline") {
@@ -1078,9 +1850,56 @@ fn spawn_kubelet() -> Option<Child> {
         debug!("No kubeconfig found - kubelet will run in standalone mode");
     }
 
+    let args = with_configured_kubelet_args(&args, &configured_kubelet_args(NODE_CONFIG_PATH));
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
     spawn_service("kubelet", kubelet_path, &args)
 }
 
+/// Declarative node configuration, shared with keel-agent
+const NODE_CONFIG_PATH: &str = "/etc/keel/node.yaml";
+
+/// Kubelet flags from the node configuration, if any
+///
+/// Configured taints come first as `--register-with-taints`, followed by
+/// `kubernetes.kubelet_args`, so an explicit flag there still wins.
+fn configured_kubelet_args(config_path: &str) -> Vec<String> {
+    if !std::path::Path::new(config_path).exists() {
+        return Vec::new();
+    }
+    match keel_config::NodeConfig::load(config_path) {
+        Ok(config) => {
+            keel_config::kubelet::register_with_taints_arg(&config.kubernetes.node_taints)
+                .into_iter()
+                .chain(config.kubernetes.kubelet_args)
+                .collect()
+        }
+        Err(e) => {
+            warn!(path = config_path, error = %e, "Failed to read kubelet args from node config");
+            Vec::new()
+        }
+    }
+}
+
+/// Managed kubelet flags with the operator's extra flags merged in
+///
+/// Invalid extra flags are dropped as a whole so kubelet still starts with
+/// the managed configuration.
+fn with_configured_kubelet_args(managed: &[&str], extra: &[String]) -> Vec<String> {
+    let managed: Vec<String> = managed.iter().map(|a| a.to_string()).collect();
+    match keel_config::kubelet::merge_kubelet_args(&managed, extra) {
+        Ok(merged) => {
+            if !extra.is_empty() {
+                info!(args = ?extra, "Adding configured kubelet args");
+            }
+            merged
+        }
+        Err(e) => {
+            warn!(error = %e, "Ignoring configured kubelet args");
+            managed
+        }
+    }
+}
+
 /// Import pre-loaded container images from known locations into containerd
 /// This ensures images like the pause container are available without network access
 fn import_preloaded_images() {
@@ -1151,19 +1970,64 @@ fn supervise_services() -> Result<(), InitError> {
     // Track restart counts for backoff
     let mut agent_restart_count: u32 = 0;
     let max_restart_delay_secs: u64 = 60;
+    let containerd_policy = containerd_restart_policy(NODE_CONFIG_PATH);
+    let mut containerd_crashes: Vec<time::Instant> = Vec::new();
+    let mut containerd_stopped = false;
+    let mut stub_resolver_crashes: Vec<time::Instant> = Vec::new();
 
     // Supervision loop
     loop {
         // Reap any zombie processes first
         reap_zombies();
 
-        // Check containerd - critical service
-        if let Some(ref mut child) = containerd {
-            if let Ok(Some(status)) = child.try_wait() {
+        // containerd stopped for a reboot that did not happen comes back
+        if containerd_stopped && !service_stop::is_stopping(SERVICE_STOP_MARKER, "containerd") {
+            info!("Service stop withdrawn, starting containerd again");
+            containerd_stopped = false;
+            containerd = spawn_service("containerd", "/usr/bin/containerd", &[]);
+        }
+
+        // Check containerd - critical service, restarted until it crash-loops
+        let containerd_exit = containerd
+            .as_mut()
+            .and_then(|child| child.try_wait().ok().flatten());
+        if let Some(status) = containerd_exit {
+            if service_stop::is_stopping(SERVICE_STOP_MARKER, "containerd") {
+                // Stopped by keel-agent before a reboot; not a crash
+                info!(service = "containerd", exit_status = %status, "Service stopped by keel-agent, not restarting");
+                containerd = None;
+                containerd_stopped = true;
+            } else {
                 error!(service = "containerd", exit_status = %status, "Critical service exited");
-                containerd = spawn_service("containerd", "/usr/bin/containerd", &[]);
-                if containerd.is_none() {
-                    error!("containerd restart failed - system degraded");
+                match restart_decision(
+                    &mut containerd_crashes,
+                    time::Instant::now(),
+                    &containerd_policy,
+                    random_seed(),
+                ) {
+                    RestartDecision::Restart(delay) => {
+                        warn!(
+                            service = "containerd",
+                            attempt = containerd_crashes.len(),
+                            backoff_ms = delay.as_millis() as u64,
+                            "Service exited, restarting with backoff"
+                        );
+                        thread::sleep(delay);
+                        containerd = spawn_service("containerd", "/usr/bin/containerd", &[]);
+                        if containerd.is_none() {
+                            error!("containerd restart failed - system degraded");
+                        }
+                    }
+                    RestartDecision::GiveUp => {
+                        let reason = format!(
+                            "containerd crashed {} times within {}s; not restarting it",
+                            containerd_crashes.len(),
+                            containerd_policy.crash_loop_window.as_secs()
+                        );
+                        error!(service = "containerd", "{}", reason);
+                        record_degraded(DEGRADED_STATE_PATH, &reason, "services");
+                        containerd = None;
+                    }
                 }
             }
         }
@@ -1171,16 +2035,17 @@ fn supervise_services() -> Result<(), InitError> {
         // Check keel-agent - restart with backoff
         if let Some(ref mut child) = agent {
             if let Ok(Some(status)) = child.try_wait() {
-                let delay = std::cmp::min(1u64 << agent_restart_count, max_restart_delay_secs);
+                let delay =
+                    jittered_backoff(agent_restart_count, max_restart_delay_secs, random_seed());
                 warn!(
                     service = "keel-agent",
                     exit_status = %status,
                     attempt = agent_restart_count + 1,
-                    backoff_secs = delay,
+                    backoff_ms = delay.as_millis() as u64,
                     "Service exited, restarting with backoff"
                 );
 
-                thread::sleep(time::Duration::from_secs(delay));
+                thread::sleep(delay);
 
                 agent = spawn_service("keel-agent", "/usr/bin/keel-agent", &[]);
                 if agent.is_some() {
@@ -1189,6 +2054,15 @@ fn supervise_services() -> Result<(), InitError> {
             }
         }
 
+        // Check the DNS-over-TLS stub resolver, without which DNS is down
+        supervise_stub_resolver(
+            &STUB_RESOLVER,
+            &mut stub_resolver_crashes,
+            &RestartPolicy::default(),
+            DEGRADED_STATE_PATH,
+            spawn_stub_resolver,
+        );
+
         // Check kubelet - log but continue (maintenance mode)
         if let Some(ref mut child) = kubelet {
             if let Ok(Some(status)) = child.try_wait() {
@@ -1197,6 +2071,12 @@ fn supervise_services() -> Result<(), InitError> {
             }
         }
 
+        // Re-apply networking in place when keel-agent asks for it
+        check_reapply_network(
+            std::path::Path::new(REAPPLY_NETWORK_MARKER),
+            reapply_network,
+        );
+
         // Check for bootstrap kubeconfig to start or restart services
         let bootstrap_kubeconfig = "/var/lib/keel/kubernetes/kubelet.kubeconfig";
         let permanent_kubeconfig = "/var/lib/kubelet/kubeconfig";
@@ -1277,4 +2157,550 @@ mod tests {
         let spawn_err = InitError::Spawn("test spawn error".to_string());
         assert!(format!("{}", spawn_err).contains("Process spawn error"));
     }
+
+    #[test]
+    fn test_jittered_backoff_within_bounds() {
+        for attempt in 0..10 {
+            let base_ms = std::cmp::min(1u64 << attempt, 60) * 1000;
+            let low = base_ms * 8 / 10;
+            let high = std::cmp::min(base_ms * 12 / 10, 60_000);
+            for seed in 0..1000u64 {
+                let delay = jittered_backoff(attempt, 60, seed).as_millis() as u64;
+                assert!(
+                    (low..=high).contains(&delay),
+                    "attempt {attempt} seed {seed}: {delay}ms outside {low}..={high}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_jittered_backoff_varies_and_is_deterministic() {
+        assert_eq!(jittered_backoff(3, 60, 42), jittered_backoff(3, 60, 42));
+
+        let distinct: std::collections::HashSet<_> = (0..100u64)
+            .map(|seed| jittered_backoff(3, 60, seed))
+            .collect();
+        assert!(distinct.len() > 50);
+    }
+
+    #[test]
+    fn test_jittered_backoff_caps_large_attempts() {
+        let delay = jittered_backoff(u32::MAX, 60, 7);
+        assert!(delay <= time::Duration::from_secs(60));
+        assert!(delay >= time::Duration::from_secs(48));
+
+        // Jitter never pushes a capped delay past the maximum
+        for seed in 0..1000u64 {
+            let delay = jittered_backoff(10, 60, seed);
+            assert!(
+                delay <= time::Duration::from_secs(60),
+                "seed {seed}: {delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_restart_decision_backs_off_then_gives_up() {
+        let policy = RestartPolicy::default();
+        let start = time::Instant::now();
+        let mut crashes = Vec::new();
+
+        let mut delays = Vec::new();
+        for i in 0..4u64 {
+            let at = start + time::Duration::from_secs(i);
+            match restart_decision(&mut crashes, at, &policy, 42) {
+                RestartDecision::Restart(delay) => delays.push(delay),
+                RestartDecision::GiveUp => panic!("gave up after {} crashes", i + 1),
+            }
+        }
+        assert!(delays.windows(2).all(|w| w[0] < w[1]), "{:?}", delays);
+
+        let fifth = start + time::Duration::from_secs(4);
+        assert_eq!(
+            restart_decision(&mut crashes, fifth, &policy, 42),
+            RestartDecision::GiveUp
+        );
+    }
+
+    #[test]
+    fn test_restart_decision_forgets_crashes_outside_window() {
+        let policy = RestartPolicy {
+            crash_loop_threshold: 2,
+            ..RestartPolicy::default()
+        };
+        let start = time::Instant::now();
+        let mut crashes = Vec::new();
+
+        assert_eq!(
+            restart_decision(&mut crashes, start, &policy, 7),
+            RestartDecision::Restart(jittered_backoff(0, 60, 7))
+        );
+        let later = start + policy.crash_loop_window;
+        assert_eq!(
+            restart_decision(&mut crashes, later, &policy, 7),
+            RestartDecision::Restart(jittered_backoff(0, 60, 7))
+        );
+        assert_eq!(crashes.len(), 1);
+    }
+
+    #[test]
+    fn test_supervise_stub_resolver_respawns_exited_resolver() {
+        let policy = RestartPolicy {
+            max_delay_secs: 0,
+            crash_loop_threshold: 2,
+            ..RestartPolicy::default()
+        };
+        let degraded = std::env::temp_dir().join(format!(
+            "keel-init-degraded-{}-{}.json",
+            std::process::id(),
+            random_seed()
+        ));
+        let degraded = degraded.to_str().unwrap();
+        let resolver = std::sync::Mutex::new(None);
+        let mut crashes = Vec::new();
+        let spawn = |cmd: &str| Command::new(cmd).spawn().ok();
+
+        // Nothing to do without a resolver, or while it runs
+        assert!(!supervise_stub_resolver(
+            &resolver,
+            &mut crashes,
+            &policy,
+            degraded,
+            || None
+        ));
+        *resolver.lock().unwrap() = Command::new("sleep").arg("10").spawn().ok();
+        assert!(!supervise_stub_resolver(
+            &resolver,
+            &mut crashes,
+            &policy,
+            degraded,
+            || None
+        ));
+        {
+            let mut guard = resolver.lock().unwrap();
+            let child = guard.as_mut().unwrap();
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+
+        // An exited resolver is respawned
+        assert!(supervise_stub_resolver(
+            &resolver,
+            &mut crashes,
+            &policy,
+            degraded,
+            || spawn("true")
+        ));
+        assert!(resolver.lock().unwrap().is_some());
+        resolver.lock().unwrap().as_mut().unwrap().wait().unwrap();
+
+        // ...until it crash-loops
+        let mut respawned = false;
+        assert!(supervise_stub_resolver(
+            &resolver,
+            &mut crashes,
+            &policy,
+            degraded,
+            || {
+                respawned = true;
+                None
+            }
+        ));
+        assert!(!respawned);
+        assert!(resolver.lock().unwrap().is_none());
+        assert!(DegradedState::load(degraded)
+            .unwrap()
+            .reason
+            .contains("unbound"));
+        let _ = fs::remove_file(degraded);
+    }
+
+    #[test]
+    fn test_restart_policy_from_config() {
+        let policy = RestartPolicy::from_config(&keel_config::ContainerdConfig {
+            max_restart_delay_secs: Some(10),
+            crash_loop_threshold: Some(0),
+            crash_loop_window_secs: Some(60),
+        });
+        assert_eq!(policy.max_delay_secs, 10);
+        assert_eq!(policy.crash_loop_threshold, 5);
+        assert_eq!(policy.crash_loop_window, time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_check_reapply_network_consumes_marker() {
+        let marker = std::env::temp_dir().join(format!(
+            "keel-init-reapply-{}-{}",
+            std::process::id(),
+            random_seed()
+        ));
+
+        let mut connected = Vec::new();
+
+        // No marker, nothing to do
+        assert!(!check_reapply_network(&marker, |c| connected.push(c)));
+        assert!(connected.is_empty());
+
+        fs::write(&marker, b"").unwrap();
+        assert!(check_reapply_network(&marker, |c| connected.push(c)));
+        assert_eq!(connected, [None]);
+        assert!(!marker.exists());
+
+        // The marker was consumed, so the next poll does not re-apply again
+        assert!(!check_reapply_network(&marker, |c| connected.push(c)));
+        assert_eq!(connected.len(), 1);
+
+        // The client's address is passed on
+        fs::write(&marker, b"192.168.1.10\n").unwrap();
+        assert!(check_reapply_network(&marker, |c| connected.push(c)));
+        assert_eq!(connected[1], Some("192.168.1.10".parse().unwrap()));
+    }
+
+    fn static_config(
+        ipv6_gateway: Option<&str>,
+        ipv6_auto: bool,
+    ) -> keel_config::network::StaticConfig {
+        keel_config::network::StaticConfig {
+            ipv4_address: "192.168.1.10/24".to_string(),
+            gateway: Some("192.168.1.1".to_string()),
+            mtu: 1500,
+            ipv6_addresses: vec!["2001:db8::10/64".to_string()],
+            ipv6_gateway: ipv6_gateway.map(str::to_string),
+            ipv6_auto,
+            gateway_metric: None,
+        }
+    }
+
+    #[test]
+    fn test_interpret_arp_probe_conflict() {
+        let output = "ARPING 192.168.1.10 from 0.0.0.0 eth0\n\
+                      Unicast reply from 192.168.1.10 [52:54:00:12:34:56]  0.612ms\n\
+                      Sent 1 probe(s) (1 broadcast(s))\n\
+                      Received 1 response(s) (0 request(s), 0 broadcast(s))\n";
+        assert_eq!(
+            interpret_arp_probe(false, output),
+            ArpProbe::Conflict(Some("52:54:00:12:34:56".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_interpret_arp_probe_address_free() {
+        let output = "ARPING 192.168.1.10 from 0.0.0.0 eth0\n\
+                      Sent 2 probe(s) (2 broadcast(s))\n\
+                      Received 0 response(s) (0 request(s), 0 broadcast(s))\n";
+        assert_eq!(interpret_arp_probe(true, output), ArpProbe::Free);
+        // A failure without any reply is an error, not a conflict
+        assert_eq!(
+            interpret_arp_probe(false, "arping: interface eth9 not found\n"),
+            ArpProbe::Inconclusive
+        );
+    }
+
+    #[test]
+    fn test_configured_kubelet_args_register_taints() {
+        let config = std::env::temp_dir().join(format!(
+            "keel-init-node-{}-{}.yaml",
+            std::process::id(),
+            random_seed()
+        ));
+        fs::write(
+            &config,
+            "version: v1\nhostname: node\nkubernetes:\n  node_taints:\n    - dedicated=edge:NoSchedule\n  kubelet_args:\n    - --v=4\ncontainers: []\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            configured_kubelet_args(config.to_str().unwrap()),
+            ["--register-with-taints=dedicated=edge:NoSchedule", "--v=4"]
+        );
+        let _ = fs::remove_file(&config);
+        assert!(configured_kubelet_args(config.to_str().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_with_configured_kubelet_args() {
+        let managed = ["--config=/etc/kubernetes/kubelet-config.yaml", "--v=2"];
+        assert_eq!(
+            with_configured_kubelet_args(&managed, &["--v=4".to_string()]),
+            vec!["--config=/etc/kubernetes/kubelet-config.yaml", "--v=4"]
+        );
+        // A conflicting flag drops the extra args rather than kubelet
+        assert_eq!(
+            with_configured_kubelet_args(
+                &managed,
+                &["--v=4".to_string(), "--config=/tmp/other.yaml".to_string()]
+            ),
+            managed.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_address_commands_flush_then_add_at_boot() {
+        let wanted = ["192.168.1.10/24".to_string(), "fd00::10/64".to_string()];
+        let commands = address_commands("eth0", &wanted, &[], None);
+        let commands: Vec<String> = commands.iter().map(|c| c.join(" ")).collect();
+        assert_eq!(
+            commands,
+            [
+                "addr flush dev eth0 scope global",
+                "addr add 192.168.1.10/24 dev eth0",
+                "-6 addr add fd00::10/64 dev eth0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_address_commands_live_keeps_configured_addresses() {
+        let current = parse_ip_addr_show(
+            "2: eth0    inet 192.168.1.10/24 brd 192.168.1.255 scope global eth0\\       valid_lft forever preferred_lft forever\n\
+             2: eth0    inet 192.168.1.99/24 scope global secondary eth0\\       valid_lft forever preferred_lft forever\n\
+             2: eth0    inet6 fd00:0::10/64 scope global \\       valid_lft forever preferred_lft forever\n",
+        );
+        assert_eq!(
+            current,
+            ["192.168.1.10/24", "192.168.1.99/24", "fd00:0::10/64"]
+        );
+
+        let wanted = [
+            "192.168.1.10/24".to_string(),
+            "fd00::10/64".to_string(),
+            "fd00::20/64".to_string(),
+        ];
+        let live = live_apply(&["192.168.1.10/24", "192.168.1.99/24"], None);
+        let commands = address_commands("eth0", &wanted, &current, Some(&live));
+        let commands: Vec<String> = commands.iter().map(|c| c.join(" ")).collect();
+        // Nothing is flushed and the kept addresses are left alone
+        assert_eq!(
+            commands,
+            [
+                "addr del 192.168.1.99/24 dev eth0",
+                "-6 addr add fd00::20/64 dev eth0",
+            ]
+        );
+
+        // Re-applying an unchanged config is a no-op
+        assert!(address_commands("eth0", &current, &current, Some(&live)).is_empty());
+    }
+
+    fn live_apply(previous: &[&str], connected: Option<&str>) -> LiveApply {
+        let mut config = keel_config::network::NetworkConfig::new();
+        config
+            .interfaces
+            .push(keel_config::network::InterfaceConfig {
+                name: "eth0".to_string(),
+                config: keel_config::network::InterfaceType::Static(
+                    keel_config::network::StaticConfig {
+                        ipv4_address: previous[0].to_string(),
+                        gateway: None,
+                        mtu: 1500,
+                        ipv6_addresses: previous[1..].iter().map(|a| a.to_string()).collect(),
+                        ipv6_gateway: None,
+                        ipv6_auto: false,
+                        gateway_metric: None,
+                    },
+                ),
+            });
+        LiveApply {
+            previous: config,
+            connected: connected.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_address_commands_live_keeps_foreign_and_connected_addresses() {
+        // .10 came from the previous config, .200 is a kube-vip VIP
+        let current = [
+            "192.168.1.10/24".to_string(),
+            "192.168.1.200/32".to_string(),
+        ];
+        let wanted = ["192.168.1.20/24".to_string()];
+
+        let live = live_apply(&["192.168.1.10/24"], None);
+        let commands = address_commands("eth0", &wanted, &current, Some(&live));
+        let commands: Vec<String> = commands.iter().map(|c| c.join(" ")).collect();
+        assert_eq!(
+            commands,
+            [
+                "addr del 192.168.1.10/24 dev eth0",
+                "addr add 192.168.1.20/24 dev eth0",
+            ]
+        );
+
+        // The address the client is connected through stays
+        let live = live_apply(&["192.168.1.10/24"], Some("192.168.1.10"));
+        let commands = address_commands("eth0", &wanted, &current, Some(&live));
+        let commands: Vec<String> = commands.iter().map(|c| c.join(" ")).collect();
+        assert_eq!(commands, ["addr add 192.168.1.20/24 dev eth0"]);
+
+        // Without a record of the previous apply nothing is removed
+        let live = live_apply(&[""], None);
+        let commands = address_commands("eth0", &wanted, &current, Some(&live));
+        assert_eq!(commands.len(), 1);
+    }
+
+    #[test]
+    fn test_ipv6_static_default_route() {
+        let cfg = static_config(Some("fe80::1"), false);
+        assert_eq!(
+            ipv6_default_route_args("eth0", &cfg).unwrap(),
+            ["-6", "route", "replace", "default", "via", "fe80::1", "dev", "eth0"]
+        );
+        assert!(ipv6_auto_sysctls("eth0", &cfg).is_empty());
+
+        let cfg = keel_config::network::StaticConfig {
+            gateway_metric: Some(200),
+            ..cfg
+        };
+        assert_eq!(
+            ipv6_default_route_args("eth0", &cfg).unwrap(),
+            [
+                "-6", "route", "replace", "default", "via", "fe80::1", "dev", "eth0", "metric",
+                "200"
+            ]
+        );
+
+        assert!(ipv6_default_route_args("eth0", &static_config(None, false)).is_none());
+    }
+
+    fn routed_config(
+        gateway: &str,
+        routes: &[(&str, &str)],
+    ) -> keel_config::network::NetworkConfig {
+        let mut config = keel_config::network::NetworkConfig::new();
+        config
+            .interfaces
+            .push(keel_config::network::InterfaceConfig {
+                name: "eth0".to_string(),
+                config: keel_config::network::InterfaceType::Static(
+                    keel_config::network::StaticConfig {
+                        gateway: Some(gateway.to_string()),
+                        ..static_config(Some("fe80::1"), false)
+                    },
+                ),
+            });
+        config.routes = routes
+            .iter()
+            .map(|(destination, gateway)| keel_config::network::RouteConfig {
+                destination: destination.to_string(),
+                gateway: gateway.to_string(),
+                metric: None,
+            })
+            .collect();
+        config
+    }
+
+    #[test]
+    fn test_reapply_replaces_existing_routes() {
+        let config = routed_config("192.168.1.1", &[("10.10.0.0/16", "192.168.1.254")]);
+        let cfg = config.interfaces[0].static_config().unwrap();
+
+        // replace succeeds whether or not the route is already there
+        assert_eq!(
+            ipv4_default_route_args("eth0", cfg).unwrap().join(" "),
+            "route replace default via 192.168.1.1 dev eth0"
+        );
+        assert_eq!(
+            route_args("replace", &config.routes[0]).join(" "),
+            "route replace 10.10.0.0/16 via 192.168.1.254"
+        );
+    }
+
+    #[test]
+    fn test_stale_route_commands_remove_dropped_routes() {
+        let previous = routed_config(
+            "192.168.1.1",
+            &[
+                ("10.10.0.0/16", "192.168.1.254"),
+                ("10.20.0.0/16", "192.168.1.254"),
+            ],
+        );
+
+        // Re-applying the same config removes nothing
+        assert!(stale_route_commands(&previous, &previous).is_empty());
+
+        let config = routed_config("192.168.1.2", &[("10.20.0.0/16", "192.168.1.254")]);
+        let commands: Vec<String> = stale_route_commands(&previous, &config)
+            .iter()
+            .map(|c| c.join(" "))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "route del default via 192.168.1.1 dev eth0",
+                "route del 10.10.0.0/16 via 192.168.1.254",
+            ]
+        );
+
+        // Dropping the interface drops both of its default routes
+        let commands: Vec<String> =
+            stale_route_commands(&previous, &keel_config::network::NetworkConfig::new())
+                .iter()
+                .map(|c| c.join(" "))
+                .collect();
+        assert_eq!(
+            commands,
+            [
+                "route del default via 192.168.1.1 dev eth0",
+                "-6 route del default via fe80::1 dev eth0",
+                "route del 10.10.0.0/16 via 192.168.1.254",
+                "route del 10.20.0.0/16 via 192.168.1.254",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_add_skipped_for_existing_links() {
+        use keel_config::network::{
+            BondConfig, BondIpConfig, BondingMode, InterfaceConfig, InterfaceType, VlanConfig,
+            VlanIpConfig,
+        };
+
+        let vlan = InterfaceConfig {
+            name: "eth0.100".to_string(),
+            config: InterfaceType::Vlan(VlanConfig {
+                parent: "eth0".to_string(),
+                vlan_id: 100,
+                ip_config: VlanIpConfig::Static(static_config(None, false)),
+            }),
+        };
+        assert_eq!(
+            link_add_args(&vlan, false).unwrap().join(" "),
+            "link add link eth0 name eth0.100 type vlan id 100"
+        );
+        assert!(link_add_args(&vlan, true).is_none());
+
+        let bond = InterfaceConfig {
+            name: "bond0".to_string(),
+            config: InterfaceType::Bond(BondConfig {
+                mode: BondingMode::ActiveBackup,
+                slaves: vec!["eth0".to_string(), "eth1".to_string()],
+                ip_config: BondIpConfig::Static(static_config(None, false)),
+            }),
+        };
+        assert_eq!(
+            link_add_args(&bond, false).unwrap().join(" "),
+            "link add bond0 type bond mode active-backup"
+        );
+        assert!(link_add_args(&bond, true).is_none());
+
+        let plain = InterfaceConfig {
+            name: "eth0".to_string(),
+            config: InterfaceType::Static(static_config(None, false)),
+        };
+        assert!(link_add_args(&plain, false).is_none());
+    }
+
+    #[test]
+    fn test_ipv6_auto_accepts_router_advertisements() {
+        let cfg = static_config(None, true);
+        assert!(ipv6_default_route_args("eth0", &cfg).is_none());
+        assert_eq!(
+            ipv6_auto_sysctls("eth0", &cfg),
+            vec![
+                ("/proc/sys/net/ipv6/conf/eth0/accept_ra".to_string(), "2"),
+                ("/proc/sys/net/ipv6/conf/eth0/autoconf".to_string(), "1"),
+            ]
+        );
+    }
 }
@@ -54,6 +54,11 @@ impl BootPhaseTracker {
         self.current_phase = Some((name, Instant::now()));
     }
 
+    /// Name of the phase in progress, if any
+    pub fn current_phase(&self) -> Option<&str> {
+        self.current_phase.as_ref().map(|(name, _)| name.as_str())
+    }
+
     /// End the current phase
     pub fn end_current_phase(&mut self) {
         if let Some((name, start)) = self.current_phase.take() {
@@ -46,16 +46,8 @@ impl CertStore {
         let key_path = cert_dir.join("client.key");
 
         fs::write(&cert_path, cert_pem)?;
-        fs::write(&key_path, key_pem)?;
-
-        // Set restrictive permissions on private key
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&key_path)?.permissions();
-            perms.set_mode(0o600);
-            fs::set_permissions(&key_path, perms)?;
-        }
+        // Private key is created with mode 0600
+        keel_crypto::write_private_file(&key_path, key_pem)?;
 
         // Save CA if provided
         let ca_path = if let Some(ca) = ca_pem {
@@ -0,0 +1,152 @@
+//! Client-side verification of update images
+//!
+//! Streams an image from its URL without storing it, so operators can check
+//! that a URL is reachable and matches the expected SHA256 before handing it
+//! to a fleet. No node is involved.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Outcome of verifying an image
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageReport {
+    pub source: String,
+    /// Bytes received
+    pub size: u64,
+    /// Size announced by the server, if it sent `Content-Length`
+    pub content_length: Option<u64>,
+    pub sha256: String,
+    /// Hash the image was checked against, if one was given
+    pub expected_sha256: Option<String>,
+    /// Whether the image matches `expected_sha256` (true without one)
+    pub matches: bool,
+}
+
+impl ImageReport {
+    /// Human-readable report, ending with the verdict
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Source:  {}\nSize:    {} bytes ({:.1} MB)\nSHA256:  {}\n",
+            self.source,
+            self.size,
+            self.size as f64 / 1_048_576.0,
+            self.sha256
+        );
+        match &self.expected_sha256 {
+            Some(expected) if self.matches => {
+                text.push_str(&format!("✅ Matches expected SHA256 {}\n", expected))
+            }
+            Some(expected) => text.push_str(&format!(
+                "❌ SHA256 mismatch: expected {}, got {}\n",
+                expected, self.sha256
+            )),
+            None => text.push_str("✅ Image is reachable (no --sha256 given to compare)\n"),
+        }
+        text
+    }
+}
+
+/// Download `source`, discarding the bytes, and compare its digest with
+/// `expected_sha256`
+///
+/// Errors are reserved for images that could not be fetched completely
+/// (unreachable, HTTP error, or fewer bytes than `Content-Length`); a hash
+/// mismatch is reported through [`ImageReport::matches`].
+pub async fn verify(source: &str, expected_sha256: Option<&str>) -> Result<ImageReport, String> {
+    if let Some(expected) = expected_sha256 {
+        if expected.len() != 64 || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("'{}' is not a SHA256 hash", expected));
+        }
+    }
+
+    let mut response = reqwest::get(source)
+        .await
+        .map_err(|e| format!("Failed to reach {}: {}", source, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server returned error: {}", response.status()));
+    }
+    let content_length = response.content_length();
+
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download failed after {} bytes: {}", size, e))?
+    {
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+    if let Some(expected) = content_length.filter(|&len| len != size) {
+        return Err(format!(
+            "Download truncated: got {} of {} bytes",
+            size, expected
+        ));
+    }
+
+    let sha256 = format!("{:x}", hasher.finalize());
+    let matches = expected_sha256.is_none_or(|expected| expected.eq_ignore_ascii_case(&sha256));
+    Ok(ImageReport {
+        source: source.to_string(),
+        size,
+        content_length,
+        sha256,
+        expected_sha256: expected_sha256.map(str::to_ascii_lowercase),
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve `payload` over plain HTTP to every connection; returns its URL
+    async fn serve_payload(payload: Vec<u8>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/keel.squashfs", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).await;
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    payload.len()
+                );
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(&payload).await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_verify_digest_match_and_mismatch() {
+        let payload = vec![0x5a; 100 * 1024];
+        let digest = format!("{:x}", Sha256::digest(&payload));
+        let url = serve_payload(payload).await;
+
+        let report = verify(&url, Some(&digest.to_uppercase())).await.unwrap();
+        assert!(report.matches);
+        assert_eq!(report.size, 100 * 1024);
+        assert_eq!(report.content_length, Some(100 * 1024));
+        assert_eq!(report.sha256, digest);
+
+        let report = verify(&url, Some(&"0".repeat(64))).await.unwrap();
+        assert!(!report.matches);
+        assert!(report.to_text().contains("SHA256 mismatch"));
+
+        // Without a hash only reachability is checked
+        assert!(verify(&url, None).await.unwrap().matches);
+        assert!(verify(&url, Some("abc")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_unreachable() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/keel.squashfs", listener.local_addr().unwrap());
+        drop(listener);
+        let err = verify(&url, None).await.unwrap_err();
+        assert!(err.contains("Failed to reach"), "{}", err);
+    }
+}
@@ -1,17 +1,22 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use keel_api::node::node_service_client::NodeServiceClient;
 use keel_api::node::{
-    AnalyzeCrashDumpRequest, BootstrapKubernetesRequest, CollectCrashDumpRequest,
-    ConfigureNetworkRequest, CreateSystemSnapshotRequest, DhcpConfig, DnsConfig,
-    EnableDebugModeRequest, EnableRecoveryModeRequest, GetBootstrapStatusRequest,
-    GetDebugStatusRequest, GetHealthRequest, GetNetworkConfigRequest, GetNetworkStatusRequest,
-    GetRollbackHistoryRequest, GetStatusRequest, InitBootstrapRequest, InstallUpdateRequest,
-    NetworkInterface, RebootRequest, StaticConfig, StreamLogsRequest, TriggerRollbackRequest,
+    AnalyzeCrashDumpRequest, BootstrapKubernetesRequest, CancelScheduledUpdateRequest,
+    CollectCrashDumpRequest, CollectDiagnosticsRequest, ConfigureNetworkRequest,
+    ConfirmUpdateRequest, CreateSystemSnapshotRequest, DhcpConfig, DnsConfig,
+    EnableDebugModeRequest, EnableRecoveryModeRequest, GetBootstrapStatusRequest, GetCaCertRequest,
+    GetDebugStatusRequest, GetHealthRequest, GetKernelLogLevelRequest, GetNetworkConfigRequest,
+    GetNetworkStatusRequest, GetRollbackHistoryRequest, GetSlotInfoRequest, GetStatusRequest,
+    GetUpdateScheduleRequest, InitBootstrapRequest, InstallUpdateRequest, NetworkInterface,
+    RebootRequest, ScheduleUpdateRequest, SetBootSlotRequest, SetKernelLogLevelRequest,
+    StaticConfig, StreamLogsRequest, TestHookRequest, TriggerRollbackRequest, UpdateSchedule,
 };
 use std::path::PathBuf;
 use tokio_stream::StreamExt;
 
 mod cert_store;
+mod image_verify;
+mod selftest;
 use cert_store::{extract_node_from_endpoint, CertStore};
 
 #[derive(Parser)]
@@ -21,10 +26,20 @@ struct Cli {
     #[arg(long, default_value = "http://[::1]:50051")]
     endpoint: String,
 
+    /// Output format for commands that support machine-readable output
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Get node status
@@ -34,14 +49,29 @@ enum Commands {
         #[arg(long, default_value = "Manual reboot via osctl")]
         reason: String,
     },
+    /// Boot a specific root partition slot on next restart
+    BootSlot {
+        /// Slot to boot: a, b (or c, ... on nodes with more slots)
+        #[arg(value_parser = parse_slot_name)]
+        slot: String,
+        /// Reboot into the slot right away
+        #[arg(long)]
+        reboot: bool,
+    },
     /// Install an OS update
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Update {
+        #[command(subcommand)]
+        action: Option<UpdateAction>,
         /// Source URL of the SquashFS image (or delta file if --delta is set)
-        #[arg(long)]
-        source: String,
+        #[arg(long, required = true)]
+        source: Option<String>,
         /// Expected SHA256 checksum
         #[arg(long)]
         sha256: Option<String>,
+        /// URL of a SHA256SUMS manifest to take the expected checksum from
+        #[arg(long)]
+        checksum_manifest: Option<String>,
         /// Use delta update (source is a delta file)
         #[arg(long, default_value_t = false)]
         delta: bool,
@@ -51,9 +81,24 @@ enum Commands {
         /// URL for full image (used as fallback if delta fails)
         #[arg(long)]
         full_image_url: Option<String>,
+        /// Authorization header for the image download (e.g. "Bearer <token>")
+        #[arg(long)]
+        auth_header: Option<String>,
+        /// Limit the download to this many bytes per second (0: node default)
+        #[arg(long, default_value_t = 0)]
+        max_bytes_per_sec: u64,
+    },
+    /// Scheduled update management
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
     },
     /// Get system health status
-    Health,
+    Health {
+        /// Run only this check (e.g. `boot`, `network`)
+        #[arg(long)]
+        check: Option<String>,
+    },
     /// Rollback operations
     Rollback {
         #[command(subcommand)]
@@ -64,6 +109,12 @@ enum Commands {
         #[command(subcommand)]
         mode: InitMode,
     },
+    /// Fetch the CA certificate that signed the node's server certificate
+    GetCa {
+        /// Write the PEM to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
     /// Join a Kubernetes cluster
     Bootstrap {
         /// Kubernetes API server endpoint (e.g., "https://k8s.example.com:6443")
@@ -94,6 +145,94 @@ enum Commands {
         #[command(subcommand)]
         action: DiagAction,
     },
+    /// Sanity-check a node by running all read-only checks
+    Selftest,
+    /// Download a redacted diagnostic bundle for support cases
+    Diagnostics {
+        /// Where to write the bundle
+        #[arg(long, default_value = "keel-diagnostics.tar.gz")]
+        out: PathBuf,
+        /// Log lines to include per log file (0 uses the node default)
+        #[arg(long, default_value_t = 0)]
+        log_lines: u32,
+    },
+    /// Update hook commands
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+    /// Update image commands (run locally, no node involved)
+    Image {
+        #[command(subcommand)]
+        action: ImageAction,
+    },
+    /// Kernel settings
+    Kernel {
+        #[command(subcommand)]
+        action: KernelAction,
+    },
+    /// Disk and partition inspection
+    Disk {
+        #[command(subcommand)]
+        action: DiskAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum DiskAction {
+    /// Show each root partition slot's flashed marker and GPT boot flags
+    Slots,
+}
+
+#[derive(Subcommand)]
+enum KernelAction {
+    /// Show the console log level, or set it to LEVEL
+    Loglevel {
+        /// 0 (emergencies only) to 7 (debug)
+        #[arg(value_parser = clap::value_parser!(u32).range(0..=7))]
+        level: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HookAction {
+    /// Run a hook on the node as an update would, without flashing anything
+    Test {
+        /// Update phase the hook is written for
+        #[arg(long = "type", value_enum)]
+        hook_type: HookType,
+        /// Hook command on the node (script path and arguments)
+        #[arg(long)]
+        path: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum HookType {
+    Pre,
+    Post,
+}
+
+#[derive(Subcommand)]
+enum ImageAction {
+    /// Download an image without installing it and check its SHA256
+    Verify {
+        /// URL of the image
+        #[arg(long)]
+        source: String,
+        /// Expected SHA256 checksum
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum UpdateAction {
+    /// Confirm an applied update works, disarming automatic rollback
+    Confirm {
+        /// Schedule ID of the update (as shown by `schedule list`)
+        id: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -104,7 +243,14 @@ enum NetworkAction {
         action: NetworkConfigAction,
     },
     /// Show network status
-    Status,
+    Status {
+        /// Sample counters over one second and show RX/TX rates
+        #[arg(long)]
+        rates: bool,
+        /// Include the loopback interface
+        #[arg(long)]
+        all: bool,
+    },
     /// Configure DNS settings
     Dns {
         #[command(subcommand)]
@@ -120,7 +266,7 @@ enum NetworkConfigAction {
         #[arg(long)]
         interface: String,
         /// Use DHCP for this interface
-        #[arg(long, conflicts_with_all = ["ip", "gateway", "ipv6", "ipv6_gateway"])]
+        #[arg(long, conflicts_with_all = ["ip", "gateway", "ipv6", "ipv6_gateway", "gateway_metric"])]
         dhcp: bool,
         /// Static IPv4 address in CIDR notation (e.g., 192.168.1.10/24)
         #[arg(long)]
@@ -134,6 +280,9 @@ enum NetworkConfigAction {
         /// IPv6 gateway IP address
         #[arg(long)]
         ipv6_gateway: Option<String>,
+        /// Metric of the default routes via --gateway and --ipv6-gateway
+        #[arg(long)]
+        gateway_metric: Option<u32>,
         /// Enable IPv6 auto-configuration (SLAAC)
         #[arg(long)]
         ipv6_auto: bool,
@@ -143,9 +292,17 @@ enum NetworkConfigAction {
         /// Auto-reboot after configuration
         #[arg(long)]
         auto_reboot: bool,
+        /// Re-apply the configuration in place instead of on next boot
+        #[arg(long, conflicts_with = "auto_reboot")]
+        apply_now: bool,
     },
     /// Show current network configuration
     Show,
+    /// Validate a network configuration file offline, without contacting a node
+    Validate {
+        /// Path to a NetworkConfig JSON file
+        file: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
@@ -158,12 +315,57 @@ enum DnsAction {
         /// DNS search domains
         #[arg(long)]
         search: Vec<String>,
+        /// Query the nameservers over DNS-over-TLS, checking their
+        /// certificates against this name
+        #[arg(long)]
+        tls_servername: Option<String>,
         /// Auto-reboot after configuration
         #[arg(long)]
         auto_reboot: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum ScheduleAction {
+    /// Schedule an OS update
+    Create {
+        /// Source URL of the SquashFS image (or delta file if --delta is set)
+        #[arg(long)]
+        source: String,
+        /// When to install, as an RFC3339 timestamp (default: as soon as possible)
+        #[arg(long, value_parser = parse_rfc3339)]
+        at: Option<String>,
+        /// Maintenance window length in seconds, starting at --at
+        #[arg(long)]
+        window_secs: Option<u32>,
+        /// Expected SHA256 checksum
+        #[arg(long)]
+        sha256: Option<String>,
+        /// Roll back automatically if the node is unhealthy after the update
+        #[arg(long)]
+        auto_rollback: bool,
+        /// Use delta update (source is a delta file)
+        #[arg(long)]
+        delta: bool,
+        /// Command to run before the update
+        #[arg(long)]
+        pre_hook: Option<String>,
+        /// Command to run after the update
+        #[arg(long)]
+        post_hook: Option<String>,
+        /// Schedule even if a pending schedule for the same source exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// List scheduled updates and their status
+    List,
+    /// Cancel a pending scheduled update
+    Cancel {
+        /// Schedule ID (as shown by `schedule list`)
+        id: String,
+    },
+}
+
 #[derive(Subcommand)]
 enum RollbackAction {
     /// Manually trigger rollback to previous partition
@@ -171,6 +373,9 @@ enum RollbackAction {
         /// Reason for rollback
         #[arg(long, default_value = "Manual rollback via osctl")]
         reason: String,
+        /// Only switch partitions; the rollback applies on the next reboot
+        #[arg(long)]
+        no_reboot: bool,
     },
     /// View rollback history
     History,
@@ -294,18 +499,170 @@ async fn connect_with_auto_tls(
     }
 }
 
+/// Accept a single slot letter (`a`, `b`, ...), case-insensitively
+fn parse_slot_name(value: &str) -> Result<String, String> {
+    let slot = value.to_ascii_lowercase();
+    if slot.len() == 1 && slot.chars().all(|c| c.is_ascii_lowercase()) {
+        Ok(slot)
+    } else {
+        Err(format!(
+            "expected a slot letter like 'a' or 'b', got '{}'",
+            value
+        ))
+    }
+}
+
+/// Load and validate a network configuration file
+///
+/// Returns a one-line summary on success, or a message naming the file and
+/// the first problem found.
+fn validate_network_config_file(path: &std::path::Path) -> Result<String, String> {
+    // load_from validates the parsed configuration before returning it
+    let config = keel_config::network::NetworkConfig::load_from(path)
+        .map_err(|e| format!("{} is invalid: {}", path.display(), e))?;
+
+    Ok(format!(
+        "{} is valid ({} interface(s), {} route(s), DNS {})",
+        path.display(),
+        config.interfaces.len(),
+        config.routes.len(),
+        if config.dns.is_some() {
+            "configured"
+        } else {
+            "not configured"
+        }
+    ))
+}
+
+/// Accept an RFC3339 timestamp, so typos fail before contacting the node
+fn parse_rfc3339(value: &str) -> Result<String, String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|_| value.to_string())
+        .map_err(|e| {
+            format!(
+                "expected an RFC3339 timestamp like 2026-03-01T02:00:00Z, got '{}': {}",
+                value, e
+            )
+        })
+}
+
+/// Schedules as a JSON array for `--output json`
+fn schedules_json(schedules: &[UpdateSchedule]) -> serde_json::Value {
+    schedules
+        .iter()
+        .map(|s| {
+            serde_json::json!({
+                "id": s.id,
+                "source_url": s.source_url,
+                "scheduled_at": s.scheduled_at,
+                "status": s.status,
+                "enable_auto_rollback": s.enable_auto_rollback,
+                "created_at": s.created_at,
+                "prestage": s.prestage,
+                "staged": s.staged,
+                "confirmed_at": s.confirmed_at,
+            })
+        })
+        .collect()
+}
+
+/// Commands that run on this machine and never contact a node
+#[derive(Debug)]
+enum LocalCommand<'a> {
+    ValidateNetworkConfig {
+        file: &'a std::path::Path,
+    },
+    VerifyImage {
+        source: &'a str,
+        sha256: Option<&'a str>,
+    },
+}
+
+impl<'a> LocalCommand<'a> {
+    /// The local command `command` stands for, if it is one
+    fn from_command(command: &'a Commands) -> Option<Self> {
+        match command {
+            Commands::Network {
+                action:
+                    NetworkAction::Config {
+                        action: NetworkConfigAction::Validate { file },
+                    },
+            } => Some(Self::ValidateNetworkConfig { file }),
+            Commands::Image {
+                action: ImageAction::Verify { source, sha256 },
+            } => Some(Self::VerifyImage {
+                source,
+                sha256: sha256.as_deref(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Run a command that needs no node
+async fn run_local(
+    command: LocalCommand<'_>,
+    output: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        LocalCommand::ValidateNetworkConfig { file } => match validate_network_config_file(file) {
+            Ok(summary) => println!("✅ {}", summary),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        },
+        LocalCommand::VerifyImage { source, sha256 } => {
+            if output == OutputFormat::Text {
+                println!("🔍 Downloading {}...", source);
+            }
+            match image_verify::verify(source, sha256).await {
+                Ok(report) => {
+                    match output {
+                        OutputFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&report)?)
+                        }
+                        OutputFormat::Text => print!("{}", report.to_text()),
+                    }
+                    if !report.matches {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    if let Some(local) = LocalCommand::from_command(&cli.command) {
+        return run_local(local, cli.output).await;
+    }
+
     // Auto-load certificates if available, fallback to HTTP
     let mut client = connect_with_auto_tls(&cli.endpoint).await?;
 
     match &cli.command {
         Commands::Status => {
             let request = tonic::Request::new(GetStatusRequest {});
-            let response = client.get_status(request).await?;
-            println!("RESPONSE={:?}", response.into_inner());
+            let response = client.get_status(request).await?.into_inner();
+            if response.degraded {
+                eprintln!(
+                    "⚠️  Node is in maintenance mode: {}",
+                    response.degraded_reason
+                );
+            }
+            if response.reboot_pending {
+                eprintln!("🔄 Reboot pending: {}", response.reboot_reason);
+            }
+            println!("RESPONSE={:?}", response);
         }
         Commands::Reboot { reason } => {
             let request = tonic::Request::new(RebootRequest {
@@ -314,27 +671,61 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let response = client.reboot(request).await?;
             println!("Reboot Scheduled: {:?}", response.into_inner().scheduled);
         }
+        Commands::BootSlot { slot, reboot } => {
+            let request = tonic::Request::new(SetBootSlotRequest {
+                slot: slot.clone(),
+                reboot: *reboot,
+            });
+            let response = client.set_boot_slot(request).await?.into_inner();
+            println!("✅ {}", response.message);
+        }
         Commands::Update {
+            action: Some(UpdateAction::Confirm { id }),
+            ..
+        } => {
+            let request = tonic::Request::new(ConfirmUpdateRequest {
+                schedule_id: id.clone(),
+            });
+            let result = client.confirm_update(request).await?.into_inner();
+            if result.success {
+                println!("✅ {}", result.message);
+            } else {
+                eprintln!("❌ {}", result.message);
+                std::process::exit(1);
+            }
+        }
+        Commands::Update {
+            action: None,
             source,
             sha256,
+            checksum_manifest,
             delta,
             fallback,
             full_image_url,
+            auth_header,
+            max_bytes_per_sec,
         } => {
             let request = tonic::Request::new(InstallUpdateRequest {
-                source_url: source.clone(),
+                source_url: source.clone().unwrap_or_default(),
                 expected_sha256: sha256.clone().unwrap_or_default(),
                 is_delta: *delta,
                 fallback_to_full: *fallback,
                 full_image_url: full_image_url.clone().unwrap_or_default(),
+                auth_header: auth_header.clone().unwrap_or_default(),
+                max_bytes_per_sec: *max_bytes_per_sec,
+                checksum_manifest_url: checksum_manifest.clone().unwrap_or_default(),
             });
             let mut stream = client.install_update(request).await?.into_inner();
             while let Some(progress) = stream.next().await {
                 let p = progress?;
-                let phase_indicator = if !p.phase.is_empty() {
-                    format!(" [{}]", p.phase)
-                } else {
-                    String::new()
+                let phase_indicator = match p.phase.as_str() {
+                    "" => String::new(),
+                    "preparing" => " [Preparing]".to_string(),
+                    "downloading" => " [Downloading]".to_string(),
+                    "verifying" => " [Verifying]".to_string(),
+                    "completed" => " [Completed]".to_string(),
+                    // A newer agent may report phases this osctl does not know
+                    other => format!(" [{}]", other),
                 };
                 println!("[{:>3}%]{} {}", p.percentage, phase_indicator, p.message);
                 if p.success {
@@ -346,12 +737,107 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         }
-        Commands::Health => {
-            let request = tonic::Request::new(GetHealthRequest {});
+        Commands::Schedule { action } => match action {
+            ScheduleAction::Create {
+                source,
+                at,
+                window_secs,
+                sha256,
+                auto_rollback,
+                delta,
+                pre_hook,
+                post_hook,
+                force,
+            } => {
+                let request = tonic::Request::new(ScheduleUpdateRequest {
+                    source_url: source.clone(),
+                    expected_sha256: sha256.clone().unwrap_or_default(),
+                    scheduled_at: at.clone().unwrap_or_default(),
+                    maintenance_window_secs: window_secs.unwrap_or_default(),
+                    enable_auto_rollback: *auto_rollback,
+                    health_check_timeout_secs: 0,
+                    pre_update_hook: pre_hook.clone().unwrap_or_default(),
+                    post_update_hook: post_hook.clone().unwrap_or_default(),
+                    is_delta: *delta,
+                    fallback_to_full: false,
+                    full_image_url: String::new(),
+                    prestage: false,
+                    force: *force,
+                });
+                let result = client.schedule_update(request).await?.into_inner();
+
+                if result.duplicate {
+                    println!("ℹ️  {}", result.message);
+                } else {
+                    println!("✅ Update scheduled");
+                }
+                println!("  ID: {}", result.schedule_id);
+                println!("  Status: {}", result.status);
+                if !result.scheduled_at.is_empty() {
+                    println!("  Scheduled: {}", result.scheduled_at);
+                }
+            }
+            ScheduleAction::List => {
+                let request = tonic::Request::new(GetUpdateScheduleRequest {});
+                let schedules = client
+                    .get_update_schedule(request)
+                    .await?
+                    .into_inner()
+                    .schedules;
+
+                match cli.output {
+                    OutputFormat::Json => println!(
+                        "{}",
+                        serde_json::to_string_pretty(&schedules_json(&schedules))?
+                    ),
+                    OutputFormat::Text if schedules.is_empty() => {
+                        println!("No scheduled updates.");
+                    }
+                    OutputFormat::Text => {
+                        println!("\n📅 Scheduled Updates ({}):\n", schedules.len());
+                        for s in &schedules {
+                            let when = if s.scheduled_at.is_empty() {
+                                "immediate"
+                            } else {
+                                &s.scheduled_at
+                            };
+                            println!("  ID: {}", s.id);
+                            println!("  Source: {}", s.source_url);
+                            println!("  Scheduled: {}", when);
+                            println!("  Status: {}", s.status);
+                            if !s.confirmed_at.is_empty() {
+                                println!("  Confirmed: {}", s.confirmed_at);
+                            }
+                            println!();
+                        }
+                    }
+                }
+            }
+            ScheduleAction::Cancel { id } => {
+                let request = tonic::Request::new(CancelScheduledUpdateRequest {
+                    schedule_id: id.clone(),
+                });
+                let result = client.cancel_scheduled_update(request).await?.into_inner();
+
+                if result.success {
+                    println!("✅ {}", result.message);
+                } else {
+                    eprintln!("❌ {}", result.message);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Health { check } => {
+            let request = tonic::Request::new(GetHealthRequest {
+                check_name: check.clone().unwrap_or_default(),
+            });
             let response = client.get_health(request).await?;
             let health = response.into_inner();
 
             println!("\n🏥 System Health: {}", health.status.to_uppercase());
+            if health.status != "healthy" && !health.summary.is_empty() {
+                println!("Reason: {}", health.summary);
+            }
             println!("Last Updated: {}\n", health.last_update_time);
 
             if !health.checks.is_empty() {
@@ -370,9 +856,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         Commands::Rollback { action } => match action {
-            RollbackAction::Trigger { reason } => {
+            RollbackAction::Trigger { reason, no_reboot } => {
                 let request = tonic::Request::new(TriggerRollbackRequest {
                     reason: reason.clone(),
+                    reboot: Some(!no_reboot),
                 });
                 let response = client.trigger_rollback(request).await?;
                 let result = response.into_inner();
@@ -503,6 +990,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("K8s operational cert initialization - TODO");
             }
         },
+        Commands::GetCa { out } => {
+            let response = client
+                .get_ca_cert(tonic::Request::new(GetCaCertRequest {}))
+                .await?;
+            let ca_cert_pem = response.into_inner().ca_cert_pem;
+
+            match out {
+                Some(path) => {
+                    std::fs::write(path, &ca_cert_pem)?;
+                    eprintln!("✓ Saved CA certificate to {}", path.display());
+                    eprintln!("⚠️  Verify its fingerprint out of band before trusting it");
+                }
+                None => print!("{}", ca_cert_pem),
+            }
+        }
         Commands::BootstrapStatus => {
             let request = tonic::Request::new(GetBootstrapStatusRequest {});
             let response = client.get_bootstrap_status(request).await?;
@@ -529,9 +1031,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         gateway,
                         ipv6,
                         ipv6_gateway,
+                        gateway_metric,
                         ipv6_auto,
                         mtu,
                         auto_reboot,
+                        apply_now,
                     } => {
                         // Build network interface configuration
                         let iface_config = if *dhcp {
@@ -547,6 +1051,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     ipv6_addresses: ipv6.clone(),
                                     ipv6_gateway: ipv6_gateway.clone().unwrap_or_default(),
                                     ipv6_auto: *ipv6_auto,
+                                    gateway_metric: gateway_metric.unwrap_or(0),
                                 },
                             ))
                         } else {
@@ -562,6 +1067,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             dns: None,
                             routes: vec![],
                             auto_reboot: *auto_reboot,
+                            apply_now: *apply_now,
+                            allow_overlapping_subnets: false,
                         });
 
                         println!("🌐 Configuring network interface '{}'...", interface);
@@ -579,6 +1086,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             std::process::exit(1);
                         }
                     }
+                    NetworkConfigAction::Validate { .. } => {
+                        // A LocalCommand, run before a node is contacted
+                        return Err("network config validate does not use a node".into());
+                    }
                     NetworkConfigAction::Show => {
                         let request = tonic::Request::new(GetNetworkConfigRequest {});
                         let response = client.get_network_config(request).await?;
@@ -612,6 +1123,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                                     println!("  IPv6 Gateway: {}", s.ipv6_gateway);
                                                 }
                                             }
+                                            if s.gateway_metric > 0 {
+                                                println!("  Gateway Metric: {}", s.gateway_metric);
+                                            }
                                             println!("  MTU: {}", s.mtu);
                                         }
                                         keel_api::node::network_interface::Config::Vlan(v) => {
@@ -651,8 +1165,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                 },
-                NetworkAction::Status => {
-                    let request = tonic::Request::new(GetNetworkStatusRequest {});
+                NetworkAction::Status { rates, all } => {
+                    let request = tonic::Request::new(GetNetworkStatusRequest {
+                        with_rates: *rates,
+                        include_loopback: *all,
+                    });
                     let response = client.get_network_status(request).await?;
                     let status = response.into_inner();
 
@@ -663,7 +1180,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         for iface in status.interfaces {
                             let state_icon = match iface.state.as_str() {
                                 "up" => "🟢",
-                                "down" => "🔴",
+                                "down" | "lowerlayerdown" | "notpresent" => "🔴",
                                 _ => "⚪",
                             };
                             println!("{} {} ({})", state_icon, iface.name, iface.state);
@@ -678,6 +1195,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 println!("  IPv6: {}", iface.ipv6_addresses.join(", "));
                             }
 
+                            if iface.ipv4_addresses.is_empty() && iface.ipv6_addresses.is_empty() {
+                                println!("  No addresses");
+                            }
+
                             if let Some(stats) = iface.statistics {
                                 let rx_mb = stats.rx_bytes as f64 / (1024.0 * 1024.0);
                                 let tx_mb = stats.tx_bytes as f64 / (1024.0 * 1024.0);
@@ -689,6 +1210,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     "  TX: {:.2} MB ({} packets, {} errors)",
                                     tx_mb, stats.tx_packets, stats.tx_errors
                                 );
+                                if *rates {
+                                    println!(
+                                        "  Rate: RX {:.2} Mbit/s, TX {:.2} Mbit/s",
+                                        stats.rx_bps as f64 / 1_000_000.0,
+                                        stats.tx_bps as f64 / 1_000_000.0
+                                    );
+                                }
                             }
                             println!();
                         }
@@ -698,6 +1226,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     DnsAction::Set {
                         nameserver,
                         search,
+                        tls_servername,
                         auto_reboot,
                     } => {
                         let request = tonic::Request::new(ConfigureNetworkRequest {
@@ -705,9 +1234,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             dns: Some(DnsConfig {
                                 nameservers: nameserver.clone(),
                                 search_domains: search.clone(),
+                                dns_over_tls: tls_servername.is_some(),
+                                tls_servername: tls_servername.clone().unwrap_or_default(),
                             }),
                             routes: vec![],
                             auto_reboot: *auto_reboot,
+                            apply_now: false,
+                            allow_overlapping_subnets: false,
                         });
 
                         println!("🌐 Configuring DNS...");
@@ -878,6 +1411,137 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         },
+        Commands::Selftest => {
+            let report = selftest::run(&mut client, &cli.endpoint).await;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report.to_text()),
+            }
+            if !report.passed {
+                std::process::exit(report.exit_code());
+            }
+        }
+        Commands::Diagnostics { out, log_lines } => {
+            use std::io::Write;
+
+            let request = tonic::Request::new(CollectDiagnosticsRequest {
+                log_lines: *log_lines,
+            });
+            println!("🩺 Collecting diagnostic bundle...");
+            let mut stream = client.collect_diagnostics(request).await?.into_inner();
+
+            // Only a complete bundle replaces `out`
+            let partial = out.with_extension("partial");
+            let mut file = std::fs::File::create(&partial)?;
+            let mut size = 0usize;
+            while let Some(chunk) = stream.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = std::fs::remove_file(&partial);
+                        return Err(e.into());
+                    }
+                };
+                file.write_all(&chunk.data)?;
+                size += chunk.data.len();
+            }
+            file.sync_all()?;
+            std::fs::rename(&partial, out)?;
+
+            println!(
+                "✅ Wrote {} ({:.1} KB, secrets redacted)",
+                out.display(),
+                size as f64 / 1024.0
+            );
+        }
+        Commands::Hook {
+            action: HookAction::Test { hook_type, path },
+        } => {
+            let phase = match hook_type {
+                HookType::Pre => "pre",
+                HookType::Post => "post",
+            };
+            let request = tonic::Request::new(TestHookRequest {
+                phase: phase.to_string(),
+                command: path.clone(),
+            });
+            println!("🪝 Running {}-update hook on the node...", phase);
+            let response = client.test_hook(request).await?.into_inner();
+
+            if !response.stdout.is_empty() {
+                println!("--- stdout ---\n{}", response.stdout.trim_end());
+            }
+            if !response.stderr.is_empty() {
+                println!("--- stderr ---\n{}", response.stderr.trim_end());
+            }
+            if response.success {
+                println!("✅ {}", response.message);
+            } else {
+                eprintln!("❌ {}", response.message);
+                std::process::exit(1);
+            }
+        }
+        Commands::Kernel {
+            action: KernelAction::Loglevel { level: None },
+        } => {
+            let request = tonic::Request::new(GetKernelLogLevelRequest {});
+            let response = client.get_kernel_log_level(request).await?.into_inner();
+            println!(
+                "Kernel log level: {} (default message level: {})",
+                response.level, response.default_message_level
+            );
+        }
+        Commands::Kernel {
+            action: KernelAction::Loglevel { level: Some(level) },
+        } => {
+            let request = tonic::Request::new(SetKernelLogLevelRequest { level: *level });
+            let response = client.set_kernel_log_level(request).await?.into_inner();
+            println!(
+                "✅ Kernel log level set to {} (was {})",
+                response.level, response.previous_level
+            );
+        }
+        Commands::Disk {
+            action: DiskAction::Slots,
+        } => {
+            let request = tonic::Request::new(GetSlotInfoRequest {});
+            let response = client.get_slot_info(request).await?.into_inner();
+            println!(
+                "\n💽 Boot Slots (boot flag scheme: {}):\n",
+                response.boot_flag_scheme
+            );
+            for slot in &response.slots {
+                println!(
+                    "  {}  {} (partition {}){}",
+                    slot.name,
+                    slot.device,
+                    slot.index,
+                    if slot.active { "  [active]" } else { "" }
+                );
+                if slot.flashed {
+                    println!("    Flashed: {}", slot.flashed_at);
+                    if !slot.image_sha256.is_empty() {
+                        println!("    SHA256: {}", slot.image_sha256);
+                    }
+                } else {
+                    println!("    Flashed: no ({})", slot.marker_error);
+                }
+                if slot.attributes_error.is_empty() {
+                    println!(
+                        "    Boot flag: {}  (attributes {:016x}, legacy_bootable={}, priority={})",
+                        if slot.boot_flag { "set" } else { "clear" },
+                        slot.attributes,
+                        slot.legacy_bootable,
+                        slot.priority
+                    );
+                } else {
+                    println!("    Boot flag: unknown ({})", slot.attributes_error);
+                }
+                println!();
+            }
+        }
+        // A LocalCommand, run before a node is contacted
+        Commands::Image { .. } => return Err("image commands do not use a node".into()),
     }
 
     Ok(())
@@ -903,6 +1567,226 @@ mod tests {
         assert_eq!(cli.endpoint, "http://localhost:9000");
     }
 
+    #[test]
+    fn test_cli_parsing_selftest() {
+        let cli = Cli::try_parse_from(["osctl", "selftest"]).unwrap();
+        assert!(matches!(cli.command, Commands::Selftest));
+        assert_eq!(cli.output, OutputFormat::Text);
+
+        let cli = Cli::try_parse_from(["osctl", "selftest", "--output", "json"]).unwrap();
+        assert!(matches!(cli.command, Commands::Selftest));
+        assert_eq!(cli.output, OutputFormat::Json);
+
+        assert!(Cli::try_parse_from(["osctl", "selftest", "--output", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_network_config_validate() {
+        let cli =
+            Cli::try_parse_from(["osctl", "network", "config", "validate", "net.json"]).unwrap();
+        match cli.command {
+            Commands::Network {
+                action:
+                    NetworkAction::Config {
+                        action: NetworkConfigAction::Validate { file },
+                    },
+            } => assert_eq!(file, PathBuf::from("net.json")),
+            _ => panic!("Expected network config validate"),
+        }
+
+        assert!(Cli::try_parse_from(["osctl", "network", "config", "validate"]).is_err());
+    }
+
+    #[test]
+    fn test_validate_network_config_file_valid() {
+        let path =
+            std::env::temp_dir().join(format!("osctl-net-valid-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"interfaces": [{"name": "eth0", "type": "static", "ipv4_address": "10.0.0.5/24", "gateway": "10.0.0.1"}],
+                "dns": {"nameservers": ["10.0.0.1"]}}"#,
+        )
+        .unwrap();
+
+        let summary = validate_network_config_file(&path).unwrap();
+        assert!(summary.contains("is valid (1 interface(s), 0 route(s), DNS configured)"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_network_config_file_invalid() {
+        let path =
+            std::env::temp_dir().join(format!("osctl-net-invalid-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"interfaces": [{"name": "eth0", "type": "static", "ipv4_address": "10.0.0.500/24"}]}"#,
+        )
+        .unwrap();
+
+        let err = validate_network_config_file(&path).unwrap_err();
+        assert!(err.contains("is invalid"), "{}", err);
+        assert!(err.contains("10.0.0.500/24"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+
+        let err = validate_network_config_file(std::path::Path::new("/nonexistent/net.json"))
+            .unwrap_err();
+        assert!(err.contains("/nonexistent/net.json"));
+    }
+
+    #[test]
+    fn test_cli_parsing_schedule_create() {
+        let cli = Cli::try_parse_from([
+            "osctl",
+            "schedule",
+            "create",
+            "--source",
+            "http://example.com/os.delta",
+            "--at",
+            "2026-03-01T02:00:00Z",
+            "--window-secs",
+            "3600",
+            "--sha256",
+            "abc123",
+            "--auto-rollback",
+            "--delta",
+            "--pre-hook",
+            "/usr/bin/drain",
+            "--post-hook",
+            "/usr/bin/uncordon",
+            "--force",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Schedule {
+                action:
+                    ScheduleAction::Create {
+                        source,
+                        at,
+                        window_secs,
+                        sha256,
+                        auto_rollback,
+                        delta,
+                        pre_hook,
+                        post_hook,
+                        force,
+                    },
+            } => {
+                assert_eq!(source, "http://example.com/os.delta");
+                assert_eq!(at.as_deref(), Some("2026-03-01T02:00:00Z"));
+                assert_eq!(window_secs, Some(3600));
+                assert_eq!(sha256.as_deref(), Some("abc123"));
+                assert!(auto_rollback);
+                assert!(delta);
+                assert_eq!(pre_hook.as_deref(), Some("/usr/bin/drain"));
+                assert_eq!(post_hook.as_deref(), Some("/usr/bin/uncordon"));
+                assert!(force);
+            }
+            _ => panic!("Expected schedule create"),
+        }
+
+        // Only --source is required
+        let cli = Cli::try_parse_from([
+            "osctl",
+            "schedule",
+            "create",
+            "--source",
+            "http://example.com/os.squashfs",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Schedule {
+                action:
+                    ScheduleAction::Create {
+                        at,
+                        auto_rollback,
+                        force,
+                        ..
+                    },
+            } => {
+                assert!(at.is_none());
+                assert!(!auto_rollback);
+                assert!(!force);
+            }
+            _ => panic!("Expected schedule create"),
+        }
+        assert!(Cli::try_parse_from(["osctl", "schedule", "create"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_schedule_create_rejects_bad_timestamp() {
+        for at in ["tomorrow", "2026-03-01 02:00", "2026-13-01T02:00:00Z"] {
+            let result = Cli::try_parse_from([
+                "osctl",
+                "schedule",
+                "create",
+                "--source",
+                "http://example.com/os.squashfs",
+                "--at",
+                at,
+            ]);
+            assert!(result.is_err(), "{at} should be rejected");
+        }
+
+        assert!(parse_rfc3339("2026-03-01T02:00:00+01:00").is_ok());
+        assert!(parse_rfc3339("tomorrow").unwrap_err().contains("RFC3339"));
+    }
+
+    #[test]
+    fn test_cli_parsing_schedule_list() {
+        let cli = Cli::try_parse_from(["osctl", "schedule", "list"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Schedule {
+                action: ScheduleAction::List
+            }
+        ));
+        assert_eq!(cli.output, OutputFormat::Text);
+
+        let cli = Cli::try_parse_from(["osctl", "schedule", "list", "--output", "json"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+    }
+
+    #[test]
+    fn test_cli_parsing_schedule_cancel() {
+        let cli = Cli::try_parse_from(["osctl", "schedule", "cancel", "abc-123"]).unwrap();
+        match cli.command {
+            Commands::Schedule {
+                action: ScheduleAction::Cancel { id },
+            } => assert_eq!(id, "abc-123"),
+            _ => panic!("Expected schedule cancel"),
+        }
+
+        assert!(Cli::try_parse_from(["osctl", "schedule", "cancel"]).is_err());
+    }
+
+    #[test]
+    fn test_schedules_json() {
+        let json = schedules_json(&[UpdateSchedule {
+            id: "abc-123".to_string(),
+            source_url: "http://example.com/os.squashfs".to_string(),
+            scheduled_at: "2026-03-01T02:00:00+00:00".to_string(),
+            status: "pending".to_string(),
+            ..Default::default()
+        }]);
+        assert_eq!(json[0]["id"], "abc-123");
+        assert_eq!(json[0]["source_url"], "http://example.com/os.squashfs");
+        assert_eq!(json[0]["scheduled_at"], "2026-03-01T02:00:00+00:00");
+        assert_eq!(json[0]["status"], "pending");
+        assert_eq!(schedules_json(&[]), serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_cli_parsing_get_ca() {
+        let cli = Cli::try_parse_from(["osctl", "get-ca"]).unwrap();
+        assert!(matches!(cli.command, Commands::GetCa { out: None }));
+
+        let cli = Cli::try_parse_from(["osctl", "get-ca", "--out", "/tmp/node-ca.pem"]).unwrap();
+        match cli.command {
+            Commands::GetCa { out } => assert_eq!(out, Some(PathBuf::from("/tmp/node-ca.pem"))),
+            _ => panic!("Expected GetCa command"),
+        }
+    }
+
     #[test]
     fn test_cli_parsing_reboot() {
         let cli = Cli::try_parse_from(["osctl", "reboot"]).unwrap();
@@ -933,7 +1817,7 @@ mod tests {
         ])
         .unwrap();
         if let Commands::Update { source, sha256, .. } = cli.command {
-            assert_eq!(source, "http://example.com/image.squashfs");
+            assert_eq!(source.as_deref(), Some("http://example.com/image.squashfs"));
             assert!(sha256.is_none());
         } else {
             panic!("Expected Update command");
@@ -952,13 +1836,40 @@ mod tests {
         ])
         .unwrap();
         if let Commands::Update { source, sha256, .. } = cli.command {
-            assert_eq!(source, "http://example.com/image.squashfs");
+            assert_eq!(source.as_deref(), Some("http://example.com/image.squashfs"));
             assert_eq!(sha256, Some("abc123def456".to_string()));
         } else {
             panic!("Expected Update command");
         }
     }
 
+    #[test]
+    fn test_cli_parsing_update_with_checksum_manifest() {
+        let cli = Cli::try_parse_from([
+            "osctl",
+            "update",
+            "--source",
+            "http://example.com/keel-1.4.0.squashfs",
+            "--checksum-manifest",
+            "http://example.com/SHA256SUMS",
+        ])
+        .unwrap();
+        if let Commands::Update {
+            checksum_manifest,
+            sha256,
+            ..
+        } = cli.command
+        {
+            assert_eq!(
+                checksum_manifest.as_deref(),
+                Some("http://example.com/SHA256SUMS")
+            );
+            assert!(sha256.is_none());
+        } else {
+            panic!("Expected Update command");
+        }
+    }
+
     #[test]
     fn test_cli_parsing_update_delta() {
         let cli = Cli::try_parse_from([
@@ -981,7 +1892,7 @@ mod tests {
             ..
         } = cli.command
         {
-            assert_eq!(source, "http://example.com/update.delta");
+            assert_eq!(source.as_deref(), Some("http://example.com/update.delta"));
             assert!(delta);
             assert!(fallback);
             assert_eq!(
@@ -999,16 +1910,179 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_cli_parsing_update_confirm() {
+        let cli = Cli::try_parse_from(["osctl", "update", "confirm", "abc-123"]).unwrap();
+        match cli.command {
+            Commands::Update {
+                action: Some(UpdateAction::Confirm { id }),
+                source,
+                ..
+            } => {
+                assert_eq!(id, "abc-123");
+                assert!(source.is_none());
+            }
+            _ => panic!("Expected update confirm"),
+        }
+
+        assert!(Cli::try_parse_from(["osctl", "update", "confirm"]).is_err());
+        assert!(Cli::try_parse_from([
+            "osctl",
+            "update",
+            "--source",
+            "http://example.com/image.squashfs",
+            "confirm",
+            "abc-123",
+        ])
+        .is_err());
+    }
+
     #[test]
     fn test_cli_help_available() {
         // Verify help is available without panicking
         let _cmd = Cli::command();
     }
 
+    #[test]
+    fn test_cli_parsing_diagnostics() {
+        let cli = Cli::try_parse_from(["osctl", "diagnostics", "--out", "bundle.tar.gz"]).unwrap();
+        if let Commands::Diagnostics { out, log_lines } = cli.command {
+            assert_eq!(out, PathBuf::from("bundle.tar.gz"));
+            assert_eq!(log_lines, 0);
+        } else {
+            panic!("Expected Diagnostics command");
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_kernel_loglevel() {
+        let cli = Cli::try_parse_from(["osctl", "kernel", "loglevel"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Kernel {
+                action: KernelAction::Loglevel { level: None }
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["osctl", "kernel", "loglevel", "7"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Kernel {
+                action: KernelAction::Loglevel { level: Some(7) }
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["osctl", "kernel", "loglevel", "8"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_disk_slots() {
+        let cli = Cli::try_parse_from(["osctl", "disk", "slots"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Disk {
+                action: DiskAction::Slots
+            }
+        ));
+        assert!(Cli::try_parse_from(["osctl", "disk"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_hook_test() {
+        let cli = Cli::try_parse_from([
+            "osctl",
+            "hook",
+            "test",
+            "--type",
+            "pre",
+            "--path",
+            "/var/lib/keel/hooks/drain.sh",
+        ])
+        .unwrap();
+        if let Commands::Hook {
+            action: HookAction::Test { hook_type, path },
+        } = cli.command
+        {
+            assert_eq!(hook_type, HookType::Pre);
+            assert_eq!(path, "/var/lib/keel/hooks/drain.sh");
+        } else {
+            panic!("Expected Hook command");
+        }
+        assert!(
+            Cli::try_parse_from(["osctl", "hook", "test", "--type", "during", "--path", "x"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_image_verify() {
+        let cli = Cli::try_parse_from([
+            "osctl",
+            "image",
+            "verify",
+            "--source",
+            "https://example.com/keel.squashfs",
+        ])
+        .unwrap();
+        if let Commands::Image {
+            action: ImageAction::Verify { source, sha256 },
+        } = cli.command
+        {
+            assert_eq!(source, "https://example.com/keel.squashfs");
+            assert_eq!(sha256, None);
+        } else {
+            panic!("Expected Image command");
+        }
+        assert!(Cli::try_parse_from(["osctl", "image", "verify"]).is_err());
+    }
+
+    #[test]
+    fn test_local_commands_need_no_node() {
+        let local = |args: &[&str]| {
+            let cli = Cli::try_parse_from(args).unwrap();
+            LocalCommand::from_command(&cli.command).map(|c| format!("{:?}", c))
+        };
+
+        assert_eq!(
+            local(&[
+                "osctl",
+                "image",
+                "verify",
+                "--source",
+                "https://example.com/os.img"
+            ]),
+            Some(format!(
+                "{:?}",
+                LocalCommand::VerifyImage {
+                    source: "https://example.com/os.img",
+                    sha256: None,
+                }
+            ))
+        );
+        assert_eq!(
+            local(&["osctl", "network", "config", "validate", "net.json"]),
+            Some(format!(
+                "{:?}",
+                LocalCommand::ValidateNetworkConfig {
+                    file: std::path::Path::new("net.json"),
+                }
+            ))
+        );
+        assert_eq!(local(&["osctl", "status"]), None);
+        assert_eq!(local(&["osctl", "network", "config", "show"]), None);
+    }
+
     #[test]
     fn test_cli_parsing_health() {
         let cli = Cli::try_parse_from(["osctl", "health"]).unwrap();
-        assert!(matches!(cli.command, Commands::Health));
+        assert!(matches!(cli.command, Commands::Health { check: None }));
+
+        let cli = Cli::try_parse_from(["osctl", "health", "--check", "boot"]).unwrap();
+        if let Commands::Health { check } = cli.command {
+            assert_eq!(check.as_deref(), Some("boot"));
+        } else {
+            panic!("Expected Health command");
+        }
     }
 
     #[test]
@@ -1178,4 +2252,26 @@ mod tests {
             panic!("Expected Diag AnalyzeDump command");
         }
     }
+
+    #[test]
+    fn test_cli_parse_boot_slot() {
+        let cli = Cli::try_parse_from(["osctl", "boot-slot", "B", "--reboot"]).unwrap();
+        match cli.command {
+            Commands::BootSlot { slot, reboot } => {
+                assert_eq!(slot, "b");
+                assert!(reboot);
+            }
+            _ => panic!("expected boot-slot command"),
+        }
+
+        let cli = Cli::try_parse_from(["osctl", "boot-slot", "a"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::BootSlot { reboot: false, .. }
+        ));
+
+        assert!(Cli::try_parse_from(["osctl", "boot-slot"]).is_err());
+        assert!(Cli::try_parse_from(["osctl", "boot-slot", "2"]).is_err());
+        assert!(Cli::try_parse_from(["osctl", "boot-slot", "ab"]).is_err());
+    }
 }
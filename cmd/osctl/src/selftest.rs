@@ -0,0 +1,288 @@
+//! Node self-test for field technicians
+//!
+//! Runs every read-only RPC plus a local client-certificate check, and
+//! aggregates the results into a single pass/fail report.
+
+use keel_api::node::node_service_client::NodeServiceClient;
+use keel_api::node::{
+    GetBootstrapStatusRequest, GetHealthRequest, GetNetworkStatusRequest, GetStatusRequest,
+};
+use serde::Serialize;
+use tonic::transport::Channel;
+
+use crate::cert_store::{extract_node_from_endpoint, CertStore};
+
+/// Outcome of a single self-test check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckOutcome {
+    Pass,
+    Fail,
+    /// Check not applicable (e.g. no client certificate stored)
+    Skip,
+}
+
+impl std::fmt::Display for CheckOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckOutcome::Pass => write!(f, "PASS"),
+            CheckOutcome::Fail => write!(f, "FAIL"),
+            CheckOutcome::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+/// Result of a single self-test check
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub outcome: CheckOutcome,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: CheckOutcome::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: CheckOutcome::Fail,
+            detail: detail.into(),
+        }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: CheckOutcome::Skip,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Aggregated self-test results
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Build a report; it passes only if no check failed
+    pub fn new(checks: Vec<CheckResult>) -> Self {
+        let passed = checks.iter().all(|c| c.outcome != CheckOutcome::Fail);
+        Self { passed, checks }
+    }
+
+    /// Process exit code for the report (0 = all passed, 1 = any failed)
+    pub fn exit_code(&self) -> i32 {
+        if self.passed {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// One line per check, followed by a summary line
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            out.push_str(&format!(
+                "{:<5} {:<10} {}\n",
+                check.outcome.to_string(),
+                check.name,
+                check.detail
+            ));
+        }
+        let failed = self
+            .checks
+            .iter()
+            .filter(|c| c.outcome == CheckOutcome::Fail)
+            .count();
+        out.push_str(&format!(
+            "{}: {}/{} checks failed\n",
+            if self.passed { "PASS" } else { "FAIL" },
+            failed,
+            self.checks.len()
+        ));
+        out
+    }
+}
+
+/// Run all self-test checks against a connected node
+pub async fn run(client: &mut NodeServiceClient<Channel>, endpoint: &str) -> SelfTestReport {
+    let mut checks = Vec::new();
+
+    checks.push(
+        match client
+            .get_status(tonic::Request::new(GetStatusRequest {}))
+            .await
+        {
+            Ok(resp) => {
+                let status = resp.into_inner();
+                CheckResult::pass(
+                    "status",
+                    format!("{} (os {})", status.hostname, status.os_version),
+                )
+            }
+            Err(e) => CheckResult::fail("status", e.message()),
+        },
+    );
+
+    checks.push(
+        match client
+            .get_health(tonic::Request::new(GetHealthRequest::default()))
+            .await
+        {
+            Ok(resp) => {
+                let health = resp.into_inner();
+                if health.status == "healthy" {
+                    CheckResult::pass("health", health.status)
+                } else {
+                    let failing: Vec<String> = health
+                        .checks
+                        .iter()
+                        .filter(|c| c.status != "pass")
+                        .map(|c| c.name.clone())
+                        .collect();
+                    CheckResult::fail(
+                        "health",
+                        format!("{} ({})", health.status, failing.join(", ")),
+                    )
+                }
+            }
+            Err(e) => CheckResult::fail("health", e.message()),
+        },
+    );
+
+    checks.push(
+        match client
+            .get_bootstrap_status(tonic::Request::new(GetBootstrapStatusRequest {}))
+            .await
+        {
+            Ok(resp) => {
+                let status = resp.into_inner();
+                if status.is_bootstrapped {
+                    CheckResult::pass(
+                        "bootstrap",
+                        format!(
+                            "joined {} as {}",
+                            status.api_server_endpoint, status.node_name
+                        ),
+                    )
+                } else {
+                    CheckResult::pass("bootstrap", "not joined to a cluster")
+                }
+            }
+            Err(e) => CheckResult::fail("bootstrap", e.message()),
+        },
+    );
+
+    checks.push(
+        match client
+            .get_network_status(tonic::Request::new(GetNetworkStatusRequest {
+                with_rates: false,
+                include_loopback: false,
+            }))
+            .await
+        {
+            Ok(resp) => {
+                let interfaces = resp.into_inner().interfaces;
+                let up = interfaces.iter().filter(|i| i.state == "up").count();
+                if up > 0 {
+                    CheckResult::pass(
+                        "network",
+                        format!("{}/{} interfaces up", up, interfaces.len()),
+                    )
+                } else {
+                    CheckResult::fail(
+                        "network",
+                        format!("no interfaces up ({} found)", interfaces.len()),
+                    )
+                }
+            }
+            Err(e) => CheckResult::fail("network", e.message()),
+        },
+    );
+
+    checks.push(check_client_cert(endpoint));
+
+    SelfTestReport::new(checks)
+}
+
+/// Check the locally stored client certificate for this node
+fn check_client_cert(endpoint: &str) -> CheckResult {
+    let paths = extract_node_from_endpoint(endpoint)
+        .ok()
+        .zip(CertStore::new().ok())
+        .and_then(|(node_id, store)| store.find_best_cert(&node_id).ok());
+
+    let Some((tier, paths)) = paths else {
+        return CheckResult::skip("cert", "no client certificate stored, using HTTP");
+    };
+
+    let expiry = std::fs::read_to_string(&paths.cert)
+        .map_err(|e| e.to_string())
+        .and_then(|pem| keel_crypto::parse_cert_expiry(&pem));
+
+    match expiry {
+        Ok(expiry) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            let days_left = (expiry.timestamp() - now) / 86_400;
+            if expiry.timestamp() <= now {
+                CheckResult::fail("cert", format!("{} certificate expired", tier))
+            } else {
+                CheckResult::pass(
+                    "cert",
+                    format!("{} certificate valid for {} days", tier, days_left),
+                )
+            }
+        }
+        Err(e) => CheckResult::fail("cert", format!("{} certificate unreadable: {}", tier, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_mixed_results_fails() {
+        let report = SelfTestReport::new(vec![
+            CheckResult::pass("status", "keel-node"),
+            CheckResult::fail("health", "unhealthy (boot)"),
+            CheckResult::skip("cert", "no client certificate"),
+        ]);
+        assert!(!report.passed);
+        assert_eq!(report.exit_code(), 1);
+        assert!(report.to_text().ends_with("FAIL: 1/3 checks failed\n"));
+    }
+
+    #[test]
+    fn test_report_skips_do_not_fail() {
+        let report = SelfTestReport::new(vec![
+            CheckResult::pass("status", "keel-node"),
+            CheckResult::skip("cert", "no client certificate"),
+        ]);
+        assert!(report.passed);
+        assert_eq!(report.exit_code(), 0);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let report = SelfTestReport::new(vec![CheckResult::fail("network", "no interfaces up")]);
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["checks"][0]["name"], "network");
+        assert_eq!(json["checks"][0]["outcome"], "fail");
+    }
+}
@@ -0,0 +1,174 @@
+//! Crash-safe file replacement
+//!
+//! The node reboots in the middle of updates, so state files must never be
+//! observed half-written. [`atomic_write`] writes to a temporary file in the
+//! same directory, syncs it, and renames it over the target: readers see
+//! either the old contents or the new ones. [`atomic_write_private`] does
+//! the same for keys and credentials, which must never be readable by
+//! anyone but the owner, not even while being written.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes temporary files of concurrent writers in one process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Atomically replace `path` with `contents`
+///
+/// Missing parent directories are created. When `path` already exists its
+/// permissions carry over to the new file; new files get the default mode.
+/// On failure the target is left untouched and the temporary file removed.
+pub fn atomic_write<P: AsRef<Path>>(path: P, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    replace(path.as_ref(), contents.as_ref(), None)
+}
+
+/// Atomically replace `path` with `contents`, with mode `0600`
+///
+/// Like [`atomic_write`], but the temporary file is created owner-only, so
+/// the contents never exist under a wider mode, whatever the mode of the
+/// file being replaced.
+pub fn atomic_write_private<P: AsRef<Path>>(path: P, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    replace(path.as_ref(), contents.as_ref(), Some(0o600))
+}
+
+/// Replace `path` through a temporary file created with `mode`, or with the
+/// mode of the file being replaced if `None`
+fn replace(path: &Path, contents: &[u8], mode: Option<u32>) -> io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let temp = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mode = mode.or_else(|| {
+        fs::metadata(path)
+            .ok()
+            .map(|existing| existing.permissions().mode() & 0o7777)
+    });
+    let result = write_and_rename(&temp, path, contents, mode);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // Persist the rename itself; not every filesystem supports syncing a
+    // directory, and the data is already safe either way
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_and_rename(
+    temp: &Path,
+    path: &Path,
+    contents: &[u8],
+    mode: Option<u32>,
+) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    let mut file = options.open(temp)?;
+    // The umask may have dropped bits the mode asked for
+    if let Some(mode) = mode {
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_atomic_write_creates_parents() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state/keel/schedules.json");
+
+        atomic_write(&path, "{}").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        assert_eq!(entries(path.parent().unwrap()), vec!["schedules.json"]);
+    }
+
+    #[test]
+    fn test_atomic_write_replaces_and_preserves_permissions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("bootstrap.json");
+        fs::write(&path, "old contents that are longer than the new ones").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        atomic_write(&path, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(entries(dir.path()), vec!["bootstrap.json"]);
+    }
+
+    #[test]
+    fn test_atomic_write_private_is_owner_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let new = dir.path().join("server.key");
+        atomic_write_private(&new, "key").unwrap();
+        assert_eq!(fs::read_to_string(&new).unwrap(), "key");
+        assert_eq!(mode(&new), 0o600);
+
+        // A world-readable file being replaced does not widen the new one
+        let existing = dir.path().join("kubelet.kubeconfig");
+        fs::write(&existing, "old").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o644)).unwrap();
+        atomic_write_private(&existing, "new").unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+        assert_eq!(mode(&existing), 0o600);
+
+        assert_eq!(
+            entries(dir.path()),
+            vec!["kubelet.kubeconfig", "server.key"]
+        );
+    }
+
+    #[test]
+    fn test_atomic_write_failure_leaves_no_partial_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // A non-empty directory cannot be replaced by a file
+        let path = dir.path().join("network.json");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("keep"), "x").unwrap();
+
+        assert!(atomic_write(&path, "{}").is_err());
+        assert!(path.is_dir());
+        assert_eq!(entries(dir.path()), vec!["network.json"]);
+    }
+}
@@ -10,6 +10,19 @@ use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
+/// Where the agent records a completed bootstrap
+pub const BOOTSTRAP_STATE_PATH: &str = "/var/lib/keel/kubernetes/bootstrap.json";
+
+/// Directory the agent writes bootstrap files under (`kubernetes/...`)
+pub const BOOTSTRAP_BASE_PATH: &str = "/var/lib/keel";
+
+/// Kubeconfig kubelet writes after TLS bootstrap, pointing at its rotated
+/// client certificate (`system:node:<name>`)
+pub const KUBELET_KUBECONFIG_PATH: &str = "/var/lib/kubelet/kubeconfig";
+
+/// File the agent creates to ask keel-init to restart kubelet
+pub const KUBELET_RESTART_SIGNAL_PATH: &str = "/run/keel/restart-kubelet";
+
 #[derive(Error, Debug)]
 pub enum BootstrapError {
     #[error("IO error: {0}")]
@@ -57,7 +70,7 @@ impl BootstrapConfig {
     /// Save configuration to a JSON file
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BootstrapError> {
         let json = serde_json::to_string_pretty(self)?;
-        fs::write(path, json)?;
+        crate::atomic_write(path, json)?;
         Ok(())
     }
 
@@ -140,6 +153,35 @@ users:
     Ok(kubeconfig)
 }
 
+/// Check that a user-supplied kubeconfig parses and defines at least one
+/// cluster, context and user
+///
+/// Catches malformed files at bootstrap time instead of when kubelet fails
+/// to start with them.
+pub fn validate_kubeconfig(content: &str) -> Result<(), BootstrapError> {
+    let kubeconfig: serde_yaml::Value = serde_yaml::from_str(content).map_err(|e| {
+        BootstrapError::InvalidConfig(format!("kubeconfig is not valid YAML: {}", e))
+    })?;
+    if !kubeconfig.is_mapping() {
+        return Err(BootstrapError::InvalidConfig(
+            "kubeconfig must be a YAML mapping".to_string(),
+        ));
+    }
+    for section in ["clusters", "contexts", "users"] {
+        let entries = kubeconfig
+            .get(section)
+            .and_then(serde_yaml::Value::as_sequence)
+            .filter(|entries| !entries.is_empty());
+        if entries.is_none() {
+            return Err(BootstrapError::InvalidConfig(format!(
+                "kubeconfig has no {}",
+                section
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Prepare the Kubernetes directory structure
 ///
 /// Creates the necessary directories for bootstrap configuration:
@@ -254,6 +296,36 @@ mod tests {
         assert!(matches!(result, Err(BootstrapError::MissingField(_))));
     }
 
+    #[test]
+    fn test_validate_kubeconfig() {
+        let kubeconfig = generate_kubeconfig(
+            "https://k8s.example.com:6443",
+            "-----BEGIN CERTIFICATE-----\ntest\n-----END CERTIFICATE-----",
+            "abcdef.0123456789abcdef",
+            "node-01",
+        )
+        .unwrap();
+        assert!(validate_kubeconfig(&kubeconfig).is_ok());
+    }
+
+    #[test]
+    fn test_validate_kubeconfig_rejects_incomplete() {
+        let no_clusters = "apiVersion: v1\nkind: Config\nclusters: []\n\
+                           contexts:\n- name: c\n  context: {cluster: k, user: u}\n\
+                           users:\n- name: u\n  user: {token: t}\n";
+        let err = validate_kubeconfig(no_clusters).unwrap_err();
+        assert!(err.to_string().contains("no clusters"), "{}", err);
+
+        let no_users = "apiVersion: v1\nkind: Config\n\
+                        clusters:\n- name: k\n  cluster: {server: 'https://k8s:6443'}\n\
+                        contexts:\n- name: c\n  context: {cluster: k, user: u}\n";
+        let err = validate_kubeconfig(no_users).unwrap_err();
+        assert!(err.to_string().contains("no users"), "{}", err);
+
+        assert!(validate_kubeconfig("clusters: [unterminated").is_err());
+        assert!(validate_kubeconfig("just a string").is_err());
+    }
+
     #[test]
     fn test_prepare_k8s_directories() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,102 @@
+//! Maintenance mode marker shared by keel-init and keel-agent
+//!
+//! When keel-init hits a fatal error it keeps running as PID 1 in a
+//! maintenance loop. It records why in [`DEGRADED_STATE_PATH`] so the agent
+//! can report the node as degraded in `GetStatus`. The file lives on `/run`
+//! and disappears on reboot.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// Where keel-init records that it entered maintenance mode
+pub const DEGRADED_STATE_PATH: &str = "/run/keel/degraded.json";
+
+/// Why the node is in maintenance mode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DegradedState {
+    /// Error that stopped the boot
+    pub reason: String,
+    /// Boot phase that failed (e.g. `services`)
+    pub phase: String,
+    /// When maintenance mode was entered (RFC3339)
+    pub since: String,
+}
+
+impl DegradedState {
+    /// State for a failure in `phase`, timestamped now
+    pub fn new(reason: impl Into<String>, phase: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            phase: phase.into(),
+            since: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Write the state to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        crate::atomic_write(path, json)
+    }
+
+    /// State recorded at `path`, `None` if the node is not degraded
+    ///
+    /// An unreadable file still means keel-init gave up, so it is reported
+    /// as degraded with the parse error as the reason.
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => return Some(Self::unreadable(e)),
+        };
+        Some(serde_json::from_str(&content).unwrap_or_else(Self::unreadable))
+    }
+
+    fn unreadable(error: impl std::fmt::Display) -> Self {
+        Self {
+            reason: format!("degraded state unreadable: {}", error),
+            phase: String::new(),
+            since: String::new(),
+        }
+    }
+
+    /// One-line description for operators
+    pub fn describe(&self) -> String {
+        if self.phase.is_empty() {
+            self.reason.clone()
+        } else {
+            format!("{} (boot phase: {})", self.reason, self.phase)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degraded_state_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("keel/degraded.json");
+        assert_eq!(DegradedState::load(&path), None);
+
+        let state = DegradedState::new("Mount error: /proc", "filesystem");
+        state.save(&path).unwrap();
+        assert_eq!(DegradedState::load(&path), Some(state.clone()));
+        assert_eq!(
+            state.describe(),
+            "Mount error: /proc (boot phase: filesystem)"
+        );
+    }
+
+    #[test]
+    fn test_corrupt_degraded_state_still_degraded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("degraded.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let state = DegradedState::load(&path).unwrap();
+        assert!(state.reason.contains("unreadable"), "{}", state.reason);
+    }
+}
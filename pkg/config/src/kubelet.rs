@@ -0,0 +1,149 @@
+//! Extra kubelet flags from `NodeConfig.kubernetes.kubelet_args`
+//!
+//! keel-init builds the kubelet command line itself. Operators can add
+//! node-specific flags (`--v=4`, `--max-pods=250`, ...), which are appended
+//! to the managed ones; a flag given again replaces the earlier value.
+//! Flags that point kubelet at its config, credentials or certificates stay
+//! under keel-init's control and are rejected.
+
+/// Flags keel-init sets itself and operators may not override
+pub const MANAGED_KUBELET_FLAGS: &[&str] = &[
+    "--config",
+    "--kubeconfig",
+    "--bootstrap-kubeconfig",
+    "--cert-dir",
+];
+
+/// Flag name of an argument (`--v=2` → `--v`)
+fn flag_name(arg: &str) -> &str {
+    arg.split_once('=').map_or(arg, |(name, _)| name)
+}
+
+/// Check operator supplied kubelet flags
+///
+/// Every entry must be a single `--flag` or `--flag=value`; values cannot be
+/// passed as a separate entry, so each entry is unambiguous on its own.
+pub fn validate_kubelet_args(args: &[String]) -> Result<(), String> {
+    for arg in args {
+        let name = flag_name(arg);
+        if !name.starts_with("--") || name.len() == 2 || name.contains(char::is_whitespace) {
+            return Err(format!(
+                "kubelet arg '{}' must be of the form --flag or --flag=value",
+                arg
+            ));
+        }
+        if MANAGED_KUBELET_FLAGS.contains(&name) {
+            return Err(format!(
+                "kubelet arg '{}' conflicts with the {} flag managed by KeelOS",
+                arg, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `--register-with-taints` for `NodeConfig.kubernetes.node_taints`
+///
+/// NodeRestriction does not let kubelet change its Node's taints after
+/// registration, so configured taints are registered with the Node instead
+/// of being patched on later.
+pub fn register_with_taints_arg(taints: &[String]) -> Option<String> {
+    if taints.is_empty() {
+        None
+    } else {
+        Some(format!("--register-with-taints={}", taints.join(",")))
+    }
+}
+
+/// Append `extra` to the managed `base` flags
+///
+/// A flag that is already present is replaced in place, so the last value
+/// wins and kubelet never sees the same flag twice.
+pub fn merge_kubelet_args(base: &[String], extra: &[String]) -> Result<Vec<String>, String> {
+    validate_kubelet_args(extra)?;
+
+    let mut merged: Vec<String> = Vec::with_capacity(base.len() + extra.len());
+    for arg in base.iter().chain(extra) {
+        match merged
+            .iter_mut()
+            .find(|existing| flag_name(existing) == flag_name(arg))
+        {
+            Some(existing) => *existing = arg.clone(),
+            None => merged.push(arg.clone()),
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_appends_and_overrides() {
+        let base = args(&[
+            "--config=/etc/kubernetes/kubelet-config.yaml",
+            "--cert-dir=/var/lib/kubelet/pki",
+            "--v=2",
+        ]);
+        let merged =
+            merge_kubelet_args(&base, &args(&["--v=4", "--max-pods=250", "--fail-swap-on"]))
+                .unwrap();
+        assert_eq!(
+            merged,
+            args(&[
+                "--config=/etc/kubernetes/kubelet-config.yaml",
+                "--cert-dir=/var/lib/kubelet/pki",
+                "--v=4",
+                "--max-pods=250",
+                "--fail-swap-on",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_merge_deduplicates_extra_args() {
+        let merged = merge_kubelet_args(
+            &args(&["--v=2"]),
+            &args(&["--max-pods=110", "--max-pods=250", "--v=2"]),
+        )
+        .unwrap();
+        assert_eq!(merged, args(&["--v=2", "--max-pods=250"]));
+    }
+
+    #[test]
+    fn test_managed_flags_rejected() {
+        for arg in [
+            "--config=/tmp/other.yaml",
+            "--kubeconfig=/tmp/admin.conf",
+            "--bootstrap-kubeconfig",
+            "--cert-dir=/tmp",
+        ] {
+            let err = merge_kubelet_args(&[], &args(&[arg])).unwrap_err();
+            assert!(err.contains("managed by KeelOS"), "{err}");
+        }
+        // Flags that merely share a prefix are fine
+        assert!(validate_kubelet_args(&args(&["--config-dir=/etc/kubelet.d"])).is_ok());
+    }
+
+    #[test]
+    fn test_register_with_taints_arg() {
+        assert_eq!(register_with_taints_arg(&[]), None);
+        assert_eq!(
+            register_with_taints_arg(&args(&["dedicated=edge:NoSchedule", "gpu:NoExecute"]))
+                .unwrap(),
+            "--register-with-taints=dedicated=edge:NoSchedule,gpu:NoExecute"
+        );
+    }
+
+    #[test]
+    fn test_malformed_args_rejected() {
+        for arg in ["v=4", "-v=4", "--", "--max-pods 250", "250"] {
+            assert!(validate_kubelet_args(&args(&[arg])).is_err(), "{arg}");
+        }
+    }
+}
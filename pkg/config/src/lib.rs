@@ -1,10 +1,18 @@
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+pub mod atomic;
 pub mod bootstrap;
+pub mod degraded;
+pub mod kubelet;
+pub mod logging;
 pub mod network;
+pub mod node_id;
+pub mod service_stop;
+
+pub use atomic::{atomic_write, atomic_write_private};
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -12,6 +20,8 @@ pub enum ConfigError {
     Io(#[from] std::io::Error),
     #[error("YAML error: {0}")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("Validation error: {0}")]
+    Validation(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,11 +31,350 @@ pub struct NodeConfig {
     #[serde(default)]
     pub kubernetes: KubernetesConfig,
     pub containers: Vec<ContainerConfig>,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub update: UpdateConfig,
+    #[serde(default)]
+    pub time: TimeConfig,
+    #[serde(default)]
+    pub containerd: ContainerdConfig,
+    #[serde(default)]
+    pub tls_policy: TlsPolicyConfig,
+    #[serde(default)]
+    pub ip_conflict: IpConflictConfig,
+    /// OS image the node converges to on its own (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desired_image: Option<DesiredImageConfig>,
+    /// Health checks the agent registers; when unset the agent registers
+    /// `boot`, `network` and `api` with their defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_checks: Option<HealthChecksConfig>,
+    /// Accept `InitBootstrap`, which clients call without a certificate
+    /// (default: true); hardened deployments provision certificates
+    /// out of band and turn this off
+    #[serde(default = "default_true")]
+    pub allow_insecure_bootstrap: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct KubernetesConfig {
     pub version: Option<String>,
+    /// Labels applied to the Node object after bootstrap (`key=value`)
+    #[serde(default)]
+    pub node_labels: Vec<String>,
+    /// Taints applied to the Node object after bootstrap (`key[=value]:Effect`)
+    #[serde(default)]
+    pub node_taints: Vec<String>,
+    /// Seconds to wait for the operational certificate CSR to be signed
+    /// (agent default: 300)
+    pub csr_max_wait_secs: Option<u64>,
+    /// Uncordon the Node once it boots healthy after an update (default: off)
+    pub auto_uncordon: Option<UncordonConfig>,
+    /// Extra kubelet flags (`--flag=value`) appended to the managed ones
+    #[serde(default)]
+    pub kubelet_args: Vec<String>,
+}
+
+/// Readiness gate for uncordoning after an update
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UncordonConfig {
+    /// Label selector for the DaemonSet pods on this node (CNI, kube-proxy,
+    /// ...) that must be `Ready` before uncordoning; unset uncordons at once
+    pub ready_selector: Option<String>,
+    /// Seconds to wait for those pods before leaving the node cordoned
+    /// (agent default: 300)
+    pub ready_timeout_secs: Option<u64>,
+}
+
+/// Settings for the agent's update schedule executor
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SchedulerConfig {
+    /// Maximum seconds between due-schedule checks (agent default: 30)
+    pub poll_interval_secs: Option<u64>,
+    /// Re-queue updates that were running when the agent restarted instead
+    /// of failing them (default: false)
+    #[serde(default)]
+    pub resume_on_restart: bool,
+    /// Most schedules that may be pending or running at once; further
+    /// `ScheduleUpdate` calls are rejected (agent default: 100)
+    pub max_in_flight: Option<usize>,
+    /// Keep only this many completed, failed or cancelled schedules, dropping
+    /// older ones when a schedule is created (default: keep all)
+    pub keep_finished: Option<usize>,
+}
+
+/// Settings for how OS updates are written and booted
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UpdateConfig {
+    /// Root partition slots updates rotate through (default: `[2, 3]`)
+    #[serde(default)]
+    pub slots: Slots,
+    /// Cap on image download speed in bytes per second so updates do not
+    /// saturate a shared uplink (default: unlimited)
+    pub max_bytes_per_sec: Option<u64>,
+    /// Bytes of a downloaded image buffered before each write to the target
+    /// partition, rounded up to the device's optimal I/O size
+    /// (agent default: 1 MiB)
+    pub write_block_bytes: Option<u64>,
+    /// Scratch directory for pre-staged images and delta downloads
+    /// (agent default: `/var/lib/keel/staging`)
+    pub staging_dir: Option<String>,
+    /// How the agent reboots into a new slot (default: reboot)
+    #[serde(default)]
+    pub reboot_method: RebootMethod,
+    /// GPT attributes the bootloader picks the slot by (default: legacy_bios)
+    #[serde(default)]
+    pub boot_flag_scheme: BootFlagScheme,
+    /// Re-hash the booted slot against its flashed marker this often; off
+    /// when unset, since it reads the whole image
+    pub verify_interval_secs: Option<u64>,
+}
+
+/// Smallest accepted `update.write_block_bytes`
+pub const MIN_WRITE_BLOCK_BYTES: u64 = 4096;
+
+/// Largest accepted `update.write_block_bytes`
+pub const MAX_WRITE_BLOCK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How the boot slot is marked in the GPT partition attributes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BootFlagScheme {
+    /// Attribute bit 2 ("legacy BIOS bootable") set on the boot slot only
+    #[default]
+    LegacyBios,
+    /// ChromeOS-style priority in attribute bits 48-51: the boot slot gets
+    /// the highest priority and every other slot's priority drops by one
+    PriorityBits,
+}
+
+/// How the agent restarts the node
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RebootMethod {
+    /// Full reboot through firmware and bootloader
+    #[default]
+    Reboot,
+    /// Load the next slot's kernel with kexec, falling back to a full
+    /// reboot if that fails
+    Kexec,
+}
+
+/// Settings for clock synchronisation checks
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TimeConfig {
+    /// NTP/SNTP server (`host` or `host:port`) the clock-skew health check
+    /// compares against; the check is disabled when unset
+    pub ntp_server: Option<String>,
+    /// Largest tolerated offset from the NTP server (agent default: 30)
+    pub max_clock_skew_secs: Option<u64>,
+}
+
+/// Settings for how keel-init supervises containerd
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ContainerdConfig {
+    /// Longest backoff between containerd restarts (init default: 60)
+    pub max_restart_delay_secs: Option<u64>,
+    /// Crashes within `crash_loop_window_secs` after which keel-init stops
+    /// respawning containerd and enters maintenance mode (init default: 5)
+    pub crash_loop_threshold: Option<u32>,
+    /// Window the crash-loop threshold is counted over (init default: 300)
+    pub crash_loop_window_secs: Option<u64>,
+}
+
+/// TLS versions and cipher suites the agent's gRPC server accepts
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TlsPolicyConfig {
+    /// Lowest TLS version, `"1.2"` or `"1.3"` (agent default: `"1.2"`)
+    pub min_version: Option<String>,
+    /// IANA cipher suite names to allow, e.g. `TLS13_AES_256_GCM_SHA384`
+    /// (default: every suite the TLS library supports)
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+}
+
+/// Health checks to register and how updates wait for them
+///
+/// Only the checks listed here run; an empty mapping (`network: {}`)
+/// enables a check with its defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct HealthChecksConfig {
+    /// System uptime check (critical)
+    pub boot: Option<BootCheckConfig>,
+    /// Network interface check
+    pub network: Option<NetworkCheckConfig>,
+    /// Local gRPC API check (critical)
+    pub api: Option<ApiCheckConfig>,
+    /// Processes that must be running, checked by exact name (critical)
+    #[serde(default)]
+    pub services: Vec<String>,
+    /// Seconds to wait for checks to pass after an update (agent default: 300)
+    pub timeout_secs: Option<u32>,
+    /// Seconds between check runs while waiting (agent default: 10)
+    pub retry_interval_secs: Option<u32>,
+    /// Check runs before giving up (agent default: 30)
+    pub max_retries: Option<u32>,
+}
+
+/// Parameters of the `boot` health check
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct BootCheckConfig {
+    /// Uptime below which the node is not considered booted (agent default: 10)
+    pub min_uptime_secs: Option<u64>,
+}
+
+/// Parameters of the `network` health check
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NetworkCheckConfig {
+    /// Treat a failure as critical, triggering rollback (default: false)
+    #[serde(default)]
+    pub critical: bool,
+}
+
+/// Parameters of the `api` health check
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ApiCheckConfig {
+    /// Local port that must accept connections (agent default: 50051)
+    pub port: Option<u16>,
+}
+
+/// OS image the agent schedules an update to whenever the running image
+/// differs from it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DesiredImageConfig {
+    /// URL of the SquashFS image
+    pub url: String,
+    /// SHA256 of the image, compared with the hash recorded for the booted slot
+    pub sha256: String,
+    /// Daily maintenance window start, `HH:MM` in UTC; unset updates right away
+    pub maintenance_window_start: Option<String>,
+    /// Length of the maintenance window (agent default: 3600)
+    pub maintenance_window_secs: Option<u32>,
+}
+
+/// `HH:MM` as hours and minutes
+pub fn parse_time_of_day(value: &str) -> Option<(u32, u32)> {
+    let (hours, minutes) = value.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some((hours, minutes))
+}
+
+/// ARP probe keel-init runs before assigning a static IPv4 address
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct IpConflictConfig {
+    /// What to do when another host answers for the address (default: off)
+    #[serde(default)]
+    pub action: IpConflictAction,
+    /// Seconds to wait for ARP replies (init default: 1)
+    pub probe_timeout_secs: Option<u64>,
+}
+
+/// Response to a static IPv4 address that is already in use
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpConflictAction {
+    /// Do not probe
+    #[default]
+    Off,
+    /// Log a warning and assign the address anyway
+    Warn,
+    /// Log an error and leave the address unassigned
+    Abort,
+}
+
+/// TLS versions accepted in `tls_policy.min_version`
+pub const TLS_MIN_VERSIONS: &[&str] = &["1.2", "1.3"];
+
+/// Partition indices of the root filesystem slots, in rotation order
+///
+/// The classic A/B layout is `[2, 3]`; staged-rollout layouts can add a
+/// third slot (`[2, 3, 4]`) so the two previous versions stay bootable.
+/// Deserializing goes through [`Slots::new`], so a list that fails its
+/// checks never makes it into a [`NodeConfig`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "Vec<u32>", into = "Vec<u32>")]
+pub struct Slots(Vec<u32>);
+
+impl TryFrom<Vec<u32>> for Slots {
+    type Error = ConfigError;
+
+    fn try_from(indices: Vec<u32>) -> Result<Self, Self::Error> {
+        Self::new(indices)
+    }
+}
+
+impl From<Slots> for Vec<u32> {
+    fn from(slots: Slots) -> Self {
+        slots.0
+    }
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Self(vec![2, 3])
+    }
+}
+
+impl Slots {
+    /// Build a validated slot list
+    pub fn new(indices: Vec<u32>) -> Result<Self, ConfigError> {
+        let slots = Self(indices);
+        slots.validate()?;
+        Ok(slots)
+    }
+
+    /// Partition indices in rotation order
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// The slot an update should be written to when `active` is booted
+    ///
+    /// Rotates to the slot after `active`, wrapping around. If `active` is
+    /// not one of the slots, the first slot is used. `None` only for an
+    /// empty list, which [`Slots::new`] does not allow.
+    pub fn next_inactive(&self, active: u32) -> Option<u32> {
+        match self.0.iter().position(|&i| i == active) {
+            Some(pos) => self.0.get((pos + 1) % self.0.len()).copied(),
+            None => self.0.first().copied(),
+        }
+    }
+
+    /// Every slot except `target`
+    pub fn others(&self, target: u32) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().copied().filter(move |&i| i != target)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.0.len() < 2 {
+            return Err(ConfigError::Validation(
+                "update.slots needs at least two partitions".into(),
+            ));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for &index in &self.0 {
+            if index == 0 {
+                return Err(ConfigError::Validation(
+                    "update.slots partition indices start at 1".into(),
+                ));
+            }
+            if !seen.insert(index) {
+                return Err(ConfigError::Validation(format!(
+                    "duplicate partition {} in update.slots",
+                    index
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -41,13 +390,200 @@ impl NodeConfig {
         Ok(config)
     }
 
+    /// Load a base config with overlays deep-merged on top, in order
+    ///
+    /// Every YAML document in every file (files may hold several documents
+    /// separated by `---`) is merged into the previous ones before
+    /// deserializing: mappings merge key by key, lists of named entries such
+    /// as `containers` merge by `name`, and any other value is replaced by
+    /// the later document.
+    pub fn load_layered(paths: &[PathBuf]) -> Result<Self, ConfigError> {
+        let mut merged = serde_yaml::Value::Null;
+        for path in paths {
+            let content = std::fs::read_to_string(path)?;
+            for document in serde_yaml::Deserializer::from_str(&content) {
+                let layer = serde_yaml::Value::deserialize(document)?;
+                merge_yaml(&mut merged, layer);
+            }
+        }
+        Ok(serde_yaml::from_value(merged)?)
+    }
+
+    /// Check the semantic constraints that YAML parsing alone cannot enforce
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.version.trim().is_empty() {
+            return Err(ConfigError::Validation("version must not be empty".into()));
+        }
+        if self.hostname.trim().is_empty() {
+            return Err(ConfigError::Validation("hostname must not be empty".into()));
+        }
+        if self.scheduler.poll_interval_secs == Some(0) {
+            return Err(ConfigError::Validation(
+                "scheduler.poll_interval_secs must be greater than 0".into(),
+            ));
+        }
+        if self.scheduler.max_in_flight == Some(0) {
+            return Err(ConfigError::Validation(
+                "scheduler.max_in_flight must be greater than 0".into(),
+            ));
+        }
+
+        if self.containerd.crash_loop_threshold == Some(0) {
+            return Err(ConfigError::Validation(
+                "containerd.crash_loop_threshold must be greater than 0".into(),
+            ));
+        }
+
+        if let Some(desired) = &self.desired_image {
+            if desired.url.trim().is_empty() {
+                return Err(ConfigError::Validation(
+                    "desired_image.url must not be empty".into(),
+                ));
+            }
+            if desired.sha256.len() != 64 || !desired.sha256.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(ConfigError::Validation(
+                    "desired_image.sha256 must be a SHA256 hex digest".into(),
+                ));
+            }
+            if let Some(start) = &desired.maintenance_window_start {
+                if parse_time_of_day(start).is_none() {
+                    return Err(ConfigError::Validation(format!(
+                        "desired_image.maintenance_window_start '{}' is not HH:MM",
+                        start
+                    )));
+                }
+            }
+            if desired.maintenance_window_secs == Some(0) {
+                return Err(ConfigError::Validation(
+                    "desired_image.maintenance_window_secs must be greater than 0".into(),
+                ));
+            }
+        }
+
+        if let Some(checks) = &self.health_checks {
+            if checks.api.as_ref().is_some_and(|api| api.port == Some(0)) {
+                return Err(ConfigError::Validation(
+                    "health_checks.api.port must not be 0".into(),
+                ));
+            }
+            if checks.services.iter().any(|s| s.trim().is_empty()) {
+                return Err(ConfigError::Validation(
+                    "health_checks.services entries must not be empty".into(),
+                ));
+            }
+            if checks.retry_interval_secs == Some(0) {
+                return Err(ConfigError::Validation(
+                    "health_checks.retry_interval_secs must be greater than 0".into(),
+                ));
+            }
+        }
+
+        if self.ip_conflict.probe_timeout_secs == Some(0) {
+            return Err(ConfigError::Validation(
+                "ip_conflict.probe_timeout_secs must be greater than 0".into(),
+            ));
+        }
+
+        if let Some(version) = &self.tls_policy.min_version {
+            if !TLS_MIN_VERSIONS.contains(&version.as_str()) {
+                return Err(ConfigError::Validation(format!(
+                    "tls_policy.min_version '{}' is not supported (use 1.2 or 1.3)",
+                    version
+                )));
+            }
+        }
+
+        if self.update.verify_interval_secs == Some(0) {
+            return Err(ConfigError::Validation(
+                "update.verify_interval_secs must be greater than 0".into(),
+            ));
+        }
+        if let Some(bytes) = self.update.write_block_bytes {
+            if !(MIN_WRITE_BLOCK_BYTES..=MAX_WRITE_BLOCK_BYTES).contains(&bytes) {
+                return Err(ConfigError::Validation(format!(
+                    "update.write_block_bytes must be between {} and {}",
+                    MIN_WRITE_BLOCK_BYTES, MAX_WRITE_BLOCK_BYTES
+                )));
+            }
+        }
+        kubelet::validate_kubelet_args(&self.kubernetes.kubelet_args)
+            .map_err(ConfigError::Validation)?;
+
+        let mut names = std::collections::HashSet::new();
+        for container in &self.containers {
+            if container.name.trim().is_empty() {
+                return Err(ConfigError::Validation(
+                    "container name must not be empty".into(),
+                ));
+            }
+            if container.image.trim().is_empty() {
+                return Err(ConfigError::Validation(format!(
+                    "container '{}' has no image",
+                    container.name
+                )));
+            }
+            if !names.insert(container.name.as_str()) {
+                return Err(ConfigError::Validation(format!(
+                    "duplicate container name '{}'",
+                    container.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn default_config() -> Self {
         Self {
             version: "v1".to_string(),
             hostname: "keel-node".to_string(),
             kubernetes: KubernetesConfig::default(),
             containers: vec![],
+            scheduler: SchedulerConfig::default(),
+            update: UpdateConfig::default(),
+            time: TimeConfig::default(),
+            containerd: ContainerdConfig::default(),
+            tls_policy: TlsPolicyConfig::default(),
+            ip_conflict: IpConflictConfig::default(),
+            desired_image: None,
+            health_checks: None,
+            allow_insecure_bootstrap: true,
+        }
+    }
+}
+
+/// Deep-merge `overlay` into `base`
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    use serde_yaml::Value;
+
+    match (base, overlay) {
+        // Empty documents leave the base untouched
+        (_, Value::Null) => {}
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
         }
+        (Value::Sequence(base), Value::Sequence(overlay))
+            if base
+                .iter()
+                .chain(overlay.iter())
+                .all(|v| v.get("name").is_some()) =>
+        {
+            for item in overlay {
+                match base.iter_mut().find(|b| b.get("name") == item.get("name")) {
+                    Some(existing) => merge_yaml(existing, item),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
     }
 }
 
@@ -99,6 +635,36 @@ containers: []
         let config = NodeConfig::load(file.path()).unwrap();
         assert_eq!(config.hostname, "k8s-node");
         assert_eq!(config.kubernetes.version, Some("1.29.0".to_string()));
+        assert!(config.kubernetes.node_labels.is_empty());
+        assert!(config.kubernetes.node_taints.is_empty());
+        assert!(config.allow_insecure_bootstrap);
+    }
+
+    #[test]
+    fn test_config_with_node_labels_and_taints() {
+        let yaml = r#"
+version: v1
+hostname: k8s-node
+kubernetes:
+  node_labels:
+    - example.com/role=edge
+    - topology.kubernetes.io/zone=eu-1a
+  node_taints:
+    - dedicated=edge:NoSchedule
+containers: []
+"#;
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", yaml).unwrap();
+
+        let config = NodeConfig::load(file.path()).unwrap();
+        assert_eq!(
+            config.kubernetes.node_labels,
+            vec!["example.com/role=edge", "topology.kubernetes.io/zone=eu-1a"]
+        );
+        assert_eq!(
+            config.kubernetes.node_taints,
+            vec!["dedicated=edge:NoSchedule"]
+        );
     }
 
     #[test]
@@ -108,11 +674,25 @@ containers: []
             hostname: "test-node".to_string(),
             kubernetes: KubernetesConfig {
                 version: Some("1.28.0".to_string()),
+                node_labels: vec!["topology.kubernetes.io/zone=eu-1a".to_string()],
+                node_taints: vec![],
+                csr_max_wait_secs: None,
+                auto_uncordon: None,
+                kubelet_args: vec!["--v=4".to_string()],
             },
             containers: vec![ContainerConfig {
                 name: "nginx".to_string(),
                 image: "nginx:latest".to_string(),
             }],
+            scheduler: SchedulerConfig::default(),
+            update: UpdateConfig::default(),
+            time: TimeConfig::default(),
+            containerd: ContainerdConfig::default(),
+            tls_policy: TlsPolicyConfig::default(),
+            ip_conflict: IpConflictConfig::default(),
+            desired_image: None,
+            health_checks: None,
+            allow_insecure_bootstrap: true,
         };
 
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -137,4 +717,278 @@ containers: []
         let result = NodeConfig::load(file.path());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_validate_rejects_duplicate_containers() {
+        let mut config = NodeConfig::default_config();
+        let container = ContainerConfig {
+            name: "app".to_string(),
+            image: "alpine:latest".to_string(),
+        };
+        config.containers = vec![container.clone(), container];
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_managed_kubelet_args() {
+        let mut config = NodeConfig::default_config();
+        config.kubernetes.kubelet_args = vec!["--v=4".to_string()];
+        assert!(config.validate().is_ok());
+
+        config.kubernetes.kubelet_args = vec!["--kubeconfig=/root/admin.conf".to_string()];
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_crash_loop_threshold() {
+        let mut config = NodeConfig::default_config();
+        config.containerd.crash_loop_threshold = Some(0);
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_health_checks_parse_and_validate() {
+        let yaml = "version: v1\nhostname: node\ncontainers: []\nhealth_checks:\n  boot:\n    min_uptime_secs: 30\n  network: {}\n  services: [kubelet]\n";
+        let mut config: NodeConfig = serde_yaml::from_str(yaml).unwrap();
+        let checks = config.health_checks.clone().unwrap();
+        assert_eq!(checks.boot.unwrap().min_uptime_secs, Some(30));
+        assert!(!checks.network.unwrap().critical);
+        assert!(checks.api.is_none());
+        assert_eq!(checks.services, ["kubelet"]);
+        assert!(config.validate().is_ok());
+
+        config.health_checks.as_mut().unwrap().api = Some(ApiCheckConfig { port: Some(0) });
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_max_in_flight() {
+        let mut config = NodeConfig::default_config();
+        config.scheduler.max_in_flight = Some(0);
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_desired_image() {
+        let mut config = NodeConfig::default_config();
+        config.desired_image = Some(DesiredImageConfig {
+            url: "https://images.example.com/keel-1.4.0.squashfs".to_string(),
+            sha256: "ab".repeat(32),
+            maintenance_window_start: Some("02:30".to_string()),
+            maintenance_window_secs: Some(7200),
+        });
+        assert!(config.validate().is_ok());
+
+        let desired = config.desired_image.as_mut().unwrap();
+        desired.maintenance_window_start = Some("25:00".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+
+        let desired = config.desired_image.as_mut().unwrap();
+        desired.maintenance_window_start = None;
+        desired.sha256 = "abc".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+
+        assert_eq!(parse_time_of_day("00:00"), Some((0, 0)));
+        assert_eq!(parse_time_of_day("23:59"), Some((23, 59)));
+        assert_eq!(parse_time_of_day("2:30"), None);
+    }
+
+    #[test]
+    fn test_reboot_method_parsing() {
+        let yaml = "version: v1\nhostname: n\ncontainers: []\nupdate:\n  reboot_method: kexec\n";
+        let config: NodeConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.update.reboot_method, RebootMethod::Kexec);
+        assert_eq!(
+            NodeConfig::default_config().update.reboot_method,
+            RebootMethod::Reboot
+        );
+    }
+
+    #[test]
+    fn test_boot_flag_scheme_parsing() {
+        let yaml =
+            "version: v1\nhostname: n\ncontainers: []\nupdate:\n  boot_flag_scheme: priority_bits\n";
+        let config: NodeConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.update.boot_flag_scheme, BootFlagScheme::PriorityBits);
+        assert_eq!(
+            NodeConfig::default_config().update.boot_flag_scheme,
+            BootFlagScheme::LegacyBios
+        );
+    }
+
+    #[test]
+    fn test_validate_write_block_bytes() {
+        let mut config = NodeConfig::default_config();
+        config.update.write_block_bytes = Some(4 * 1024 * 1024);
+        assert!(config.validate().is_ok());
+
+        for bytes in [0, 512, MAX_WRITE_BLOCK_BYTES + 1] {
+            config.update.write_block_bytes = Some(bytes);
+            assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn test_validate_verify_interval() {
+        let mut config = NodeConfig::default_config();
+        config.update.verify_interval_secs = Some(86400);
+        assert!(config.validate().is_ok());
+
+        config.update.verify_interval_secs = Some(0);
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_ip_conflict_action_parsing() {
+        let yaml = "version: v1\nhostname: n\ncontainers: []\nip_conflict:\n  action: abort\n";
+        let config: NodeConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.ip_conflict.action, IpConflictAction::Abort);
+        assert_eq!(
+            NodeConfig::default_config().ip_conflict.action,
+            IpConflictAction::Off
+        );
+
+        let mut config = NodeConfig::default_config();
+        config.ip_conflict.probe_timeout_secs = Some(0);
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_old_tls_versions() {
+        let mut config = NodeConfig::default_config();
+        config.tls_policy.min_version = Some("1.3".to_string());
+        assert!(config.validate().is_ok());
+
+        config.tls_policy.min_version = Some("1.0".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Validation(_))));
+    }
+
+    #[test]
+    fn test_slots_two_slot_default() {
+        let slots = Slots::default();
+        assert_eq!(slots.indices(), &[2, 3]);
+        assert_eq!(slots.next_inactive(2), Some(3));
+        assert_eq!(slots.next_inactive(3), Some(2));
+        // Booted from something that is not a slot: start at the first one
+        assert_eq!(slots.next_inactive(7), Some(2));
+    }
+
+    #[test]
+    fn test_slots_three_slot_rotation() {
+        let slots = Slots::new(vec![2, 3, 4]).unwrap();
+        assert_eq!(slots.next_inactive(2), Some(3));
+        assert_eq!(slots.next_inactive(3), Some(4));
+        assert_eq!(slots.next_inactive(4), Some(2));
+        assert_eq!(slots.others(3).collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_slots_validation() {
+        assert!(Slots::new(vec![2]).is_err());
+        assert!(Slots::new(vec![2, 2]).is_err());
+        assert!(Slots::new(vec![0, 2]).is_err());
+
+        let yaml = "version: v1\nhostname: n\ncontainers: []\nupdate:\n  slots: [2, 3, 4]\n";
+        let config: NodeConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.update.slots.indices(), &[2, 3, 4]);
+        assert!(config.validate().is_ok());
+
+        // Invalid lists are rejected while parsing, including the empty one
+        for slots in ["[2]", "[]", "[2, 2]"] {
+            let yaml = format!(
+                "version: v1\nhostname: n\ncontainers: []\nupdate:\n  slots: {}\n",
+                slots
+            );
+            let err = serde_yaml::from_str::<NodeConfig>(&yaml).unwrap_err();
+            assert!(err.to_string().contains("update.slots"), "{}", err);
+        }
+
+        // Serialized as the plain list
+        let yaml = serde_yaml::to_string(&Slots::new(vec![2, 3, 4]).unwrap()).unwrap();
+        assert_eq!(serde_yaml::from_str::<Vec<u32>>(&yaml).unwrap(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_validate_default_config() {
+        assert!(NodeConfig::default_config().validate().is_ok());
+    }
+
+    fn yaml_file(content: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", content).unwrap();
+        file
+    }
+
+    const BASE: &str = r#"
+version: v1
+hostname: keel-base
+kubernetes:
+  version: "1.29.0"
+containers:
+  - name: exporter
+    image: prom/node-exporter:v1.7.0
+  - name: logger
+    image: fluent-bit:2
+"#;
+
+    #[test]
+    fn test_load_layered_scalar_override() {
+        let base = yaml_file(BASE);
+        let overlay = yaml_file("hostname: keel-prod-01\nscheduler:\n  poll_interval_secs: 10\n");
+
+        let config =
+            NodeConfig::load_layered(&[base.path().into(), overlay.path().into()]).unwrap();
+        assert_eq!(config.hostname, "keel-prod-01");
+        assert_eq!(config.version, "v1");
+        // Untouched nested keys survive
+        assert_eq!(config.kubernetes.version, Some("1.29.0".to_string()));
+        assert_eq!(config.scheduler.poll_interval_secs, Some(10));
+    }
+
+    #[test]
+    fn test_load_layered_containers_merge_by_name() {
+        let base = yaml_file(BASE);
+        let overlay = yaml_file(
+            "containers:\n  - name: exporter\n    image: prom/node-exporter:v1.8.0\n  - name: tracer\n    image: otel/collector:0.100\n",
+        );
+
+        let config =
+            NodeConfig::load_layered(&[base.path().into(), overlay.path().into()]).unwrap();
+        let containers: Vec<(&str, &str)> = config
+            .containers
+            .iter()
+            .map(|c| (c.name.as_str(), c.image.as_str()))
+            .collect();
+        assert_eq!(
+            containers,
+            vec![
+                ("exporter", "prom/node-exporter:v1.8.0"),
+                ("logger", "fluent-bit:2"),
+                ("tracer", "otel/collector:0.100"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_layered_multi_document_file() {
+        let file = yaml_file(&format!(
+            "{}---\nhostname: keel-staging\n---\ncontainers:\n  - name: debug\n    image: busybox\n",
+            BASE
+        ));
+
+        let config = NodeConfig::load_layered(&[file.path().into()]).unwrap();
+        assert_eq!(config.hostname, "keel-staging");
+        assert_eq!(config.containers.len(), 3);
+        assert_eq!(config.containers[2].name, "debug");
+    }
+
+    #[test]
+    fn test_load_layered_missing_file() {
+        let base = yaml_file(BASE);
+        let result = NodeConfig::load_layered(&[
+            base.path().into(),
+            PathBuf::from("/nonexistent/overlay.yaml"),
+        ]);
+        assert!(matches!(result, Err(ConfigError::Io(_))));
+    }
 }
@@ -0,0 +1,125 @@
+//! Log level and format selection for keel-init and keel-agent
+//!
+//! Both binaries read the same environment variables. For keel-init these
+//! come from the kernel command line (e.g. `KEEL_LOG_FORMAT=json`), which
+//! the kernel passes to PID 1 as its environment.
+
+/// Log filter (`debug`, or a directive like `info,keel_agent=debug`)
+pub const LOG_LEVEL_ENV: &str = "KEEL_LOG_LEVEL";
+
+/// Log format: `text` (default) or `json`
+pub const LOG_FORMAT_ENV: &str = "KEEL_LOG_FORMAT";
+
+/// Filter used when neither `KEEL_LOG_LEVEL` nor `RUST_LOG` is set
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// How log lines are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Compact human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with span and event fields as keys
+    Json,
+}
+
+/// Log settings for a binary's tracing subscriber
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSettings {
+    /// `EnvFilter` directive string
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl LogSettings {
+    /// Settings from the given variable values
+    ///
+    /// `KEEL_LOG_LEVEL` takes precedence over `RUST_LOG`; empty values count
+    /// as unset. An unknown format falls back to text.
+    pub fn from_values<'a>(
+        rust_log: Option<&'a str>,
+        keel_log_level: Option<&'a str>,
+        keel_log_format: Option<&str>,
+    ) -> Self {
+        let set = |value: Option<&'a str>| value.map(str::trim).filter(|v| !v.is_empty());
+        let filter = set(keel_log_level)
+            .or(set(rust_log))
+            .unwrap_or(DEFAULT_LOG_LEVEL)
+            .to_string();
+
+        let format = match keel_log_format.map(|f| f.trim().to_ascii_lowercase()) {
+            Some(f) if f == "json" => LogFormat::Json,
+            _ => LogFormat::Text,
+        };
+
+        Self { filter, format }
+    }
+
+    /// Settings from `RUST_LOG`, `KEEL_LOG_LEVEL` and `KEEL_LOG_FORMAT`
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        Self::from_values(
+            var("RUST_LOG").as_deref(),
+            var(LOG_LEVEL_ENV).as_deref(),
+            var(LOG_FORMAT_ENV).as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_settings_defaults() {
+        assert_eq!(
+            LogSettings::from_values(None, None, None),
+            LogSettings {
+                filter: "info".to_string(),
+                format: LogFormat::Text,
+            }
+        );
+        // Empty values are treated as unset
+        assert_eq!(
+            LogSettings::from_values(Some(""), Some("  "), Some("")),
+            LogSettings::from_values(None, None, None)
+        );
+    }
+
+    #[test]
+    fn test_log_settings_level_precedence() {
+        assert_eq!(
+            LogSettings::from_values(Some("warn,keel_agent=debug"), None, None).filter,
+            "warn,keel_agent=debug"
+        );
+        assert_eq!(
+            LogSettings::from_values(Some("warn"), Some("debug"), None).filter,
+            "debug"
+        );
+        // An empty KEEL_LOG_LEVEL does not hide RUST_LOG
+        assert_eq!(
+            LogSettings::from_values(Some("warn"), Some(" "), None).filter,
+            "warn"
+        );
+    }
+
+    #[test]
+    fn test_log_settings_format() {
+        assert_eq!(
+            LogSettings::from_values(None, None, Some("json")).format,
+            LogFormat::Json
+        );
+        assert_eq!(
+            LogSettings::from_values(None, None, Some("JSON")).format,
+            LogFormat::Json
+        );
+        assert_eq!(
+            LogSettings::from_values(None, None, Some("text")).format,
+            LogFormat::Text
+        );
+        assert_eq!(
+            LogSettings::from_values(None, None, Some("yaml")).format,
+            LogFormat::Text
+        );
+    }
+}
@@ -4,15 +4,21 @@
 //! Network configuration is persisted to `/var/lib/keel/network/config.json`
 //! and applied during boot by `keel-init`.
 
-use ipnetwork::{Ipv4Network, Ipv6Network};
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
 use thiserror::Error;
 
 const CONFIG_PATH: &str = "/var/lib/keel/network/config.json";
 
+/// Configuration `keel-init` last applied, rewritten after every apply
+///
+/// The saved configuration may be newer than what is on the interfaces, so
+/// anything comparing against the running state reads this file instead.
+pub const APPLIED_CONFIG_PATH: &str = "/run/keel/network-applied.json";
+
 #[derive(Debug, Error)]
 pub enum NetworkConfigError {
     #[error("Invalid IP address: {0}")]
@@ -30,6 +36,9 @@ pub enum NetworkConfigError {
     #[error("Invalid bonding mode: {0}")]
     InvalidBondingMode(String),
 
+    #[error("Address family mismatch: {0}")]
+    AddressFamilyMismatch(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -38,6 +47,27 @@ pub enum NetworkConfigError {
 
     #[error("Configuration validation error: {0}")]
     Validation(String),
+
+    #[error(
+        "Overlapping subnets: {first} on {first_interface} and {second} on {second_interface} \
+         (set allow_overlapping_subnets to permit)"
+    )]
+    OverlappingSubnets {
+        first_interface: String,
+        first: String,
+        second_interface: String,
+        second: String,
+    },
+
+    #[error(
+        "Ambiguous default route: {first} and {second} both provide an {family} default \
+         route without distinct metrics"
+    )]
+    AmbiguousDefaultRoute {
+        family: &'static str,
+        first: String,
+        second: String,
+    },
 }
 
 /// Complete network configuration for a node
@@ -54,6 +84,38 @@ pub struct NetworkConfig {
     /// Custom routes
     #[serde(default)]
     pub routes: Vec<RouteConfig>,
+
+    /// MAC-address based interface renames, applied by `keel-init` before
+    /// any interface is configured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interface_aliases: Vec<InterfaceAlias>,
+
+    /// Accept static interfaces whose subnets overlap, which otherwise makes
+    /// routing between them unpredictable
+    #[serde(default)]
+    pub allow_overlapping_subnets: bool,
+}
+
+/// Pin an interface name to a MAC address
+///
+/// Lets a config written for `eth0` work on hardware where the kernel
+/// names the same NIC `enp3s0`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InterfaceAlias {
+    /// Hardware address (e.g., "52:54:00:12:34:56"), matched case-insensitively
+    pub mac: String,
+
+    /// Desired interface name
+    pub name: String,
+}
+
+/// A single rename planned from the interface aliases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceRename {
+    /// Name the kernel assigned
+    pub current: String,
+    /// Name from the matching alias
+    pub desired: String,
 }
 
 /// Network interface configuration
@@ -104,6 +166,13 @@ pub struct StaticConfig {
     /// Enable IPv6 auto-configuration (SLAAC)
     #[serde(default)]
     pub ipv6_auto: bool,
+
+    /// Metric of the default routes via `gateway` and `ipv6_gateway`
+    ///
+    /// Required when another interface or route also provides a default
+    /// route in the same address family.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway_metric: Option<u32>,
 }
 
 fn default_mtu() -> u32 {
@@ -197,8 +266,33 @@ pub struct DnsConfig {
     /// DNS search domains
     #[serde(default)]
     pub search_domains: Vec<String>,
+
+    /// Query the nameservers over DNS-over-TLS through a local stub
+    /// resolver instead of listing them in `/etc/resolv.conf`
+    #[serde(default)]
+    pub dns_over_tls: bool,
+
+    /// Name the nameservers' certificates must match (required with
+    /// `dns_over_tls`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_servername: Option<String>,
 }
 
+/// Address the DNS-over-TLS stub resolver listens on
+pub const STUB_RESOLVER_ADDRESS: &str = "127.0.0.1";
+
+/// Port nameservers are queried on with DNS-over-TLS
+pub const DNS_OVER_TLS_PORT: u16 = 853;
+
+/// CA bundle the stub resolver verifies nameserver certificates against
+pub const TLS_CERT_BUNDLE: &str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// Where the stub resolver records its PID
+pub const STUB_RESOLVER_PID_FILE: &str = "/run/unbound.pid";
+
+/// unbound binary run as the DNS-over-TLS stub resolver
+pub const STUB_RESOLVER_PATH: &str = "/usr/sbin/unbound";
+
 /// Static route configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RouteConfig {
@@ -220,6 +314,8 @@ impl NetworkConfig {
             interfaces: Vec::new(),
             dns: None,
             routes: Vec::new(),
+            interface_aliases: Vec::new(),
+            allow_overlapping_subnets: false,
         }
     }
 
@@ -245,16 +341,62 @@ impl NetworkConfig {
     pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), NetworkConfigError> {
         self.validate()?;
 
-        // Ensure parent directory exists
-        if let Some(parent) = path.as_ref().parent() {
-            fs::create_dir_all(parent)?;
-        }
-
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(path, content)?;
+        crate::atomic_write(path, content)?;
         Ok(())
     }
 
+    /// Static addresses this configuration puts on the interface `name`
+    pub fn addresses_on(&self, name: &str) -> Vec<&str> {
+        self.interfaces
+            .iter()
+            .filter(|iface| iface.name == name)
+            .filter_map(InterfaceConfig::static_config)
+            .flat_map(|cfg| {
+                std::iter::once(cfg.ipv4_address.as_str())
+                    .filter(|address| !address.is_empty())
+                    .chain(cfg.ipv6_addresses.iter().map(String::as_str))
+            })
+            .collect()
+    }
+
+    /// Whether any interface gets a static address with IP `ip`
+    pub fn has_address(&self, ip: IpAddr) -> bool {
+        self.interfaces.iter().any(|iface| {
+            self.addresses_on(&iface.name).iter().any(|address| {
+                address
+                    .split('/')
+                    .next()
+                    .and_then(|a| a.parse::<IpAddr>().ok())
+                    == Some(ip)
+            })
+        })
+    }
+
+    /// Whether moving from `previous` to this configuration needs a reboot
+    ///
+    /// Interface and route changes are only applied by `keel-init` at boot.
+    /// DNS changes can be applied live by rewriting `/etc/resolv.conf`, so a
+    /// diff that only adds or changes DNS settings does not need a reboot.
+    /// Removing DNS entirely does, since there is nothing to write live, and
+    /// so does any DNS change involving DNS-over-TLS.
+    pub fn requires_reboot(&self, previous: &NetworkConfig) -> bool {
+        if self.interfaces != previous.interfaces
+            || self.routes != previous.routes
+            || self.interface_aliases != previous.interface_aliases
+        {
+            return true;
+        }
+
+        // keel-init runs the DNS-over-TLS stub resolver
+        let uses_tls = |config: &NetworkConfig| config.dns.as_ref().is_some_and(|d| d.dns_over_tls);
+        if self.dns != previous.dns && (uses_tls(self) || uses_tls(previous)) {
+            return true;
+        }
+
+        self.dns.is_none() && previous.dns.is_some()
+    }
+
     /// Validate the entire network configuration
     pub fn validate(&self) -> Result<(), NetworkConfigError> {
         // Validate all interfaces
@@ -283,6 +425,218 @@ impl NetworkConfig {
             }
         }
 
+        // Aliases must map each MAC and each name at most once
+        let mut macs = std::collections::HashSet::new();
+        let mut alias_names = std::collections::HashSet::new();
+        for alias in &self.interface_aliases {
+            alias.validate()?;
+            if !macs.insert(alias.mac.to_lowercase()) {
+                return Err(NetworkConfigError::Validation(format!(
+                    "Duplicate interface alias MAC: {}",
+                    alias.mac
+                )));
+            }
+            if !alias_names.insert(&alias.name) {
+                return Err(NetworkConfigError::Validation(format!(
+                    "Duplicate interface alias name: {}",
+                    alias.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate a configuration submitted to replace the saved one
+    ///
+    /// On top of [`NetworkConfig::validate`] this rejects setups that work
+    /// but are probably mistakes: gateways and routes in the wrong address
+    /// family, overlapping subnets and ambiguous default routes. Those
+    /// checks are not part of `validate`,
+    /// which also runs when loading, so a configuration saved before they
+    /// existed still loads at boot.
+    pub fn validate_new(&self) -> Result<(), NetworkConfigError> {
+        // Before `validate`, whose format errors would hide the mismatch
+        for cfg in self
+            .interfaces
+            .iter()
+            .filter_map(InterfaceConfig::static_config)
+        {
+            cfg.check_gateways()?;
+        }
+        for route in &self.routes {
+            route.check_family()?;
+        }
+        self.validate()?;
+
+        if !self.allow_overlapping_subnets {
+            self.check_subnet_overlap()?;
+        }
+        self.check_default_routes()
+    }
+
+    /// Reject static addresses on different interfaces whose networks overlap
+    ///
+    /// IPv6 link-local networks are left out, since every interface has one.
+    fn check_subnet_overlap(&self) -> Result<(), NetworkConfigError> {
+        let mut networks: Vec<(&str, IpNetwork)> = Vec::new();
+        for iface in &self.interfaces {
+            let Some(cfg) = iface.static_config() else {
+                continue;
+            };
+            // Formats were checked by the per-interface validation
+            if let Ok(network) = cfg.ipv4_address.parse::<Ipv4Network>() {
+                networks.push((&iface.name, IpNetwork::V4(network)));
+            }
+            for address in &cfg.ipv6_addresses {
+                if let Ok(network) = address.parse::<Ipv6Network>() {
+                    if !Ipv6Network::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10)
+                        .is_ok_and(|link_local| link_local.contains(network.ip()))
+                    {
+                        networks.push((&iface.name, IpNetwork::V6(network)));
+                    }
+                }
+            }
+        }
+
+        for (i, (first_interface, first)) in networks.iter().enumerate() {
+            for (second_interface, second) in &networks[i + 1..] {
+                let overlaps = match (first, second) {
+                    (IpNetwork::V4(a), IpNetwork::V4(b)) => a.overlaps(*b),
+                    (IpNetwork::V6(a), IpNetwork::V6(b)) => a.overlaps(*b),
+                    _ => false,
+                };
+                if overlaps && first_interface != second_interface {
+                    return Err(NetworkConfigError::OverlappingSubnets {
+                        first_interface: first_interface.to_string(),
+                        first: first.to_string(),
+                        second_interface: second_interface.to_string(),
+                        second: second.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Require distinct metrics when several default routes share a family
+    ///
+    /// Defaults come from static interface gateways and from routes to
+    /// `0.0.0.0/0` or `::/0`. Without a metric on each, which one the kernel
+    /// uses depends on the order they were added in. Defaults learned over
+    /// DHCP or router advertisements are not known here.
+    fn check_default_routes(&self) -> Result<(), NetworkConfigError> {
+        let mut ipv4: Vec<(String, Option<u32>)> = Vec::new();
+        let mut ipv6: Vec<(String, Option<u32>)> = Vec::new();
+        for iface in &self.interfaces {
+            let Some(cfg) = iface.static_config() else {
+                continue;
+            };
+            if let Some(ref gw) = cfg.gateway {
+                ipv4.push((format!("{} via {}", iface.name, gw), cfg.gateway_metric));
+            }
+            if let Some(ref gw) = cfg.ipv6_gateway {
+                ipv6.push((format!("{} via {}", iface.name, gw), cfg.gateway_metric));
+            }
+        }
+        for route in &self.routes {
+            let source = format!("route {} via {}", route.destination, route.gateway);
+            if let Ok(network) = route.destination.parse::<Ipv4Network>() {
+                if network.prefix() == 0 {
+                    ipv4.push((source, route.metric));
+                }
+            } else if let Ok(network) = route.destination.parse::<Ipv6Network>() {
+                if network.prefix() == 0 {
+                    ipv6.push((source, route.metric));
+                }
+            }
+        }
+
+        for (family, defaults) in [("IPv4", ipv4), ("IPv6", ipv6)] {
+            for (i, (first, first_metric)) in defaults.iter().enumerate() {
+                for (second, second_metric) in &defaults[i + 1..] {
+                    if first_metric.is_none()
+                        || second_metric.is_none()
+                        || first_metric == second_metric
+                    {
+                        return Err(NetworkConfigError::AmbiguousDefaultRoute {
+                            family,
+                            first: first.clone(),
+                            second: second.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Work out which interfaces to rename from the aliases
+    ///
+    /// `sys_class_net` is normally `/sys/class/net`; each entry's `address`
+    /// file holds its MAC. Interfaces that already have the desired name are
+    /// skipped, as are renames whose target name is taken by a different
+    /// interface (the kernel would refuse them anyway).
+    pub fn plan_interface_renames<P: AsRef<Path>>(
+        &self,
+        sys_class_net: P,
+    ) -> Result<Vec<InterfaceRename>, NetworkConfigError> {
+        if self.interface_aliases.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut present = Vec::new();
+        for entry in fs::read_dir(sys_class_net)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let mac = fs::read_to_string(entry.path().join("address"))
+                .map(|m| m.trim().to_lowercase())
+                .unwrap_or_default();
+            present.push((name, mac));
+        }
+        present.sort();
+
+        let mut renames = Vec::new();
+        for alias in &self.interface_aliases {
+            let mac = alias.mac.to_lowercase();
+            let Some((current, _)) = present.iter().find(|(_, m)| *m == mac) else {
+                continue;
+            };
+            if *current == alias.name {
+                continue;
+            }
+            if present.iter().any(|(n, _)| *n == alias.name) {
+                continue;
+            }
+            renames.push(InterfaceRename {
+                current: current.clone(),
+                desired: alias.name.clone(),
+            });
+        }
+
+        Ok(renames)
+    }
+}
+
+impl InterfaceAlias {
+    /// Validate the MAC address and target name
+    fn validate(&self) -> Result<(), NetworkConfigError> {
+        let octets: Vec<&str> = self.mac.split(':').collect();
+        let valid_mac = octets.len() == 6
+            && octets
+                .iter()
+                .all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()));
+        if !valid_mac {
+            return Err(NetworkConfigError::Validation(format!(
+                "Invalid MAC address in interface alias: {}",
+                self.mac
+            )));
+        }
+
+        if self.name.is_empty() || self.name.len() > 15 {
+            return Err(NetworkConfigError::InvalidInterfaceName(self.name.clone()));
+        }
+
         Ok(())
     }
 }
@@ -294,6 +648,22 @@ impl Default for NetworkConfig {
 }
 
 impl InterfaceConfig {
+    /// Static addressing of this interface, if it has any
+    pub fn static_config(&self) -> Option<&StaticConfig> {
+        match &self.config {
+            InterfaceType::Static(cfg)
+            | InterfaceType::Vlan(VlanConfig {
+                ip_config: VlanIpConfig::Static(cfg),
+                ..
+            })
+            | InterfaceType::Bond(BondConfig {
+                ip_config: BondIpConfig::Static(cfg),
+                ..
+            }) => Some(cfg),
+            _ => None,
+        }
+    }
+
     /// Validate interface configuration
     fn validate(&self) -> Result<(), NetworkConfigError> {
         // Validate interface name (basic check)
@@ -358,6 +728,47 @@ impl StaticConfig {
 
         Ok(())
     }
+
+    /// Reject gateways of the wrong address family, and gateways without an
+    /// address of their family to reach them from (see
+    /// [`NetworkConfig::validate_new`])
+    fn check_gateways(&self) -> Result<(), NetworkConfigError> {
+        if let Some(ref gw) = self.gateway {
+            if gw.parse::<Ipv6Addr>().is_ok() {
+                return Err(NetworkConfigError::AddressFamilyMismatch(format!(
+                    "IPv4 gateway {} is an IPv6 address (use ipv6_gateway)",
+                    gw
+                )));
+            }
+            gw.parse::<Ipv4Addr>()
+                .map_err(|_| NetworkConfigError::InvalidIpAddress(gw.clone()))?;
+            if self.ipv4_address.is_empty() {
+                return Err(NetworkConfigError::Validation(format!(
+                    "IPv4 gateway {} requires an IPv4 address",
+                    gw
+                )));
+            }
+        }
+
+        if let Some(ref gw6) = self.ipv6_gateway {
+            if gw6.parse::<Ipv4Addr>().is_ok() {
+                return Err(NetworkConfigError::AddressFamilyMismatch(format!(
+                    "IPv6 gateway {} is an IPv4 address (use gateway)",
+                    gw6
+                )));
+            }
+            gw6.parse::<Ipv6Addr>()
+                .map_err(|_| NetworkConfigError::InvalidIpAddress(gw6.clone()))?;
+            if self.ipv6_addresses.is_empty() && !self.ipv6_auto {
+                return Err(NetworkConfigError::Validation(format!(
+                    "IPv6 gateway {} requires an IPv6 address or IPv6 auto-config",
+                    gw6
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl VlanConfig {
@@ -402,6 +813,60 @@ impl BondConfig {
 }
 
 impl DnsConfig {
+    /// Render this configuration as `/etc/resolv.conf` contents
+    ///
+    /// With `dns_over_tls` the only nameserver is the local stub resolver
+    /// (see [`Self::to_unbound_conf`]).
+    pub fn to_resolv_conf(&self, generated_by: &str) -> String {
+        let mut resolv_conf = format!("# Generated by {}\n", generated_by);
+
+        if self.dns_over_tls {
+            resolv_conf.push_str(&format!("nameserver {}\n", STUB_RESOLVER_ADDRESS));
+        } else {
+            for ns in &self.nameservers {
+                resolv_conf.push_str(&format!("nameserver {}\n", ns));
+            }
+        }
+
+        if !self.search_domains.is_empty() {
+            resolv_conf.push_str(&format!("search {}\n", self.search_domains.join(" ")));
+        }
+
+        resolv_conf
+    }
+
+    /// Render an unbound configuration forwarding all queries to the
+    /// nameservers over DNS-over-TLS
+    ///
+    /// unbound listens on [`STUB_RESOLVER_ADDRESS`] and checks each
+    /// nameserver's certificate against `tls_servername`.
+    pub fn to_unbound_conf(&self, generated_by: &str) -> String {
+        let mut conf = format!("# Generated by {}\n", generated_by);
+        conf.push_str("server:\n");
+        conf.push_str(&format!("    interface: {}\n", STUB_RESOLVER_ADDRESS));
+        conf.push_str("    port: 53\n");
+        conf.push_str("    do-daemonize: no\n");
+        conf.push_str(&format!("    pidfile: \"{}\"\n", STUB_RESOLVER_PID_FILE));
+        // There is no unprivileged user or chroot on a KeelOS root
+        conf.push_str("    username: \"\"\n");
+        conf.push_str("    chroot: \"\"\n");
+        conf.push_str("    hide-identity: yes\n");
+        conf.push_str("    hide-version: yes\n");
+        conf.push_str(&format!("    tls-cert-bundle: \"{}\"\n", TLS_CERT_BUNDLE));
+        conf.push_str("forward-zone:\n");
+        conf.push_str("    name: \".\"\n");
+        conf.push_str("    forward-tls-upstream: yes\n");
+
+        let servername = self.tls_servername.as_deref().unwrap_or_default();
+        for ns in &self.nameservers {
+            conf.push_str(&format!(
+                "    forward-addr: {}@{}#{}\n",
+                ns, DNS_OVER_TLS_PORT, servername
+            ));
+        }
+        conf
+    }
+
     /// Validate DNS configuration
     fn validate(&self) -> Result<(), NetworkConfigError> {
         if self.nameservers.is_empty() {
@@ -410,6 +875,26 @@ impl DnsConfig {
             ));
         }
 
+        if self.dns_over_tls
+            && self
+                .tls_servername
+                .as_deref()
+                .is_none_or(|name| name.trim().is_empty())
+        {
+            return Err(NetworkConfigError::Validation(
+                "dns_over_tls requires tls_servername".to_string(),
+            ));
+        }
+        if self
+            .tls_servername
+            .as_deref()
+            .is_some_and(|name| name.contains(|c: char| c.is_whitespace() || c == '#'))
+        {
+            return Err(NetworkConfigError::Validation(
+                "tls_servername must be a host name".to_string(),
+            ));
+        }
+
         // Validate each nameserver is a valid IP
         for ns in &self.nameservers {
             ns.parse::<Ipv4Addr>()
@@ -425,27 +910,48 @@ impl RouteConfig {
     /// Validate route configuration
     fn validate(&self) -> Result<(), NetworkConfigError> {
         // Validate destination CIDR
-        self.destination
-            .parse::<Ipv4Network>()
-            .or_else(|_| {
-                self.destination
-                    .parse::<Ipv6Network>()
-                    .map(|_| Ipv4Network::new(Ipv4Addr::new(0, 0, 0, 0), 0).unwrap())
-            })
-            .map_err(|_| NetworkConfigError::InvalidCidr(self.destination.clone()))?;
+        if self.destination.parse::<Ipv4Network>().is_err()
+            && self.destination.parse::<Ipv6Network>().is_err()
+        {
+            return Err(NetworkConfigError::InvalidCidr(self.destination.clone()));
+        }
 
         // Validate gateway IP
         self.gateway
-            .parse::<Ipv4Addr>()
-            .or_else(|_| {
-                self.gateway
-                    .parse::<Ipv6Addr>()
-                    .map(|_| Ipv4Addr::new(0, 0, 0, 0))
-            })
+            .parse::<IpAddr>()
             .map_err(|_| NetworkConfigError::InvalidIpAddress(self.gateway.clone()))?;
 
         Ok(())
     }
+
+    /// Reject a gateway outside the destination's address family (see
+    /// [`NetworkConfig::validate_new`])
+    fn check_family(&self) -> Result<(), NetworkConfigError> {
+        // Malformed values are left to `validate`
+        let destination_is_ipv4 = if self.destination.parse::<Ipv4Network>().is_ok() {
+            true
+        } else if self.destination.parse::<Ipv6Network>().is_ok() {
+            false
+        } else {
+            return Ok(());
+        };
+        let Ok(gateway) = self.gateway.parse::<IpAddr>() else {
+            return Ok(());
+        };
+
+        // The gateway must be reachable in the destination's address family
+        if gateway.is_ipv4() != destination_is_ipv4 {
+            return Err(NetworkConfigError::AddressFamilyMismatch(format!(
+                "route to {} via {}: destination is {} but gateway is {}",
+                self.destination,
+                self.gateway,
+                if destination_is_ipv4 { "IPv4" } else { "IPv6" },
+                if gateway.is_ipv4() { "IPv4" } else { "IPv6" },
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -462,6 +968,7 @@ mod tests {
             ipv6_addresses: vec![],
             ipv6_gateway: None,
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(valid_ipv4.validate().is_ok());
 
@@ -473,6 +980,7 @@ mod tests {
             ipv6_addresses: vec!["2001:db8::1/64".to_string()],
             ipv6_gateway: Some("2001:db8::ff".to_string()),
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(valid_ipv6.validate().is_ok());
 
@@ -484,6 +992,7 @@ mod tests {
             ipv6_addresses: vec!["2001:db8::1/64".to_string(), "fd00::1/64".to_string()],
             ipv6_gateway: Some("2001:db8::ff".to_string()),
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(valid_dual.validate().is_ok());
 
@@ -495,6 +1004,7 @@ mod tests {
             ipv6_addresses: vec![],
             ipv6_gateway: None,
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(no_ips.validate().is_err());
 
@@ -506,6 +1016,7 @@ mod tests {
             ipv6_addresses: vec![],
             ipv6_gateway: None,
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(invalid_cidr.validate().is_err());
 
@@ -517,6 +1028,7 @@ mod tests {
             ipv6_addresses: vec![],
             ipv6_gateway: None,
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(invalid_gateway.validate().is_err());
 
@@ -528,6 +1040,7 @@ mod tests {
             ipv6_addresses: vec!["gggg::1/64".to_string()], // Invalid IPv6
             ipv6_gateway: None,
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(invalid_ipv6_cidr.validate().is_err());
 
@@ -539,6 +1052,7 @@ mod tests {
             ipv6_addresses: vec!["2001:db8::1/64".to_string()],
             ipv6_gateway: Some("not-an-ipv6".to_string()),
             ipv6_auto: false,
+            gateway_metric: None,
         };
         assert!(invalid_ipv6_gateway.validate().is_err());
     }
@@ -565,12 +1079,16 @@ mod tests {
         let valid = DnsConfig {
             nameservers: vec!["8.8.8.8".to_string(), "1.1.1.1".to_string()],
             search_domains: vec!["example.com".to_string()],
+            dns_over_tls: false,
+            tls_servername: None,
         };
         assert!(valid.validate().is_ok());
 
         let invalid = DnsConfig {
             nameservers: vec!["not-an-ip".to_string()],
             search_domains: vec![],
+            dns_over_tls: false,
+            tls_servername: None,
         };
         assert!(invalid.validate().is_err());
     }
@@ -587,13 +1105,18 @@ mod tests {
                     ipv6_addresses: vec!["2001:db8::100/64".to_string()],
                     ipv6_gateway: Some("2001:db8::1".to_string()),
                     ipv6_auto: false,
+                    gateway_metric: None,
                 }),
             }],
             dns: Some(DnsConfig {
                 nameservers: vec!["8.8.8.8".to_string()],
                 search_domains: vec![],
+                dns_over_tls: false,
+                tls_servername: None,
             }),
             routes: vec![],
+            interface_aliases: vec![],
+            allow_overlapping_subnets: false,
         };
 
         let json = serde_json::to_string(&config).unwrap();
@@ -601,6 +1124,231 @@ mod tests {
         assert_eq!(config, deserialized);
     }
 
+    fn static_iface(name: &str, ipv4: &str, ipv6: &[&str]) -> InterfaceConfig {
+        InterfaceConfig {
+            name: name.to_string(),
+            config: InterfaceType::Static(StaticConfig {
+                ipv4_address: ipv4.to_string(),
+                gateway: None,
+                mtu: 1500,
+                ipv6_addresses: ipv6.iter().map(|a| a.to_string()).collect(),
+                ipv6_gateway: None,
+                ipv6_auto: false,
+                gateway_metric: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_overlapping_subnets_rejected() {
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            static_iface("eth0", "10.0.0.5/16", &[]),
+            static_iface("eth1", "10.0.3.7/24", &[]),
+        ];
+        match config.validate_new() {
+            Err(NetworkConfigError::OverlappingSubnets {
+                first_interface,
+                second_interface,
+                ..
+            }) => {
+                assert_eq!(first_interface, "eth0");
+                assert_eq!(second_interface, "eth1");
+            }
+            other => panic!("expected overlapping subnets, got {:?}", other),
+        }
+
+        // IPv6, with the second network on a VLAN
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            static_iface("eth0", "", &["2001:db8::1/48"]),
+            InterfaceConfig {
+                name: "eth0.100".to_string(),
+                config: InterfaceType::Vlan(VlanConfig {
+                    parent: "eth0".to_string(),
+                    vlan_id: 100,
+                    ip_config: VlanIpConfig::Static(StaticConfig {
+                        ipv4_address: String::new(),
+                        gateway: None,
+                        mtu: 1500,
+                        ipv6_addresses: vec!["2001:db8:0:5::1/64".to_string()],
+                        ipv6_gateway: None,
+                        ipv6_auto: false,
+                        gateway_metric: None,
+                    }),
+                }),
+            },
+        ];
+        let err = config.validate_new().unwrap_err();
+        assert!(err.to_string().contains("2001:db8::1/48"), "{}", err);
+    }
+
+    #[test]
+    fn test_disjoint_subnets_accepted() {
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            static_iface("eth0", "10.0.0.5/24", &["2001:db8:1::1/64", "fe80::1/64"]),
+            static_iface("eth1", "10.0.1.5/24", &["2001:db8:2::1/64", "fe80::2/64"]),
+            InterfaceConfig {
+                name: "eth2".to_string(),
+                config: InterfaceType::Dhcp,
+            },
+        ];
+        assert!(config.validate_new().is_ok());
+    }
+
+    #[test]
+    fn test_overlapping_subnets_allowed_explicitly() {
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            static_iface("eth0", "192.168.1.10/24", &[]),
+            static_iface("eth1", "192.168.1.11/24", &[]),
+        ];
+        assert!(config.validate_new().is_err());
+
+        config.allow_overlapping_subnets = true;
+        assert!(config.validate_new().is_ok());
+
+        // The override survives a round trip through the config file
+        let json = serde_json::to_string(&config).unwrap();
+        let loaded: NetworkConfig = serde_json::from_str(&json).unwrap();
+        assert!(loaded.validate_new().is_ok());
+    }
+
+    #[test]
+    fn test_overlapping_subnets_saved_earlier_still_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            static_iface("eth0", "192.168.1.10/24", &[]),
+            static_iface("eth1", "192.168.1.11/24", &[]),
+        ];
+        config.save_to(&path).unwrap();
+
+        let loaded = NetworkConfig::load_from(&path).unwrap();
+        assert_eq!(loaded, config);
+        assert!(loaded.validate_new().is_err());
+    }
+
+    fn gateway_iface(
+        name: &str,
+        ipv4: &str,
+        gateway: &str,
+        metric: Option<u32>,
+    ) -> InterfaceConfig {
+        let mut iface = static_iface(name, ipv4, &[]);
+        if let InterfaceType::Static(ref mut cfg) = iface.config {
+            cfg.gateway = Some(gateway.to_string());
+            cfg.gateway_metric = metric;
+        }
+        iface
+    }
+
+    #[test]
+    fn test_multiple_default_routes_rejected() {
+        // Two interfaces with gateways
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", None),
+            gateway_iface("eth1", "10.0.1.5/24", "10.0.1.1", Some(100)),
+        ];
+        match config.validate_new() {
+            Err(NetworkConfigError::AmbiguousDefaultRoute {
+                family,
+                first,
+                second,
+            }) => {
+                assert_eq!(family, "IPv4");
+                assert_eq!(first, "eth0 via 10.0.0.1");
+                assert_eq!(second, "eth1 via 10.0.1.1");
+            }
+            other => panic!("expected AmbiguousDefaultRoute, got {:?}", other),
+        }
+
+        // The same metric does not tell them apart either
+        config.interfaces[0] = gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", Some(100));
+        assert!(config.validate_new().is_err());
+
+        // An interface gateway and an explicit default route
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", Some(100))];
+        config.routes = vec![RouteConfig {
+            destination: "0.0.0.0/0".to_string(),
+            gateway: "10.0.0.254".to_string(),
+            metric: None,
+        }];
+        let err = config.validate_new().unwrap_err();
+        assert!(
+            err.to_string().contains("route 0.0.0.0/0 via 10.0.0.254"),
+            "{}",
+            err
+        );
+
+        // Two IPv6 default routes
+        let mut config = NetworkConfig::new();
+        config.routes = ["2001:db8::1", "2001:db8::2"]
+            .map(|gateway| RouteConfig {
+                destination: "::/0".to_string(),
+                gateway: gateway.to_string(),
+                metric: Some(1024),
+            })
+            .to_vec();
+        assert!(matches!(
+            config.validate_new(),
+            Err(NetworkConfigError::AmbiguousDefaultRoute { family: "IPv6", .. })
+        ));
+    }
+
+    #[test]
+    fn test_default_routes_disambiguated_by_metric() {
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", Some(100)),
+            gateway_iface("eth1", "10.0.1.5/24", "10.0.1.1", Some(200)),
+        ];
+        config.routes = vec![
+            RouteConfig {
+                destination: "0.0.0.0/0".to_string(),
+                gateway: "10.0.0.254".to_string(),
+                metric: Some(300),
+            },
+            // Not a default route, so it needs no metric
+            RouteConfig {
+                destination: "172.16.0.0/12".to_string(),
+                gateway: "10.0.1.254".to_string(),
+                metric: None,
+            },
+        ];
+        assert!(config.validate_new().is_ok());
+
+        // A single default in each family needs no metric
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", None)];
+        config.routes = vec![RouteConfig {
+            destination: "::/0".to_string(),
+            gateway: "2001:db8::1".to_string(),
+            metric: None,
+        }];
+        assert!(config.validate_new().is_ok());
+    }
+
+    #[test]
+    fn test_ambiguous_default_routes_saved_earlier_still_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+        let mut config = NetworkConfig::new();
+        config.interfaces = vec![
+            gateway_iface("eth0", "10.0.0.5/24", "10.0.0.1", None),
+            gateway_iface("eth1", "10.0.1.5/24", "10.0.1.1", None),
+        ];
+        config.save_to(&path).unwrap();
+
+        let loaded = NetworkConfig::load_from(&path).unwrap();
+        assert_eq!(loaded, config);
+        assert!(loaded.validate_new().is_err());
+    }
+
     #[test]
     fn test_duplicate_interface_names() {
         let config = NetworkConfig {
@@ -616,8 +1364,443 @@ mod tests {
             ],
             dns: None,
             routes: vec![],
+            interface_aliases: vec![],
+            allow_overlapping_subnets: false,
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_route_config_validation_ipv6() {
+        let valid = RouteConfig {
+            destination: "2001:db8:1::/48".to_string(),
+            gateway: "fe80::1".to_string(),
+            metric: Some(100),
+        };
+        assert!(valid.validate().is_ok());
+
+        let default_v6 = RouteConfig {
+            destination: "::/0".to_string(),
+            gateway: "2001:db8::1".to_string(),
+            metric: None,
+        };
+        assert!(default_v6.validate().is_ok());
+
+        let invalid_dest = RouteConfig {
+            destination: "2001:db8::/129".to_string(),
+            gateway: "2001:db8::1".to_string(),
+            metric: None,
+        };
+        assert!(matches!(
+            invalid_dest.validate(),
+            Err(NetworkConfigError::InvalidCidr(_))
+        ));
+
+        let invalid_gw = RouteConfig {
+            destination: "2001:db8:1::/48".to_string(),
+            gateway: "2001:db8::zz".to_string(),
+            metric: None,
+        };
+        assert!(matches!(
+            invalid_gw.validate(),
+            Err(NetworkConfigError::InvalidIpAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_route_config_family_mismatch() {
+        let v4_dest_v6_gw = RouteConfig {
+            destination: "10.0.0.0/8".to_string(),
+            gateway: "2001:db8::1".to_string(),
+            metric: None,
+        };
+        assert!(v4_dest_v6_gw.validate().is_ok());
+        assert!(matches!(
+            v4_dest_v6_gw.check_family(),
+            Err(NetworkConfigError::AddressFamilyMismatch(_))
+        ));
+
+        let v6_dest_v4_gw = RouteConfig {
+            destination: "2001:db8:1::/48".to_string(),
+            gateway: "10.0.0.1".to_string(),
+            metric: None,
+        };
+        assert!(matches!(
+            v6_dest_v4_gw.check_family(),
+            Err(NetworkConfigError::AddressFamilyMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_static_config_gateway_family_mismatch() {
+        let v6_in_v4_gateway = StaticConfig {
+            ipv4_address: "192.168.1.100/24".to_string(),
+            gateway: Some("2001:db8::1".to_string()),
+            mtu: 1500,
+            ipv6_addresses: vec![],
+            ipv6_gateway: None,
+            ipv6_auto: false,
+            gateway_metric: None,
+        };
+        assert!(matches!(
+            v6_in_v4_gateway.check_gateways(),
+            Err(NetworkConfigError::AddressFamilyMismatch(_))
+        ));
+
+        let v4_in_v6_gateway = StaticConfig {
+            ipv4_address: String::new(),
+            gateway: None,
+            mtu: 1500,
+            ipv6_addresses: vec!["2001:db8::10/64".to_string()],
+            ipv6_gateway: Some("192.168.1.1".to_string()),
+            ipv6_auto: false,
+            gateway_metric: None,
+        };
+        assert!(matches!(
+            v4_in_v6_gateway.check_gateways(),
+            Err(NetworkConfigError::AddressFamilyMismatch(_))
+        ));
+
+        // IPv6 gateway with SLAAC but no static IPv6 address is fine
+        let slaac_with_gateway = StaticConfig {
+            ipv4_address: String::new(),
+            gateway: None,
+            mtu: 1500,
+            ipv6_addresses: vec![],
+            ipv6_gateway: Some("fe80::1".to_string()),
+            ipv6_auto: true,
+            gateway_metric: None,
+        };
+        assert!(slaac_with_gateway.check_gateways().is_ok());
+    }
+
+    #[test]
+    fn test_gateway_family_errors_saved_earlier_still_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+        // Accepted before address families were checked: an IPv4 gateway
+        // on an IPv6-only interface and a route via the wrong family
+        std::fs::write(
+            &path,
+            r#"{
+                "interfaces": [{
+                    "name": "eth0",
+                    "type": "static",
+                    "ipv6_addresses": ["2001:db8::10/64"],
+                    "gateway": "192.168.1.1"
+                }],
+                "routes": [{"destination": "10.0.0.0/8", "gateway": "2001:db8::1"}]
+            }"#,
+        )
+        .unwrap();
+
+        let loaded = NetworkConfig::load_from(&path).unwrap();
+        assert_eq!(loaded.interfaces.len(), 1);
+        assert!(matches!(
+            loaded.validate_new(),
+            Err(NetworkConfigError::Validation(_))
+        ));
+
+        let mut routes_only = loaded.clone();
+        routes_only.interfaces.clear();
+        assert!(matches!(
+            routes_only.validate_new(),
+            Err(NetworkConfigError::AddressFamilyMismatch(_))
+        ));
+    }
+
+    fn static_eth0(ip: &str) -> InterfaceConfig {
+        InterfaceConfig {
+            name: "eth0".to_string(),
+            config: InterfaceType::Static(StaticConfig {
+                ipv4_address: ip.to_string(),
+                gateway: None,
+                mtu: 1500,
+                ipv6_addresses: vec![],
+                ipv6_gateway: None,
+                ipv6_auto: false,
+                gateway_metric: None,
+            }),
+        }
+    }
+
+    fn dns(nameserver: &str) -> Option<DnsConfig> {
+        Some(DnsConfig {
+            nameservers: vec![nameserver.to_string()],
+            search_domains: vec![],
+            dns_over_tls: false,
+            tls_servername: None,
+        })
+    }
+
+    #[test]
+    fn test_requires_reboot_dns_only_change() {
+        let previous = NetworkConfig {
+            interfaces: vec![static_eth0("10.0.0.5/24")],
+            dns: dns("8.8.8.8"),
+            routes: vec![],
+            interface_aliases: vec![],
+            allow_overlapping_subnets: false,
+        };
+        let mut next = previous.clone();
+        next.dns = dns("1.1.1.1");
+        assert!(!next.requires_reboot(&previous));
+
+        // Adding DNS where there was none can also be applied live
+        let mut no_dns = previous.clone();
+        no_dns.dns = None;
+        assert!(!previous.requires_reboot(&no_dns));
+
+        // Identical configs never need a reboot
+        assert!(!previous.requires_reboot(&previous.clone()));
+
+        // The stub resolver is only (re)started by keel-init
+        let mut tls = previous.clone();
+        if let Some(dns) = tls.dns.as_mut() {
+            dns.dns_over_tls = true;
+            dns.tls_servername = Some("dns.google".to_string());
+        }
+        assert!(tls.requires_reboot(&previous));
+        assert!(previous.requires_reboot(&tls));
+        assert!(!tls.requires_reboot(&tls.clone()));
+    }
+
+    #[test]
+    fn test_requires_reboot_interface_change() {
+        let previous = NetworkConfig {
+            interfaces: vec![static_eth0("10.0.0.5/24")],
+            dns: None,
+            routes: vec![],
+            interface_aliases: vec![],
+            allow_overlapping_subnets: false,
         };
 
+        let mut changed_ip = previous.clone();
+        changed_ip.interfaces = vec![static_eth0("10.0.0.6/24")];
+        assert!(changed_ip.requires_reboot(&previous));
+
+        let mut bond = previous.clone();
+        bond.interfaces.push(InterfaceConfig {
+            name: "bond0".to_string(),
+            config: InterfaceType::Bond(BondConfig {
+                mode: BondingMode::ActiveBackup,
+                slaves: vec!["eth1".to_string(), "eth2".to_string()],
+                ip_config: BondIpConfig::Dhcp,
+            }),
+        });
+        assert!(bond.requires_reboot(&previous));
+    }
+
+    #[test]
+    fn test_addresses_on_interface() {
+        let mut config = NetworkConfig::new();
+        config.interfaces.push(static_eth0("10.0.0.5/24"));
+        if let InterfaceType::Static(cfg) = &mut config.interfaces[0].config {
+            cfg.ipv6_addresses.push("fd00::5/64".to_string());
+        }
+        config.interfaces.push(InterfaceConfig {
+            name: "eth1".to_string(),
+            config: InterfaceType::Dhcp,
+        });
+
+        assert_eq!(config.addresses_on("eth0"), ["10.0.0.5/24", "fd00::5/64"]);
+        assert!(config.addresses_on("eth1").is_empty());
+        assert!(config.has_address("10.0.0.5".parse().unwrap()));
+        assert!(config.has_address("fd00::5".parse().unwrap()));
+        assert!(!config.has_address("10.0.0.6".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_requires_reboot_route_change_and_dns_removal() {
+        let previous = NetworkConfig {
+            interfaces: vec![],
+            dns: dns("8.8.8.8"),
+            routes: vec![],
+            interface_aliases: vec![],
+            allow_overlapping_subnets: false,
+        };
+
+        let mut with_route = previous.clone();
+        with_route.routes.push(RouteConfig {
+            destination: "10.1.0.0/16".to_string(),
+            gateway: "10.0.0.1".to_string(),
+            metric: None,
+        });
+        assert!(with_route.requires_reboot(&previous));
+
+        let mut dns_removed = previous.clone();
+        dns_removed.dns = None;
+        assert!(dns_removed.requires_reboot(&previous));
+    }
+
+    #[test]
+    fn test_dns_to_resolv_conf() {
+        let dns = DnsConfig {
+            nameservers: vec!["8.8.8.8".to_string(), "2001:4860:4860::8888".to_string()],
+            search_domains: vec!["example.com".to_string(), "corp".to_string()],
+            dns_over_tls: false,
+            tls_servername: None,
+        };
+        assert_eq!(
+            dns.to_resolv_conf("keel-agent"),
+            "# Generated by keel-agent\nnameserver 8.8.8.8\nnameserver 2001:4860:4860::8888\nsearch example.com corp\n"
+        );
+    }
+
+    #[test]
+    fn test_dns_over_tls_rendering() {
+        let mut dns = DnsConfig {
+            nameservers: vec!["1.1.1.1".to_string(), "2606:4700:4700::1111".to_string()],
+            search_domains: vec!["example.com".to_string()],
+            dns_over_tls: true,
+            tls_servername: Some("cloudflare-dns.com".to_string()),
+        };
+        assert!(dns.validate().is_ok());
+
+        // resolv.conf only points at the stub resolver
+        assert_eq!(
+            dns.to_resolv_conf("keel-init"),
+            "# Generated by keel-init\nnameserver 127.0.0.1\nsearch example.com\n"
+        );
+        assert_eq!(
+            dns.to_unbound_conf("keel-init"),
+            "# Generated by keel-init\n\
+             server:\n    interface: 127.0.0.1\n    port: 53\n    do-daemonize: no\n\
+             \x20   pidfile: \"/run/unbound.pid\"\n    username: \"\"\n    chroot: \"\"\n\
+             \x20   hide-identity: yes\n    hide-version: yes\n\
+             \x20   tls-cert-bundle: \"/etc/ssl/certs/ca-certificates.crt\"\n\
+             forward-zone:\n    name: \".\"\n    forward-tls-upstream: yes\n\
+             \x20   forward-addr: 1.1.1.1@853#cloudflare-dns.com\n\
+             \x20   forward-addr: 2606:4700:4700::1111@853#cloudflare-dns.com\n"
+        );
+
+        // Disabled: plain nameservers, the server name is ignored
+        dns.dns_over_tls = false;
+        assert_eq!(
+            dns.to_resolv_conf("keel-init"),
+            "# Generated by keel-init\nnameserver 1.1.1.1\nnameserver 2606:4700:4700::1111\nsearch example.com\n"
+        );
+
+        // Certificates cannot be checked without a server name
+        dns.dns_over_tls = true;
+        dns.tls_servername = None;
+        assert!(dns.validate().is_err());
+        dns.tls_servername = Some("bad#name".to_string());
+        assert!(dns.validate().is_err());
+    }
+
+    fn alias(mac: &str, name: &str) -> InterfaceAlias {
+        InterfaceAlias {
+            mac: mac.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Build a `/sys/class/net`-like tree of `<iface>/address` files
+    fn sysfs_fixture(ifaces: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        for (name, mac) in ifaces {
+            let iface_dir = dir.path().join(name);
+            fs::create_dir(&iface_dir).unwrap();
+            fs::write(iface_dir.join("address"), format!("{}\n", mac)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_plan_interface_renames() {
+        let sysfs = sysfs_fixture(&[
+            ("lo", "00:00:00:00:00:00"),
+            ("enp3s0", "52:54:00:12:34:56"),
+            ("enp4s0", "52:54:00:ab:cd:ef"),
+        ]);
+
+        let mut config = NetworkConfig::new();
+        config.interface_aliases = vec![
+            alias("52:54:00:12:34:56", "eth0"),
+            // Case-insensitive match against sysfs
+            alias("52:54:00:AB:CD:EF", "eth1"),
+            // No such NIC on this machine
+            alias("52:54:00:99:99:99", "eth2"),
+        ];
+
+        let renames = config.plan_interface_renames(sysfs.path()).unwrap();
+        assert_eq!(
+            renames,
+            vec![
+                InterfaceRename {
+                    current: "enp3s0".to_string(),
+                    desired: "eth0".to_string(),
+                },
+                InterfaceRename {
+                    current: "enp4s0".to_string(),
+                    desired: "eth1".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_interface_renames_skips_named_and_taken() {
+        let sysfs = sysfs_fixture(&[
+            ("eth0", "52:54:00:12:34:56"),
+            ("eth1", "52:54:00:00:00:01"),
+            ("enp5s0", "52:54:00:00:00:02"),
+        ]);
+
+        let mut config = NetworkConfig::new();
+        config.interface_aliases = vec![
+            // Already has the desired name
+            alias("52:54:00:12:34:56", "eth0"),
+            // eth1 is held by a different NIC
+            alias("52:54:00:00:00:02", "eth1"),
+        ];
+
+        assert!(config
+            .plan_interface_renames(sysfs.path())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_interface_alias_validation() {
+        let mut config = NetworkConfig::new();
+        config.interface_aliases = vec![alias("52:54:00:12:34:56", "eth0")];
+        assert!(config.validate().is_ok());
+
+        config.interface_aliases = vec![alias("52:54:00:12:34", "eth0")];
+        assert!(config.validate().is_err());
+
+        config.interface_aliases = vec![alias("52:54:00:12:34:zz", "eth0")];
         assert!(config.validate().is_err());
+
+        config.interface_aliases = vec![alias("52:54:00:12:34:56", "a-very-long-ifname")];
+        assert!(matches!(
+            config.validate(),
+            Err(NetworkConfigError::InvalidInterfaceName(_))
+        ));
+
+        config.interface_aliases = vec![
+            alias("52:54:00:12:34:56", "eth0"),
+            alias("52:54:00:12:34:56", "eth1"),
+        ];
+        assert!(config.validate().is_err());
+
+        config.interface_aliases = vec![
+            alias("52:54:00:12:34:56", "eth0"),
+            alias("52:54:00:12:34:57", "eth0"),
+        ];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_interface_aliases_default_and_reboot() {
+        let config: NetworkConfig = serde_json::from_str(r#"{"interfaces": []}"#).unwrap();
+        assert!(config.interface_aliases.is_empty());
+
+        let mut aliased = config.clone();
+        aliased.interface_aliases = vec![alias("52:54:00:12:34:56", "eth0")];
+        assert!(aliased.requires_reboot(&config));
     }
 }
@@ -0,0 +1,114 @@
+//! Stable per-node identifier shared by keel-init and keel-agent
+//!
+//! keel-init generates a random UUID on first boot and stores it on the
+//! data partition, so it survives OS updates and hostname changes. The
+//! agent only reads it.
+
+use std::io;
+use std::path::Path;
+
+/// Where the node ID is stored (bind-mounted from the data partition)
+pub const NODE_ID_PATH: &str = "/var/lib/keel/node-id";
+
+/// Format 16 random bytes as a version 4 UUID
+pub fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Node ID stored at `path`
+pub fn read_node_id<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let id = std::fs::read_to_string(path)?.trim().to_string();
+    if id.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "node ID is empty",
+        ));
+    }
+    Ok(id)
+}
+
+/// Node ID at `path`, generating and storing one if the file does not exist
+///
+/// An empty file (e.g. left by a crash during first boot) counts as missing.
+/// Any other existing file is never overwritten, even if it cannot be read,
+/// so a transient error cannot change the node's identity.
+pub fn ensure_node_id<P: AsRef<Path>>(
+    path: P,
+    generate: impl FnOnce() -> io::Result<String>,
+) -> io::Result<String> {
+    let path = path.as_ref();
+    match std::fs::read_to_string(path) {
+        Ok(id) if !id.trim().is_empty() => return Ok(id.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let id = generate()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    crate::atomic_write(path, format!("{}\n", id))?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid_v4_format() {
+        let id = uuid_v4([0xff; 16]);
+        assert_eq!(id, "ffffffff-ffff-4fff-bfff-ffffffffffff");
+        assert_eq!(uuid_v4([0; 16]), "00000000-0000-4000-8000-000000000000");
+    }
+
+    #[test]
+    fn test_ensure_node_id_generates_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("keel/node-id");
+
+        let id = ensure_node_id(&path, || Ok(uuid_v4([7; 16]))).unwrap();
+        assert_eq!(read_node_id(&path).unwrap(), id);
+
+        let again = ensure_node_id(&path, || panic!("must not regenerate")).unwrap();
+        assert_eq!(again, id);
+    }
+
+    #[test]
+    fn test_ensure_node_id_keeps_existing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("node-id");
+        std::fs::write(&path, "existing-id\n").unwrap();
+        assert_eq!(
+            ensure_node_id(&path, || panic!("must not regenerate")).unwrap(),
+            "existing-id"
+        );
+
+        // Unreadable files are kept
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+        let err = ensure_node_id(&path, || panic!("must not regenerate")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_ensure_node_id_replaces_empty_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("node-id");
+        std::fs::write(&path, " \n").unwrap();
+
+        let id = ensure_node_id(&path, || Ok(uuid_v4([7; 16]))).unwrap();
+        assert_eq!(id, uuid_v4([7; 16]));
+        assert_eq!(read_node_id(&path).unwrap(), id);
+    }
+}
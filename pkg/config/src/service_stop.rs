@@ -0,0 +1,55 @@
+//! Intentional service stops shared by keel-agent and keel-init
+//!
+//! Before a reboot keel-agent stops services that keel-init supervises.
+//! It first lists them in [`SERVICE_STOP_MARKER`], so keel-init neither
+//! respawns them nor counts their exit toward crash-loop detection. The
+//! file lives on `/run` and disappears on reboot.
+
+use std::io;
+use std::path::Path;
+
+/// Services keel-agent is stopping on purpose, one name per line
+pub const SERVICE_STOP_MARKER: &str = "/run/keel/stopping-services";
+
+/// Record that the services in `names` are being stopped on purpose
+pub fn mark_stopping<P: AsRef<Path>>(path: P, names: &[&str]) -> io::Result<()> {
+    let mut content = names.join("\n");
+    content.push('\n');
+    crate::atomic_write(path, content)
+}
+
+/// Withdraw the marker, letting keel-init restart the services again
+pub fn clear<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `name` is listed in the marker at `path`
+pub fn is_stopping<P: AsRef<Path>>(path: P, name: &str) -> bool {
+    std::fs::read_to_string(path)
+        .map(|content| content.lines().any(|line| line.trim() == name))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_and_clear() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let marker = dir.path().join("stopping-services");
+        assert!(!is_stopping(&marker, "containerd"));
+
+        mark_stopping(&marker, &["containerd", "kubelet"]).unwrap();
+        assert!(is_stopping(&marker, "containerd"));
+        assert!(is_stopping(&marker, "kubelet"));
+        assert!(!is_stopping(&marker, "keel-agent"));
+
+        clear(&marker).unwrap();
+        assert!(!is_stopping(&marker, "containerd"));
+        clear(&marker).unwrap();
+    }
+}
@@ -164,6 +164,7 @@ export VARIANT_CLOUD_INIT="${INITRAMFS_CLOUD_INIT}"
 export VARIANT_MINIMAL="${INITRAMFS_MINIMAL}"
 export VARIANT_STRIP_BINARIES="${STRIP_BINARIES}"
 export VARIANT_CARGO_PROFILE="${CARGO_PROFILE}"
+export KEEL_VERSION="${VERSION}"
 
 "${PROJECT_ROOT}/tools/builder/initramfs-build.sh"
 
@@ -31,6 +31,20 @@ mkdir -p "${INITRAMFS_DIR}/etc/containerd"
 mkdir -p "${INITRAMFS_DIR}/lib/modules"
 mkdir -p "${OUTPUT_DIR}"
 
+# keel-agent reports VERSION_ID as the running OS version and checks update
+# images against it; development builds carry no version
+echo ">>> Writing /etc/os-release (version: ${KEEL_VERSION:-dev})..."
+{
+    echo 'NAME="KeelOS"'
+    echo 'ID=keelos'
+    if [ -n "${KEEL_VERSION:-}" ] && [ "${KEEL_VERSION}" != "dev" ]; then
+        echo "VERSION_ID=\"${KEEL_VERSION#v}\""
+        echo "PRETTY_NAME=\"KeelOS ${KEEL_VERSION}\""
+    else
+        echo 'PRETTY_NAME="KeelOS (development build)"'
+    fi
+} > "${INITRAMFS_DIR}/etc/os-release"
+
 echo ">>> Copying external binaries..."
 # These come from the build container's /usr/local/bin or /usr/local/sbin
 cp -L /usr/local/bin/containerd* "${INITRAMFS_DIR}/usr/bin/"
@@ -124,6 +138,31 @@ else
     echo "    WARNING: No CA certificates found in build container"
 fi
 
+echo ">>> Copying unbound (DNS-over-TLS stub resolver)..."
+if [ -f /usr/sbin/unbound ]; then
+    cp -L /usr/sbin/unbound "${INITRAMFS_DIR}/usr/sbin/unbound"
+    # Shared libraries unbound links against (libssl, libevent, ...)
+    for lib in $(ldd /usr/sbin/unbound | awk '/=> \// {print $3}'); do
+        cp -L "${lib}" "${INITRAMFS_DIR}/lib/"
+    done
+    mkdir -p "${INITRAMFS_DIR}/etc/unbound"
+else
+    echo "ERROR: unbound not found in build container; dns_over_tls would break DNS"
+    exit 1
+fi
+
+echo ">>> Copying kexec (for update.reboot_method: kexec)..."
+if [ -f /usr/sbin/kexec ] || [ -f /sbin/kexec ]; then
+    KEXEC_BIN=$( [ -f /usr/sbin/kexec ] && echo /usr/sbin/kexec || echo /sbin/kexec )
+    cp -L "${KEXEC_BIN}" "${INITRAMFS_DIR}/usr/sbin/kexec"
+    # Shared libraries kexec links against (zlib, liblzma)
+    for lib in $(ldd "${KEXEC_BIN}" | awk '/=> \// {print $3}'); do
+        cp -L "${lib}" "${INITRAMFS_DIR}/lib/"
+    done
+else
+    echo "WARNING: kexec not found in build container; reboot_method: kexec will fall back to a normal reboot"
+fi
+
 echo ">>> Copying Kubernetes binaries..."
 mkdir -p "${INITRAMFS_DIR}/var/lib/kubelet"
 mkdir -p "${INITRAMFS_DIR}/var/lib/kubelet/pki"  # For TLS certificates generated via bootstrap
@@ -194,6 +233,8 @@ echo ">>> Copying iproute2 (for advanced networking)..."
 # Use BusyBox ip by default for reliability in this minimal environment.
 echo "Using BusyBox ip command"
 ln -sf ../bin/busybox "${INITRAMFS_DIR}/sbin/ip"
+# arping probes for IP conflicts before static addresses are assigned
+ln -sf ../bin/busybox "${INITRAMFS_DIR}/sbin/arping"
 
 echo ">>> Copying kernel modules for networking..."
 # Copy VLAN (802.1Q) and bonding kernel modules if available
@@ -110,6 +110,9 @@ make ARCH=x86_64 CROSS_COMPILE=x86_64-linux-gnu- x86_64_defconfig
 ./scripts/config --enable CONFIG_SIGNALFD
 ./scripts/config --enable CONFIG_TIMERFD
 
+# kexec reboots (update.reboot_method: kexec)
+./scripts/config --enable CONFIG_KEXEC
+
 # =============================================================================
 # Variant-specific kernel configuration
 # =============================================================================